@@ -0,0 +1,190 @@
+// OSX Scrobbler web UI
+// Talks to the /api endpoints with the token from the config file
+
+const REFRESH_MS = 5000;
+
+const params = new URLSearchParams(location.search);
+if (params.has("token")) {
+  localStorage.setItem("token", params.get("token"));
+  history.replaceState(null, "", location.pathname);
+}
+
+const $ = (id) => document.getElementById(id);
+
+// Name of the art being shown, so it's only fetched when the album changes
+let artworkName = null;
+
+async function api(path, options = {}) {
+  const response = await fetch(path, {
+    ...options,
+    headers: { Authorization: `Bearer ${localStorage.getItem("token")}` },
+  });
+  if (response.status === 401) {
+    showLogin();
+    throw new Error("Not signed in");
+  }
+  if (!response.ok) {
+    throw new Error(await response.text());
+  }
+  return response;
+}
+
+function showLogin() {
+  $("app").hidden = true;
+  $("login").hidden = false;
+}
+
+function formatSeconds(seconds) {
+  return `${Math.floor(seconds / 60)}:${String(seconds % 60).padStart(2, "0")}`;
+}
+
+function row(...cells) {
+  const tr = document.createElement("tr");
+  for (const cell of cells) {
+    const td = document.createElement("td");
+    td.textContent = cell;
+    tr.append(td);
+  }
+  return tr;
+}
+
+function renderNowPlaying(session) {
+  const label = $("progress-label");
+  const progress = $("progress");
+  if (!session) {
+    $("now-playing").textContent = "Nothing playing";
+    $("now-playing").className = "muted";
+    progress.hidden = true;
+    label.textContent = "";
+    return;
+  }
+
+  const { track } = session;
+  $("now-playing").className = "";
+  $("now-playing").textContent =
+    `${track.artist} – ${track.title}` + (track.album ? ` (${track.album})` : "");
+
+  progress.hidden = session.duration === 0;
+  progress.value = session.duration ? session.listened_seconds / session.duration : 0;
+
+  let text = formatSeconds(session.listened_seconds);
+  if (session.duration) {
+    text += ` / ${formatSeconds(session.duration)}`;
+  }
+  if (session.scrobbled) {
+    text += " · scrobbled";
+  } else if (session.scrobble_at !== null) {
+    text += ` · scrobbles at ${formatSeconds(session.scrobble_at)}`;
+  } else {
+    text += " · not scrobbled";
+  }
+  label.textContent = text;
+}
+
+async function renderArtwork(name) {
+  if (name === artworkName) {
+    return;
+  }
+  artworkName = name;
+
+  const img = $("artwork");
+  if (img.src) {
+    URL.revokeObjectURL(img.src);
+    img.removeAttribute("src");
+  }
+  img.hidden = !name;
+  if (name) {
+    try {
+      // Fetched rather than linked, since <img> can't send the token
+      img.src = URL.createObjectURL(await (await api("/api/artwork")).blob());
+    } catch (e) {
+      artworkName = null; // Try again on the next refresh
+      throw e;
+    }
+  }
+}
+
+async function refresh() {
+  const status = await (await api("/api/status")).json();
+  renderNowPlaying(status.now_playing);
+  await renderArtwork(status.artwork);
+  $("services").replaceChildren(
+    ...status.services.map((s) => row(s.key, s.enabled ? "enabled" : "disabled", s.queued)),
+  );
+  $("scrobbles").textContent = `${status.scrobbles} scrobble(s) in the history`;
+
+  const entries = await (await api("/api/history?limit=50")).json();
+  $("history").replaceChildren(
+    ...entries.map((e) => {
+      const status = e.guest
+        ? "guest"
+        : e.private
+        ? "private"
+        : e.submissions.map((s) => `${s.submitted ? "✓" : "✗"} ${s.service}`).join(", ");
+      const tags = (e.tags || []).map((t) => ` #${t}`).join("");
+      return row(new Date(e.played_at).toLocaleString(), `${e.artist} – ${e.title}${tags}`, status);
+    }),
+  );
+}
+
+async function loadConfig() {
+  $("config").value = await (await api("/api/config")).text();
+}
+
+// Refresh right away when a track starts or is scrobbled, instead of on the next poll
+let events = null;
+
+function listen() {
+  if (events) {
+    return;
+  }
+  const scheme = location.protocol === "https:" ? "wss:" : "ws:";
+  const token = encodeURIComponent(localStorage.getItem("token"));
+  events = new WebSocket(`${scheme}//${location.host}/api/events?token=${token}`);
+  events.onmessage = () => refresh().catch(console.error);
+  events.onclose = () => {
+    events = null;
+    setTimeout(listen, REFRESH_MS);
+  };
+}
+
+async function start() {
+  $("login").hidden = true;
+  $("app").hidden = false;
+  listen();
+  try {
+    await Promise.all([refresh(), loadConfig()]);
+  } catch (e) {
+    console.error(e);
+  }
+}
+
+$("login").addEventListener("submit", (event) => {
+  event.preventDefault();
+  localStorage.setItem("token", $("token").value);
+  start();
+});
+
+$("save").addEventListener("click", async () => {
+  const result = $("save-result");
+  try {
+    const response = await api("/api/config", { method: "PUT", body: $("config").value });
+    result.textContent = await response.text();
+    result.className = "ok";
+  } catch (e) {
+    result.textContent = e.message;
+    result.className = "error";
+  }
+});
+
+setInterval(() => {
+  if (!$("app").hidden) {
+    refresh().catch(console.error);
+  }
+}, REFRESH_MS);
+
+if (localStorage.getItem("token")) {
+  start();
+} else {
+  showLogin();
+}
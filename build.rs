@@ -0,0 +1,52 @@
+// Records the git commit and build time, for `--version --verbose` and the About window
+
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    if let Some(commit) = git_commit().or_else(published_commit) {
+        println!("cargo:rustc-env=OSX_SCROBBLER_GIT_COMMIT={}", commit);
+    }
+
+    // Reproducible builds set the time themselves
+    let build_time = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs())
+        });
+    println!("cargo:rustc-env=OSX_SCROBBLER_BUILD_TIME={}", build_time);
+}
+
+/// Short hash of the checked out commit, with `-dirty` when there are local changes
+fn git_commit() -> Option<String> {
+    let output = Command::new("git")
+        .args([
+            "describe",
+            "--always",
+            "--dirty",
+            "--abbrev=10",
+            "--exclude=*",
+        ])
+        .output()
+        .ok()?;
+    let commit = String::from_utf8(output.stdout).ok()?;
+    (output.status.success() && !commit.trim().is_empty()).then(|| commit.trim().to_string())
+}
+
+/// Commit the crate was published from, when built by `cargo install` from crates.io
+fn published_commit() -> Option<String> {
+    let info = std::fs::read_to_string(".cargo_vcs_info.json").ok()?;
+    let start = info.find("\"sha1\"")?;
+    let hash = info[start + 6..]
+        .split('"')
+        .nth(1)
+        .filter(|hash| hash.chars().all(|c| c.is_ascii_hexdigit()))?;
+    Some(hash.chars().take(10).collect())
+}
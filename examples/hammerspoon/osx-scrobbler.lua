@@ -0,0 +1,97 @@
+-- OSX Scrobbler for Hammerspoon
+--
+-- Shows the current track in the menu bar and a notification for every scrobble, using the
+-- events pushed over OSX Scrobbler's control socket. Clicking the menu bar item pauses or
+-- resumes scrobbling.
+--
+-- Copy this file to ~/.hammerspoon/ and add `require("osx-scrobbler")` to your init.lua.
+
+local socketPath = os.getenv("HOME") .. "/Library/Application Support/osx_scrobbler.sock"
+
+local menu = hs.menubar.new()
+local socket = nil
+local paused = false
+local nowPlaying = nil
+local nextId = 1
+
+local function updateMenu()
+  local title = "♪"
+  if nowPlaying then
+    title = title .. " " .. nowPlaying.artist .. " – " .. nowPlaying.title
+  end
+  if paused then
+    title = title .. " (paused)"
+  end
+  menu:setTitle(title)
+end
+
+local function send(method, params)
+  if not socket or not socket:connected() then
+    return
+  end
+  local request = { jsonrpc = "2.0", id = nextId, method = method, params = params }
+  nextId = nextId + 1
+  socket:write(hs.json.encode(request) .. "\n")
+end
+
+local function handle(message)
+  if message.method == "now_playing" then
+    nowPlaying = message.params.track
+  elseif message.method == "session_ended" then
+    nowPlaying = nil
+  elseif message.method == "paused" then
+    paused = message.params.paused
+  elseif message.method == "scrobble" then
+    local track = message.params.track
+    local notification = hs.notify.new({
+      title = message.params.private and "Recorded (paused)" or "Scrobbled",
+      informativeText = track.artist .. " – " .. track.title,
+      withdrawAfter = 5,
+    })
+    -- Album art, when `[artwork] enabled = true` and it's been downloaded
+    if message.params.artwork then
+      notification:contentImage(hs.image.imageFromPath(message.params.artwork))
+    end
+    notification:send()
+  elseif message.result and message.result.api_version then
+    -- Answer to the `status` request sent on connect
+    paused = message.result.paused
+    nowPlaying = message.result.now_playing and message.result.now_playing.track
+  end
+  updateMenu()
+end
+
+local function connect()
+  socket = hs.socket.new(function(data)
+    local ok, message = pcall(hs.json.decode, data)
+    if ok and message then
+      handle(message)
+    end
+    socket:read("\n")
+  end)
+
+  socket:connect(socketPath, function()
+    send("subscribe")
+    send("status")
+    socket:read("\n")
+  end)
+end
+
+menu:setClickCallback(function()
+  send(paused and "resume" or "pause")
+end)
+
+-- Reconnect when the app restarts
+local reconnector = hs.timer.doEvery(10, function()
+  if not socket or not socket:connected() then
+    nowPlaying = nil
+    menu:setTitle("♪ –")
+    connect()
+  end
+end)
+
+updateMenu()
+connect()
+
+-- Returned so `require` keeps the menu bar item and timer from being garbage collected
+return { menu = menu, reconnector = reconnector }
@@ -0,0 +1,67 @@
+// Example OSX Scrobbler plugin
+// Removes remaster suffixes like " - Remastered 2011" or " (2009 Remaster)" from titles and albums
+//
+// Build with `cargo build --release` and add the library to the config:
+//
+//   plugins = ["/path/to/strip-remaster/target/release/libstrip_remaster.dylib"]
+
+use regex::Regex;
+use serde_json::{json, Value};
+use std::ffi::{c_char, CStr, CString};
+use std::sync::OnceLock;
+
+#[no_mangle]
+pub extern "C" fn osx_scrobbler_plugin_abi_version() -> u32 {
+    1
+}
+
+/// # Safety
+///
+/// `request` must be a NUL-terminated string, as passed by OSX Scrobbler.
+#[no_mangle]
+pub unsafe extern "C" fn osx_scrobbler_transform(request: *const c_char) -> *mut c_char {
+    let Ok(request) = CStr::from_ptr(request).to_str() else {
+        return std::ptr::null_mut();
+    };
+    let Ok(mut request) = serde_json::from_str::<Value>(request) else {
+        return std::ptr::null_mut();
+    };
+
+    let track = &mut request["track"];
+    let mut changed = false;
+    for field in ["title", "album"] {
+        if let Some(text) = track[field].as_str() {
+            let stripped = remaster_pattern().replace(text, "").into_owned();
+            if stripped != text {
+                track[field] = Value::String(stripped);
+                changed = true;
+            }
+        }
+    }
+
+    let response = match changed {
+        true => json!({"action": "rewrite", "track": track}),
+        false => json!({"action": "keep"}),
+    };
+    CString::new(response.to_string())
+        .map(CString::into_raw)
+        .unwrap_or(std::ptr::null_mut())
+}
+
+/// # Safety
+///
+/// `response` must come from `osx_scrobbler_transform`.
+#[no_mangle]
+pub unsafe extern "C" fn osx_scrobbler_free(response: *mut c_char) {
+    if !response.is_null() {
+        drop(CString::from_raw(response));
+    }
+}
+
+fn remaster_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(r"(?i)\s*(- (\d{4} )?remaster(ed)?( \d{4})?( version)?|[(\[](\d{4} )?remaster(ed)?( \d{4})?( version)?[)\]])$")
+            .unwrap()
+    })
+}
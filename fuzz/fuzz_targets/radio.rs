@@ -0,0 +1,37 @@
+// Radio fuzz target
+// Resolves Apple Music radio tracks with each radio policy, with the station as the artist
+// (so the artist and title are parsed from the track name) or as the album
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use osx_scrobbler::apple_music::{RadioHandler, APPLE_MUSIC_BUNDLE_ID};
+use osx_scrobbler::config::{AppleMusicConfig, RadioPolicy};
+use osx_scrobbler::scrobbler::Track;
+
+const STATION: &str = "Apple Music 1";
+
+fuzz_target!(|input: (bool, &str, &str)| {
+    let (station_artist, title, other) = input;
+    let (artist, album) = if station_artist {
+        (STATION, other)
+    } else {
+        (other, STATION)
+    };
+    let mut track = Track::new(artist, title);
+    track.album = Some(album.to_string());
+
+    for radio in [RadioPolicy::Parse, RadioPolicy::Skip, RadioPolicy::Scrobble] {
+        let handler = RadioHandler::new(&AppleMusicConfig {
+            radio,
+            ..AppleMusicConfig::default()
+        });
+        if let Some(resolved) = handler.resolve(Some(APPLE_MUSIC_BUNDLE_ID), track.clone()) {
+            assert!(resolved.radio);
+            // A parsed track has both parts
+            if radio == RadioPolicy::Parse && station_artist {
+                assert!(!resolved.artist.is_empty() && !resolved.title.is_empty());
+            }
+        }
+    }
+});
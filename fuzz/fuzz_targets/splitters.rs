@@ -0,0 +1,21 @@
+// Splitters fuzz target
+// Splits live recording details off titles and dual script names into their parts, checking
+// that the parts come from the text
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use osx_scrobbler::text_cleanup::{split_dual_script, split_live};
+
+fuzz_target!(|text: &str| {
+    if let Some((title, live)) = split_live(text) {
+        assert!(!title.is_empty());
+        assert!(text.starts_with(title));
+        assert!(text.contains(live));
+    }
+
+    if let Some((original, latin)) = split_dual_script(text) {
+        assert!(text.contains(original));
+        assert!(text.contains(latin));
+    }
+});
@@ -0,0 +1,38 @@
+// Text cleaner fuzz target
+// Runs titles, artists and albums through the default cleanup patterns, the version suffixes,
+// the live policy and both dual script choices
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use osx_scrobbler::config::{CleanupConfig, DualScript, LivePolicy};
+use osx_scrobbler::text_cleanup::TextCleaner;
+use std::sync::OnceLock;
+
+/// Cleaners with every step enabled, built once as compiling the patterns is slow
+fn cleaners() -> &'static [TextCleaner] {
+    static CLEANERS: OnceLock<Vec<TextCleaner>> = OnceLock::new();
+    CLEANERS.get_or_init(|| {
+        [DualScript::Original, DualScript::Latin]
+            .into_iter()
+            .map(|dual_script| {
+                let mut config = CleanupConfig {
+                    dual_script,
+                    live: LivePolicy::Tag,
+                    ..CleanupConfig::default()
+                };
+                config.suffixes.enabled = true;
+                TextCleaner::new(&config)
+            })
+            .collect()
+    })
+}
+
+fuzz_target!(|text: &str| {
+    for cleaner in cleaners() {
+        let _ = cleaner.clean(text);
+        let (title, _live) = cleaner.live(cleaner.clean_title(text));
+        // Cleaning a cleaned title again must not panic either
+        let _ = cleaner.clean_title(&title);
+    }
+});
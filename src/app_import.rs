@@ -0,0 +1,427 @@
+// App filtering import module
+// Reads allow/ignore decisions from other macOS scrobblers' preference files
+
+use crate::config::AppFilteringConfig;
+use anyhow::{Context, Result};
+use plist::Value;
+use regex::Regex;
+use std::path::{Path, PathBuf};
+
+/// Another scrobbler whose preferences we know how to look for
+pub struct ImportSource {
+    /// Identifier used on the command line
+    pub id: &'static str,
+    /// Human readable name
+    pub name: &'static str,
+    /// Candidate preference files, relative to the home directory
+    pub preference_files: &'static [&'static str],
+}
+
+/// Known scrobblers to import from
+pub const SOURCES: &[ImportSource] = &[
+    ImportSource {
+        id: "neptunes",
+        name: "NepTunes",
+        preference_files: &[
+            "Library/Preferences/pl.micropixels.NepTunes.plist",
+            "Library/Containers/pl.micropixels.NepTunes/Data/Library/Preferences/pl.micropixels.NepTunes.plist",
+        ],
+    },
+    ImportSource {
+        id: "lastfm",
+        name: "Last.fm desktop scrobbler",
+        preference_files: &[
+            "Library/Preferences/fm.last.Scrobbler.plist",
+            "Library/Preferences/fm.last.Last.fm.plist",
+        ],
+    },
+];
+
+/// Players that other scrobblers commonly expose as on/off settings
+/// (lowercase name as it appears in the preference key, bundle ID)
+const KNOWN_PLAYERS: &[(&str, &str)] = &[
+    ("spotify", "com.spotify.client"),
+    ("itunes", "com.apple.iTunes"),
+    ("music", "com.apple.Music"),
+    ("vox", "com.coppertino.Vox"),
+    ("swinsian", "com.swinsian.Swinsian"),
+    ("deezer", "com.deezer.deezer-desktop"),
+    ("tidal", "com.tidal.desktop"),
+    ("vlc", "org.videolan.vlc"),
+];
+
+/// Words in a preference key that invert its meaning
+const NEGATIVE_WORDS: &[&str] = &["ignore", "disable", "exclude", "blacklist", "block", "skip"];
+
+/// Verbs a player setting starts with, e.g. `scrobbleSpotify` or `ignoreFromiTunes`
+const SETTING_VERBS: &[&str] = &[
+    "scrobble",
+    "scrobbling",
+    "enable",
+    "allow",
+    "ignore",
+    "disable",
+    "exclude",
+    "blacklist",
+    "block",
+    "skip",
+];
+
+/// States a player setting ends with, e.g. `spotifyEnabled` or `vlcScrobblingDisabled`
+const SETTING_STATES: &[&str] = &[
+    "enabled",
+    "allowed",
+    "ignored",
+    "disabled",
+    "excluded",
+    "blacklisted",
+    "blocked",
+    "skipped",
+];
+
+/// Words in a preference key that suggest it holds app bundle IDs (singular; plurals match too)
+const APP_LIST_WORDS: &[&str] = &["app", "player", "source", "bundle", "client"];
+
+/// A single imported decision
+#[derive(Debug, Clone, PartialEq)]
+pub enum AppDecision {
+    Allow(String),
+    Ignore(String),
+}
+
+impl AppDecision {
+    fn bundle_id(&self) -> &str {
+        match self {
+            Self::Allow(id) | Self::Ignore(id) => id,
+        }
+    }
+}
+
+/// Result of merging imported decisions into the config
+#[derive(Debug, Default, PartialEq)]
+pub struct MergeSummary {
+    pub allowed: Vec<String>,
+    pub ignored: Vec<String>,
+    /// Bundle IDs skipped because the config already has a decision for them
+    pub skipped: Vec<String>,
+}
+
+/// Find a known source by its command line identifier
+pub fn find_source(id: &str) -> Option<&'static ImportSource> {
+    SOURCES.iter().find(|s| s.id.eq_ignore_ascii_case(id))
+}
+
+/// Locate the first existing preference file for a source
+pub fn locate(source: &ImportSource) -> Option<PathBuf> {
+    let home = dirs::home_dir()?;
+    source
+        .preference_files
+        .iter()
+        .map(|file| home.join(file))
+        .find(|path| path.exists())
+}
+
+/// Read decisions from a preference file (binary or XML plist)
+pub fn read_decisions(path: &Path) -> Result<Vec<AppDecision>> {
+    let value = Value::from_file(path)
+        .with_context(|| format!("Failed to read preferences from {}", path.display()))?;
+
+    Ok(extract_decisions(&value))
+}
+
+/// Walk a preferences tree and collect app decisions
+///
+/// Boolean settings that are nothing but a known player's name and a verb or state (e.g.
+/// `scrobbleSpotify` or `vlcEnabled`) map to that player's bundle ID, so settings that merely
+/// mention one (`useMusicBrainz`, `scrobbleMusicVideos`) are left alone. Bundle IDs listed under
+/// app-related keys are taken as-is. Keys containing words like `ignore` or `exclude` invert the
+/// decision.
+pub fn extract_decisions(value: &Value) -> Vec<AppDecision> {
+    let patterns = Patterns::new();
+    let mut decisions = Vec::new();
+    walk(value, "", &patterns, &mut decisions);
+
+    // Keep only the first decision per bundle ID
+    let mut seen = Vec::new();
+    decisions.retain(|d| {
+        if seen.contains(&d.bundle_id().to_string()) {
+            false
+        } else {
+            seen.push(d.bundle_id().to_string());
+            true
+        }
+    });
+
+    decisions
+}
+
+/// Regexes for recognizing settings, built once per import
+struct Patterns {
+    bundle_id: Regex,
+    /// A whole lowercase key naming a player setting; the player is the `player` group
+    player_setting: Regex,
+}
+
+impl Patterns {
+    fn new() -> Self {
+        let players = KNOWN_PLAYERS
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join("|");
+        let player_setting = format!(
+            r"^(?:(?:{verbs})(?:from|for|in)?(?P<player>{players})|(?P<player_first>{players})(?:scrobbling)?(?:{states}))$",
+            verbs = SETTING_VERBS.join("|"),
+            states = SETTING_STATES.join("|"),
+        );
+
+        Self {
+            bundle_id: Regex::new(r"^[A-Za-z0-9-]+(\.[A-Za-z0-9-]+){2,}$")
+                .expect("valid bundle ID regex"),
+            player_setting: Regex::new(&player_setting).expect("valid player setting regex"),
+        }
+    }
+
+    /// Bundle ID of the player a boolean setting turns on or off
+    fn player(&self, key_lower: &str) -> Option<&'static str> {
+        let captures = self.player_setting.captures(key_lower)?;
+        let name = captures
+            .name("player")
+            .or_else(|| captures.name("player_first"))?
+            .as_str();
+        KNOWN_PLAYERS
+            .iter()
+            .find(|(player, _)| *player == name)
+            .map(|(_, bundle_id)| *bundle_id)
+    }
+}
+
+fn walk(value: &Value, key: &str, patterns: &Patterns, decisions: &mut Vec<AppDecision>) {
+    let key_lower = key.to_ascii_lowercase();
+    let negative = NEGATIVE_WORDS.iter().any(|w| key_lower.contains(w));
+
+    match value {
+        Value::Dictionary(dict) => {
+            for (child_key, child) in dict.iter() {
+                walk(child, child_key, patterns, decisions);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                walk(item, key, patterns, decisions);
+            }
+        }
+        Value::Boolean(enabled) => {
+            if let Some(bundle_id) = patterns.player(&key_lower) {
+                let allow = *enabled != negative;
+                decisions.push(decision(bundle_id, allow));
+            }
+        }
+        Value::String(text) => {
+            let is_app_list = key_words(key).iter().any(|word| {
+                APP_LIST_WORDS
+                    .iter()
+                    .any(|w| word == w || word.strip_suffix('s') == Some(w))
+            });
+            if is_app_list && patterns.bundle_id.is_match(text) {
+                decisions.push(decision(text, !negative));
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Lowercase words of a camelCase or snake_case key, e.g. `excludedApps` → `excluded`, `apps`
+fn key_words(key: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for c in key.chars() {
+        let camel_boundary =
+            c.is_ascii_uppercase() && word.chars().last().is_some_and(|p| p.is_ascii_lowercase());
+        if (!c.is_ascii_alphanumeric() || camel_boundary) && !word.is_empty() {
+            words.push(std::mem::take(&mut word).to_ascii_lowercase());
+        }
+        if c.is_ascii_alphanumeric() {
+            word.push(c);
+        }
+    }
+    if !word.is_empty() {
+        words.push(word.to_ascii_lowercase());
+    }
+    words
+}
+
+fn decision(bundle_id: &str, allow: bool) -> AppDecision {
+    if allow {
+        AppDecision::Allow(bundle_id.to_string())
+    } else {
+        AppDecision::Ignore(bundle_id.to_string())
+    }
+}
+
+/// Merge imported decisions into the app filtering config
+///
+/// Existing decisions always win; only apps the config doesn't know about are added.
+pub fn merge(app_filtering: &mut AppFilteringConfig, decisions: &[AppDecision]) -> MergeSummary {
+    let mut summary = MergeSummary::default();
+
+    for decision in decisions {
+        let bundle_id = decision.bundle_id().to_string();
+        if app_filtering.allowed_apps.contains(&bundle_id)
+            || app_filtering.ignored_apps.contains(&bundle_id)
+        {
+            summary.skipped.push(bundle_id);
+            continue;
+        }
+
+        match decision {
+            AppDecision::Allow(_) => {
+                app_filtering.allowed_apps.push(bundle_id.clone());
+                summary.allowed.push(bundle_id);
+            }
+            AppDecision::Ignore(_) => {
+                app_filtering.ignored_apps.push(bundle_id.clone());
+                summary.ignored.push(bundle_id);
+            }
+        }
+    }
+
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plist::Dictionary;
+
+    fn dict(entries: Vec<(&str, Value)>) -> Value {
+        let mut dict = Dictionary::new();
+        for (key, value) in entries {
+            dict.insert(key.to_string(), value);
+        }
+        Value::Dictionary(dict)
+    }
+
+    #[test]
+    fn test_boolean_player_settings() {
+        let prefs = dict(vec![
+            ("scrobbleSpotify", Value::Boolean(true)),
+            ("scrobbleFromiTunes", Value::Boolean(false)),
+            ("showNotifications", Value::Boolean(true)),
+        ]);
+
+        assert_eq!(
+            extract_decisions(&prefs),
+            vec![
+                AppDecision::Allow("com.spotify.client".to_string()),
+                AppDecision::Ignore("com.apple.iTunes".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_settings_mentioning_a_player_are_not_player_settings() {
+        let prefs = dict(vec![
+            ("useMusicBrainz", Value::Boolean(true)),
+            ("showMusicNotifications", Value::Boolean(true)),
+            ("scrobbleMusicVideos", Value::Boolean(false)),
+            ("spotifyEnabled", Value::Boolean(true)),
+        ]);
+
+        assert_eq!(
+            extract_decisions(&prefs),
+            vec![AppDecision::Allow("com.spotify.client".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_app_list_keys_match_whole_words() {
+        let prefs = dict(vec![
+            (
+                "lastAppcastFeed",
+                Value::String("com.example.appcast.v2".to_string()),
+            ),
+            (
+                "allowed_players",
+                Value::Array(vec![Value::String("com.swinsian.Swinsian".to_string())]),
+            ),
+        ]);
+
+        assert_eq!(
+            extract_decisions(&prefs),
+            vec![AppDecision::Allow("com.swinsian.Swinsian".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_key_words() {
+        assert_eq!(key_words("excludedApps"), ["excluded", "apps"]);
+        assert_eq!(key_words("allowed_players"), ["allowed", "players"]);
+        assert_eq!(key_words("SUFeedURL"), ["sufeed", "url"]);
+    }
+
+    #[test]
+    fn test_negative_keys_invert_decision() {
+        let prefs = dict(vec![("ignoreSpotify", Value::Boolean(true))]);
+
+        assert_eq!(
+            extract_decisions(&prefs),
+            vec![AppDecision::Ignore("com.spotify.client".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_bundle_id_lists() {
+        let prefs = dict(vec![
+            (
+                "excludedApps",
+                Value::Array(vec![Value::String("com.apple.Safari".to_string())]),
+            ),
+            (
+                "players",
+                Value::Array(vec![Value::String("org.videolan.vlc".to_string())]),
+            ),
+            (
+                "SUFeedURL",
+                Value::String("https://example.com/appcast.xml".to_string()),
+            ),
+        ]);
+
+        let decisions = extract_decisions(&prefs);
+        assert!(decisions.contains(&AppDecision::Ignore("com.apple.Safari".to_string())));
+        assert!(decisions.contains(&AppDecision::Allow("org.videolan.vlc".to_string())));
+        assert_eq!(decisions.len(), 2);
+    }
+
+    #[test]
+    fn test_merge_keeps_existing_decisions() {
+        let mut app_filtering = AppFilteringConfig {
+            ignored_apps: vec!["com.spotify.client".to_string()],
+            ..Default::default()
+        };
+        let decisions = vec![
+            AppDecision::Allow("com.spotify.client".to_string()),
+            AppDecision::Allow("com.apple.Music".to_string()),
+            AppDecision::Ignore("com.apple.Safari".to_string()),
+        ];
+
+        let summary = merge(&mut app_filtering, &decisions);
+
+        assert_eq!(summary.allowed, vec!["com.apple.Music".to_string()]);
+        assert_eq!(summary.ignored, vec!["com.apple.Safari".to_string()]);
+        assert_eq!(summary.skipped, vec!["com.spotify.client".to_string()]);
+        assert_eq!(
+            app_filtering.ignored_apps,
+            vec![
+                "com.spotify.client".to_string(),
+                "com.apple.Safari".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_source() {
+        assert_eq!(find_source("NepTunes").map(|s| s.id), Some("neptunes"));
+        assert!(find_source("unknown").is_none());
+    }
+}
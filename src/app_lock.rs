@@ -0,0 +1,93 @@
+// App lock module
+// A file the running app keeps locked, so commands that change its files can tell it's running
+// even with the control API turned off
+
+use anyhow::{Context, Result};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::path::{Path, PathBuf};
+
+/// Lock held for as long as the app runs, released when dropped or when the process exits
+#[derive(Debug)]
+pub struct AppLock {
+    _file: File,
+}
+
+impl AppLock {
+    /// Path of the lock file
+    pub fn path() -> Result<PathBuf> {
+        Ok(crate::paths::Paths::current()?.lock)
+    }
+
+    /// Take the lock for the running app
+    pub fn acquire() -> Result<Self> {
+        Self::acquire_at(&Self::path()?)
+    }
+
+    fn acquire_at(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let file = open(path)?;
+        match file.try_lock() {
+            Ok(()) => Ok(Self { _file: file }),
+            Err(TryLockError::WouldBlock) => {
+                anyhow::bail!("Another instance holds {}", path.display())
+            }
+            Err(TryLockError::Error(e)) => {
+                Err(e).with_context(|| format!("Failed to lock {}", path.display()))
+            }
+        }
+    }
+}
+
+/// Check whether a running app holds the lock
+pub fn is_held() -> Result<bool> {
+    is_held_at(&AppLock::path()?)
+}
+
+fn is_held_at(path: &Path) -> Result<bool> {
+    // No lock file means the app hasn't run with these paths yet
+    if !path.exists() {
+        return Ok(false);
+    }
+    match open(path)?.try_lock_shared() {
+        Ok(()) => Ok(false),
+        Err(TryLockError::WouldBlock) => Ok(true),
+        Err(TryLockError::Error(e)) => {
+            Err(e).with_context(|| format!("Failed to check {}", path.display()))
+        }
+    }
+}
+
+fn open(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_held_until_dropped() {
+        let path = std::env::temp_dir().join(format!(
+            "osx_scrobbler_lock_test_{}.lock",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        assert!(!is_held_at(&path).unwrap());
+
+        let lock = AppLock::acquire_at(&path).unwrap();
+        assert!(is_held_at(&path).unwrap());
+        assert!(AppLock::acquire_at(&path).is_err());
+
+        drop(lock);
+        assert!(!is_held_at(&path).unwrap());
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,469 @@
+// Apple Music module
+// Detects Apple Music radio playback and applies the configured radio policy, and reads
+// metadata Media Remote doesn't expose from the Music app
+
+use crate::config::{AppleMusicConfig, RadioPolicy};
+use crate::energy::{self, Activity};
+use crate::scrobbler::Track;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::process::Command;
+
+pub const APPLE_MUSIC_BUNDLE_ID: &str = "com.apple.Music";
+
+/// Apple Music's live stations, which report themselves as the artist or album
+const BUILTIN_STATIONS: &[&str] = &[
+    "Apple Music 1",
+    "Apple Music Hits",
+    "Apple Music Country",
+    "Apple Música Uno",
+    "Apple Music Club",
+    "Apple Music Chill",
+];
+
+/// Album artists compilations are credited to, instead of an actual artist
+const VARIOUS_ARTISTS: &[&str] = &[
+    "Various Artists",
+    "Various",
+    "VA",
+    "V.A.",
+    "Varios Artistas",
+    "Verschiedene Interpreten",
+    "Artistes Divers",
+];
+
+/// Separators stations put between artist and title when both end up in the title
+const TITLE_SEPARATORS: &[&str] = &[" - ", " – ", " — "];
+
+pub struct RadioHandler {
+    policy: RadioPolicy,
+    stations: Vec<String>,
+}
+
+impl RadioHandler {
+    pub fn new(config: &AppleMusicConfig) -> Self {
+        let stations = BUILTIN_STATIONS
+            .iter()
+            .map(|s| s.to_string())
+            .chain(config.stations.iter().cloned())
+            .collect();
+
+        Self {
+            policy: config.radio,
+            stations,
+        }
+    }
+
+    fn is_station(&self, name: &str) -> bool {
+        let name = name.trim();
+        self.stations.iter().any(|s| s.eq_ignore_ascii_case(name))
+    }
+
+    /// Apply the radio policy to a track reported by a player
+    ///
+    /// Tracks from other players, and Apple Music tracks not coming from a station, are
+    /// returned unchanged. Returns `None` if the track should not be scrobbled.
+    pub fn resolve(&self, bundle_id: Option<&str>, track: Track) -> Option<Track> {
+        if bundle_id != Some(APPLE_MUSIC_BUNDLE_ID) {
+            return Some(track);
+        }
+
+        let station_artist = self.is_station(&track.artist);
+        let station_album = track.album.as_deref().is_some_and(|a| self.is_station(a));
+        if !station_artist && !station_album {
+            return Some(track);
+        }
+
+        match self.policy {
+            RadioPolicy::Scrobble => Some(Track {
+                radio: true,
+                ..track
+            }),
+            RadioPolicy::Skip => {
+                log::debug!(
+                    "Skipping Apple Music radio: {} - {}",
+                    track.artist,
+                    track.title
+                );
+                None
+            }
+            RadioPolicy::Parse if station_artist => {
+                // The real artist is only in the title, if at all (shows and DJ talk have none)
+                let Some((artist, title)) = split_title(&track.title) else {
+                    log::debug!("No track in Apple Music radio title: {}", track.title);
+                    return None;
+                };
+
+                Some(Track {
+                    title,
+                    artist,
+                    album: None,
+                    duration: track.duration,
+                    origin_url: None,
+                    radio: true,
+                    mbid: None,
+                    album_artist: None,
+                    live: None,
+                })
+            }
+            // Proper artist/title, with the station standing in for the album
+            RadioPolicy::Parse => Some(Track {
+                album: None,
+                radio: true,
+                ..track
+            }),
+        }
+    }
+}
+
+/// Credits compilation tracks to their own artist, with the album artist passed separately
+pub struct CompilationHandler {
+    enabled: bool,
+}
+
+impl CompilationHandler {
+    pub fn new(config: &AppleMusicConfig) -> Self {
+        Self {
+            enabled: config.compilations,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Give a Music app track its own artist and the album artist, when they differ
+    ///
+    /// A track reported as by "Various Artists" gets the track artist from the Music app.
+    /// Compilations without an album artist are credited to "Various Artists".
+    pub fn apply(&self, track: Track, details: &TrackDetails) -> Track {
+        if !self.enabled {
+            return track;
+        }
+
+        let artist = match details.artist.as_deref() {
+            Some(artist) if is_various_artists(&track.artist) && !is_various_artists(artist) => {
+                log::debug!("Scrobbling compilation track as by {}", artist);
+                artist.to_string()
+            }
+            _ => track.artist,
+        };
+        let album_artist = details
+            .album_artist
+            .clone()
+            .or_else(|| details.compilation.then(|| VARIOUS_ARTISTS[0].to_string()))
+            .filter(|album_artist| !album_artist.eq_ignore_ascii_case(&artist));
+
+        Track {
+            artist,
+            album_artist,
+            ..track
+        }
+    }
+}
+
+/// Whether a name is one compilations are credited to rather than a real artist
+pub fn is_various_artists(name: &str) -> bool {
+    let name = name.trim();
+    VARIOUS_ARTISTS
+        .iter()
+        .any(|va| va.eq_ignore_ascii_case(name))
+}
+
+/// Metadata of the Music app's current track that Media Remote doesn't report
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TrackDetails {
+    pub composer: Option<String>,
+    pub genre: Option<String>,
+    /// e.g. `song` or `audiobook`
+    pub media_kind: Option<String>,
+    pub persistent_id: Option<String>,
+    pub album_artist: Option<String>,
+    /// Part of a compilation (the "Album is a compilation of songs by various artists" option)
+    pub compilation: bool,
+    /// Track artist as tagged in the library
+    pub artist: Option<String>,
+}
+
+/// Ask the Music app for the current track's composer, genre, media kind, persistent ID, album
+/// artist, compilation flag and artist
+pub fn current_track_details() -> Result<TrackDetails> {
+    // Fields are separated by a tab, which doesn't occur in tags
+    const SCRIPT: &str = r#"if application "Music" is running then
+    tell application "Music"
+        set t to current track
+        get (composer of t) & tab & (genre of t) & tab & (media kind of t as text) ¬
+            & tab & (persistent ID of t) & tab & (album artist of t) ¬
+            & tab & (compilation of t as text) & tab & (artist of t)
+    end tell
+end if"#;
+
+    energy::record(Activity::Script);
+    let output = Command::new("osascript")
+        .args(["-e", SCRIPT])
+        .output()
+        .context("Failed to run osascript")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to query the Music app: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(parse_track_details(&String::from_utf8_lossy(
+        &output.stdout,
+    )))
+}
+
+/// Ask the Music app for the current track's artwork, as PNG or JPEG data
+///
+/// Returns `None` if the track has no artwork, like radio streams.
+pub fn current_artwork() -> Result<Option<Vec<u8>>> {
+    // osascript can't print binary data, so the script writes it to a file
+    const SCRIPT: &str = r#"on run argv
+    if application "Music" is not running then return ""
+    tell application "Music"
+        if (count of artworks of current track) is 0 then return ""
+        set artworkData to raw data of artwork 1 of current track
+    end tell
+    set artworkFile to open for access POSIX file (item 1 of argv) with write permission
+    try
+        set eof artworkFile to 0
+        write artworkData to artworkFile
+    end try
+    close access artworkFile
+    return "written"
+end run"#;
+
+    let path = std::env::temp_dir().join(format!("osx-scrobbler-artwork-{}", std::process::id()));
+    energy::record(Activity::Script);
+    let output = Command::new("osascript")
+        .args(["-e", SCRIPT])
+        .arg(&path)
+        .output()
+        .context("Failed to run osascript")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to query the Music app: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    if String::from_utf8_lossy(&output.stdout).trim() != "written" {
+        return Ok(None);
+    }
+
+    let image = std::fs::read(&path).context("Failed to read artwork from the Music app");
+    let _ = std::fs::remove_file(&path);
+    Ok(Some(image?).filter(|image| !image.is_empty()))
+}
+
+fn parse_track_details(output: &str) -> TrackDetails {
+    let mut fields = output.trim_end_matches('\n').split('\t').map(|field| {
+        let field = field.trim();
+        (!field.is_empty()).then(|| field.to_string())
+    });
+
+    TrackDetails {
+        composer: fields.next().flatten(),
+        genre: fields.next().flatten(),
+        media_kind: fields.next().flatten(),
+        persistent_id: fields.next().flatten(),
+        album_artist: fields.next().flatten(),
+        compilation: fields.next().flatten().as_deref() == Some("true"),
+        artist: fields.next().flatten(),
+    }
+}
+
+/// Split an `Artist - Title` string at the first separator
+fn split_title(title: &str) -> Option<(String, String)> {
+    let (index, separator) = TITLE_SEPARATORS
+        .iter()
+        .filter_map(|separator| Some((title.find(separator)?, separator)))
+        .min_by_key(|(index, _)| *index)?;
+
+    let artist = title[..index].trim();
+    let title = title[index + separator.len()..].trim();
+    (!artist.is_empty() && !title.is_empty()).then(|| (artist.to_string(), title.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handler(policy: RadioPolicy) -> RadioHandler {
+        RadioHandler::new(&AppleMusicConfig {
+            radio: policy,
+            stations: vec!["Local FM".to_string()],
+            compilations: true,
+        })
+    }
+
+    fn track(artist: &str, title: &str, album: Option<&str>) -> Track {
+        Track {
+            title: title.to_string(),
+            artist: artist.to_string(),
+            album: album.map(str::to_string),
+            duration: Some(200),
+            ..Default::default()
+        }
+    }
+
+    /// The track as picked by a station
+    fn from_radio(track: Track) -> Track {
+        Track {
+            radio: true,
+            ..track
+        }
+    }
+
+    #[test]
+    fn test_other_players_untouched() {
+        let played = track("Apple Music 1", "Artist - Song", None);
+        assert_eq!(
+            handler(RadioPolicy::Skip).resolve(Some("com.spotify.client"), played.clone()),
+            Some(played)
+        );
+    }
+
+    #[test]
+    fn test_parse_station_artist() {
+        let handler = handler(RadioPolicy::Parse);
+
+        assert_eq!(
+            handler.resolve(
+                Some(APPLE_MUSIC_BUNDLE_ID),
+                track(
+                    "Apple Music 1",
+                    "Artist — Song - Remix",
+                    Some("Apple Music 1")
+                )
+            ),
+            Some(from_radio(track("Artist", "Song - Remix", None)))
+        );
+        assert_eq!(
+            handler.resolve(
+                Some(APPLE_MUSIC_BUNDLE_ID),
+                track("apple music hits", "The Morning Show", None)
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_station_album() {
+        assert_eq!(
+            handler(RadioPolicy::Parse).resolve(
+                Some(APPLE_MUSIC_BUNDLE_ID),
+                track("Artist", "Song", Some("Local FM"))
+            ),
+            Some(from_radio(track("Artist", "Song", None)))
+        );
+    }
+
+    #[test]
+    fn test_parse_track_details() {
+        assert_eq!(
+            parse_track_details("Ludwig van Beethoven\tClassical\tsong\t5D3A0C2B9E8F7A61\n"),
+            TrackDetails {
+                composer: Some("Ludwig van Beethoven".to_string()),
+                genre: Some("Classical".to_string()),
+                media_kind: Some("song".to_string()),
+                persistent_id: Some("5D3A0C2B9E8F7A61".to_string()),
+                ..TrackDetails::default()
+            }
+        );
+        assert_eq!(
+            parse_track_details("\tPop\tsong\tABC\tVarious Artists\ttrue\tBand\n"),
+            TrackDetails {
+                composer: None,
+                genre: Some("Pop".to_string()),
+                media_kind: Some("song".to_string()),
+                persistent_id: Some("ABC".to_string()),
+                album_artist: Some("Various Artists".to_string()),
+                compilation: true,
+                artist: Some("Band".to_string()),
+            }
+        );
+        assert_eq!(
+            parse_track_details("\tPop\n"),
+            TrackDetails {
+                composer: None,
+                genre: Some("Pop".to_string()),
+                media_kind: None,
+                persistent_id: None,
+                ..TrackDetails::default()
+            }
+        );
+        assert_eq!(parse_track_details(""), TrackDetails::default());
+    }
+
+    #[test]
+    fn test_skip_and_scrobble_policies() {
+        let played = track("Apple Music Chill", "Artist - Song", None);
+
+        assert_eq!(
+            handler(RadioPolicy::Skip).resolve(Some(APPLE_MUSIC_BUNDLE_ID), played.clone()),
+            None
+        );
+        assert_eq!(
+            handler(RadioPolicy::Scrobble).resolve(Some(APPLE_MUSIC_BUNDLE_ID), played.clone()),
+            Some(from_radio(played))
+        );
+    }
+
+    fn details(album_artist: Option<&str>, compilation: bool, artist: &str) -> TrackDetails {
+        TrackDetails {
+            album_artist: album_artist.map(str::to_string),
+            compilation,
+            artist: Some(artist.to_string()),
+            ..TrackDetails::default()
+        }
+    }
+
+    #[test]
+    fn test_compilation_uses_track_artist() {
+        let handler = CompilationHandler::new(&AppleMusicConfig::default());
+
+        let played = track("Various Artists", "Song", Some("Hits"));
+        let resolved = handler.apply(played, &details(Some("Various Artists"), true, "Band"));
+        assert_eq!(resolved.artist, "Band");
+        assert_eq!(resolved.album_artist.as_deref(), Some("Various Artists"));
+
+        // Flagged as a compilation without an album artist
+        let played = track("Band", "Song", Some("Hits"));
+        let resolved = handler.apply(played, &details(None, true, "Band"));
+        assert_eq!(resolved.artist, "Band");
+        assert_eq!(resolved.album_artist.as_deref(), Some("Various Artists"));
+    }
+
+    #[test]
+    fn test_compilation_album_artist() {
+        let handler = CompilationHandler::new(&AppleMusicConfig::default());
+
+        // The usual album: no album artist to pass
+        let played = track("Band", "Song", Some("Album"));
+        let resolved = handler.apply(played.clone(), &details(Some("band"), false, "Band"));
+        assert_eq!(resolved, played);
+
+        // A guest appearance keeps the album artist
+        let played = track("Band feat. Guest", "Song", Some("Album"));
+        let resolved = handler.apply(played, &details(Some("Band"), false, "Band feat. Guest"));
+        assert_eq!(resolved.album_artist.as_deref(), Some("Band"));
+
+        // Nothing better to scrobble than "VA"
+        let played = track("VA", "Song", None);
+        let resolved = handler.apply(played, &details(None, true, "V.A."));
+        assert_eq!(resolved.artist, "VA");
+        assert_eq!(resolved.album_artist.as_deref(), Some("Various Artists"));
+
+        let disabled = CompilationHandler::new(&AppleMusicConfig {
+            compilations: false,
+            ..AppleMusicConfig::default()
+        });
+        let played = track("Various Artists", "Song", None);
+        assert_eq!(
+            disabled.apply(played.clone(), &details(None, true, "Band")),
+            played
+        );
+    }
+}
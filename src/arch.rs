@@ -0,0 +1,135 @@
+// Arch module
+// Which CPU architectures a binary was built for and which one the Mac has, so the installer
+// can catch an Intel-only build on Apple Silicon (or the reverse)
+
+use anyhow::{Context, Result};
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::process::Command;
+
+const MH_MAGIC_64: u32 = 0xfeed_facf;
+const FAT_MAGIC: u32 = 0xcafe_babe;
+const CPU_TYPE_X86_64: u32 = 0x0100_0007;
+const CPU_TYPE_ARM64: u32 = 0x0100_000c;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arch {
+    Arm64,
+    X86_64,
+    /// Any other Mach-O CPU type
+    Other(u32),
+}
+
+impl Arch {
+    fn from_cpu_type(cpu_type: u32) -> Self {
+        match cpu_type {
+            CPU_TYPE_ARM64 => Self::Arm64,
+            CPU_TYPE_X86_64 => Self::X86_64,
+            other => Self::Other(other),
+        }
+    }
+}
+
+impl fmt::Display for Arch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Arm64 => write!(f, "arm64"),
+            Self::X86_64 => write!(f, "x86_64"),
+            Self::Other(cpu_type) => write!(f, "CPU type {:#x}", cpu_type),
+        }
+    }
+}
+
+/// The Mac's own architecture, also when this process runs under Rosetta
+pub fn machine() -> Arch {
+    // Only Apple Silicon has this key, and it isn't hidden from translated processes
+    let arm64 = Command::new("sysctl")
+        .args(["-n", "hw.optional.arm64"])
+        .output()
+        .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).trim() == "1");
+    if arm64 {
+        Arch::Arm64
+    } else {
+        Arch::X86_64
+    }
+}
+
+/// Whether this process is an Intel build running under Rosetta
+pub fn is_translated() -> bool {
+    Command::new("sysctl")
+        .args(["-n", "sysctl.proc_translated"])
+        .output()
+        .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).trim() == "1")
+}
+
+/// Architectures the Mach-O binary at `path` contains, two for a universal binary
+pub fn binary(path: &Path) -> Result<Vec<Arch>> {
+    let mut header = Vec::new();
+    File::open(path)
+        .and_then(|file| file.take(4096).read_to_end(&mut header))
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    parse_header(&header).with_context(|| format!("{} is not a Mach-O binary", path.display()))
+}
+
+fn parse_header(header: &[u8]) -> Option<Vec<Arch>> {
+    let u32_at = |offset: usize, big_endian: bool| {
+        let bytes: [u8; 4] = header.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    };
+
+    // Universal binaries have a big-endian table of the binaries they hold
+    if u32_at(0, true)? == FAT_MAGIC {
+        let count = u32_at(4, true)? as usize;
+        return (0..count)
+            .map(|i| u32_at(8 + i * 20, true).map(Arch::from_cpu_type))
+            .collect();
+    }
+    // Thin binaries are in the byte order of their CPU, little-endian for both Mac ones
+    if u32_at(0, false)? == MH_MAGIC_64 {
+        return Some(vec![Arch::from_cpu_type(u32_at(4, false)?)]);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn thin(cpu_type: u32) -> Vec<u8> {
+        let mut header = MH_MAGIC_64.to_le_bytes().to_vec();
+        header.extend(cpu_type.to_le_bytes());
+        header.extend([0; 24]);
+        header
+    }
+
+    #[test]
+    fn test_thin_binary() {
+        assert_eq!(parse_header(&thin(CPU_TYPE_ARM64)), Some(vec![Arch::Arm64]));
+        assert_eq!(
+            parse_header(&thin(CPU_TYPE_X86_64)),
+            Some(vec![Arch::X86_64])
+        );
+        assert_eq!(parse_header(b"#!/bin/sh\n"), None);
+        assert_eq!(parse_header(&[]), None);
+    }
+
+    #[test]
+    fn test_universal_binary() {
+        let mut header = FAT_MAGIC.to_be_bytes().to_vec();
+        header.extend(2u32.to_be_bytes());
+        for cpu_type in [CPU_TYPE_X86_64, CPU_TYPE_ARM64] {
+            header.extend(cpu_type.to_be_bytes());
+            header.extend([0; 16]);
+        }
+
+        assert_eq!(parse_header(&header), Some(vec![Arch::X86_64, Arch::Arm64]));
+        // Cut short
+        assert_eq!(parse_header(&header[..30]), None);
+    }
+}
@@ -0,0 +1,287 @@
+// Artist aliases module
+// Maps other spellings of an artist's name (another script, transliterations, ...) to one
+// canonical name, from overrides in the config and MusicBrainz lookups cached on disk
+
+use crate::config::ArtistAliasConfig;
+use crate::http;
+use crate::scrobbler::Track;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+const MUSICBRAINZ_ARTIST_URL: &str = "https://musicbrainz.org/ws/2/artist/";
+
+/// Lowest MusicBrainz search score taken as the same artist
+const MIN_MUSICBRAINZ_SCORE: u64 = 90;
+
+/// Canonical artist names, from the config and MusicBrainz
+pub struct ArtistAliases {
+    /// Lowercased reported name -> canonical name
+    overrides: HashMap<String, String>,
+    lookups: Option<Lookups>,
+}
+
+/// MusicBrainz lookups, shared with the threads doing them
+struct Lookups {
+    path: PathBuf,
+    locale: Option<String>,
+    cache: Arc<Mutex<AliasCache>>,
+    /// Names being looked up, so an artist isn't looked up twice at once
+    in_flight: Arc<Mutex<HashSet<String>>>,
+    /// Set when a lookup found a new name, so processed tracks can be redone
+    updated: Arc<AtomicBool>,
+}
+
+/// Results of MusicBrainz lookups, as stored on disk
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct AliasCache {
+    /// Locale the names were picked for; the cache is started over when it changes
+    #[serde(default)]
+    locale: Option<String>,
+    /// Lowercased reported name -> canonical name, or None if MusicBrainz doesn't know it
+    #[serde(default)]
+    artists: HashMap<String, Option<String>>,
+}
+
+impl ArtistAliases {
+    pub fn new(config: &ArtistAliasConfig) -> Self {
+        let overrides = config
+            .overrides
+            .iter()
+            .map(|(from, to)| (from.trim().to_lowercase(), to.trim().to_string()))
+            .collect();
+
+        let lookups = config
+            .musicbrainz
+            .then(|| Lookups::new(config.locale.clone()))
+            .and_then(|lookups| {
+                lookups
+                    .map_err(|e| log::warn!("Not looking up artist aliases: {:#}", e))
+                    .ok()
+            });
+
+        Self { overrides, lookups }
+    }
+
+    /// Canonical name of an artist, if it's known to differ from the reported one
+    ///
+    /// Artists MusicBrainz hasn't been asked about yet are looked up in the background and
+    /// renamed from their next play on.
+    pub fn canonical(&self, artist: &str) -> Option<String> {
+        let key = artist.trim().to_lowercase();
+        let name = match self.overrides.get(&key) {
+            Some(name) => Some(name.clone()),
+            None => self.lookups.as_ref()?.canonical(&key, artist),
+        };
+        name.filter(|name| name != artist)
+    }
+
+    /// Rename the track's artist to its canonical name, returning whether it was changed
+    pub fn apply(&self, track: &mut Track) -> bool {
+        let Some(name) = self.canonical(&track.artist) else {
+            return false;
+        };
+
+        log::debug!("Scrobbling artist {} as {}", track.artist, name);
+        track.artist = name;
+        true
+    }
+
+    /// Whether a lookup has found a name since the last call
+    pub fn take_updated(&self) -> bool {
+        self.lookups
+            .as_ref()
+            .is_some_and(|lookups| lookups.updated.swap(false, Ordering::Relaxed))
+    }
+}
+
+impl Lookups {
+    fn new(locale: Option<String>) -> Result<Self> {
+        let path = crate::paths::Paths::current()?.artist_aliases;
+        let cache = match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str::<AliasCache>(&text)
+                .with_context(|| format!("Failed to parse {}", path.display()))?,
+            Err(_) => AliasCache::default(),
+        };
+        let cache = if cache.locale == locale {
+            cache
+        } else {
+            AliasCache {
+                locale: locale.clone(),
+                artists: HashMap::new(),
+            }
+        };
+
+        Ok(Self {
+            path,
+            locale,
+            cache: Arc::new(Mutex::new(cache)),
+            in_flight: Arc::default(),
+            updated: Arc::default(),
+        })
+    }
+
+    fn canonical(&self, key: &str, artist: &str) -> Option<String> {
+        if let Some(name) = self.cache.lock().unwrap().artists.get(key) {
+            return name.clone();
+        }
+        if !self.in_flight.lock().unwrap().insert(key.to_string()) {
+            return None;
+        }
+
+        let key = key.to_string();
+        let artist = artist.to_string();
+        let path = self.path.clone();
+        let locale = self.locale.clone();
+        let cache = self.cache.clone();
+        let in_flight = self.in_flight.clone();
+        let updated = self.updated.clone();
+        std::thread::spawn(move || {
+            match look_up(&artist, locale.as_deref()) {
+                Ok(name) => {
+                    if name.as_ref().is_some_and(|name| *name != artist) {
+                        updated.store(true, Ordering::Relaxed);
+                    }
+                    let mut cache = cache.lock().unwrap();
+                    cache.artists.insert(key.clone(), name);
+                    if let Err(e) = save(&path, &cache) {
+                        log::warn!("Failed to save artist aliases: {:#}", e);
+                    }
+                }
+                Err(e) => log::warn!("Failed to look up artist {}: {:#}", artist, e),
+            }
+            in_flight.lock().unwrap().remove(&key);
+        });
+        None
+    }
+}
+
+fn save(path: &Path, cache: &AliasCache) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    let text = serde_json::to_string(cache).context("Failed to serialize artist aliases")?;
+    fs::write(path, text).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn look_up(artist: &str, locale: Option<&str>) -> Result<Option<String>> {
+    let name = artist.replace('"', "");
+    let query = format!("artist:\"{}\" OR alias:\"{}\"", name, name);
+    let request = http::shared()
+        .get(MUSICBRAINZ_ARTIST_URL)
+        .query("query", &query)
+        .query("fmt", "json")
+        .query("limit", "1");
+    let response: Value = http::send(request, None)?
+        .into_json()
+        .context("Invalid MusicBrainz response")?;
+
+    Ok(canonical_name(&response, artist, locale))
+}
+
+/// Canonical name of the best matching artist, if it's a close match that has `reported` as its
+/// name or one of its aliases
+///
+/// That's the artist's primary alias in `locale` when there is one, and their name otherwise.
+fn canonical_name(response: &Value, reported: &str, locale: Option<&str>) -> Option<String> {
+    let artist = response["artists"].as_array()?.first()?;
+    if artist["score"].as_u64()? < MIN_MUSICBRAINZ_SCORE {
+        return None;
+    }
+
+    let aliases = artist["aliases"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or(&[]);
+    let is_reported = |value: &Value| {
+        value
+            .as_str()
+            .is_some_and(|name| name.trim().to_lowercase() == reported.trim().to_lowercase())
+    };
+    let known = is_reported(&artist["name"])
+        || is_reported(&artist["sort-name"])
+        || aliases.iter().any(|alias| is_reported(&alias["name"]));
+    if !known {
+        return None;
+    }
+
+    let localized = locale.and_then(|locale| {
+        aliases
+            .iter()
+            .find(|alias| alias["locale"] == locale && alias["primary"] == true)
+    });
+    localized
+        .unwrap_or(artist)
+        .get("name")
+        .and_then(Value::as_str)
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    fn response(score: u64) -> Value {
+        json!({
+            "artists": [{
+                "score": score,
+                "name": "Кино",
+                "sort-name": "Kino",
+                "aliases": [
+                    {"name": "Kino", "locale": "en", "primary": true},
+                    {"name": "Kino", "locale": null, "primary": null},
+                    {"name": "Кіно", "locale": "uk", "primary": false},
+                ],
+            }],
+        })
+    }
+
+    #[test]
+    fn test_canonical_name() {
+        assert_eq!(
+            canonical_name(&response(100), "kino", None).as_deref(),
+            Some("Кино")
+        );
+        assert_eq!(
+            canonical_name(&response(100), "Кіно", Some("en")).as_deref(),
+            Some("Kino")
+        );
+        // No primary alias in that locale
+        assert_eq!(
+            canonical_name(&response(100), "Kino", Some("uk")).as_deref(),
+            Some("Кино")
+        );
+        assert_eq!(canonical_name(&response(60), "Kino", None), None);
+        // A partial match isn't the same artist
+        assert_eq!(canonical_name(&response(100), "Kino Club", None), None);
+        assert_eq!(canonical_name(&json!({"artists": []}), "Kino", None), None);
+    }
+
+    #[test]
+    fn test_overrides() {
+        let aliases = ArtistAliases::new(&ArtistAliasConfig {
+            musicbrainz: false,
+            locale: None,
+            overrides: BTreeMap::from([("Кино".to_string(), "Kino".to_string())]),
+        });
+        let mut track = Track {
+            title: "Gruppa krovi".to_string(),
+            artist: "КИНО".to_string(),
+            ..Default::default()
+        };
+
+        assert!(aliases.apply(&mut track));
+        assert_eq!(track.artist, "Kino");
+        assert!(!aliases.apply(&mut track));
+        assert_eq!(aliases.canonical("Other"), None);
+        assert!(!aliases.take_updated());
+    }
+}
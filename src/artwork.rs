@@ -0,0 +1,382 @@
+// Artwork module
+// Album art from the Music app, the iTunes Search API or the Cover Art Archive, cached on disk
+// so it's only downloaded once
+
+use crate::apple_music::{self, APPLE_MUSIC_BUNDLE_ID};
+use crate::config::ArtworkConfig;
+use crate::http;
+use crate::scrobbler::Track;
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+const ITUNES_SEARCH_URL: &str = "https://itunes.apple.com/search";
+const MUSICBRAINZ_URL: &str = "https://musicbrainz.org/ws/2/release-group/";
+const COVER_ART_ARCHIVE_URL: &str = "https://coverartarchive.org/release-group";
+
+/// Lowest MusicBrainz search score taken as the same release
+const MIN_MUSICBRAINZ_SCORE: u64 = 90;
+
+/// Largest image downloaded
+const MAX_IMAGE_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Image formats art is stored as, with the file extension each is cached under
+const FORMATS: &[(&str, &[u8])] = &[("png", b"\x89PNG"), ("jpg", b"\xff\xd8")];
+
+/// Album art cached on disk, least recently used first out
+pub struct ArtworkCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    /// Keys being downloaded, so a track isn't fetched twice at once
+    in_flight: Arc<Mutex<HashSet<String>>>,
+    /// Keys no source has art for, not asked again until restart
+    missing: Arc<Mutex<HashSet<String>>>,
+}
+
+impl ArtworkCache {
+    /// Directory art is cached in
+    pub fn cache_dir() -> Result<PathBuf> {
+        Ok(crate::paths::Paths::current()?.artwork)
+    }
+
+    pub fn new(config: &ArtworkConfig) -> Result<Self> {
+        let dir = Self::cache_dir()?;
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create artwork cache {}", dir.display()))?;
+
+        Ok(Self {
+            dir,
+            max_bytes: config.max_cache_mb * 1024 * 1024,
+            in_flight: Arc::default(),
+            missing: Arc::default(),
+        })
+    }
+
+    /// Path of the track's art, if it's cached
+    pub fn cached(&self, track: &Track) -> Option<PathBuf> {
+        cached_path(&self.dir, &key(track))
+    }
+
+    /// Get the track's art in the background, unless it's cached or known to be missing
+    ///
+    /// Apple Music tracks get the art from the Music app when it has some, without going online.
+    /// Other art is downloaded, unless `online` is false.
+    pub fn fetch(&self, track: &Track, bundle_id: Option<&str>, online: bool) {
+        let from_music = bundle_id == Some(APPLE_MUSIC_BUNDLE_ID);
+        if !from_music && !online {
+            return;
+        }
+
+        let key = key(track);
+        if let Some(path) = cached_path(&self.dir, &key) {
+            // Mark it as recently used
+            let _ = fs::File::options()
+                .append(true)
+                .open(path)
+                .and_then(|file| file.set_modified(SystemTime::now()));
+            return;
+        }
+        if self.missing.lock().unwrap().contains(&key)
+            || !self.in_flight.lock().unwrap().insert(key.clone())
+        {
+            return;
+        }
+
+        let track = track.clone();
+        let dir = self.dir.clone();
+        let max_bytes = self.max_bytes;
+        let in_flight = self.in_flight.clone();
+        let missing = self.missing.clone();
+        std::thread::spawn(move || {
+            match download(&track, from_music, online) {
+                Ok(Some(image)) => {
+                    if let Err(e) = store(&dir, &key, &image, max_bytes) {
+                        log::warn!("Failed to cache artwork: {:#}", e);
+                    }
+                }
+                // Only known to be missing once every source was asked
+                Ok(None) if !online => {}
+                Ok(None) => {
+                    log::debug!("No artwork found for {} - {}", track.artist, track.title);
+                    missing.lock().unwrap().insert(key.clone());
+                }
+                Err(e) => log::warn!("Failed to download artwork: {:#}", e),
+            }
+            in_flight.lock().unwrap().remove(&key);
+        });
+    }
+}
+
+/// Content type of a cached image
+pub fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("png") => "image/png",
+        _ => "image/jpeg",
+    }
+}
+
+/// Art is shared by the tracks of an album; singles without one are looked up by title
+fn key(track: &Track) -> String {
+    let release = track.album.as_deref().unwrap_or(&track.title);
+    let normalized = format!(
+        "{}\0{}",
+        track.artist.trim().to_lowercase(),
+        release.trim().to_lowercase()
+    );
+
+    format!("{:x}", md5::compute(normalized))
+}
+
+fn cached_path(dir: &Path, key: &str) -> Option<PathBuf> {
+    FORMATS
+        .iter()
+        .map(|(extension, _)| dir.join(format!("{}.{}", key, extension)))
+        .find(|path| path.exists())
+}
+
+/// Write an image to the cache, then evict the least recently used ones over the size limit
+fn store(dir: &Path, key: &str, image: &[u8], max_bytes: u64) -> Result<()> {
+    let extension = FORMATS
+        .iter()
+        .find(|(_, magic)| image.starts_with(magic))
+        .map(|(extension, _)| *extension)
+        .context("Not a PNG or JPEG image")?;
+
+    let path = dir.join(format!("{}.{}", key, extension));
+    fs::write(&path, image).with_context(|| format!("Failed to write {}", path.display()))?;
+
+    evict(dir, max_bytes)
+}
+
+fn evict(dir: &Path, max_bytes: u64) -> Result<()> {
+    let mut files: Vec<(SystemTime, u64, PathBuf)> = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read {}", dir.display()))?
+        .flatten()
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            Some((metadata.modified().ok()?, metadata.len(), entry.path()))
+        })
+        .collect();
+    files.sort();
+
+    let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+    for (_, len, path) in files {
+        if total <= max_bytes {
+            break;
+        }
+        fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+        total -= len;
+    }
+
+    Ok(())
+}
+
+/// Art from the first source that has it
+fn download(track: &Track, from_music: bool, online: bool) -> Result<Option<Vec<u8>>> {
+    if from_music {
+        match apple_music::current_artwork() {
+            Ok(Some(image)) => return Ok(Some(image)),
+            Ok(None) => {}
+            Err(e) => log::debug!("No artwork from the Music app: {:#}", e),
+        }
+    }
+    if !online {
+        return Ok(None);
+    }
+
+    let url = match itunes_artwork(track)? {
+        Some(url) => Some(url),
+        None => cover_art_archive(track)?,
+    };
+
+    match url {
+        Some(url) => get_image(&url),
+        None => Ok(None),
+    }
+}
+
+fn itunes_artwork(track: &Track) -> Result<Option<String>> {
+    let (term, entity) = match &track.album {
+        Some(album) => (format!("{} {}", track.artist, album), "album"),
+        None => (format!("{} {}", track.artist, track.title), "song"),
+    };
+
+    let request = http::shared()
+        .get(ITUNES_SEARCH_URL)
+        .query("term", &term)
+        .query("media", "music")
+        .query("entity", entity)
+        .query("limit", "10");
+    let response: Value = http::send(request, None)?
+        .into_json()
+        .context("Invalid iTunes Search response")?;
+
+    Ok(itunes_artwork_url(&response, &track.artist))
+}
+
+/// Artwork URL of the first result by the artist, at a larger size than the search returns
+fn itunes_artwork_url(response: &Value, artist: &str) -> Option<String> {
+    response["results"]
+        .as_array()?
+        .iter()
+        .filter(|result| {
+            result["artistName"]
+                .as_str()
+                .is_some_and(|name| name.eq_ignore_ascii_case(artist))
+        })
+        .find_map(|result| result["artworkUrl100"].as_str())
+        .map(|url| url.replace("100x100bb", "600x600bb"))
+}
+
+fn cover_art_archive(track: &Track) -> Result<Option<String>> {
+    let Some(album) = &track.album else {
+        return Ok(None);
+    };
+
+    let query = format!(
+        "artist:\"{}\" AND releasegroup:\"{}\"",
+        track.artist.replace('"', ""),
+        album.replace('"', "")
+    );
+    let request = http::shared()
+        .get(MUSICBRAINZ_URL)
+        .query("query", &query)
+        .query("fmt", "json")
+        .query("limit", "1");
+    let response: Value = http::send(request, None)?
+        .into_json()
+        .context("Invalid MusicBrainz response")?;
+
+    Ok(musicbrainz_release_group(&response)
+        .map(|id| format!("{}/{}/front-500", COVER_ART_ARCHIVE_URL, id)))
+}
+
+/// ID of the best matching release group, if it's a close enough match
+fn musicbrainz_release_group(response: &Value) -> Option<String> {
+    let group = response["release-groups"].as_array()?.first()?;
+    if group["score"].as_u64()? < MIN_MUSICBRAINZ_SCORE {
+        return None;
+    }
+
+    group["id"].as_str().map(str::to_string)
+}
+
+fn get_image(url: &str) -> Result<Option<Vec<u8>>> {
+    let response = http::send(http::shared().get(url), None)?;
+    match response.status() {
+        200 => {}
+        404 => return Ok(None),
+        status => anyhow::bail!("Artwork request failed with status {}", status),
+    }
+
+    let mut image = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_IMAGE_BYTES)
+        .read_to_end(&mut image)
+        .context("Failed to download artwork")?;
+
+    Ok(Some(image))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+
+    fn track(album: Option<&str>) -> Track {
+        Track {
+            title: "Song".to_string(),
+            artist: "Band".to_string(),
+            album: album.map(str::to_string),
+            duration: Some(200),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_key_is_shared_by_album() {
+        let other_song = Track {
+            title: "Other Song".to_string(),
+            artist: " band".to_string(),
+            ..track(Some("ALBUM"))
+        };
+
+        assert_eq!(key(&track(Some("Album"))), key(&other_song));
+        assert_ne!(key(&track(Some("Album"))), key(&track(None)));
+    }
+
+    #[test]
+    fn test_itunes_artwork_url() {
+        let response = json!({"results": [
+            {"artistName": "Band Tribute", "artworkUrl100": "https://x/wrong/100x100bb.jpg"},
+            {"artistName": "BAND", "artworkUrl100": "https://x/right/100x100bb.jpg"},
+        ]});
+
+        assert_eq!(
+            itunes_artwork_url(&response, "Band").as_deref(),
+            Some("https://x/right/600x600bb.jpg")
+        );
+        assert_eq!(itunes_artwork_url(&response, "Other"), None);
+        assert_eq!(itunes_artwork_url(&json!({}), "Band"), None);
+    }
+
+    #[test]
+    fn test_musicbrainz_release_group() {
+        let response = |score: u64| json!({"release-groups": [{"id": "abc", "score": score}]});
+
+        assert_eq!(
+            musicbrainz_release_group(&response(100)).as_deref(),
+            Some("abc")
+        );
+        assert_eq!(musicbrainz_release_group(&response(60)), None);
+        assert_eq!(
+            musicbrainz_release_group(&json!({"release-groups": []})),
+            None
+        );
+    }
+
+    #[test]
+    fn test_store_evicts_least_recently_used() {
+        let dir =
+            std::env::temp_dir().join(format!("osx_scrobbler_artwork_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let image = |fill: u8| [b"\xff\xd8".as_slice(), &[fill; 98]].concat();
+        let set_modified = |name: &str, time: SystemTime| {
+            fs::File::options()
+                .append(true)
+                .open(dir.join(name))
+                .unwrap()
+                .set_modified(time)
+                .unwrap();
+        };
+        store(&dir, "old", &image(1), 250).unwrap();
+        set_modified("old.jpg", SystemTime::now() - Duration::from_secs(60));
+        store(&dir, "used", &image(2), 250).unwrap();
+        // Touched after being stored, like a cache hit
+        set_modified("used.jpg", SystemTime::now() + Duration::from_secs(60));
+        store(&dir, "new", &image(3), 250).unwrap();
+        let png = store(&dir, "png", b"\x89PNG....", 1000);
+
+        let old = cached_path(&dir, "old");
+        let used = cached_path(&dir, "used");
+        let new = cached_path(&dir, "new");
+        let png_path = cached_path(&dir, "png");
+        let gif = store(&dir, "gif", b"GIF89a", 1000);
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(old, None);
+        assert!(used.is_some());
+        assert!(new.is_some());
+        assert!(png.is_ok());
+        assert_eq!(png_path.as_deref().map(content_type), Some("image/png"));
+        assert!(gif.is_err());
+    }
+}
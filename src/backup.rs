@@ -0,0 +1,251 @@
+// Backup module
+// Bundles the config (app decisions included) and the scrobble history into one file for moving
+// to another machine
+
+use crate::config::{Config, LastFmConfig, ListenBrainzConfig};
+use crate::history::HistoryEntry;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Version of the backup file layout, increased when older versions can't read it
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Everything needed to set up the scrobbler on another machine
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Backup {
+    pub format: u32,
+    /// Version of the app that made the backup
+    pub app_version: String,
+    pub created_at: DateTime<Utc>,
+    /// Whether credentials were kept in the config
+    pub secrets: bool,
+    pub config: Config,
+    pub history: Vec<HistoryEntry>,
+}
+
+impl Backup {
+    pub fn new(config: &Config, history: &[HistoryEntry], include_secrets: bool) -> Self {
+        let mut config = config.clone();
+        if !include_secrets {
+            strip_secrets(&mut config);
+        }
+
+        Self {
+            format: FORMAT_VERSION,
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+            created_at: Utc::now(),
+            secrets: include_secrets,
+            config,
+            history: history.to_vec(),
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_string(self).context("Failed to serialize backup")?;
+        fs::write(path, content).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let format = serde_json::from_str::<serde_json::Value>(&content)
+            .with_context(|| format!("{} is not a backup", path.display()))?["format"]
+            .as_u64()
+            .with_context(|| format!("{} is not a backup", path.display()))?;
+        if format > FORMAT_VERSION as u64 {
+            anyhow::bail!("The backup is from a newer version of OSX Scrobbler, update first");
+        }
+
+        serde_json::from_str(&content).with_context(|| format!("Invalid backup {}", path.display()))
+    }
+}
+
+/// Remove credentials and tokens, in the top-level services and in every profile
+pub fn strip_secrets(config: &mut Config) {
+    config.web.token.clear();
+    // Webhooks are often authenticated by a header
+    for webhook in &mut config.webhook {
+        webhook.headers.clear();
+    }
+    for (lastfm, listenbrainz) in services_mut(config) {
+        if let Some(lastfm) = lastfm {
+            lastfm.api_key.clear();
+            lastfm.api_secret.clear();
+            lastfm.session_key.clear();
+        }
+        for lb in listenbrainz {
+            lb.token.clear();
+            lb.password = None;
+            // Proxies are often authenticated by a header
+            lb.headers.clear();
+        }
+    }
+}
+
+/// Fill in credentials missing from `config` with those of `current` (matched by profile and
+/// ListenBrainz instance name), then turn off services that still have none
+///
+/// Returns the names of the services turned off.
+pub fn restore_secrets(config: &mut Config, current: &Config) -> Vec<String> {
+    if config.web.token.is_empty() {
+        config.web.token = current.web.token.clone();
+    }
+    for webhook in &mut config.webhook {
+        let current = current.webhook.iter().find(|c| c.name == webhook.name);
+        if let Some(current) = current.filter(|_| webhook.headers.is_empty()) {
+            webhook.headers = current.headers.clone();
+        }
+    }
+
+    let mut disabled = Vec::new();
+    let profiles: Vec<Option<String>> = std::iter::once(None)
+        .chain(config.profiles.iter().map(|p| Some(p.name.clone())))
+        .collect();
+    for ((lastfm, listenbrainz), profile) in services_mut(config).into_iter().zip(profiles) {
+        let (current_lastfm, current_listenbrainz) = match &profile {
+            None => (current.lastfm.as_ref(), current.listenbrainz.as_slice()),
+            Some(name) => current
+                .profiles
+                .iter()
+                .find(|p| &p.name == name)
+                .map_or((None, &[][..]), |p| {
+                    (p.lastfm.as_ref(), p.listenbrainz.as_slice())
+                }),
+        };
+        let label = |service: String| match &profile {
+            Some(profile) => format!("{} [{}]", service, profile),
+            None => service,
+        };
+
+        if let Some(lastfm) = lastfm {
+            if let Some(current) = current_lastfm.filter(|_| lastfm.api_key.is_empty()) {
+                lastfm.api_key = current.api_key.clone();
+                lastfm.api_secret = current.api_secret.clone();
+                lastfm.session_key = current.session_key.clone();
+            }
+            if lastfm.enabled && (lastfm.api_key.is_empty() || lastfm.api_secret.is_empty()) {
+                lastfm.enabled = false;
+                disabled.push(label("Last.fm".to_string()));
+            }
+        }
+
+        for lb in listenbrainz {
+            let current = current_listenbrainz.iter().find(|c| c.name == lb.name);
+            if let Some(current) = current.filter(|_| lb.token.is_empty()) {
+                lb.token = current.token.clone();
+                lb.password = current.password.clone();
+                lb.headers = current.headers.clone();
+            }
+            if lb.enabled && lb.token.is_empty() {
+                lb.enabled = false;
+                disabled.push(label(format!("ListenBrainz ({})", lb.name)));
+            }
+        }
+    }
+
+    disabled
+}
+
+/// Last.fm and ListenBrainz settings: the top-level ones, then each profile's
+fn services_mut(
+    config: &mut Config,
+) -> Vec<(Option<&mut LastFmConfig>, &mut Vec<ListenBrainzConfig>)> {
+    std::iter::once((config.lastfm.as_mut(), &mut config.listenbrainz))
+        .chain(
+            config
+                .profiles
+                .iter_mut()
+                .map(|p| (p.lastfm.as_mut(), &mut p.listenbrainz)),
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ProfileConfig, WebhookConfig, WebhookEvent};
+
+    fn config() -> Config {
+        let mut config = Config::default();
+        config.web.token = "web-token".to_string();
+        if let Some(lastfm) = config.lastfm.as_mut() {
+            lastfm.enabled = true;
+            lastfm.api_key = "key".to_string();
+            lastfm.api_secret = "secret".to_string();
+            lastfm.session_key = "session".to_string();
+        }
+        config.listenbrainz[0].enabled = true;
+        config.listenbrainz[0].token = "lb-token".to_string();
+        config.profiles = vec![ProfileConfig {
+            name: "Work".to_string(),
+            lastfm: None,
+            listenbrainz: config.listenbrainz.clone(),
+        }];
+        config.webhook = vec![WebhookConfig {
+            enabled: true,
+            name: "Home".to_string(),
+            url: "http://localhost:8123/hook".to_string(),
+            headers: [("Authorization".to_string(), "hook-token".to_string())].into(),
+            events: vec![WebhookEvent::Scrobble],
+            body: "{}".to_string(),
+        }];
+        config.app_filtering.allowed_apps = vec!["com.spotify.client".to_string()];
+        config
+    }
+
+    #[test]
+    fn test_strip_secrets() {
+        let backup = Backup::new(&config(), &[], false);
+
+        let json = serde_json::to_string(&backup).unwrap();
+        for secret in [
+            "web-token",
+            "key",
+            "secret",
+            "session",
+            "lb-token",
+            "hook-token",
+        ] {
+            assert!(!json.contains(&format!("\"{}\"", secret)), "{}", secret);
+        }
+        assert_eq!(
+            backup.config.app_filtering.allowed_apps,
+            vec!["com.spotify.client"]
+        );
+        assert!(Backup::new(&config(), &[], true).secrets);
+    }
+
+    #[test]
+    fn test_restore_secrets_from_current_config() {
+        let mut restored = config();
+        strip_secrets(&mut restored);
+
+        let disabled = restore_secrets(&mut restored, &config());
+        assert!(disabled.is_empty());
+        assert_eq!(restored.web.token, "web-token");
+        assert_eq!(restored.lastfm.as_ref().unwrap().session_key, "session");
+        assert_eq!(restored.profiles[0].listenbrainz[0].token, "lb-token");
+        assert_eq!(restored.webhook[0].headers["Authorization"], "hook-token");
+    }
+
+    #[test]
+    fn test_restore_secrets_disables_services_without_them() {
+        let mut restored = config();
+        strip_secrets(&mut restored);
+
+        let disabled = restore_secrets(&mut restored, &Config::default());
+        assert_eq!(
+            disabled,
+            vec![
+                "Last.fm",
+                "ListenBrainz (Primary)",
+                "ListenBrainz (Primary) [Work]"
+            ]
+        );
+        assert!(!restored.lastfm.as_ref().unwrap().enabled);
+        assert!(restored.validate().is_ok());
+    }
+}
@@ -0,0 +1,143 @@
+// Browser module
+// Finds the page a browser is playing media from, since Media Remote only reports the title
+
+use crate::energy::{self, Activity};
+use anyhow::{Context, Result};
+use std::process::Command;
+
+/// How a browser's tabs are read over AppleScript
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dialect {
+    /// Tabs have a `name` and a `URL`
+    Safari,
+    /// Tabs have a `title` and a `URL`
+    Chromium,
+}
+
+/// Scriptable browsers: bundle ID, application name and scripting dialect
+///
+/// Firefox has no tab scripting, so its plays are recorded without a URL.
+const BROWSERS: &[(&str, &str, Dialect)] = &[
+    ("com.apple.Safari", "Safari", Dialect::Safari),
+    (
+        "com.apple.SafariTechnologyPreview",
+        "Safari Technology Preview",
+        Dialect::Safari,
+    ),
+    ("com.google.Chrome", "Google Chrome", Dialect::Chromium),
+    ("com.brave.Browser", "Brave Browser", Dialect::Chromium),
+    ("com.microsoft.edgemac", "Microsoft Edge", Dialect::Chromium),
+    ("com.vivaldi.Vivaldi", "Vivaldi", Dialect::Chromium),
+    ("company.thebrowser.Browser", "Arc", Dialect::Chromium),
+];
+
+/// Whether the app is a browser whose tabs can be read
+pub fn is_supported(bundle_id: &str) -> bool {
+    BROWSERS.iter().any(|(id, _, _)| *id == bundle_id)
+}
+
+/// URL of the tab playing `title` in the browser, if it can be told apart from the other tabs
+///
+/// Media Remote doesn't say which tab is playing, so the tab is found by its title, which sites
+/// like YouTube build from the media title. The first time this runs macOS asks whether the
+/// scrobbler may control the browser.
+pub fn origin_url(bundle_id: &str, title: &str) -> Result<Option<String>> {
+    let Some((_, app, dialect)) = BROWSERS.iter().find(|(id, _, _)| *id == bundle_id) else {
+        return Ok(None);
+    };
+    let title_property = match dialect {
+        Dialect::Safari => "name",
+        Dialect::Chromium => "title",
+    };
+    // One tab per line, title and URL separated by a tab
+    let script = format!(
+        r#"if application "{app}" is running then
+    set out to ""
+    tell application "{app}"
+        repeat with w in windows
+            repeat with t in tabs of w
+                set out to out & ({title_property} of t) & tab & (URL of t) & linefeed
+            end repeat
+        end repeat
+    end tell
+    return out
+end if"#
+    );
+
+    energy::record(Activity::Script);
+    let output = Command::new("osascript")
+        .args(["-e", &script])
+        .output()
+        .context("Failed to run osascript")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to read {} tabs: {}",
+            app,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(find_tab(&String::from_utf8_lossy(&output.stdout), title))
+}
+
+/// URL of the one web page whose tab title contains `title`
+///
+/// Returns None when no tab or several different pages match, rather than guess.
+fn find_tab(tabs: &str, title: &str) -> Option<String> {
+    let title = title.trim().to_lowercase();
+    if title.is_empty() {
+        return None;
+    }
+
+    let mut urls = tabs
+        .lines()
+        .filter_map(|line| line.rsplit_once('\t'))
+        .filter(|(name, url)| {
+            name.to_lowercase().contains(&title)
+                && (url.starts_with("https://") || url.starts_with("http://"))
+        })
+        .map(|(_, url)| url.trim());
+    let url = urls.next()?;
+
+    urls.all(|other| other == url).then(|| url.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_tab_by_title() {
+        let tabs = "Inbox (3) - Mail\thttps://mail.example.com/\n\
+                    Daft Punk - Get Lucky (Official Video) - YouTube\thttps://www.youtube.com/watch?v=5NV6Rdv1a3I\n\
+                    Settings\tchrome://settings/\n";
+
+        assert_eq!(
+            find_tab(tabs, "Daft Punk - Get Lucky (Official Video)").as_deref(),
+            Some("https://www.youtube.com/watch?v=5NV6Rdv1a3I")
+        );
+        assert_eq!(find_tab(tabs, "Something Else"), None);
+        assert_eq!(find_tab(tabs, "settings"), None);
+        assert_eq!(find_tab(tabs, " "), None);
+    }
+
+    #[test]
+    fn test_find_tab_ambiguous() {
+        let same = "Song - YouTube\thttps://youtu.be/a\nSong - YouTube\thttps://youtu.be/a\n";
+        assert_eq!(
+            find_tab(same, "song").as_deref(),
+            Some("https://youtu.be/a")
+        );
+
+        let different = "Song - YouTube\thttps://youtu.be/a\nSong (Live)\thttps://youtu.be/b\n";
+        assert_eq!(find_tab(different, "song"), None);
+    }
+
+    #[test]
+    fn test_is_supported() {
+        assert!(is_supported("com.apple.Safari"));
+        assert!(is_supported("com.google.Chrome"));
+        assert!(!is_supported("org.mozilla.firefox"));
+        assert!(!is_supported("com.spotify.client"));
+    }
+}
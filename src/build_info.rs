@@ -0,0 +1,74 @@
+// Build info module
+// What was built and where it keeps its files, for `--version --verbose` and the About window
+
+use crate::paths::Paths;
+use chrono::{DateTime, Utc};
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Commit the binary was built from, with `-dirty` if it had local changes
+pub fn git_commit() -> Option<&'static str> {
+    option_env!("OSX_SCROBBLER_GIT_COMMIT")
+}
+
+pub fn build_date() -> Option<DateTime<Utc>> {
+    option_env!("OSX_SCROBBLER_BUILD_TIME")
+        .and_then(|secs| secs.parse().ok())
+        .and_then(|secs| DateTime::from_timestamp(secs, 0))
+}
+
+/// Optional Cargo features compiled in
+pub fn features() -> Vec<&'static str> {
+    [
+        ("tui", cfg!(feature = "tui")),
+        ("web", cfg!(feature = "web")),
+        ("artwork", cfg!(feature = "artwork")),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect()
+}
+
+/// Everything worth pasting into a bug report, one `Label: value` per line
+pub fn details(paths: Option<&Paths>) -> String {
+    let mut lines = vec![
+        format!("Version:  {}", VERSION),
+        format!("Commit:   {}", git_commit().unwrap_or("unknown")),
+        format!(
+            "Built:    {}",
+            build_date().map_or("unknown".to_string(), |d| d
+                .format("%Y-%m-%d %H:%M UTC")
+                .to_string())
+        ),
+        format!(
+            "Target:   {}-{}",
+            std::env::consts::ARCH,
+            std::env::consts::OS
+        ),
+        format!(
+            "Features: {}",
+            Some(features().join(", "))
+                .filter(|f| !f.is_empty())
+                .unwrap_or_else(|| "none".to_string())
+        ),
+    ];
+    if let Some(paths) = paths {
+        lines.push(format!("Config:   {}", paths.config.display()));
+        lines.push(format!("Log:      {}", paths.log.display()));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_details() {
+        let details = details(None);
+        assert!(details.starts_with(&format!("Version:  {}\n", VERSION)));
+        assert!(details.contains(&format!("Target:   {}", std::env::consts::ARCH)));
+        assert!(!details.contains("Config:"));
+        assert_eq!(details.contains("artwork"), features().contains(&"artwork"));
+    }
+}
@@ -0,0 +1,159 @@
+// Classical music module
+// Rewrites classical tracks to follow common tagging conventions (composer as artist, no numbering)
+
+use crate::apple_music::TrackDetails;
+use crate::config::{ClassicalArtist, ClassicalConfig};
+use crate::scrobbler::Track;
+use regex::Regex;
+
+pub struct ClassicalFormatter {
+    enabled: bool,
+    artist: ClassicalArtist,
+    genres: Vec<String>,
+    numbering: Vec<Regex>,
+}
+
+impl ClassicalFormatter {
+    pub fn new(config: &ClassicalConfig) -> Self {
+        let numbering = if config.strip_numbering {
+            [
+                // Track numbers: "01. ", "1-03 - "
+                r"^\d{1,3}(?:-\d{1,3})?(?:\.|\s+-)\s+",
+                // Movement numbers after the work: "Symphony No. 5: I. Allegro"
+                r"(:\s+)[IVXLC]+\.\s+",
+            ]
+            .iter()
+            .map(|pattern| Regex::new(pattern).expect("valid numbering pattern"))
+            .collect()
+        } else {
+            Vec::new()
+        };
+
+        Self {
+            enabled: config.enabled,
+            artist: config.artist,
+            genres: config.genres.clone(),
+            numbering,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Check whether a track is classical, going by its genre
+    pub fn is_classical(&self, details: &TrackDetails) -> bool {
+        self.enabled
+            && details.genre.as_deref().is_some_and(|genre| {
+                self.genres
+                    .iter()
+                    .any(|g| genre.to_lowercase().contains(&g.to_lowercase()))
+            })
+    }
+
+    /// Rewrite a classical track
+    ///
+    /// The title is kept whole apart from numbering, since work titles are long by nature and
+    /// the catalogue numbers in them identify the piece.
+    pub fn apply(&self, track: Track, details: &TrackDetails) -> Track {
+        let artist = match (self.artist, &details.composer) {
+            (ClassicalArtist::Composer, Some(composer)) => composer.clone(),
+            _ => track.artist,
+        };
+
+        let mut title = track.title;
+        for pattern in &self.numbering {
+            title = pattern.replace_all(&title, "$1").into_owned();
+        }
+
+        Track {
+            title: title.trim().to_string(),
+            artist,
+            ..track
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> ClassicalConfig {
+        ClassicalConfig {
+            enabled: true,
+            ..ClassicalConfig::default()
+        }
+    }
+
+    fn details(composer: Option<&str>, genre: &str) -> TrackDetails {
+        TrackDetails {
+            composer: composer.map(str::to_string),
+            genre: Some(genre.to_string()),
+            ..TrackDetails::default()
+        }
+    }
+
+    fn track(artist: &str, title: &str) -> Track {
+        Track {
+            title: title.to_string(),
+            artist: artist.to_string(),
+            album: Some("Symphonies".to_string()),
+            duration: Some(400),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_is_classical_by_genre() {
+        let formatter = ClassicalFormatter::new(&config());
+
+        assert!(formatter.is_classical(&details(None, "Classical")));
+        assert!(formatter.is_classical(&details(None, "Modern Classical")));
+        assert!(!formatter.is_classical(&details(Some("Max Martin"), "Pop")));
+        assert!(!ClassicalFormatter::new(&ClassicalConfig::default())
+            .is_classical(&details(None, "Classical")));
+    }
+
+    #[test]
+    fn test_composer_as_artist_and_numbering() {
+        let formatter = ClassicalFormatter::new(&config());
+        let played = track(
+            "Berliner Philharmoniker",
+            "01. Symphony No. 5 in C Minor, Op. 67: I. Allegro con brio",
+        );
+
+        assert_eq!(
+            formatter.apply(played, &details(Some("Ludwig van Beethoven"), "Classical")),
+            track(
+                "Ludwig van Beethoven",
+                "Symphony No. 5 in C Minor, Op. 67: Allegro con brio"
+            )
+        );
+    }
+
+    #[test]
+    fn test_performer_kept_without_stripping() {
+        let formatter = ClassicalFormatter::new(&ClassicalConfig {
+            artist: ClassicalArtist::Performer,
+            strip_numbering: false,
+            ..config()
+        });
+        let played = track("Glenn Gould", "1812 Overture: I. Largo");
+
+        assert_eq!(
+            formatter.apply(played.clone(), &details(Some("Tchaikovsky"), "Classical")),
+            played
+        );
+    }
+
+    #[test]
+    fn test_missing_composer_keeps_artist() {
+        let formatter = ClassicalFormatter::new(&config());
+        let played = track("Glenn Gould", "Goldberg Variations, BWV 988: Aria");
+
+        assert_eq!(
+            formatter.apply(played.clone(), &details(None, "Classical")),
+            played
+        );
+    }
+}
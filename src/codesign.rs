@@ -0,0 +1,296 @@
+// Codesign module
+// Checks how a build is signed and notarized, and staples notarization tickets, for the installer;
+// and finds quarantined app bundles that Gatekeeper won't start at login
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Extended attribute macOS puts on downloaded files until Gatekeeper has checked them
+const QUARANTINE: &str = "com.apple.quarantine";
+
+/// How a binary is signed, from `codesign -dv`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Signature {
+    Unsigned,
+    /// Signed without an identity, as the linker does for every Apple Silicon build
+    AdHoc,
+    /// Signed with a certificate
+    Identity {
+        /// Leaf certificate, e.g. "Developer ID Application: Name (TEAMID)"
+        authority: String,
+        team: Option<String>,
+    },
+}
+
+impl Signature {
+    /// Whether Gatekeeper can accept it on other Macs (after notarization)
+    pub fn is_developer_id(&self) -> bool {
+        match self {
+            Self::Identity { authority, .. } => authority.starts_with("Developer ID Application"),
+            _ => false,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Unsigned => "not signed".to_string(),
+            Self::AdHoc => "ad-hoc signed (no identity)".to_string(),
+            Self::Identity {
+                authority,
+                team: Some(team),
+            } if !authority.contains(team.as_str()) => {
+                format!("signed by {} (team {})", authority, team)
+            }
+            Self::Identity { authority, .. } => format!("signed by {}", authority),
+        }
+    }
+}
+
+/// Read the signature of `path`
+pub fn signature(path: &Path) -> Result<Signature> {
+    let output = Command::new("codesign")
+        .args(["-dv", "--verbose=2"])
+        .arg(path)
+        .output()
+        .context("Failed to run codesign")?;
+    // codesign prints its details to stderr, whether or not the binary is signed
+    Ok(parse_signature(&String::from_utf8_lossy(&output.stderr)))
+}
+
+/// Check that the signature of `path` is intact; the error has codesign's explanation
+pub fn verify(path: &Path) -> Result<()> {
+    let output = Command::new("codesign")
+        .args(["--verify", "--strict"])
+        .arg(path)
+        .output()
+        .context("Failed to run codesign")?;
+    if !output.status.success() {
+        anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+/// Gatekeeper's verdict on `path`, from `spctl --assess`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assessment {
+    pub accepted: bool,
+    /// Why, e.g. "Notarized Developer ID" or "no usable signature"
+    pub source: Option<String>,
+}
+
+impl Assessment {
+    pub fn is_notarized(&self) -> bool {
+        self.accepted
+            && self
+                .source
+                .as_deref()
+                .is_some_and(|s| s.starts_with("Notarized"))
+    }
+}
+
+/// Sign `path` again without an identity, replacing a signature its contents no longer match
+pub fn sign_ad_hoc(path: &Path) -> Result<()> {
+    let output = Command::new("codesign")
+        .args(["--force", "--sign", "-"])
+        .arg(path)
+        .output()
+        .context("Failed to run codesign")?;
+    if !output.status.success() {
+        anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+/// Ask Gatekeeper whether it would launch `path`
+pub fn assess(path: &Path) -> Result<Assessment> {
+    let output = Command::new("spctl")
+        .args(["--assess", "--type", "execute", "-vv"])
+        .arg(path)
+        .output()
+        .context("Failed to run spctl")?;
+    Ok(parse_assessment(&String::from_utf8_lossy(&output.stderr)))
+}
+
+/// Staple a notarization ticket downloaded for the app to the bundle at `app_path`
+///
+/// `stapler staple` only works online and for the exact build that was notarized; copying the
+/// ticket into place lets a release pipeline hand it over with the binary. The result is checked
+/// with `stapler validate`.
+pub fn staple(app_path: &Path, ticket: &Path) -> Result<()> {
+    let target = app_path.join("Contents").join("CodeResources");
+    fs::copy(ticket, &target).with_context(|| {
+        format!(
+            "Failed to copy {} to {}",
+            ticket.display(),
+            target.display()
+        )
+    })?;
+
+    let output = Command::new("xcrun")
+        .args(["stapler", "validate"])
+        .arg(app_path)
+        .output()
+        .context("Failed to run xcrun stapler")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "The ticket doesn't match this build: {}",
+            String::from_utf8_lossy(&output.stdout).trim()
+        );
+    }
+    Ok(())
+}
+
+/// App bundle containing the executable at `exe`, if it runs from one
+pub fn app_bundle(exe: &Path) -> Option<PathBuf> {
+    let macos = exe.parent()?;
+    let contents = macos.parent()?;
+    let bundle = contents.parent()?;
+    (macos.file_name()? == "MacOS"
+        && contents.file_name()? == "Contents"
+        && bundle.extension()? == "app")
+        .then(|| bundle.to_path_buf())
+}
+
+/// Whether macOS runs the bundle from a randomized read-only copy (App Translocation), which it
+/// does for quarantined apps that weren't moved with Finder
+pub fn is_translocated(bundle: &Path) -> bool {
+    bundle
+        .components()
+        .any(|c| c.as_os_str() == "AppTranslocation")
+}
+
+pub fn is_quarantined(path: &Path) -> bool {
+    Command::new("xattr")
+        .args(["-p", QUARANTINE])
+        .arg(path)
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+/// Remove the quarantine attribute from `path` and everything in it
+pub fn clear_quarantine(path: &Path) -> Result<()> {
+    let output = Command::new("xattr")
+        .args(["-dr", QUARANTINE])
+        .arg(path)
+        .output()
+        .context("Failed to run xattr")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to clear the quarantine on {}: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+fn field<'a>(output: &'a str, key: &str) -> Option<&'a str> {
+    output.lines().find_map(|line| {
+        line.strip_prefix(key)
+            .and_then(|rest| rest.strip_prefix('='))
+            .map(str::trim)
+    })
+}
+
+fn parse_signature(output: &str) -> Signature {
+    if field(output, "Signature") == Some("adhoc") {
+        return Signature::AdHoc;
+    }
+    // The first Authority line is the leaf certificate
+    match field(output, "Authority") {
+        Some(authority) => Signature::Identity {
+            authority: authority.to_string(),
+            team: field(output, "TeamIdentifier")
+                .filter(|team| *team != "not set")
+                .map(str::to_string),
+        },
+        None => Signature::Unsigned,
+    }
+}
+
+fn parse_assessment(output: &str) -> Assessment {
+    Assessment {
+        accepted: output
+            .lines()
+            .next()
+            .is_some_and(|l| l.ends_with(": accepted")),
+        source: field(output, "source").map(str::to_string),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_signature() {
+        let unsigned = "/usr/local/bin/osx-scrobbler: code object is not signed at all\n";
+        assert_eq!(parse_signature(unsigned), Signature::Unsigned);
+
+        let adhoc = "Executable=/Users/me/.cargo/bin/osx-scrobbler\n\
+                     Identifier=osx_scrobbler-5d2f\n\
+                     Format=Mach-O thin (arm64)\n\
+                     Signature=adhoc\n\
+                     TeamIdentifier=not set\n";
+        assert_eq!(parse_signature(adhoc), Signature::AdHoc);
+        assert!(!Signature::AdHoc.is_developer_id());
+
+        let signed = "Executable=/tmp/osx-scrobbler\n\
+                      Authority=Developer ID Application: Jane Doe (AB12CD34EF)\n\
+                      Authority=Developer ID Certification Authority\n\
+                      Authority=Apple Root CA\n\
+                      TeamIdentifier=AB12CD34EF\n";
+        let signature = parse_signature(signed);
+        assert!(signature.is_developer_id());
+        assert_eq!(
+            signature.describe(),
+            "signed by Developer ID Application: Jane Doe (AB12CD34EF)"
+        );
+    }
+
+    #[test]
+    fn test_app_bundle() {
+        assert_eq!(
+            app_bundle(Path::new(
+                "/Applications/OSX Scrobbler.app/Contents/MacOS/osx-scrobbler"
+            )),
+            Some(PathBuf::from("/Applications/OSX Scrobbler.app"))
+        );
+        assert_eq!(
+            app_bundle(Path::new("/Users/me/.cargo/bin/osx-scrobbler")),
+            None
+        );
+
+        let translocated = app_bundle(Path::new(
+            "/private/var/folders/x/AppTranslocation/ABCD/d/OSX Scrobbler.app/Contents/MacOS/\
+             osx-scrobbler",
+        ))
+        .unwrap();
+        assert!(is_translocated(&translocated));
+        assert!(!is_translocated(Path::new(
+            "/Applications/OSX Scrobbler.app"
+        )));
+    }
+
+    #[test]
+    fn test_parse_assessment() {
+        let notarized = "/Applications/OSX Scrobbler.app: accepted\n\
+                         source=Notarized Developer ID\n\
+                         origin=Developer ID Application: Jane Doe (AB12CD34EF)\n";
+        assert!(parse_assessment(notarized).is_notarized());
+
+        let signed = "/tmp/osx-scrobbler: accepted\nsource=Developer ID\n";
+        assert!(!parse_assessment(signed).is_notarized());
+
+        let rejected = "/tmp/osx-scrobbler: rejected\nsource=no usable signature\n";
+        assert_eq!(
+            parse_assessment(rejected),
+            Assessment {
+                accepted: false,
+                source: Some("no usable signature".to_string()),
+            }
+        );
+    }
+}
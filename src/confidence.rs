@@ -0,0 +1,280 @@
+// Confidence module
+// Scores how likely playback is real music, so game and app audio with odd metadata isn't scrobbled
+
+use crate::config::ConfidenceConfig;
+use regex::Regex;
+use std::collections::HashMap;
+
+/// Music players whose metadata is always trusted
+const TRUSTED_APPS: &[&str] = &[
+    "com.apple.Music",
+    "com.apple.iTunes",
+    "com.spotify.client",
+    "com.tidal.desktop",
+    "com.deezer.deezer-desktop",
+    "com.swinsian.Swinsian",
+    "com.coppertino.Vox",
+    "com.amazon.music",
+];
+
+/// Artist names that say nothing about the music
+const GENERIC_ARTISTS: &[&str] = &["unknown", "unknown artist", "artist", "untitled"];
+
+/// Share of a title and artist cleanup can rewrite before the metadata looks suspect
+const HEAVY_REWRITE: f64 = 0.5;
+
+/// Metadata a confidence score is based on
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Signals<'a> {
+    pub bundle_id: Option<&'a str>,
+    pub bundle_name: Option<&'a str>,
+    pub title: &'a str,
+    pub artist: &'a str,
+    pub album: Option<&'a str>,
+    pub duration: Option<u64>,
+    /// Share of the reported title and artist that cleanup removed or rewrote, from 0 to 1
+    pub rewritten: f64,
+}
+
+pub struct ConfidenceScorer {
+    threshold: f64,
+    review_threshold: f64,
+    trusted: Vec<String>,
+    asset_name: Regex,
+    /// Running score per source (bundle ID, or app name for sources without one)
+    sources: HashMap<String, f64>,
+}
+
+impl ConfidenceScorer {
+    pub fn new(config: &ConfidenceConfig) -> Self {
+        Self {
+            threshold: config.threshold,
+            review_threshold: config.review_threshold,
+            trusted: TRUSTED_APPS
+                .iter()
+                .map(|s| s.to_string())
+                .chain(config.trusted_apps.iter().cloned())
+                .collect(),
+            // File names and asset identifiers: "theme.ogg", "bgm_forest_02", "Track 7"
+            asset_name: Regex::new(
+                r"(?i)(\.(mp3|m4a|aac|wav|ogg|flac|aiff?)$|^[a-z0-9]+(_[a-z0-9]+)+$|^(track|bgm|music|sfx|audio)[\s_-]*\d+$)",
+            )
+            .expect("valid asset name pattern"),
+            sources: HashMap::new(),
+        }
+    }
+
+    /// Score a single track's metadata from 0 (noise) to 1 (clearly music)
+    fn track_score(&self, signals: &Signals) -> f64 {
+        let mut score: f64 = 1.0;
+
+        if signals.bundle_id.is_none_or(str::is_empty) {
+            score -= 0.3;
+        }
+        if signals.bundle_name.is_none_or(str::is_empty) {
+            score -= 0.1;
+        }
+        if signals.duration.is_none_or(|d| d == 0) {
+            score -= 0.2;
+        }
+        if signals.album.is_none_or(str::is_empty) {
+            score -= 0.1;
+        }
+
+        let artist = signals.artist.trim();
+        if GENERIC_ARTISTS
+            .iter()
+            .any(|g| g.eq_ignore_ascii_case(artist))
+            || artist.eq_ignore_ascii_case(signals.title.trim())
+            || signals
+                .bundle_name
+                .is_some_and(|name| artist.eq_ignore_ascii_case(name.trim()))
+        {
+            score -= 0.4;
+        }
+        if self.asset_name.is_match(signals.title.trim()) {
+            score -= 0.4;
+        }
+        if signals.rewritten > HEAVY_REWRITE {
+            score -= 0.2;
+        }
+
+        score.clamp(0.0, 1.0)
+    }
+
+    /// Score a new track, taking its source's earlier tracks into account
+    ///
+    /// The source's running score moves halfway towards each new track's score, so one odd
+    /// title from a music app doesn't sink it, while a source that keeps reporting noise stays low.
+    pub fn score(&mut self, signals: &Signals) -> f64 {
+        if signals
+            .bundle_id
+            .is_some_and(|id| self.trusted.iter().any(|t| t == id))
+        {
+            return 1.0;
+        }
+
+        let track = self.track_score(signals);
+        let source = signals
+            .bundle_id
+            .or(signals.bundle_name)
+            .unwrap_or_default()
+            .to_string();
+
+        let score = match self.sources.get(&source) {
+            Some(previous) => (previous + track) / 2.0,
+            None => track,
+        };
+        self.sources.insert(source, score);
+
+        score
+    }
+
+    /// Check whether a score is high enough to scrobble
+    pub fn accepts(&self, score: f64) -> bool {
+        score >= self.threshold
+    }
+
+    /// Check whether an accepted score is too low to submit without the user's review
+    pub fn needs_review(&self, score: f64) -> bool {
+        score < self.review_threshold
+    }
+}
+
+/// Share of the reported texts that's gone from the cleaned ones, from 0 (untouched) to 1
+///
+/// Text kept at the start or end of each counts as untouched, so removing a tag from the end of
+/// a title only counts the tag.
+pub fn rewritten_share(reported: &[&str], cleaned: &[&str]) -> f64 {
+    let mut total = 0;
+    let mut kept = 0;
+    for (reported, cleaned) in reported.iter().zip(cleaned) {
+        let reported: Vec<char> = reported.chars().collect();
+        let cleaned: Vec<char> = cleaned.chars().collect();
+        let prefix = reported
+            .iter()
+            .zip(&cleaned)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let suffix = reported[prefix..]
+            .iter()
+            .rev()
+            .zip(cleaned[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        total += reported.len();
+        kept += prefix + suffix;
+    }
+
+    if total == 0 {
+        return 0.0;
+    }
+    1.0 - kept as f64 / total as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scorer() -> ConfidenceScorer {
+        ConfidenceScorer::new(&ConfidenceConfig::default())
+    }
+
+    fn music() -> Signals<'static> {
+        Signals {
+            bundle_id: Some("org.videolan.vlc"),
+            bundle_name: Some("VLC"),
+            title: "Song",
+            artist: "Band",
+            album: Some("Album"),
+            duration: Some(200),
+            rewritten: 0.0,
+        }
+    }
+
+    fn game() -> Signals<'static> {
+        Signals {
+            title: "bgm_forest_02",
+            artist: "Unknown Artist",
+            ..Signals::default()
+        }
+    }
+
+    #[test]
+    fn test_well_tagged_music_scores_high() {
+        let mut scorer = scorer();
+        assert_eq!(scorer.score(&music()), 1.0);
+        assert!(scorer.accepts(1.0));
+    }
+
+    #[test]
+    fn test_game_audio_scores_low() {
+        let mut scorer = scorer();
+        let score = scorer.score(&game());
+
+        assert!(score < 0.1, "{}", score);
+        assert!(!scorer.accepts(score));
+    }
+
+    #[test]
+    fn test_trusted_apps_always_accepted() {
+        let mut scorer = scorer();
+        let signals = Signals {
+            bundle_id: Some("com.spotify.client"),
+            ..game()
+        };
+
+        assert_eq!(scorer.score(&signals), 1.0);
+    }
+
+    #[test]
+    fn test_source_score_carries_over() {
+        let mut scorer = scorer();
+        let junk = Signals {
+            title: "theme.ogg",
+            album: None,
+            ..music()
+        };
+
+        // Earlier noise from a source lowers the score of its next track
+        let first = scorer.score(&junk);
+        let second = scorer.score(&music());
+        assert!(first < second && second < 1.0);
+        assert!(scorer.accepts(second));
+    }
+
+    #[test]
+    fn test_heavy_rewrite_lowers_score() {
+        let rewritten = Signals {
+            rewritten: 0.8,
+            ..music()
+        };
+
+        assert!(scorer().score(&rewritten) < scorer().score(&music()));
+    }
+
+    #[test]
+    fn test_review_threshold() {
+        let scorer = ConfidenceScorer::new(&ConfidenceConfig {
+            threshold: 0.3,
+            review_threshold: 0.7,
+            trusted_apps: Vec::new(),
+        });
+
+        assert!(scorer.accepts(0.5) && scorer.needs_review(0.5));
+        assert!(!scorer.needs_review(0.7));
+        // Off by default
+        assert!(!ConfidenceScorer::new(&ConfidenceConfig::default()).needs_review(0.0));
+    }
+
+    #[test]
+    fn test_rewritten_share() {
+        assert_eq!(rewritten_share(&["Song", "Band"], &["Song", "Band"]), 0.0);
+        // "[Explicit]" is 11 of the 19 characters
+        let share = rewritten_share(&["Song [Explicit]", "Band"], &["Song", "Band"]);
+        assert!((share - 11.0 / 19.0).abs() < 1e-9, "{}", share);
+        assert_eq!(rewritten_share(&["abc"], &[""]), 1.0);
+        assert_eq!(rewritten_share(&[""], &[""]), 0.0);
+    }
+}
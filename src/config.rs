@@ -1,8 +1,10 @@
 // Configuration management module
 // Handles loading, saving, and validating configuration
 
+use crate::lastfm_api::{LASTFM_API_URL, LASTFM_AUTH_URL};
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
 use std::fs;
 use std::path::PathBuf;
 
@@ -12,9 +14,27 @@ pub struct Config {
     /// Refresh interval in seconds for polling now playing status
     pub refresh_interval: u64,
 
+    /// Refresh interval in seconds while nothing is playing (never shorter than
+    /// `refresh_interval`)
+    #[serde(default = "default_idle_refresh_interval")]
+    pub idle_refresh_interval: u64,
+
     /// Scrobble after playing this percentage of the track (50% default)
     pub scrobble_threshold: u8,
 
+    /// How `scrobble_threshold` and the 4 minute rule combine
+    #[serde(default)]
+    pub scrobble_threshold_mode: ThresholdMode,
+
+    /// Seconds to play before scrobbling, for `scrobble_threshold_mode = "fixed_seconds"`
+    #[serde(default = "default_scrobble_threshold_seconds")]
+    pub scrobble_threshold_seconds: u64,
+
+    /// Polls a playing track's position can stand still, or be past the end of the track, before
+    /// the player is taken to be gone (0 never expires sessions)
+    #[serde(default = "default_stalled_polls")]
+    pub stalled_polls: u32,
+
     /// Text cleanup configuration
     #[serde(default)]
     pub cleanup: CleanupConfig,
@@ -23,11 +43,119 @@ pub struct Config {
     #[serde(default)]
     pub app_filtering: AppFilteringConfig,
 
+    /// Apple Music specific handling
+    #[serde(default)]
+    pub apple_music: AppleMusicConfig,
+
+    /// Classical music tagging conventions
+    #[serde(default)]
+    pub classical: ClassicalConfig,
+
+    /// Audiobooks and other long-form content
+    #[serde(default)]
+    pub long_form: LongFormConfig,
+
+    /// Podcasts and mixes that change metadata per chapter
+    #[serde(default)]
+    pub chapters: ChapterConfig,
+
+    /// How sure playback has to be music before it's scrobbled
+    #[serde(default)]
+    pub confidence: ConfidenceConfig,
+
+    /// Submission scheduling for retries and backfills
+    #[serde(default)]
+    pub scheduler: SchedulerConfig,
+
+    /// Waiting for the system to be ready after a login launch
+    #[serde(default)]
+    pub startup: StartupConfig,
+
     /// Last.fm configuration
     pub lastfm: Option<LastFmConfig>,
 
     /// ListenBrainz configurations (can have multiple instances)
     pub listenbrainz: Vec<ListenBrainzConfig>,
+
+    /// URLs that get now playing updates and scrobbles as JSON
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub webhook: Vec<WebhookConfig>,
+
+    /// Local `.scrobbler.log` archive of every scrobble
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scrobbler_log: Option<ScrobblerLogConfig>,
+
+    /// Named credential sets to switch between from the menu bar
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub profiles: Vec<ProfileConfig>,
+
+    /// Profile whose credentials are used (the top-level `lastfm`/`listenbrainz` when unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_profile: Option<String>,
+
+    /// Primary/backup service pairs
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failover: Vec<FailoverConfig>,
+
+    /// Send plays to a subset of the services by app and time of day
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub routes: Vec<RouteConfig>,
+
+    /// Keep the other services in sync with a canonical one
+    #[serde(default)]
+    pub mirror: Option<MirrorConfig>,
+
+    /// Local web UI for status, history and settings
+    #[serde(default)]
+    pub web: WebConfig,
+
+    /// JSON-RPC control API on a local socket
+    #[serde(default)]
+    pub control: ControlConfig,
+
+    /// Album art downloads and their disk cache
+    #[serde(default)]
+    pub artwork: ArtworkConfig,
+
+    /// Now playing notifications
+    #[serde(default)]
+    pub notifications: NotificationConfig,
+
+    /// Guest mode, for when someone else is using the machine
+    #[serde(default)]
+    pub guest_mode: GuestModeConfig,
+
+    /// Text shown next to the menu bar icon
+    #[serde(default)]
+    pub menu_bar: MenuBarConfig,
+
+    /// Plays in web browsers
+    #[serde(default)]
+    pub browser: BrowserConfig,
+
+    /// Artist/title corrections applied before scrobbling (added by "Edit Last Scrobble")
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub corrections: Vec<CorrectionRule>,
+
+    /// Apps that report the title and artist in other fields
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub field_mapping: Vec<FieldMappingConfig>,
+
+    /// Spellings of artist names mapped to one canonical name before scrobbling
+    #[serde(default)]
+    pub artist_aliases: ArtistAliasConfig,
+
+    /// Filter rules like `app == "com.spotify.client" && duration < 60 -> ignore`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub filters: Vec<String>,
+
+    /// Metadata transform plugins (dynamic libraries), applied in order after cleanup
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub plugins: Vec<PathBuf>,
+
+    /// Extra logging for tracking down why something was scrobbled
+    #[serde(default)]
+    pub debug: DebugConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -38,6 +166,91 @@ pub struct CleanupConfig {
     /// Regex patterns to remove from track/album/artist names
     /// Applied in order, each pattern is removed from the text
     pub patterns: Vec<String>,
+
+    /// Which name to keep when one is given in two scripts, like `宇多田ヒカル (Hikaru Utada)`
+    #[serde(default)]
+    pub dual_script: DualScript,
+
+    /// What to do with live recording details like `(Live at Wembley)` in titles
+    #[serde(default)]
+    pub live: LivePolicy,
+
+    /// Generic version suffixes like `(Radio Edit)` to remove from titles
+    #[serde(default)]
+    pub suffixes: SuffixConfig,
+}
+
+/// Removal of generic version suffixes, keeping remix credits
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SuffixConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Regexes for whole suffixes to remove (case-insensitive), e.g. `Radio Edit`
+    #[serde(default = "default_strip_suffixes")]
+    pub strip: Vec<String>,
+
+    /// Regexes for suffixes that are always kept, even if a `strip` pattern matches them
+    #[serde(default = "default_preserve_suffixes")]
+    pub preserve: Vec<String>,
+}
+
+impl Default for SuffixConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            strip: default_strip_suffixes(),
+            preserve: default_preserve_suffixes(),
+        }
+    }
+}
+
+fn default_strip_suffixes() -> Vec<String> {
+    [
+        r"(radio|single|album|original|extended|short) (edit|version|mix)",
+        r"original",
+        r"edit",
+        r"(\d{4} )?remaster(ed)?( \d{4})?( version)?",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect()
+}
+
+fn default_preserve_suffixes() -> Vec<String> {
+    [
+        r"\b(remix|rework|bootleg|vip|dub)\b",
+        r"\bfeat\.?\s",
+        r"\bft\.?\s",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DualScript {
+    /// Keep both, as reported
+    #[default]
+    Keep,
+    /// The name in its original script (`宇多田ヒカル`)
+    Original,
+    /// The name in Latin letters (`Hikaru Utada`)
+    Latin,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LivePolicy {
+    /// Leave them in the title
+    #[default]
+    Keep,
+    /// Remove them from the title
+    Strip,
+    /// Remove them from the title and send them along separately (ListenBrainz
+    /// `additional_info.live`)
+    Tag,
 }
 
 impl Default for CleanupConfig {
@@ -52,16 +265,313 @@ impl Default for CleanupConfig {
                 r"\s*- Explicit".to_string(),
                 r"\s*- Clean".to_string(),
             ],
+            dual_script: DualScript::Keep,
+            live: LivePolicy::Keep,
+            suffixes: SuffixConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppleMusicConfig {
+    /// What to do with tracks played from an Apple Music radio station
+    #[serde(default)]
+    pub radio: RadioPolicy,
+
+    /// Extra station names reported as the artist or album, besides Apple Music's own
+    #[serde(default)]
+    pub stations: Vec<String>,
+
+    /// Scrobble compilation tracks under their own artist, sending the album artist separately
+    #[serde(default = "default_true")]
+    pub compilations: bool,
+}
+
+impl Default for AppleMusicConfig {
+    fn default() -> Self {
+        Self {
+            radio: RadioPolicy::default(),
+            stations: Vec::new(),
+            compilations: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RadioPolicy {
+    /// Take the artist and title from the station's `Artist - Title` track name
+    #[default]
+    Parse,
+    /// Don't scrobble radio at all
+    Skip,
+    /// Scrobble the metadata as reported
+    Scrobble,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClassicalConfig {
+    /// Rewrite classical tracks (detected by genre, Apple Music only)
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Who to scrobble as the artist
+    #[serde(default)]
+    pub artist: ClassicalArtist,
+
+    /// Remove track and movement numbers from titles
+    #[serde(default = "default_true")]
+    pub strip_numbering: bool,
+
+    /// Genres treated as classical (matched as case-insensitive substrings)
+    #[serde(default = "default_classical_genres")]
+    pub genres: Vec<String>,
+}
+
+impl Default for ClassicalConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            artist: ClassicalArtist::default(),
+            strip_numbering: true,
+            genres: default_classical_genres(),
+        }
+    }
+}
+
+fn default_idle_refresh_interval() -> u64 {
+    15
+}
+
+fn default_stalled_polls() -> u32 {
+    60
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_classical_genres() -> Vec<String> {
+    vec!["Classical".to_string(), "Opera".to_string()]
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClassicalArtist {
+    /// The composer, when the player knows it
+    #[default]
+    Composer,
+    /// The performer, as reported by the player
+    Performer,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LongFormConfig {
+    /// Don't scrobble audiobooks and long-form content
+    #[serde(default = "default_true")]
+    pub exclude: bool,
+
+    /// Tracks longer than this many hours are treated as long-form
+    #[serde(default = "default_long_form_max_hours")]
+    pub max_hours: f64,
+
+    /// Apps that only play books (bundle IDs)
+    #[serde(default = "default_long_form_apps")]
+    pub apps: Vec<String>,
+}
+
+impl Default for LongFormConfig {
+    fn default() -> Self {
+        Self {
+            exclude: true,
+            max_hours: default_long_form_max_hours(),
+            apps: default_long_form_apps(),
+        }
+    }
+}
+
+fn default_long_form_max_hours() -> f64 {
+    2.0
+}
+
+fn default_long_form_apps() -> Vec<String> {
+    vec!["com.apple.iBooksX".to_string()]
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChapterConfig {
+    /// What to do when a file moves on to its next chapter
+    #[serde(default)]
+    pub policy: ChapterPolicy,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChapterPolicy {
+    /// Keep playing the file as the track it started as
+    #[default]
+    Ignore,
+    /// Scrobble every chapter as a track of its own
+    Tracks,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfidenceConfig {
+    /// Minimum confidence (0-1) that a source is playing music; 0 scrobbles everything
+    #[serde(default = "default_confidence_threshold")]
+    pub threshold: f64,
+
+    /// Confidence (0-1) below which plays that pass `threshold` are recorded but held back for
+    /// review instead of submitted; 0 submits them all
+    #[serde(default)]
+    pub review_threshold: f64,
+
+    /// Apps whose metadata is always trusted, besides the built-in music players (bundle IDs)
+    #[serde(default)]
+    pub trusted_apps: Vec<String>,
+}
+
+impl Default for ConfidenceConfig {
+    fn default() -> Self {
+        Self {
+            threshold: default_confidence_threshold(),
+            review_threshold: 0.0,
+            trusted_apps: Vec::new(),
+        }
+    }
+}
+
+fn default_confidence_threshold() -> f64 {
+    0.5
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StartupConfig {
+    /// Seconds to wait before the first poll when launched soon after boot
+    #[serde(default = "default_grace_period")]
+    pub grace_period: u64,
+
+    /// Seconds after the grace period to wait for the network and Media Remote, before polling
+    /// anyway (0 doesn't wait)
+    #[serde(default = "default_ready_timeout")]
+    pub ready_timeout: u64,
+}
+
+impl Default for StartupConfig {
+    fn default() -> Self {
+        Self {
+            grace_period: default_grace_period(),
+            ready_timeout: default_ready_timeout(),
+        }
+    }
+}
+
+fn default_grace_period() -> u64 {
+    10
+}
+
+fn default_ready_timeout() -> u64 {
+    60
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SchedulerConfig {
+    /// Periodically flush queued (failed or backfilled) submissions
+    pub enabled: bool,
+
+    /// Minutes between flush windows, aligned to midnight (60 = top of every hour)
+    pub flush_interval_minutes: u64,
+
+    /// Maximum queued submissions sent to each service per flush window
+    pub max_per_flush: usize,
+
+    /// Which time queued scrobbles are submitted with
+    #[serde(default)]
+    pub timestamps: TimestampPolicy,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            flush_interval_minutes: 60,
+            max_per_flush: 50,
+            timestamps: TimestampPolicy::default(),
         }
     }
 }
 
+/// When a track is scrobbled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThresholdMode {
+    /// After `scrobble_threshold` percent of the track or 4 minutes, whichever comes first
+    #[default]
+    LastfmStandard,
+    /// After `scrobble_threshold` percent of the track, however long it is
+    PercentOnly,
+    /// After `scrobble_threshold_seconds`, however long the track is
+    FixedSeconds,
+}
+
+fn default_scrobble_threshold_seconds() -> u64 {
+    240
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimestampPolicy {
+    /// Always the time the track was played, even if the service will refuse it
+    #[default]
+    Original,
+    /// The time it was played, but plays older than Last.fm accepts go to an export file instead
+    Validate,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LastFmConfig {
     pub enabled: bool,
     pub api_key: String,
     pub api_secret: String,
     pub session_key: String,
+
+    /// API endpoint, for Libre.fm and other servers speaking the Last.fm protocol
+    #[serde(default = "default_lastfm_api_url")]
+    pub api_url: String,
+
+    /// Page where the user authorizes the app (derived from `api_url` when not set)
+    #[serde(default)]
+    pub auth_url: Option<String>,
+}
+
+fn default_lastfm_api_url() -> String {
+    LASTFM_API_URL.to_string()
+}
+
+impl LastFmConfig {
+    /// Whether this talks to a server other than Last.fm itself
+    pub fn is_custom_server(&self) -> bool {
+        self.api_url != LASTFM_API_URL
+    }
+
+    /// Page where the user authorizes the app
+    ///
+    /// Servers other than Last.fm have it at `/api/auth/` on the API's host, as Libre.fm does.
+    pub fn auth_url(&self) -> String {
+        if let Some(url) = &self.auth_url {
+            return url.clone();
+        }
+        if !self.is_custom_server() {
+            return LASTFM_AUTH_URL.to_string();
+        }
+        let origin = match self.api_url.split_once("://") {
+            Some((scheme, rest)) => {
+                format!("{}://{}", scheme, rest.split('/').next().unwrap_or(rest))
+            }
+            None => self.api_url.trim_end_matches('/').to_string(),
+        };
+        format!("{}/api/auth/", origin)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -70,6 +580,368 @@ pub struct ListenBrainzConfig {
     pub name: String,
     pub token: String,
     pub api_url: String,
+
+    /// PEM file with extra CA certificates to trust (e.g. a private CA)
+    #[serde(default)]
+    pub ca_cert: Option<PathBuf>,
+
+    /// PEM client certificate for servers behind a mutual TLS proxy
+    #[serde(default)]
+    pub client_cert: Option<PathBuf>,
+
+    /// PEM (PKCS#8) private key for `client_cert`
+    #[serde(default)]
+    pub client_key: Option<PathBuf>,
+
+    /// Basic auth credentials for a reverse proxy in front of the server
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+
+    /// Extra headers sent with every request
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    pub name: String,
+    pub url: String,
+
+    /// Extra headers sent with every request (e.g. `Authorization`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+
+    /// Events posted: `now_playing` and/or `scrobble`
+    #[serde(default = "default_webhook_events")]
+    pub events: Vec<WebhookEvent>,
+
+    /// JSON body, with `{{artist}}`-style placeholders (see the README for the list)
+    #[serde(default = "default_webhook_body")]
+    pub body: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    NowPlaying,
+    Scrobble,
+}
+
+fn default_webhook_events() -> Vec<WebhookEvent> {
+    vec![WebhookEvent::NowPlaying, WebhookEvent::Scrobble]
+}
+
+fn default_webhook_body() -> String {
+    crate::scrobbler::webhook::DEFAULT_BODY.to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScrobblerLogConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// File scrobbles are appended to (a leading `~/` is the home directory)
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileConfig {
+    /// Shown in the Profile menu, and prefixed to the profile's service keys
+    pub name: String,
+
+    #[serde(default)]
+    pub lastfm: Option<LastFmConfig>,
+
+    #[serde(default)]
+    pub listenbrainz: Vec<ListenBrainzConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailoverConfig {
+    /// Service submitted to normally (e.g. `listenbrainz:Self-hosted`)
+    pub primary: String,
+
+    /// Service that also gets submissions while the primary is down
+    pub backup: String,
+
+    /// Minutes the primary has to be failing before the backup takes over
+    #[serde(default = "default_failover_after_minutes")]
+    pub after_minutes: u64,
+}
+
+fn default_failover_after_minutes() -> u64 {
+    60
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteConfig {
+    /// Bundle IDs of the apps the route applies to (any app when empty)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub apps: Vec<String>,
+
+    /// Local time of day (`HH:MM`) the route applies from; set with `until`
+    #[serde(default)]
+    pub from: Option<String>,
+
+    /// Local time of day the route applies until (exclusive); may be past midnight
+    #[serde(default)]
+    pub until: Option<String>,
+
+    /// Days the route applies on (`mon`, `tue`, ...; every day when empty)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub days: Vec<String>,
+
+    /// Services matching plays go to (keys like `lastfm`, or `listenbrainz` for every instance)
+    pub services: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MirrorConfig {
+    /// Service treated as the source of truth (e.g. `listenbrainz:Primary`)
+    pub canonical: String,
+
+    /// How far back (in hours) to compare listens on each run
+    #[serde(default = "default_mirror_lookback_hours")]
+    pub lookback_hours: u64,
+}
+
+fn default_mirror_lookback_hours() -> u64 {
+    24
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebConfig {
+    /// Serve the web UI
+    pub enabled: bool,
+
+    /// Address to listen on; use `0.0.0.0:<port>` to reach it from other machines
+    #[serde(default = "default_web_address")]
+    pub address: String,
+
+    /// Token required by the web UI's API (generated when empty)
+    #[serde(default)]
+    pub token: String,
+}
+
+impl Default for WebConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: default_web_address(),
+            token: String::new(),
+        }
+    }
+}
+
+fn default_web_address() -> String {
+    "127.0.0.1:8470".to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ControlConfig {
+    /// Listen for control API requests on the local socket
+    pub enabled: bool,
+}
+
+impl Default for ControlConfig {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArtworkConfig {
+    /// Look up album art online (iTunes Search, then the Cover Art Archive)
+    pub enabled: bool,
+
+    /// Size of the artwork cache in megabytes, least recently used art is removed first
+    #[serde(default = "default_artwork_cache_mb")]
+    pub max_cache_mb: u64,
+}
+
+impl Default for ArtworkConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_cache_mb: default_artwork_cache_mb(),
+        }
+    }
+}
+
+fn default_artwork_cache_mb() -> u64 {
+    100
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ArtistAliasConfig {
+    /// Look artists up on MusicBrainz and scrobble them under their MusicBrainz name
+    #[serde(default)]
+    pub musicbrainz: bool,
+
+    /// Scrobble the artist's primary MusicBrainz alias in this locale (e.g. `en`) instead of
+    /// their name, when there is one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+
+    /// Reported spelling -> name scrobbled, ignoring case; these win over MusicBrainz
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub overrides: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GuestModeConfig {
+    /// Hours after which guest mode turns itself off (0 = stays on until turned off)
+    #[serde(default = "default_guest_mode_hours")]
+    pub expire_after_hours: u64,
+}
+
+impl Default for GuestModeConfig {
+    fn default() -> Self {
+        Self {
+            expire_after_hours: default_guest_mode_hours(),
+        }
+    }
+}
+
+fn default_guest_mode_hours() -> u64 {
+    3
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MenuBarConfig {
+    /// Show the current track next to the icon
+    #[serde(default)]
+    pub show_track: bool,
+
+    /// Characters of the track shown before it's cut off with `…` (0 = no limit)
+    #[serde(default = "default_menu_bar_max_length")]
+    pub max_length: usize,
+}
+
+impl Default for MenuBarConfig {
+    fn default() -> Self {
+        Self {
+            show_track: false,
+            max_length: default_menu_bar_max_length(),
+        }
+    }
+}
+
+fn default_menu_bar_max_length() -> usize {
+    30
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DebugConfig {
+    /// Log every request sent to the services, secrets redacted, and the Media Remote info each
+    /// new play came from (at debug level, so with `RUST_LOG=debug`)
+    #[serde(default)]
+    pub log_payloads: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BrowserConfig {
+    /// Record the page a browser play comes from (macOS asks for permission to read its tabs)
+    #[serde(default)]
+    pub capture_url: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationConfig {
+    /// Show a notification for each new track; unset until the first-run prompt is answered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+
+    /// Notify when a new track starts playing
+    #[serde(default = "default_true")]
+    pub now_playing: bool,
+
+    /// Also notify when a track is scrobbled
+    #[serde(default)]
+    pub scrobbled: bool,
+
+    /// Also notify when a scrobble can't be submitted to a service
+    #[serde(default)]
+    pub failed: bool,
+
+    /// Notification types still shown while a Focus mode is on
+    #[serde(default = "default_during_focus")]
+    pub during_focus: Vec<NotificationKind>,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: None,
+            now_playing: true,
+            scrobbled: false,
+            failed: false,
+            during_focus: default_during_focus(),
+        }
+    }
+}
+
+fn default_during_focus() -> Vec<NotificationKind> {
+    vec![NotificationKind::AuthExpired]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    /// A new track started playing
+    NowPlaying,
+    /// A track was scrobbled
+    Scrobbled,
+    /// A service rejected its credentials, so nothing more gets scrobbled to it
+    AuthExpired,
+    /// A scrobble couldn't be submitted to a service, and was queued to retry
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldMappingConfig {
+    /// Bundle IDs of the apps the mapping applies to
+    pub apps: Vec<String>,
+
+    /// Reported field scrobbled as the title
+    #[serde(default = "default_title_from")]
+    pub title_from: TrackField,
+
+    /// Reported field scrobbled as the artist
+    #[serde(default = "default_artist_from")]
+    pub artist_from: TrackField,
+}
+
+/// A field of the track a player reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackField {
+    Title,
+    Artist,
+    Album,
+}
+
+fn default_title_from() -> TrackField {
+    TrackField::Title
+}
+
+fn default_artist_from() -> TrackField {
+    TrackField::Artist
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CorrectionRule {
+    /// Artist and title as reported by the player (after cleanup)
+    pub artist: String,
+    pub title: String,
+
+    /// Artist and title to scrobble instead
+    pub corrected_artist: String,
+    pub corrected_title: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -85,6 +957,63 @@ pub struct AppFilteringConfig {
 
     /// Apps to ignore (bundle IDs)
     pub ignored_apps: Vec<String>,
+
+    /// Radio and autoplay apps (bundle IDs), whose plays Last.fm is told the user didn't choose
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub radio_apps: Vec<String>,
+
+    /// System processes that report audio as now playing (bundle IDs), ignored without asking
+    /// unless in `allowed_apps`
+    #[serde(default = "default_system_apps")]
+    pub system_apps: Vec<String>,
+
+    /// Which app to follow when Media Remote switches between two that are both playing
+    #[serde(default)]
+    pub multiple_players: PlayerPolicy,
+
+    /// Polls another app has to be reported for in a row before it takes over a held session
+    ///
+    /// The session it takes over from is kept, but gains no listening time until its app is
+    /// reported again: Media Remote only reports one app at a time.
+    #[serde(default = "default_player_switch_polls")]
+    pub player_switch_polls: u32,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlayerPolicy {
+    /// Follow whichever app is reported as playing
+    #[default]
+    MostRecent,
+    /// Apps in `allowed_apps` keep their session against apps that aren't
+    PreferAllowed,
+    /// The app that started a session keeps it against every other app
+    Lock,
+}
+
+fn default_player_switch_polls() -> u32 {
+    6
+}
+
+fn default_system_apps() -> Vec<String> {
+    [
+        // Picture in Picture controls
+        "com.apple.PIPAgent",
+        // Screen recordings
+        "com.apple.screencaptureui",
+        "com.apple.screencapture",
+        // FaceTime and other calls
+        "com.apple.FaceTime",
+        "com.apple.avconferenced",
+        "com.apple.TelephonyUtilities",
+        // Control Center and Siri
+        "com.apple.controlcenter",
+        "com.apple.Siri",
+        "com.apple.assistantd",
+    ]
+    .iter()
+    .map(|id| id.to_string())
+    .collect()
 }
 
 impl Default for AppFilteringConfig {
@@ -94,6 +1023,10 @@ impl Default for AppFilteringConfig {
             scrobble_unknown: true,
             allowed_apps: Vec::new(),
             ignored_apps: Vec::new(),
+            radio_apps: Vec::new(),
+            system_apps: default_system_apps(),
+            multiple_players: PlayerPolicy::default(),
+            player_switch_polls: default_player_switch_polls(),
         }
     }
 }
@@ -102,21 +1035,60 @@ impl Default for Config {
     fn default() -> Self {
         Self {
             refresh_interval: 5,
+            idle_refresh_interval: default_idle_refresh_interval(),
             scrobble_threshold: 50,
+            scrobble_threshold_mode: ThresholdMode::default(),
+            scrobble_threshold_seconds: default_scrobble_threshold_seconds(),
+            stalled_polls: default_stalled_polls(),
             cleanup: CleanupConfig::default(),
             app_filtering: AppFilteringConfig::default(),
+            apple_music: AppleMusicConfig::default(),
+            classical: ClassicalConfig::default(),
+            long_form: LongFormConfig::default(),
+            chapters: ChapterConfig::default(),
+            confidence: ConfidenceConfig::default(),
+            scheduler: SchedulerConfig::default(),
+            startup: StartupConfig::default(),
             lastfm: Some(LastFmConfig {
                 enabled: false,
                 api_key: String::new(),
                 api_secret: String::new(),
                 session_key: String::new(),
+                api_url: default_lastfm_api_url(),
+                auth_url: None,
             }),
             listenbrainz: vec![ListenBrainzConfig {
                 enabled: false,
                 name: "Primary".to_string(),
                 token: String::new(),
                 api_url: "https://api.listenbrainz.org".to_string(),
+                ca_cert: None,
+                client_cert: None,
+                client_key: None,
+                username: None,
+                password: None,
+                headers: BTreeMap::new(),
             }],
+            webhook: Vec::new(),
+            scrobbler_log: None,
+            profiles: Vec::new(),
+            active_profile: None,
+            failover: Vec::new(),
+            routes: Vec::new(),
+            field_mapping: Vec::new(),
+            mirror: None,
+            web: WebConfig::default(),
+            control: ControlConfig::default(),
+            artwork: ArtworkConfig::default(),
+            notifications: NotificationConfig::default(),
+            guest_mode: GuestModeConfig::default(),
+            menu_bar: MenuBarConfig::default(),
+            browser: BrowserConfig::default(),
+            corrections: Vec::new(),
+            artist_aliases: ArtistAliasConfig::default(),
+            filters: Vec::new(),
+            plugins: Vec::new(),
+            debug: DebugConfig::default(),
         }
     }
 }
@@ -124,9 +1096,7 @@ impl Default for Config {
 impl Config {
     /// Get the path to the configuration file
     pub fn config_path() -> Result<PathBuf> {
-        let config_dir = dirs::config_dir().context("Failed to get config directory")?;
-
-        Ok(config_dir.join("osx_scrobbler.conf"))
+        Ok(crate::paths::Paths::current()?.config)
     }
 
     /// Load configuration from file, or create default if it doesn't exist
@@ -170,6 +1140,66 @@ impl Config {
         Ok(())
     }
 
+    /// The active profile's Last.fm and ListenBrainz settings
+    pub fn credentials(&self) -> (Option<&LastFmConfig>, &[ListenBrainzConfig]) {
+        match self.active_profile() {
+            Some(profile) => (profile.lastfm.as_ref(), &profile.listenbrainz),
+            None => (self.lastfm.as_ref(), &self.listenbrainz),
+        }
+    }
+
+    /// The active profile's Last.fm settings, to store a new session key in
+    pub fn lastfm_mut(&mut self) -> Option<&mut LastFmConfig> {
+        match self.active_profile.clone() {
+            Some(name) => self
+                .profiles
+                .iter_mut()
+                .find(|profile| profile.name == name)?
+                .lastfm
+                .as_mut(),
+            None => self.lastfm.as_mut(),
+        }
+    }
+
+    /// The active profile's ListenBrainz instances, to edit their tokens
+    pub fn listenbrainz_mut(&mut self) -> &mut Vec<ListenBrainzConfig> {
+        let active = self.active_profile.clone();
+        match self
+            .profiles
+            .iter_mut()
+            .find(|profile| Some(&profile.name) == active.as_ref())
+        {
+            Some(profile) => &mut profile.listenbrainz,
+            None => &mut self.listenbrainz,
+        }
+    }
+
+    /// History key of a service from the active credentials (matches `Scrobbler::key`)
+    pub fn service_key(&self, key: &str) -> String {
+        match &self.active_profile {
+            Some(profile) => format!("{}/{}", profile, key),
+            None => key.to_string(),
+        }
+    }
+
+    /// The profile selected by `active_profile`, if any
+    pub fn active_profile(&self) -> Option<&ProfileConfig> {
+        let name = self.active_profile.as_ref()?;
+        self.profiles.iter().find(|profile| &profile.name == name)
+    }
+
+    /// Cargo features of the integrations that are enabled here but not built in
+    pub fn missing_features(&self) -> Vec<&'static str> {
+        let mut missing = Vec::new();
+        if self.web.enabled && !cfg!(feature = "web") {
+            missing.push("web");
+        }
+        if self.artwork.enabled && !cfg!(feature = "artwork") {
+            missing.push("artwork");
+        }
+        missing
+    }
+
     /// Validate the configuration
     pub fn validate(&self) -> Result<()> {
         // Validate refresh interval
@@ -181,17 +1211,78 @@ impl Config {
         if self.scrobble_threshold == 0 || self.scrobble_threshold > 100 {
             anyhow::bail!("scrobble_threshold must be between 1 and 100");
         }
+        if self.scrobble_threshold_mode == ThresholdMode::FixedSeconds
+            && self.scrobble_threshold_seconds == 0
+        {
+            anyhow::bail!("scrobble_threshold_seconds must be greater than 0");
+        }
+
+        // Validate scheduler (flush windows must fit within a day)
+        if self.scheduler.flush_interval_minutes == 0
+            || self.scheduler.flush_interval_minutes > 24 * 60
+        {
+            anyhow::bail!("scheduler.flush_interval_minutes must be between 1 and 1440");
+        }
+
+        if !(0.0..=1.0).contains(&self.confidence.threshold) {
+            anyhow::bail!("confidence.threshold must be between 0 and 1");
+        }
+        if !(0.0..=1.0).contains(&self.confidence.review_threshold) {
+            anyhow::bail!("confidence.review_threshold must be between 0 and 1");
+        }
+
+        if self.long_form.max_hours <= 0.0 {
+            anyhow::bail!("long_form.max_hours must be greater than 0");
+        }
+
+        crate::filters::Filters::new(&self.filters)?;
+
+        if self
+            .field_mapping
+            .iter()
+            .any(|mapping| mapping.apps.is_empty())
+        {
+            anyhow::bail!("field_mapping.apps must list at least one app");
+        }
+
+        if self.artwork.enabled && self.artwork.max_cache_mb == 0 {
+            anyhow::bail!("artwork.max_cache_mb must be greater than 0");
+        }
+
+        if self.web.enabled {
+            self.web
+                .address
+                .parse::<std::net::SocketAddr>()
+                .context("web.address must be an IP address and port")?;
+        }
+
+        // Validate profiles
+        let mut profile_names = HashSet::new();
+        for profile in &self.profiles {
+            if profile.name.is_empty() || profile.name.contains('/') {
+                anyhow::bail!("Profile names must be non-empty and can't contain '/'");
+            }
+            if !profile_names.insert(profile.name.as_str()) {
+                anyhow::bail!("Duplicate profile: {}", profile.name);
+            }
+        }
+        if let Some(active) = &self.active_profile {
+            if !profile_names.contains(active.as_str()) {
+                anyhow::bail!("active_profile '{}' is not a configured profile", active);
+            }
+        }
 
         // Check that at least one scrobbler is enabled
-        let lastfm_enabled = self.lastfm.as_ref().map(|l| l.enabled).unwrap_or(false);
-        let listenbrainz_enabled = self.listenbrainz.iter().any(|l| l.enabled);
+        let (lastfm, listenbrainz) = self.credentials();
+        let lastfm_enabled = lastfm.map(|l| l.enabled).unwrap_or(false);
+        let listenbrainz_enabled = listenbrainz.iter().any(|l| l.enabled);
 
         if !lastfm_enabled && !listenbrainz_enabled {
             log::warn!("No scrobbling services are enabled");
         }
 
         // Validate Last.fm config if enabled
-        if let Some(lastfm) = &self.lastfm {
+        if let Some(lastfm) = lastfm {
             if lastfm.enabled {
                 if lastfm.api_key.is_empty() {
                     anyhow::bail!("Last.fm api_key is required when Last.fm is enabled");
@@ -199,11 +1290,21 @@ impl Config {
                 if lastfm.api_secret.is_empty() {
                     anyhow::bail!("Last.fm api_secret is required when Last.fm is enabled");
                 }
+                for (name, url) in [
+                    ("api_url", Some(lastfm.api_url.as_str())),
+                    ("auth_url", lastfm.auth_url.as_deref()),
+                ] {
+                    if url.is_some_and(|url| {
+                        !url.starts_with("https://") && !url.starts_with("http://")
+                    }) {
+                        anyhow::bail!("Last.fm {} must be an http:// or https:// URL", name);
+                    }
+                }
             }
         }
 
         // Validate ListenBrainz configs if enabled
-        for lb in &self.listenbrainz {
+        for lb in listenbrainz {
             if lb.enabled {
                 if lb.token.is_empty() {
                     anyhow::bail!(
@@ -214,6 +1315,121 @@ impl Config {
                 if lb.api_url.is_empty() {
                     anyhow::bail!("ListenBrainz api_url is required (instance: {})", lb.name);
                 }
+                if lb.password.is_some() && lb.username.is_none() {
+                    anyhow::bail!(
+                        "ListenBrainz password requires a username (instance: {})",
+                        lb.name
+                    );
+                }
+                if lb.client_cert.is_some() != lb.client_key.is_some() {
+                    anyhow::bail!(
+                        "ListenBrainz client_cert and client_key must be set together (instance: {})",
+                        lb.name
+                    );
+                }
+            }
+        }
+
+        // Validate webhooks
+        let mut webhook_names = HashSet::new();
+        for webhook in &self.webhook {
+            if webhook.name.is_empty() || !webhook_names.insert(webhook.name.as_str()) {
+                anyhow::bail!("Webhook names must be non-empty and unique");
+            }
+            if !webhook.url.starts_with("https://") && !webhook.url.starts_with("http://") {
+                anyhow::bail!(
+                    "Webhook url must be an http:// or https:// URL (webhook: {})",
+                    webhook.name
+                );
+            }
+            crate::scrobbler::webhook::check_body(&webhook.body)
+                .with_context(|| format!("Invalid body for webhook {}", webhook.name))?;
+        }
+
+        if let Some(log) = &self.scrobbler_log {
+            if log.path.as_os_str().is_empty() {
+                anyhow::bail!("Scrobbler log path must not be empty");
+            }
+        }
+
+        // Validate failover groups
+        let service_keys: Vec<String> = self
+            .lastfm
+            .iter()
+            .map(|_| "lastfm".to_string())
+            .chain(
+                self.listenbrainz
+                    .iter()
+                    .map(|lb| format!("listenbrainz:{}", lb.name)),
+            )
+            .collect();
+        for group in &self.failover {
+            for key in [&group.primary, &group.backup] {
+                if !service_keys.contains(key) {
+                    anyhow::bail!("Failover service '{}' is not configured", key);
+                }
+            }
+            if group.primary == group.backup {
+                anyhow::bail!(
+                    "Failover primary and backup must differ ({})",
+                    group.primary
+                );
+            }
+            if group.after_minutes == 0 {
+                anyhow::bail!("Failover after_minutes must be greater than 0");
+            }
+        }
+
+        if self
+            .artist_aliases
+            .overrides
+            .iter()
+            .any(|(from, to)| from.trim().is_empty() || to.trim().is_empty())
+        {
+            anyhow::bail!("Artist alias overrides must map a non-empty name to a non-empty name");
+        }
+
+        // Validate routes (their services can also be in a profile)
+        crate::routing::Routes::new(&self.routes)?;
+        let profile_keys: Vec<String> = self
+            .profiles
+            .iter()
+            .flat_map(|profile| {
+                profile
+                    .lastfm
+                    .iter()
+                    .map(|_| "lastfm".to_string())
+                    .chain(
+                        profile
+                            .listenbrainz
+                            .iter()
+                            .map(|lb| format!("listenbrainz:{}", lb.name)),
+                    )
+                    .map(|key| format!("{}/{}", profile.name, key))
+            })
+            .collect();
+        for route in &self.routes {
+            for filter in &route.services {
+                if !service_keys
+                    .iter()
+                    .chain(&profile_keys)
+                    .any(|key| crate::history::service_matches(key, filter))
+                {
+                    anyhow::bail!("Route service '{}' is not configured", filter);
+                }
+            }
+        }
+
+        // Validate mirror mode
+        if let Some(mirror) = &self.mirror {
+            if !service_keys.contains(&mirror.canonical) {
+                anyhow::bail!(
+                    "Mirror canonical service '{}' is not configured",
+                    mirror.canonical
+                );
+            }
+            if mirror.lookback_hours == 0 {
+                anyhow::bail!("Mirror lookback_hours must be greater than 0");
             }
         }
 
@@ -0,0 +1,450 @@
+// Control API module
+// JSON-RPC 2.0 over a local Unix socket, for tools like Raycast or Hammerspoon
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Version of the request and response schema, bumped on incompatible changes
+pub const API_VERSION: u32 = 1;
+
+/// How long a client waits for the engine to get to its request
+const REPLY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Subscribers that don't take an event within this time are dropped
+const EVENT_WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long shutting down waits for answers still being written, e.g. to `quit` itself
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_ERROR: i64 = -32000;
+
+/// Something a client asked the engine to do
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Status,
+    /// Stop submitting; scrobbles are kept in the history as private until resumed
+    Pause,
+    Resume,
+    Scrobble(ManualScrobble),
+    /// Scrobble the current track without waiting for the threshold
+    ScrobbleNow,
+    /// Don't scrobble the current play of the track
+    SkipScrobble,
+    /// Forget a track the player is stuck on
+    ClearNowPlaying,
+    /// Record plays as guest listens, never submitted, until ended or expired
+    StartGuestMode,
+    EndGuestMode,
+    /// Use a profile's credentials (the default ones when None)
+    SwitchProfile(Option<String>),
+    ReloadConfig,
+    /// How often each cleanup pattern has changed a text
+    CleanupStats,
+    /// Shut the app down, once the request is answered
+    Quit,
+}
+
+/// Parameters of the `scrobble` method
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ManualScrobble {
+    pub artist: String,
+    pub title: String,
+    #[serde(default)]
+    pub album: Option<String>,
+    #[serde(default)]
+    pub duration: Option<u64>,
+    /// When the track was played; now if not given
+    #[serde(default)]
+    pub played_at: Option<DateTime<Utc>>,
+}
+
+/// Parameters of the `switch_profile` method
+#[derive(Deserialize)]
+struct SwitchProfileParams {
+    #[serde(default)]
+    name: Option<String>,
+}
+
+/// A parsed request: either for the engine, or a subscription handled by the connection itself
+#[derive(Debug, PartialEq)]
+enum Request {
+    Command(Command),
+    Subscribe,
+}
+
+/// A command waiting for the engine, with the client's connection waiting on the answer
+pub struct PendingCommand {
+    pub command: Command,
+    reply: Sender<Result<Value, String>>,
+}
+
+impl PendingCommand {
+    /// Send the result back to the client
+    pub fn reply(self, result: Result<Value>) {
+        let _ = self.reply.send(result.map_err(|e| format!("{:#}", e)));
+    }
+}
+
+type Waker = Arc<Mutex<Option<Box<dyn Fn() + Send>>>>;
+type Subscribers = Arc<Mutex<Vec<UnixStream>>>;
+
+/// Listens on the control socket, hands commands over to the engine and pushes events to
+/// subscribed connections
+pub struct ControlServer {
+    path: PathBuf,
+    commands: Receiver<PendingCommand>,
+    waker: Waker,
+    subscribers: Subscribers,
+    /// Commands handed to the engine whose answer isn't written back yet
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl ControlServer {
+    /// Path of the control socket
+    pub fn socket_path() -> Result<PathBuf> {
+        Ok(crate::paths::Paths::current()?.socket)
+    }
+
+    /// Start listening on the control socket
+    pub fn start() -> Result<Self> {
+        let path = Self::socket_path()?;
+
+        // A socket left behind by an earlier run would make binding fail
+        if UnixStream::connect(&path).is_ok() {
+            anyhow::bail!("Another instance is listening on {}", path.display());
+        }
+        let _ = std::fs::remove_file(&path);
+
+        let listener = UnixListener::bind(&path)
+            .with_context(|| format!("Failed to listen on {}", path.display()))?;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))
+            .context("Failed to restrict control socket permissions")?;
+
+        let (sender, commands) = mpsc::channel();
+        let waker: Waker = Arc::default();
+        let subscribers: Subscribers = Arc::default();
+        let in_flight: Arc<AtomicUsize> = Arc::default();
+
+        let thread_waker = waker.clone();
+        let thread_subscribers = subscribers.clone();
+        let thread_in_flight = in_flight.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let sender = sender.clone();
+                let waker = thread_waker.clone();
+                let subscribers = thread_subscribers.clone();
+                let in_flight = thread_in_flight.clone();
+                std::thread::spawn(move || {
+                    serve(stream, &sender, &waker, &subscribers, &in_flight)
+                });
+            }
+        });
+
+        log::info!("Control API listening on {}", path.display());
+        Ok(Self {
+            path,
+            commands,
+            waker,
+            subscribers,
+            in_flight,
+        })
+    }
+
+    /// Finish writing the answers to handled commands and remove the socket, before quitting
+    pub fn shutdown(&self) {
+        let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+        while self.in_flight.load(Ordering::SeqCst) > 0 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let _ = std::fs::remove_file(&self.path);
+    }
+
+    /// Call `waker` whenever a command arrives, so the frontend can handle it right away
+    /// instead of on the next poll
+    pub fn set_waker(&self, waker: impl Fn() + Send + 'static) {
+        *self.waker.lock().unwrap() = Some(Box::new(waker));
+    }
+
+    /// Commands received since the last call
+    pub fn pending(&self) -> Vec<PendingCommand> {
+        self.commands.try_iter().collect()
+    }
+
+    /// Send an event to every subscribed connection, as a JSON-RPC notification
+    pub fn notify(&self, event: &str, params: Value) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        if subscribers.is_empty() {
+            return;
+        }
+
+        let line = json!({"jsonrpc": "2.0", "method": event, "params": params});
+        subscribers.retain_mut(|stream| write_line(stream, &line).is_ok());
+    }
+}
+
+/// Call a method of the running app's control API, returning its result
+pub fn call(method: &str) -> Result<Value> {
+    let path = ControlServer::socket_path()?;
+    let mut stream = UnixStream::connect(&path).with_context(|| {
+        format!(
+            "Failed to connect to {}; is OSX Scrobbler running with the control API enabled?",
+            path.display()
+        )
+    })?;
+    stream
+        .set_read_timeout(Some(REPLY_TIMEOUT))
+        .context("Failed to set control socket timeout")?;
+
+    let request = json!({"jsonrpc": "2.0", "id": 1, "method": method});
+    write_line(&mut stream, &request).context("Failed to send control API request")?;
+
+    let mut line = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut line)
+        .context("Failed to read control API response")?;
+    let response: Value = serde_json::from_str(&line).context("Invalid control API response")?;
+
+    if let Some(error) = response.get("error") {
+        anyhow::bail!("{}", error["message"].as_str().unwrap_or("Unknown error"));
+    }
+    Ok(response["result"].clone())
+}
+
+/// Answer requests on one connection, one JSON object per line
+fn serve(
+    stream: UnixStream,
+    commands: &Sender<PendingCommand>,
+    waker: &Waker,
+    subscribers: &Subscribers,
+    in_flight: &AtomicUsize,
+) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(e) => {
+            log::warn!("Failed to set up control connection: {}", e);
+            return;
+        }
+    };
+
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else {
+            return;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match parse_request(&line) {
+            Ok((id, Request::Subscribe)) => match subscribe(&writer, subscribers) {
+                Ok(()) => json!({"jsonrpc": "2.0", "id": id, "result": {"subscribed": true}}),
+                Err(e) => error_response(id, SERVER_ERROR, &format!("{:#}", e)),
+            },
+            Ok((id, Request::Command(command))) => {
+                let (reply, result) = mpsc::channel();
+                in_flight.fetch_add(1, Ordering::SeqCst);
+                if commands.send(PendingCommand { command, reply }).is_err() {
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    return;
+                }
+                if let Some(wake) = waker.lock().unwrap().as_ref() {
+                    wake();
+                }
+
+                let response = match result.recv_timeout(REPLY_TIMEOUT) {
+                    Ok(Ok(result)) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+                    Ok(Err(message)) => error_response(id, SERVER_ERROR, &message),
+                    Err(_) => error_response(id, SERVER_ERROR, "Timed out waiting for the app"),
+                };
+                // Counted until written, so shutting down doesn't cut the answer off
+                let written = write_line(&mut writer, &response);
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                if written.is_err() {
+                    return;
+                }
+                continue;
+            }
+            Err((id, code, message)) => error_response(id, code, &message),
+        };
+
+        if write_line(&mut writer, &response).is_err() {
+            return;
+        }
+    }
+}
+
+/// Write a message in a single write, so events and responses on a connection don't interleave
+fn write_line(stream: &mut UnixStream, message: &Value) -> std::io::Result<()> {
+    stream.write_all(format!("{}\n", message).as_bytes())
+}
+
+/// Add a connection to the event subscribers
+fn subscribe(stream: &UnixStream, subscribers: &Subscribers) -> Result<()> {
+    let stream = stream
+        .try_clone()
+        .context("Failed to set up event subscription")?;
+    stream
+        .set_write_timeout(Some(EVENT_WRITE_TIMEOUT))
+        .context("Failed to set up event subscription")?;
+    subscribers.lock().unwrap().push(stream);
+    Ok(())
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}})
+}
+
+/// Parse a JSON-RPC request into its ID and what it asks for
+fn parse_request(line: &str) -> Result<(Value, Request), (Value, i64, String)> {
+    let request: Value =
+        serde_json::from_str(line).map_err(|e| (Value::Null, PARSE_ERROR, e.to_string()))?;
+
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let invalid = |message: &str| (id.clone(), INVALID_REQUEST, message.to_string());
+
+    if request.get("jsonrpc").and_then(Value::as_str) != Some("2.0") {
+        return Err(invalid("jsonrpc must be \"2.0\""));
+    }
+    let method = request
+        .get("method")
+        .and_then(Value::as_str)
+        .ok_or_else(|| invalid("method is missing"))?;
+
+    let command = match method {
+        "subscribe" => return Ok((id, Request::Subscribe)),
+        "status" => Command::Status,
+        "pause" => Command::Pause,
+        "resume" => Command::Resume,
+        "scrobble_now" => Command::ScrobbleNow,
+        "skip_scrobble" => Command::SkipScrobble,
+        "clear_now_playing" => Command::ClearNowPlaying,
+        "start_guest_mode" => Command::StartGuestMode,
+        "end_guest_mode" => Command::EndGuestMode,
+        "reload_config" => Command::ReloadConfig,
+        "cleanup_stats" => Command::CleanupStats,
+        "quit" => Command::Quit,
+        "scrobble" => {
+            let params = request.get("params").cloned().unwrap_or(Value::Null);
+            let scrobble: ManualScrobble = serde_json::from_value(params)
+                .map_err(|e| (id.clone(), INVALID_PARAMS, e.to_string()))?;
+            Command::Scrobble(scrobble)
+        }
+        "switch_profile" => {
+            let params = request.get("params").cloned().unwrap_or(json!({}));
+            let params: SwitchProfileParams = serde_json::from_value(params)
+                .map_err(|e| (id.clone(), INVALID_PARAMS, e.to_string()))?;
+            Command::SwitchProfile(params.name)
+        }
+        other => {
+            return Err((
+                id.clone(),
+                METHOD_NOT_FOUND,
+                format!("Unknown method: {}", other),
+            ))
+        }
+    };
+
+    Ok((id, Request::Command(command)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_simple_methods() {
+        assert_eq!(
+            parse_request(r#"{"jsonrpc": "2.0", "id": 1, "method": "status"}"#),
+            Ok((json!(1), Request::Command(Command::Status)))
+        );
+        assert_eq!(
+            parse_request(r#"{"jsonrpc": "2.0", "id": "a", "method": "pause"}"#),
+            Ok((json!("a"), Request::Command(Command::Pause)))
+        );
+        assert_eq!(
+            parse_request(r#"{"jsonrpc": "2.0", "id": 2, "method": "scrobble_now"}"#),
+            Ok((json!(2), Request::Command(Command::ScrobbleNow)))
+        );
+        assert_eq!(
+            parse_request(r#"{"jsonrpc": "2.0", "id": 2, "method": "skip_scrobble"}"#),
+            Ok((json!(2), Request::Command(Command::SkipScrobble)))
+        );
+        assert_eq!(
+            parse_request(r#"{"jsonrpc": "2.0", "id": 2, "method": "clear_now_playing"}"#),
+            Ok((json!(2), Request::Command(Command::ClearNowPlaying)))
+        );
+        assert_eq!(
+            parse_request(r#"{"jsonrpc": "2.0", "id": 2, "method": "quit"}"#),
+            Ok((json!(2), Request::Command(Command::Quit)))
+        );
+        assert_eq!(
+            parse_request(r#"{"jsonrpc": "2.0", "id": 2, "method": "cleanup_stats"}"#),
+            Ok((json!(2), Request::Command(Command::CleanupStats)))
+        );
+        assert_eq!(
+            parse_request(r#"{"jsonrpc": "2.0", "id": 3, "method": "subscribe"}"#),
+            Ok((json!(3), Request::Subscribe))
+        );
+        assert_eq!(
+            parse_request(
+                r#"{"jsonrpc": "2.0", "id": 4, "method": "switch_profile", "params": {"name": "Work"}}"#
+            ),
+            Ok((
+                json!(4),
+                Request::Command(Command::SwitchProfile(Some("Work".to_string())))
+            ))
+        );
+        assert_eq!(
+            parse_request(r#"{"jsonrpc": "2.0", "id": 5, "method": "switch_profile"}"#),
+            Ok((json!(5), Request::Command(Command::SwitchProfile(None))))
+        );
+    }
+
+    #[test]
+    fn test_parse_scrobble() {
+        let request = r#"{"jsonrpc": "2.0", "id": 2, "method": "scrobble",
+            "params": {"artist": "Band", "title": "Song", "played_at": "2026-01-01T12:00:00Z"}}"#;
+
+        let (_, command) = parse_request(&request.replace('\n', " ")).unwrap();
+        assert_eq!(
+            command,
+            Request::Command(Command::Scrobble(ManualScrobble {
+                artist: "Band".to_string(),
+                title: "Song".to_string(),
+                album: None,
+                duration: None,
+                played_at: Some("2026-01-01T12:00:00Z".parse().unwrap()),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let code = |line: &str| parse_request(line).unwrap_err().1;
+
+        assert_eq!(code("not json"), PARSE_ERROR);
+        assert_eq!(code(r#"{"id": 1, "method": "status"}"#), INVALID_REQUEST);
+        assert_eq!(
+            code(r#"{"jsonrpc": "2.0", "id": 1, "method": "stop"}"#),
+            METHOD_NOT_FOUND
+        );
+        assert_eq!(
+            code(r#"{"jsonrpc": "2.0", "id": 1, "method": "scrobble", "params": {"artist": "A"}}"#),
+            INVALID_PARAMS
+        );
+    }
+}
@@ -0,0 +1,116 @@
+// Metadata corrections module
+// Rewrites artist/title pairs the user has corrected before (e.g. via "Edit Last Scrobble")
+
+use crate::config::CorrectionRule;
+use crate::scrobbler::Track;
+
+pub struct Corrections {
+    rules: Vec<CorrectionRule>,
+}
+
+impl Corrections {
+    pub fn new(rules: &[CorrectionRule]) -> Self {
+        Self {
+            rules: rules.to_vec(),
+        }
+    }
+
+    /// Apply the first matching rule to a track, returning whether it was changed
+    ///
+    /// Rules match the (already cleaned up) artist and title, ignoring case.
+    pub fn apply(&self, track: &mut Track) -> bool {
+        let Some(rule) = self.rules.iter().find(|r| r.matches(track)) else {
+            return false;
+        };
+
+        log::debug!(
+            "Correcting {} - {} to {} - {}",
+            track.artist,
+            track.title,
+            rule.corrected_artist,
+            rule.corrected_title
+        );
+        track.artist = rule.corrected_artist.clone();
+        track.title = rule.corrected_title.clone();
+        true
+    }
+}
+
+impl CorrectionRule {
+    fn matches(&self, track: &Track) -> bool {
+        self.artist.eq_ignore_ascii_case(&track.artist)
+            && self.title.eq_ignore_ascii_case(&track.title)
+    }
+}
+
+/// Add a rule correcting `from` to `to`, replacing any rule for the same track
+///
+/// Existing rules that produced `from` are pointed at `to` as well, so corrections don't chain.
+pub fn add_rule(rules: &mut Vec<CorrectionRule>, from: &Track, to: &Track) {
+    let rule = CorrectionRule {
+        artist: from.artist.clone(),
+        title: from.title.clone(),
+        corrected_artist: to.artist.clone(),
+        corrected_title: to.title.clone(),
+    };
+
+    for existing in rules.iter_mut() {
+        if existing.corrected_artist == from.artist && existing.corrected_title == from.title {
+            existing.corrected_artist = to.artist.clone();
+            existing.corrected_title = to.title.clone();
+        }
+    }
+
+    rules.retain(|r| !r.matches(from));
+    rules.push(rule);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(artist: &str, title: &str) -> Track {
+        Track {
+            title: title.to_string(),
+            artist: artist.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_apply_matches_ignoring_case() {
+        let mut rules = Vec::new();
+        add_rule(
+            &mut rules,
+            &track("Beatles", "Help"),
+            &track("The Beatles", "Help!"),
+        );
+        let corrections = Corrections::new(&rules);
+
+        let mut played = track("beatles", "HELP");
+        assert!(corrections.apply(&mut played));
+        assert_eq!(played, track("The Beatles", "Help!"));
+
+        let mut other = track("Beatles", "Yesterday");
+        assert!(!corrections.apply(&mut other));
+    }
+
+    #[test]
+    fn test_add_rule_replaces_and_collapses_chains() {
+        let mut rules = Vec::new();
+        add_rule(&mut rules, &track("A", "Song"), &track("B", "Song"));
+        add_rule(&mut rules, &track("B", "Song"), &track("C", "Song"));
+        add_rule(&mut rules, &track("A", "Song"), &track("C", "Song (Live)"));
+
+        assert_eq!(rules.len(), 2);
+        let corrections = Corrections::new(&rules);
+
+        let mut played = track("A", "Song");
+        corrections.apply(&mut played);
+        assert_eq!(played, track("C", "Song (Live)"));
+
+        let mut played = track("B", "Song");
+        corrections.apply(&mut played);
+        assert_eq!(played, track("C", "Song"));
+    }
+}
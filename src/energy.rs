@@ -0,0 +1,196 @@
+// Energy module
+// Counts what wakes the app up and what it does then, for `--profile-energy`
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static STARTED: OnceLock<Instant> = OnceLock::new();
+static COUNTS: [AtomicU64; Activity::ALL.len()] =
+    [const { AtomicU64::new(0) }; Activity::ALL.len()];
+/// When the last report went to the log
+static LAST_LOGGED: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Something that costs energy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Activity {
+    /// The event loop woke up, for any reason
+    Wakeup,
+    /// The player was polled
+    Poll,
+    /// An AppleScript ran through `osascript` (Music app details, browser tabs)
+    Script,
+    /// An HTTP request went out
+    Network,
+    /// The menu bar menu was changed
+    TrayUpdate,
+}
+
+impl Activity {
+    pub const ALL: [Activity; 5] = [
+        Self::Wakeup,
+        Self::Poll,
+        Self::Script,
+        Self::Network,
+        Self::TrayUpdate,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Wakeup => "Wakeups",
+            Self::Poll => "Polls",
+            Self::Script => "osascript runs",
+            Self::Network => "Network requests",
+            Self::TrayUpdate => "Menu updates",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Start counting; until then [`record`] does nothing
+pub fn enable() {
+    STARTED.get_or_init(Instant::now);
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Count an activity, if profiling is on
+pub fn record(activity: Activity) {
+    if is_enabled() {
+        COUNTS[activity.index()].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Counts so far
+pub fn report() -> Report {
+    Report {
+        elapsed: STARTED.get().map_or(Duration::ZERO, Instant::elapsed),
+        counts: Activity::ALL.map(|a| COUNTS[a.index()].load(Ordering::Relaxed)),
+    }
+}
+
+/// Log the report once an hour while profiling
+pub fn log_hourly() {
+    if !is_enabled() {
+        return;
+    }
+    let Ok(mut last) = LAST_LOGGED.lock() else {
+        return;
+    };
+    let since = last.or(STARTED.get().copied()).unwrap_or_else(Instant::now);
+    if since.elapsed() >= Duration::from_secs(3600) {
+        *last = Some(Instant::now());
+        log::info!("Energy profile:\n{}", report());
+    }
+}
+
+/// What happened since profiling started
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub elapsed: Duration,
+    /// In the order of [`Activity::ALL`]
+    pub counts: [u64; Activity::ALL.len()],
+}
+
+impl Report {
+    pub fn count(&self, activity: Activity) -> u64 {
+        self.counts[activity.index()]
+    }
+
+    /// Average per hour, over at least a minute so a short run doesn't blow it up
+    pub fn per_hour(&self, activity: Activity) -> f64 {
+        let hours = self.elapsed.max(Duration::from_secs(60)).as_secs_f64() / 3600.0;
+        self.count(activity) as f64 / hours
+    }
+
+    /// Settings worth changing, given what was counted
+    pub fn suggestions(&self) -> Vec<&'static str> {
+        let mut suggestions = Vec::new();
+        let polls = self.per_hour(Activity::Poll);
+
+        if self.per_hour(Activity::Wakeup) > polls * 3.0 {
+            suggestions.push(
+                "Most wakeups aren't polls: check for control API clients sending requests \
+                 in a loop",
+            );
+        }
+        if polls > 1200.0 {
+            suggestions
+                .push("Polling more than every 3 seconds: raise refresh_interval to 5 or more");
+        }
+        if self.per_hour(Activity::Script) > 60.0 {
+            suggestions.push(
+                "AppleScript runs often: turn off browser.capture_url or apple_music settings \
+                 you don't need",
+            );
+        }
+        if self.per_hour(Activity::Network) > 600.0 {
+            suggestions
+                .push("Many network requests: lower scheduler.max_per_flush or turn off artwork");
+        }
+
+        suggestions
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let minutes = self.elapsed.as_secs() / 60;
+        writeln!(f, "Profiled for {}h {:02}m", minutes / 60, minutes % 60)?;
+        for activity in Activity::ALL {
+            writeln!(
+                f,
+                "  {:<18} {:>8}  ({:.0}/hour)",
+                activity.label(),
+                self.count(activity),
+                self.per_hour(activity)
+            )?;
+        }
+        for suggestion in self.suggestions() {
+            writeln!(f, "  - {}", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(hours: u64, counts: [u64; 5]) -> Report {
+        Report {
+            elapsed: Duration::from_secs(hours * 3600),
+            counts,
+        }
+    }
+
+    #[test]
+    fn test_per_hour() {
+        let two_hours = report(2, [1440, 1440, 10, 40, 0]);
+        assert_eq!(two_hours.per_hour(Activity::Poll), 720.0);
+        assert_eq!(two_hours.per_hour(Activity::Network), 20.0);
+        assert!(two_hours.suggestions().is_empty());
+
+        // Short runs count as a minute
+        let short = Report {
+            elapsed: Duration::from_secs(1),
+            counts: [0, 1, 0, 0, 0],
+        };
+        assert_eq!(short.per_hour(Activity::Poll), 60.0);
+    }
+
+    #[test]
+    fn test_suggestions() {
+        let busy = report(1, [10_000, 1440, 200, 1000, 0]);
+        assert_eq!(busy.suggestions().len(), 4);
+        assert!(busy.to_string().contains("Network requests"));
+    }
+}
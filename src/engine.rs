@@ -0,0 +1,1640 @@
+// Engine module
+// Builds the scrobbling services from the config and keeps the history's queued submissions moving
+
+use crate::app_lock::AppLock;
+#[cfg(feature = "artwork")]
+use crate::artwork::ArtworkCache;
+use crate::config::{Config, TimestampPolicy};
+use crate::control::{self, Command, ControlServer, ManualScrobble};
+use crate::energy::{self, Activity};
+use crate::history::{History, HistoryEntry};
+use crate::media_monitor::{MediaEvent, MediaMonitor, SessionProgress};
+use crate::routing::Routes;
+use crate::scrobbler::{self, Scrobbler, Track};
+use crate::session_check::SessionChecks;
+use crate::startup::StartupGate;
+#[cfg(feature = "web")]
+use crate::web::{self, WebServer};
+use crate::{
+    config, corrections, failover, field_mapping, history, http, rate_limit, reconcile, scheduler,
+};
+use anyhow::{Context, Result};
+use backoff::{retry, ExponentialBackoff};
+use chrono::{DateTime, Local, Utc};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Name of each service a track was loved on, and how it went
+pub type LoveResults = Vec<(String, Result<()>)>;
+
+/// What a poll changed, for frontends to show
+#[derive(Debug, Default)]
+pub struct PollUpdate {
+    pub now_playing: Option<Track>,
+    /// The track shown as playing was cleared (see [`Engine::clear_now_playing`])
+    pub now_playing_cleared: bool,
+    pub scrobbled: Option<Track>,
+    /// App that isn't allowed or ignored yet; answer with [`Engine::set_app_allowed`]
+    pub unknown_app: Option<String>,
+    /// Services that just started rejecting their credentials and need to be signed in again
+    pub auth_expired: Vec<String>,
+    /// Services the scrobble couldn't be submitted to, with the error (queued to retry)
+    pub failed: Vec<(String, String)>,
+}
+
+/// Monitors playback and submits now playing updates and scrobbles to every service
+///
+/// Frontends call [`Engine::poll`] every `refresh_interval` seconds and display the result.
+pub struct Engine {
+    config: Config,
+    scrobblers: Vec<Box<dyn Scrobbler>>,
+    history: Option<History>,
+    media_monitor: MediaMonitor,
+    /// Scheduler for queued (failed or backfilled) submissions
+    submission_scheduler: scheduler::SubmissionScheduler,
+    failover_groups: Vec<failover::FailoverGroup>,
+    /// Which services plays go to, by app and time of day
+    routes: Routes,
+    /// History entries of the play sessions, by app, updated with listening time when they end
+    entry_ids: HashMap<Option<String>, u64>,
+    #[cfg(feature = "web")]
+    web: Option<WebServer>,
+    control: Option<ControlServer>,
+    /// Tells CLI commands that change the history that the app is running
+    _lock: Option<AppLock>,
+    #[cfg(feature = "artwork")]
+    artwork: Option<ArtworkCache>,
+    /// Keys of services whose credentials were rejected, reported once until they work again
+    auth_expired: HashSet<String>,
+    /// Latest credential check of each service
+    session_checks: SessionChecks,
+    /// What happened between polls (e.g. [`Engine::scrobble_now`]), reported by the next poll
+    pending_update: PollUpdate,
+    /// Private session: scrobbles are only recorded, as private, until resumed
+    paused: bool,
+    /// When a timed pause ends
+    paused_until: Option<DateTime<Utc>>,
+    /// Someone else is listening: plays are recorded as guest listens and never submitted
+    guest_mode: bool,
+    /// When guest mode turns itself off
+    guest_mode_expires: Option<DateTime<Utc>>,
+    /// Holds off polling after a login launch until the system is ready
+    startup: Option<StartupGate>,
+    /// A control API client asked the app to quit
+    quit_requested: bool,
+    /// Apps already warned about for reporting tracks that look swapped
+    swap_warned: HashSet<String>,
+}
+
+impl Engine {
+    pub fn new(config: Config) -> Self {
+        warn_missing_features(&config);
+        http::set_log_payloads(config.debug.log_payloads);
+
+        let lock = AppLock::acquire()
+            .map_err(|e| log::error!("Failed to lock the app's files: {:#}", e))
+            .ok();
+
+        let control = if config.control.enabled {
+            ControlServer::start()
+                .map_err(|e| log::error!("Failed to start the control API: {:#}", e))
+                .ok()
+        } else {
+            None
+        };
+
+        let scrobblers = build_scrobblers(&config);
+        if scrobblers.is_empty() {
+            log::warn!(
+                "No scrobblers enabled! The app will monitor media but won't scrobble anywhere."
+            );
+        }
+
+        let history = match History::load() {
+            Ok(history) => Some(history),
+            Err(e) => {
+                log::error!("Failed to load scrobble history: {}", e);
+                None
+            }
+        };
+
+        if config.cleanup.enabled {
+            log::info!(
+                "Text cleanup enabled with {} patterns",
+                config.cleanup.patterns.len()
+            );
+        }
+
+        #[allow(unused_mut)]
+        let mut engine = Self {
+            scrobblers,
+            history,
+            media_monitor: MediaMonitor::new(&config),
+            submission_scheduler: scheduler::SubmissionScheduler::new(&config.scheduler),
+            failover_groups: config
+                .failover
+                .iter()
+                .map(failover::FailoverGroup::new)
+                .collect(),
+            routes: build_routes(&config),
+            entry_ids: HashMap::new(),
+            #[cfg(feature = "web")]
+            web: None,
+            control,
+            _lock: lock,
+            #[cfg(feature = "artwork")]
+            artwork: build_artwork(&config),
+            auth_expired: HashSet::new(),
+            session_checks: SessionChecks::load(),
+            pending_update: PollUpdate::default(),
+            paused: false,
+            paused_until: None,
+            guest_mode: false,
+            guest_mode_expires: None,
+            startup: StartupGate::new(&config),
+            quit_requested: false,
+            swap_warned: HashSet::new(),
+            config,
+        };
+
+        #[cfg(feature = "web")]
+        if engine.config.web.enabled {
+            engine.web = start_web(&mut engine.config)
+                .map_err(|e| log::error!("Failed to start the web UI: {:#}", e))
+                .ok();
+        }
+
+        engine
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Scrobble history, unless it failed to load
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    /// Progress of the track being played, if any
+    pub fn session(&self) -> Option<SessionProgress> {
+        self.media_monitor.current_session()
+    }
+
+    /// Cached album art of the track being played, if it's been downloaded
+    pub fn artwork(&self) -> Option<PathBuf> {
+        let session = self.media_monitor.current_session()?;
+        self.cached_artwork(&session.track)
+    }
+
+    #[cfg(feature = "artwork")]
+    fn cached_artwork(&self, track: &Track) -> Option<PathBuf> {
+        self.artwork.as_ref()?.cached(track)
+    }
+
+    #[cfg(not(feature = "artwork"))]
+    fn cached_artwork(&self, _track: &Track) -> Option<PathBuf> {
+        None
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// When a timed pause ends, if it does
+    pub fn paused_until(&self) -> Option<DateTime<Utc>> {
+        self.paused_until
+    }
+
+    /// Start or end a private session
+    pub fn set_paused(&mut self, paused: bool) {
+        if paused {
+            self.pause_until(None);
+            return;
+        }
+        if self.paused {
+            log::info!("Scrobbling resumed");
+            self.notify("paused", json!({ "paused": false, "until": null }));
+        }
+        self.paused = false;
+        self.paused_until = None;
+    }
+
+    /// Start a private session that ends on its own at `until`, or lasts until resumed
+    pub fn pause_until(&mut self, until: Option<DateTime<Utc>>) {
+        if !self.paused || until != self.paused_until {
+            match until {
+                Some(until) => log::info!(
+                    "Scrobbling paused until {}",
+                    until.with_timezone(&Local).format("%H:%M")
+                ),
+                None => log::info!("Scrobbling paused"),
+            }
+            self.notify("paused", json!({ "paused": true, "until": until }));
+        }
+        self.paused = true;
+        self.paused_until = until;
+    }
+
+    pub fn is_guest_mode(&self) -> bool {
+        self.guest_mode
+    }
+
+    /// When guest mode turns itself off, if it does
+    pub fn guest_mode_expires(&self) -> Option<DateTime<Utc>> {
+        self.guest_mode_expires
+    }
+
+    /// Turn guest mode on, for `[guest_mode] expire_after_hours`, or off
+    pub fn set_guest_mode(&mut self, on: bool) {
+        let hours = self.config.guest_mode.expire_after_hours;
+        let expires = (on && hours > 0).then(|| Utc::now() + chrono::Duration::hours(hours as i64));
+        if on != self.guest_mode {
+            log::info!("Guest mode {}", if on { "on" } else { "off" });
+            self.notify(
+                "guest_mode",
+                json!({ "guest_mode": on, "expires": expires }),
+            );
+        }
+        self.guest_mode = on;
+        self.guest_mode_expires = expires;
+    }
+
+    /// Name of the credential profile in use (None for the default credentials)
+    pub fn active_profile(&self) -> Option<&str> {
+        self.config.active_profile.as_deref()
+    }
+
+    /// Switch to a profile's credentials, or back to the default ones, and save the choice
+    ///
+    /// The outgoing profile's queue is flushed first; its failed submissions stay queued for
+    /// when it's active again.
+    pub fn switch_profile(&mut self, name: Option<&str>) -> Result<()> {
+        if name == self.active_profile() {
+            return Ok(());
+        }
+
+        let mut config = self.config.clone();
+        config.active_profile = name.map(str::to_string);
+        config.validate()?;
+
+        if !self.paused {
+            if let Some(history) = self.history.as_mut() {
+                flush_queued_submissions(&self.submission_scheduler, history, &self.scrobblers);
+            }
+        }
+
+        config.save()?;
+        log::info!("Switched to profile {}", name.unwrap_or("Default"));
+
+        self.scrobblers = build_scrobblers(&config);
+        self.auth_expired.clear();
+        self.config = config;
+        self.notify("profile", json!({ "profile": name }));
+
+        Ok(())
+    }
+
+    /// Whether plays are submitted, rather than only recorded
+    fn submitting(&self) -> bool {
+        !self.paused && !self.guest_mode
+    }
+
+    /// Love a track on every service that can, returning each one's name and result
+    pub fn love(&self, track: &Track) -> LoveResults {
+        self.scrobblers
+            .iter()
+            .filter(|scrobbler| scrobbler.can_love())
+            .map(|scrobbler| {
+                let result = scrobbler.love(track);
+                if let Err(e) = &result {
+                    log::error!("{:#}", e);
+                }
+                (scrobbler.display_name(), result)
+            })
+            .collect()
+    }
+
+    /// Love the track being played on every service that can
+    pub fn love_current(&self) -> Result<(Track, LoveResults)> {
+        let track = self.session().context("Nothing is playing")?.track;
+        let results = self.love(&track);
+        if results.is_empty() {
+            anyhow::bail!("None of the enabled services can love tracks");
+        }
+        Ok((track, results))
+    }
+
+    /// Don't scrobble a track that's playing, if it isn't scrobbled yet
+    pub fn skip_scrobble(&mut self, track: &Track) -> bool {
+        let skipped = self.media_monitor.skip_scrobble(track);
+        if skipped {
+            log::info!("Not scrobbling {} - {}", track.artist, track.title);
+        }
+        skipped
+    }
+
+    /// Don't scrobble this play of the current track, without adding a rule for it
+    pub fn skip_current(&mut self) -> Result<Track> {
+        let track = self.session().context("Nothing is playing")?.track;
+        if !self.skip_scrobble(&track) {
+            anyhow::bail!("{} - {} is already scrobbled", track.artist, track.title);
+        }
+        Ok(track)
+    }
+
+    /// Forget a track the player keeps reporting after it's gone, e.g. when the app crashed
+    ///
+    /// The track is ignored until the player reports something else. Its listening time isn't
+    /// recorded, as a stuck player reports it as playing all along. The services' now playing
+    /// status expires on its own. Frontends see it in the next [`Engine::poll`].
+    pub fn clear_now_playing(&mut self) -> Result<Track> {
+        let (track, bundle_id) = self
+            .media_monitor
+            .clear_now_playing()
+            .context("Nothing is playing")?;
+        self.entry_ids.remove(&bundle_id);
+        self.pending_update.now_playing_cleared = true;
+        self.notify(
+            "now_playing_cleared",
+            json!({ "track": track, "stalled": false }),
+        );
+        Ok(track)
+    }
+
+    /// Push an event to control API subscribers
+    fn notify(&self, event: &str, params: Value) {
+        #[cfg(feature = "web")]
+        if let Some(web) = &self.web {
+            web.notify(event, params.clone());
+        }
+        if let Some(control) = &self.control {
+            control.notify(event, params);
+        }
+    }
+
+    /// Have `waker` called when a control API request arrives, so the frontend can call
+    /// [`Engine::handle_control`] right away instead of waiting for the next poll
+    pub fn set_waker(&self, waker: impl Fn() + Send + 'static) {
+        if let Some(control) = &self.control {
+            control.set_waker(waker);
+        }
+    }
+
+    /// Answer pending control API requests
+    pub fn handle_control(&mut self) {
+        let Some(control) = &self.control else {
+            return;
+        };
+
+        for pending in control.pending() {
+            let result = self.run_command(&pending.command);
+            pending.reply(result);
+        }
+    }
+
+    fn run_command(&mut self, command: &Command) -> Result<Value> {
+        match command {
+            Command::Status => Ok(self.status()),
+            Command::Pause | Command::Resume => {
+                self.set_paused(*command == Command::Pause);
+                Ok(json!({ "paused": self.paused }))
+            }
+            Command::Scrobble(scrobble) => self.manual_scrobble(scrobble),
+            Command::ScrobbleNow => {
+                let track = self.scrobble_now()?;
+                Ok(json!({ "track": track, "private": !self.submitting() }))
+            }
+            Command::StartGuestMode | Command::EndGuestMode => {
+                self.set_guest_mode(*command == Command::StartGuestMode);
+                Ok(json!({ "guest_mode": self.guest_mode, "expires": self.guest_mode_expires }))
+            }
+            Command::SkipScrobble => {
+                let track = self.skip_current()?;
+                Ok(json!({ "track": track }))
+            }
+            Command::ClearNowPlaying => {
+                let track = self.clear_now_playing()?;
+                Ok(json!({ "track": track }))
+            }
+            Command::SwitchProfile(name) => {
+                self.switch_profile(name.as_deref())?;
+                Ok(json!({ "profile": self.active_profile() }))
+            }
+            Command::ReloadConfig => {
+                self.reload_config()?;
+                Ok(json!({}))
+            }
+            Command::CleanupStats => Ok(json!({ "patterns": self.media_monitor.cleanup_stats() })),
+            Command::Quit => {
+                log::info!("Quit requested through the control API");
+                self.quit_requested = true;
+                Ok(json!({}))
+            }
+        }
+    }
+
+    /// Whether a control API client asked the app to quit; the frontend should call
+    /// [`Engine::shutdown`] and exit
+    pub fn quit_requested(&self) -> bool {
+        self.quit_requested
+    }
+
+    /// Finish answering control API requests, remove the socket and save the services' state,
+    /// before the app exits
+    pub fn shutdown(&self) {
+        if let Some(control) = &self.control {
+            control.shutdown();
+        }
+        for scrobbler in &self.scrobblers {
+            scrobbler.save_state();
+        }
+    }
+
+    /// Result of the control API's `status` method
+    fn status(&self) -> Value {
+        let standby = standby_services(&self.failover_groups, self.history.as_ref());
+        let services: Vec<Value> = self
+            .scrobblers
+            .iter()
+            .map(|s| {
+                let key = s.key();
+                let queued = self.history.as_ref().map_or(0, |h| {
+                    h.entries()
+                        .iter()
+                        .filter(|e| !e.private && !e.deleted)
+                        .filter(|e| {
+                            e.submissions
+                                .iter()
+                                .any(|sub| sub.service == key && sub.is_queued())
+                        })
+                        .count()
+                });
+                json!({
+                    "key": key,
+                    "standby": standby.contains(&key),
+                    "queued": queued,
+                    "session": self.session_checks.get(&key),
+                })
+            })
+            .collect();
+
+        json!({
+            "api_version": control::API_VERSION,
+            "version": env!("CARGO_PKG_VERSION"),
+            "paused": self.paused,
+            "paused_until": self.paused_until,
+            "guest_mode": self.guest_mode,
+            "guest_mode_expires": self.guest_mode_expires,
+            "profile": self.active_profile(),
+            "now_playing": self.session(),
+            "artwork": self.artwork(),
+            "services": services,
+        })
+    }
+
+    /// Submit a scrobble given through the control API to the active services
+    fn manual_scrobble(&mut self, scrobble: &ManualScrobble) -> Result<Value> {
+        let history = self
+            .history
+            .as_mut()
+            .context("Scrobble history is unavailable")?;
+
+        let track = Track {
+            title: scrobble.title.clone(),
+            artist: scrobble.artist.clone(),
+            album: scrobble.album.clone(),
+            duration: scrobble.duration,
+            origin_url: None,
+            radio: false,
+            mbid: None,
+            album_artist: None,
+            live: None,
+        };
+        let played_at = scrobble.played_at.unwrap_or_else(chrono::Utc::now);
+        log::info!("Manual scrobble: {} - {}", track.artist, track.title);
+
+        let mut entry = history.new_entry(&track, played_at, None);
+        let standby = standby_services(&self.failover_groups, Some(history));
+        let local_time = played_at.with_timezone(&Local).naive_local();
+        for scrobbler in self.scrobblers.iter().filter(|s| {
+            !standby.contains(&s.key()) && self.routes.allows(&s.key(), None, local_time)
+        }) {
+            let result = scrobble_with_retries(scrobbler.as_ref(), &track, played_at, None);
+            if let Err(ref e) = result {
+                log::error!("Failed to scrobble after retries: {}", e);
+            }
+            entry.set_submission(&scrobbler.key(), &result);
+        }
+
+        let submissions = entry.submissions.clone();
+        let id = history.record(entry)?;
+        let response = json!({ "id": id, "submissions": submissions });
+
+        self.notify(
+            "scrobble",
+            json!({
+                "id": response["id"],
+                "track": track,
+                "played_at": played_at,
+                "bundle_id": null,
+                "private": false,
+                "submissions": response["submissions"],
+            }),
+        );
+        Ok(response)
+    }
+
+    /// How long frontends wait until the next [`Engine::poll`], longer while nothing is playing
+    pub fn poll_interval(&self) -> Duration {
+        let seconds = if self.media_monitor.current_session().is_some() {
+            self.config.refresh_interval
+        } else {
+            self.config
+                .idle_refresh_interval
+                .max(self.config.refresh_interval)
+        };
+        Duration::from_secs(seconds)
+    }
+
+    /// Check the player, submit what it reports, and flush queued submissions when due
+    pub fn poll(&mut self) -> PollUpdate {
+        energy::record(Activity::Poll);
+        self.handle_control();
+
+        if let Some(startup) = &self.startup {
+            if !startup.is_open() {
+                return PollUpdate::default();
+            }
+            self.startup = None;
+        }
+
+        if self
+            .guest_mode_expires
+            .is_some_and(|expires| Utc::now() >= expires)
+        {
+            log::info!("Guest mode expired");
+            self.set_guest_mode(false);
+        }
+        if self.paused_until.is_some_and(|until| Utc::now() >= until) {
+            log::info!("Timed pause is over");
+            self.set_paused(false);
+        }
+
+        let mut update = match self.media_monitor.poll(&self.config.app_filtering) {
+            Ok(events) => self.handle_events(events),
+            Err(e) => {
+                log::error!("Error polling media: {}", e);
+                PollUpdate::default()
+            }
+        };
+        let pending = std::mem::take(&mut self.pending_update);
+        update.scrobbled = update.scrobbled.or(pending.scrobbled);
+        update.now_playing_cleared |= pending.now_playing_cleared && update.now_playing.is_none();
+        update.auth_expired.extend(pending.auth_expired);
+        update.failed.extend(pending.failed);
+
+        // Flush queued submissions once per window, between polls (held back while paused)
+        if !self.paused && self.submission_scheduler.is_due(chrono::Utc::now()) {
+            if let Some(history) = self.history.as_mut() {
+                update_failover_queues(&self.failover_groups, history);
+                if let Some(ref mirror) = self.config.mirror {
+                    mirror_canonical(mirror, history, &self.scrobblers, &self.failover_groups);
+                }
+                flush_queued_submissions(&self.submission_scheduler, history, &self.scrobblers);
+            }
+            self.submission_scheduler.advance();
+        }
+
+        self.verify_due_session(&mut update);
+
+        #[cfg(feature = "web")]
+        if let Some(web) = &self.web {
+            web.publish(self.media_monitor.current_session(), self.artwork());
+            if web.take_config_change() {
+                if let Err(e) = self.reload_config() {
+                    log::error!("Failed to reload config: {:#}", e);
+                }
+            }
+        }
+
+        update
+    }
+
+    /// Apply a config file changed by someone else (the web UI or a control API client)
+    ///
+    /// Services are rebuilt; the web UI and control API keep their settings until restarted.
+    pub fn reload_config(&mut self) -> Result<()> {
+        let config = Config::load()?;
+        log::info!("Reloading config");
+        http::set_log_payloads(config.debug.log_payloads);
+
+        self.scrobblers = build_scrobblers(&config);
+        self.media_monitor.reconfigure(&config);
+        #[cfg(feature = "artwork")]
+        {
+            self.artwork = build_artwork(&config);
+        }
+        warn_missing_features(&config);
+        self.auth_expired.clear();
+        self.submission_scheduler = scheduler::SubmissionScheduler::new(&config.scheduler);
+        self.failover_groups = config
+            .failover
+            .iter()
+            .map(failover::FailoverGroup::new)
+            .collect();
+        self.routes = build_routes(&config);
+        self.config = config;
+
+        Ok(())
+    }
+
+    /// Save an edited config and apply it like [`Engine::reload_config`]
+    pub fn save_config(&mut self, config: Config) -> Result<()> {
+        config.validate()?;
+        config.save()?;
+        self.reload_config()
+    }
+
+    /// Append each poll's player info to `path`, see `osx-scrobbler replay`
+    pub fn record_polls(&mut self, path: &Path) -> Result<()> {
+        self.media_monitor.record_polls(path)
+    }
+
+    fn handle_events(&mut self, events: Vec<MediaEvent>) -> PollUpdate {
+        let mut update = PollUpdate::default();
+
+        // In the order they happened: a scrobble of the session that just ended (a chapter, or
+        // a track that crossed the threshold between polls) comes before its listening time
+        for event in events {
+            match event {
+                MediaEvent::NowPlaying { track, bundle_id } => {
+                    self.handle_now_playing(track, bundle_id, &mut update)
+                }
+                MediaEvent::Scrobble {
+                    track,
+                    timestamp,
+                    bundle_id,
+                    review,
+                } => self.handle_scrobble(track, timestamp, bundle_id, review, &mut update),
+                MediaEvent::UnknownApp(app) => update.unknown_app = Some(app),
+                MediaEvent::Stalled { track, bundle_id } => {
+                    self.handle_stalled(track, bundle_id, &mut update)
+                }
+                MediaEvent::SessionEnded {
+                    track,
+                    listened_seconds,
+                    bundle_id,
+                } => self.handle_session_ended(track, listened_seconds, bundle_id),
+            }
+        }
+
+        update
+    }
+
+    fn handle_now_playing(
+        &mut self,
+        track: Track,
+        bundle_id: Option<String>,
+        update: &mut PollUpdate,
+    ) {
+        log::info!(
+            "Now playing: {} - {} (album: {}) from {:?}",
+            track.artist,
+            track.title,
+            track.album.as_deref().unwrap_or("Unknown"),
+            bundle_id
+        );
+        self.check_swapped(&track, bundle_id.as_deref());
+
+        self.notify(
+            "now_playing",
+            json!({ "track": track, "bundle_id": bundle_id }),
+        );
+
+        // Send to scrobblers immediately with retries (nothing is sent while paused or in
+        // guest mode)
+        let standby = standby_services(&self.failover_groups, self.history.as_ref());
+        let now = Local::now().naive_local();
+        let mut results = Vec::new();
+        for scrobbler in self.scrobblers.iter().filter(|s| {
+            self.submitting()
+                && !standby.contains(&s.key())
+                && self.routes.allows(&s.key(), bundle_id.as_deref(), now)
+        }) {
+            let backoff = ExponentialBackoff {
+                max_elapsed_time: Some(Duration::from_secs(10)),
+                ..Default::default()
+            };
+
+            let result = retry(backoff, || {
+                scrobbler
+                    .now_playing(&track, bundle_id.as_deref())
+                    .map_err(retry_error)
+            })
+            .map_err(|e| match e {
+                backoff::Error::Permanent(e) | backoff::Error::Transient { err: e, .. } => e,
+            });
+
+            if let Err(ref e) = result {
+                log::error!("Failed to send now playing after retries: {}", e);
+            }
+            results.push((scrobbler.key(), result));
+        }
+        self.check_auth(&results, update);
+
+        // Looking up art online would tell Apple or MusicBrainz what's played in a private session
+        #[cfg(feature = "artwork")]
+        if let Some(artwork) = self.artwork.as_ref() {
+            artwork.fetch(&track, bundle_id.as_deref(), self.submitting());
+        }
+
+        update.now_playing = Some(track);
+        update.now_playing_cleared = false;
+    }
+
+    /// Warn once per app when a track's artist and title look swapped, suggesting a mapping
+    fn check_swapped(&mut self, track: &Track, bundle_id: Option<&str>) {
+        let app = bundle_id.unwrap_or("unknown app");
+        let Some(history) = &self.history else {
+            return;
+        };
+        if self.swap_warned.contains(app) || !field_mapping::looks_swapped(track, history.entries())
+        {
+            return;
+        }
+
+        log::warn!(
+            "{} - {} from {} looks like its artist and title are swapped; if the app always \
+             swaps them, add a [[field_mapping]] with apps = [\"{}\"], title_from = \"artist\" \
+             and artist_from = \"title\"",
+            track.artist,
+            track.title,
+            app,
+            app
+        );
+        self.swap_warned.insert(app.to_string());
+    }
+
+    fn handle_scrobble(
+        &mut self,
+        track: Track,
+        timestamp: DateTime<Utc>,
+        bundle_id: Option<String>,
+        review: bool,
+        update: &mut PollUpdate,
+    ) {
+        log::info!(
+            "Scrobble: {} - {} at {} from {:?}",
+            track.artist,
+            track.title,
+            timestamp.format("%Y-%m-%d %H:%M:%S"),
+            bundle_id
+        );
+
+        let mut entry = self
+            .history
+            .as_ref()
+            .map(|h| h.new_entry(&track, timestamp, bundle_id.clone()));
+
+        // Backups in a failover group only get scrobbles while the primary is down
+        let standby = standby_services(&self.failover_groups, self.history.as_ref());
+        let active = if !self.submitting() {
+            if self.guest_mode {
+                log::info!("Guest mode, keeping the scrobble as a guest listen");
+            } else {
+                log::info!("Paused, keeping the scrobble as private");
+            }
+            if let Some(ref mut entry) = entry {
+                entry.private = true;
+                entry.guest = self.guest_mode;
+            }
+            [].iter()
+        } else if review {
+            log::info!("Confidence is low, keeping the scrobble for review");
+            if let Some(ref mut entry) = entry {
+                entry.private = true;
+                entry.review = true;
+            }
+            [].iter()
+        } else {
+            self.scrobblers.iter()
+        };
+        // Routes leave other services out of the entry entirely, so they never queue it
+        let played_at = timestamp.with_timezone(&Local).naive_local();
+        let mut results = Vec::new();
+        for scrobbler in active.filter(|s| {
+            !standby.contains(&s.key())
+                && self
+                    .routes
+                    .allows(&s.key(), bundle_id.as_deref(), played_at)
+        }) {
+            let result =
+                scrobble_with_retries(scrobbler.as_ref(), &track, timestamp, bundle_id.as_deref());
+
+            if let Err(ref e) = result {
+                log::error!("Failed to scrobble after retries: {}", e);
+                // Rejected credentials get their own notice from check_auth, and throttled
+                // scrobbles are only queued
+                if !scrobbler::is_auth_error(e) && !rate_limit::is_throttled(e) {
+                    update
+                        .failed
+                        .push((scrobbler.display_name(), format!("{:#}", e)));
+                }
+            }
+            if let Some(ref mut entry) = entry {
+                entry.set_submission(&scrobbler.key(), &result);
+            }
+            results.push((scrobbler.key(), result));
+        }
+        self.check_auth(&results, update);
+
+        // Recorded first, so the event carries the ID the database gave it
+        let submissions = entry.as_ref().map(|e| e.submissions.clone());
+        let id = match (self.history.as_mut(), entry) {
+            (Some(history), Some(entry)) => match history.record(entry) {
+                Ok(id) => {
+                    self.entry_ids.insert(bundle_id.clone(), id);
+                    Some(id)
+                }
+                Err(e) => {
+                    log::error!("Failed to record scrobble history: {}", e);
+                    None
+                }
+            },
+            _ => None,
+        };
+
+        self.notify(
+            "scrobble",
+            json!({
+                "id": id,
+                "track": track,
+                "played_at": timestamp,
+                "bundle_id": bundle_id,
+                "private": !self.submitting() || review,
+                "guest": self.guest_mode,
+                "review": review && self.submitting(),
+                "submissions": submissions,
+                "artwork": self.cached_artwork(&track),
+            }),
+        );
+
+        if self.submitting() {
+            update.scrobbled = Some(track);
+        }
+    }
+
+    /// Like clear_now_playing, without the listening time a frozen player made up
+    fn handle_stalled(&mut self, track: Track, bundle_id: Option<String>, update: &mut PollUpdate) {
+        self.entry_ids.remove(&bundle_id);
+        self.notify(
+            "now_playing_cleared",
+            json!({ "track": track, "stalled": true }),
+        );
+        update.now_playing_cleared = update.now_playing.is_none();
+    }
+
+    /// Store the listening time of a finished session
+    fn handle_session_ended(&mut self, track: Track, listened: u64, bundle_id: Option<String>) {
+        log::debug!(
+            "Listened to {} - {} for {}s",
+            track.artist,
+            track.title,
+            listened
+        );
+        self.notify(
+            "session_ended",
+            json!({ "track": track, "listened_seconds": listened }),
+        );
+        if let (Some(history), Some(id)) =
+            (self.history.as_mut(), self.entry_ids.remove(&bundle_id))
+        {
+            if let Err(e) = history.set_listened(id, listened) {
+                log::error!("Failed to update listening time: {}", e);
+            }
+        }
+    }
+
+    /// Scrobble the current track now, even if it hasn't played long enough yet
+    ///
+    /// Frontends see it in the next [`Engine::poll`], like any other scrobble.
+    pub fn scrobble_now(&mut self) -> Result<Track> {
+        let (track, timestamp, bundle_id) = self
+            .media_monitor
+            .force_scrobble()
+            .context("Nothing is playing, or it was already scrobbled")?;
+
+        // Asking for it counts as approving a play that would be held for review
+        let update = self.handle_events(vec![MediaEvent::Scrobble {
+            track: track.clone(),
+            timestamp,
+            bundle_id,
+            review: false,
+        }]);
+        self.pending_update.scrobbled = update.scrobbled;
+        self.pending_update.auth_expired.extend(update.auth_expired);
+        self.pending_update.failed.extend(update.failed);
+
+        Ok(track)
+    }
+
+    /// Verify the credentials of one service that hasn't been checked for a week
+    fn verify_due_session(&mut self, update: &mut PollUpdate) {
+        let now = Utc::now();
+        let Some(scrobbler) = self
+            .scrobblers
+            .iter()
+            .find(|s| s.can_verify() && self.session_checks.is_due(&s.key(), now))
+        else {
+            return;
+        };
+
+        let key = scrobbler.key();
+        let result = scrobbler.verify_session();
+        if let Err(ref e) = result {
+            log::warn!("{:#}", e);
+        }
+        let rejected = result.as_ref().is_err_and(scrobbler::is_auth_error);
+        self.session_checks.record(&key, &result, rejected, now);
+        if let Err(e) = self.session_checks.save() {
+            log::warn!("Failed to save session checks: {:#}", e);
+        }
+        self.check_auth(&[(key, result)], update);
+    }
+
+    /// Note services that started or stopped rejecting their credentials
+    fn check_auth(&mut self, results: &[(String, Result<()>)], update: &mut PollUpdate) {
+        for (key, result) in results {
+            // Submissions keep the recorded session check up to date when it changes
+            let stale = match result {
+                Ok(()) => self
+                    .session_checks
+                    .get(key)
+                    .is_some_and(|c| c.error.is_some()),
+                Err(e) => {
+                    scrobbler::is_auth_error(e)
+                        && !self.session_checks.get(key).is_some_and(|c| c.rejected)
+                }
+            };
+            if stale {
+                let rejected = result.as_ref().is_err_and(scrobbler::is_auth_error);
+                self.session_checks
+                    .record(key, result, rejected, Utc::now());
+                if let Err(e) = self.session_checks.save() {
+                    log::warn!("Failed to save session checks: {:#}", e);
+                }
+            }
+
+            match result {
+                Err(e) if scrobbler::is_auth_error(e) => {
+                    if self.auth_expired.insert(key.clone()) {
+                        let name = self
+                            .scrobblers
+                            .iter()
+                            .find(|s| &s.key() == key)
+                            .map_or_else(|| key.clone(), |s| s.display_name());
+                        log::error!("{} rejected its credentials, sign in again", name);
+                        self.notify("auth_expired", json!({ "service": key }));
+                        update.auth_expired.push(name);
+                    }
+                }
+                Ok(()) => {
+                    self.auth_expired.remove(key);
+                }
+                Err(_) => {}
+            }
+        }
+    }
+
+    /// Add an app to the allowed or ignored apps, taking it off the other list, and save the
+    /// config
+    pub fn set_app_allowed(&mut self, bundle_id: &str, allowed: bool) {
+        let filtering = &mut self.config.app_filtering;
+        let (apps, other, kind) = if allowed {
+            (
+                &mut filtering.allowed_apps,
+                &mut filtering.ignored_apps,
+                "allowed",
+            )
+        } else {
+            (
+                &mut filtering.ignored_apps,
+                &mut filtering.allowed_apps,
+                "ignored",
+            )
+        };
+        if apps.iter().any(|app| app == bundle_id) {
+            return;
+        }
+
+        other.retain(|app| app != bundle_id);
+        apps.push(bundle_id.to_string());
+        match self.config.save() {
+            Ok(()) => log::info!("Added {} to {} apps", bundle_id, kind),
+            Err(e) => log::error!("Failed to save config: {}", e),
+        }
+    }
+
+    /// Take an app off the allowed and ignored apps and save the config, so it's asked about again
+    pub fn remove_app(&mut self, bundle_id: &str) {
+        let filtering = &mut self.config.app_filtering;
+        let listed = filtering.allowed_apps.len() + filtering.ignored_apps.len();
+        filtering.allowed_apps.retain(|app| app != bundle_id);
+        filtering.ignored_apps.retain(|app| app != bundle_id);
+        if filtering.allowed_apps.len() + filtering.ignored_apps.len() == listed {
+            return;
+        }
+
+        match self.config.save() {
+            Ok(()) => log::info!("Removed {} from the app lists", bundle_id),
+            Err(e) => log::error!("Failed to save config: {}", e),
+        }
+    }
+
+    /// Save the answer to the notification prompt
+    pub fn set_notifications_enabled(&mut self, enabled: bool) {
+        self.config.notifications.enabled = Some(enabled);
+        match self.config.save() {
+            Ok(()) => log::info!(
+                "Notifications {}",
+                if enabled { "enabled" } else { "disabled" }
+            ),
+            Err(e) => log::error!("Failed to save config: {}", e),
+        }
+    }
+
+    /// Correct the artist and title of the most recent scrobble
+    ///
+    /// `prompt` gets the current artist and title and returns the corrected ones, or `None` to
+    /// cancel. The old scrobble is deleted from the services where possible, the corrected one
+    /// is submitted in its place, and a correction rule is saved so later plays are fixed too.
+    /// Returns the new history entry, or `None` if nothing changed.
+    pub fn edit_last_scrobble(
+        &mut self,
+        prompt: impl FnOnce(&str, &str) -> Option<(String, String)>,
+    ) -> Result<Option<HistoryEntry>> {
+        let history = self
+            .history
+            .as_mut()
+            .context("Scrobble history is unavailable")?;
+        let standby = standby_services(&self.failover_groups, Some(history));
+        let scrobblers: Vec<&dyn Scrobbler> = self
+            .scrobblers
+            .iter()
+            .filter(|s| !standby.contains(&s.key()))
+            .map(|s| s.as_ref())
+            .collect();
+
+        let Some(last) = history
+            .entries()
+            .iter()
+            .rev()
+            .find(|e| !e.deleted && !e.private)
+        else {
+            log::info!("No scrobble to edit");
+            return Ok(None);
+        };
+
+        let Some((artist, title)) = prompt(&last.artist, &last.title) else {
+            return Ok(None);
+        };
+        if artist == last.artist && title == last.title {
+            return Ok(None);
+        }
+
+        let old_id = last.id;
+        let old_track = last.track();
+        let mut track = old_track.clone();
+        track.artist = artist;
+        track.title = title;
+
+        let mut entry = history.new_entry(&track, last.played_at, last.bundle_id.clone());
+        entry.listened_seconds = last.listened_seconds;
+
+        let old = history
+            .entry_mut(old_id)
+            .context("Last scrobble disappeared from the history")?;
+        for (service, result) in delete_from_services(old, &scrobblers) {
+            if let Err(e) = result {
+                log::warn!("Could not delete old scrobble from {}: {:#}", service, e);
+            }
+        }
+        old.deleted = true;
+
+        let played_at = entry.played_at.with_timezone(&Local).naive_local();
+        let bundle_id = entry.bundle_id.clone();
+        for scrobbler in scrobblers.iter().filter(|s| {
+            self.routes
+                .allows(&s.key(), bundle_id.as_deref(), played_at)
+        }) {
+            let result = scrobble_with_retries(
+                *scrobbler,
+                &track,
+                entry.played_at,
+                entry.bundle_id.as_deref(),
+            );
+            if let Err(ref e) = result {
+                log::error!("Failed to scrobble corrected track after retries: {}", e);
+            }
+            entry.set_submission(&scrobbler.key(), &result);
+        }
+
+        log::info!(
+            "Corrected {} - {} to {} - {}",
+            old_track.artist,
+            old_track.title,
+            track.artist,
+            track.title
+        );
+
+        history.save()?;
+        entry.id = history.record(entry.clone())?;
+        for id in self.entry_ids.values_mut().filter(|id| **id == old_id) {
+            *id = entry.id;
+        }
+
+        corrections::add_rule(&mut self.config.corrections, &old_track, &track);
+        if let Err(e) = self.config.save() {
+            log::error!("Failed to save config: {}", e);
+        }
+        self.media_monitor.reconfigure(&self.config);
+
+        Ok(Some(entry))
+    }
+
+    /// Plays held back for review, most recent first
+    pub fn review_queue(&self) -> Vec<&HistoryEntry> {
+        let Some(history) = self.history.as_ref() else {
+            return Vec::new();
+        };
+        history
+            .entries()
+            .iter()
+            .rev()
+            .filter(|e| e.review && !e.deleted)
+            .collect()
+    }
+
+    /// Submit a play held back for review, under a corrected artist and title if given
+    ///
+    /// A correction also saves a correction rule, so later plays of the track are fixed too.
+    /// Returns the updated history entry.
+    pub fn approve_review(
+        &mut self,
+        id: u64,
+        correction: Option<(String, String)>,
+    ) -> Result<HistoryEntry> {
+        let history = self
+            .history
+            .as_mut()
+            .context("Scrobble history is unavailable")?;
+        let standby = standby_services(&self.failover_groups, Some(history));
+
+        let entry = history
+            .entry_mut(id)
+            .filter(|e| e.review && !e.deleted)
+            .with_context(|| format!("No scrobble with ID {} is held for review", id))?;
+        let old_track = entry.track();
+        if let Some((artist, title)) = correction {
+            entry.artist = artist;
+            entry.title = title;
+        }
+        entry.review = false;
+        entry.private = false;
+        let track = entry.track();
+
+        let played_at = entry.played_at.with_timezone(&Local).naive_local();
+        let bundle_id = entry.bundle_id.clone();
+        for scrobbler in self.scrobblers.iter().filter(|s| {
+            !standby.contains(&s.key())
+                && self
+                    .routes
+                    .allows(&s.key(), bundle_id.as_deref(), played_at)
+        }) {
+            let result = scrobble_with_retries(
+                scrobbler.as_ref(),
+                &track,
+                entry.played_at,
+                entry.bundle_id.as_deref(),
+            );
+            if let Err(ref e) = result {
+                log::error!("Failed to scrobble reviewed track after retries: {}", e);
+            }
+            entry.set_submission(&scrobbler.key(), &result);
+        }
+        let entry = entry.clone();
+        history.save()?;
+
+        if track.artist != old_track.artist || track.title != old_track.title {
+            log::info!(
+                "Approved {} - {} as {} - {}",
+                old_track.artist,
+                old_track.title,
+                track.artist,
+                track.title
+            );
+            corrections::add_rule(&mut self.config.corrections, &old_track, &track);
+            if let Err(e) = self.config.save() {
+                log::error!("Failed to save config: {}", e);
+            }
+            self.media_monitor.reconfigure(&self.config);
+        } else {
+            log::info!("Approved {} - {}", track.artist, track.title);
+        }
+
+        Ok(entry)
+    }
+
+    /// Discard plays held back for review, keeping them in the history as deleted
+    pub fn discard_review(&mut self, ids: &[u64]) -> Result<()> {
+        let history = self
+            .history
+            .as_mut()
+            .context("Scrobble history is unavailable")?;
+        for &id in ids {
+            let entry = history
+                .entry_mut(id)
+                .filter(|e| e.review && !e.deleted)
+                .with_context(|| format!("No scrobble with ID {} is held for review", id))?;
+            entry.review = false;
+            entry.deleted = true;
+            log::info!("Discarded {} - {}", entry.artist, entry.title);
+        }
+        history.save()
+    }
+}
+
+/// Warn about enabled settings for integrations this build leaves out
+fn warn_missing_features(config: &Config) {
+    for feature in config.missing_features() {
+        log::warn!(
+            "[{}] is enabled, but this build doesn't include it; reinstall with `--features {}`",
+            feature,
+            feature
+        );
+    }
+}
+
+/// Start the web UI, generating its token on first use
+#[cfg(feature = "web")]
+fn start_web(config: &mut Config) -> Result<WebServer> {
+    if config.web.token.is_empty() {
+        config.web.token = web::generate_token()?;
+        config.save()?;
+        log::info!("Generated a web UI token (see [web] in the config file)");
+    }
+
+    WebServer::start(&config.web)
+}
+
+/// Routes from the config, or none if they're invalid
+fn build_routes(config: &Config) -> Routes {
+    Routes::new(&config.routes).unwrap_or_else(|e| {
+        log::error!("Ignoring routes: {:#}", e);
+        Routes::default()
+    })
+}
+
+/// Artwork cache, if enabled and its directory can be created
+#[cfg(feature = "artwork")]
+fn build_artwork(config: &Config) -> Option<ArtworkCache> {
+    if !config.artwork.enabled {
+        return None;
+    }
+
+    ArtworkCache::new(&config.artwork)
+        .map_err(|e| log::error!("Failed to set up the artwork cache: {:#}", e))
+        .ok()
+}
+
+/// Create scrobbling services for every enabled and configured backend
+pub fn build_scrobblers(config: &config::Config) -> Vec<Box<dyn Scrobbler>> {
+    scrobbler::build_all(config)
+}
+
+/// Backup services that shouldn't get submissions (all of them without a history to go by)
+pub fn standby_services(
+    groups: &[failover::FailoverGroup],
+    history: Option<&history::History>,
+) -> Vec<String> {
+    match history {
+        Some(history) => failover::standby_services(groups, history.entries(), chrono::Utc::now()),
+        None => groups.iter().map(|g| g.backup.clone()).collect(),
+    }
+}
+
+/// Queue outage scrobbles for active backups, and drop them again once the primary is back
+pub fn update_failover_queues(groups: &[failover::FailoverGroup], history: &mut history::History) {
+    let now = chrono::Utc::now();
+    let mut changed = false;
+
+    // Only the entries that changed get marked for saving, so a flush stays cheap
+    for group in groups {
+        if let Some(since) = group.active_since(history.entries(), now) {
+            let queued = history.update_each(|entry| group.queue_entry(entry, since));
+            changed |= queued > 0;
+            if queued > 0 {
+                log::warn!(
+                    "{} is down, queued {} scrobble(s) for backup {}",
+                    group.primary,
+                    queued,
+                    group.backup
+                );
+            }
+        } else {
+            let mut dropped = 0;
+            history.update_each(|entry| {
+                let count = group.dedupe_entry(entry);
+                dropped += count;
+                count > 0
+            });
+            changed |= dropped > 0;
+            if dropped > 0 {
+                log::info!(
+                    "{} is back, dropped {} queued scrobble(s) for backup {}",
+                    group.primary,
+                    dropped,
+                    group.backup
+                );
+            }
+        }
+    }
+
+    if changed {
+        if let Err(e) = history.save() {
+            log::error!("Failed to save scrobble history: {}", e);
+        }
+    }
+}
+
+/// Queue listens found on the canonical service for every other service that lacks them
+pub fn mirror_canonical(
+    mirror: &config::MirrorConfig,
+    history: &mut history::History,
+    scrobblers: &[Box<dyn Scrobbler>],
+    failover_groups: &[failover::FailoverGroup],
+) {
+    let Some(canonical) = scrobblers.iter().find(|s| s.key() == mirror.canonical) else {
+        log::warn!(
+            "Mirror canonical service {} is not enabled",
+            mirror.canonical
+        );
+        return;
+    };
+
+    let since = chrono::Utc::now() - chrono::Duration::hours(mirror.lookback_hours as i64);
+    let remote = match canonical.recent_listens(since) {
+        Ok(remote) => remote,
+        Err(e) => {
+            log::warn!("Skipping mirror run: {:#}", e);
+            return;
+        }
+    };
+
+    // Failover backups only get scrobbles while their primary is down
+    let targets: Vec<String> = scrobblers
+        .iter()
+        .map(|s| s.key())
+        .filter(|key| *key != mirror.canonical)
+        .filter(|key| !failover_groups.iter().any(|g| g.backup == *key))
+        .collect();
+
+    let comparison = reconcile::compare(history.entries(), &remote, since);
+
+    let mut queued = 0;
+    for id in &comparison.matched {
+        if let Some(entry) = history.entry_mut(*id) {
+            queued += reconcile::queue_mirror(entry, &mirror.canonical, &targets);
+        }
+    }
+
+    let mut added = Vec::new();
+    for listen in &comparison.missing_locally {
+        let mut entry = history.new_entry(&listen.track(), listen.played_at, None);
+        queued += reconcile::queue_mirror(&mut entry, &mirror.canonical, &targets);
+        added.push(entry);
+    }
+    let added_count = added.len();
+
+    let result = if added.is_empty() {
+        history.save()
+    } else {
+        history.insert(added)
+    };
+    if let Err(e) = result {
+        log::error!("Failed to save scrobble history: {}", e);
+    }
+
+    log::info!(
+        "Mirrored {}: {} listen(s) added to history, {} submission(s) queued",
+        mirror.canonical,
+        added_count,
+        queued
+    );
+}
+
+/// Submit the queued submissions planned for the current flush window
+pub fn flush_queued_submissions(
+    submission_scheduler: &scheduler::SubmissionScheduler,
+    history: &mut history::History,
+    scrobblers: &[Box<dyn Scrobbler>],
+) {
+    if submission_scheduler.timestamps() == TimestampPolicy::Validate {
+        divert_expired(history, scrobblers);
+    }
+
+    let services: Vec<(String, Option<usize>)> = scrobblers
+        .iter()
+        .map(|s| (s.key(), s.remaining_budget()))
+        .collect();
+    let planned = submission_scheduler.plan(history.entries(), &services);
+    if planned.is_empty() {
+        return;
+    }
+
+    log::info!("Flushing {} queued submission(s)", planned.len());
+
+    let mut submitted = 0;
+    for submission in planned {
+        let Some(scrobbler) = scrobblers.iter().find(|s| s.key() == submission.service) else {
+            continue;
+        };
+        let Some(entry) = history.entry_mut(submission.entry_id) else {
+            continue;
+        };
+
+        let result = scrobble_with_retries(
+            scrobbler.as_ref(),
+            &entry.track(),
+            entry.played_at,
+            entry.bundle_id.as_deref(),
+        );
+        match result {
+            Ok(()) => submitted += 1,
+            Err(ref e) => log::warn!(
+                "Queued scrobble of {} - {} to {} failed again: {}",
+                entry.artist,
+                entry.title,
+                submission.service,
+                e
+            ),
+        }
+        entry.set_submission(&submission.service, &result);
+    }
+
+    log::info!("Flushed {} queued submission(s) successfully", submitted);
+
+    if let Err(e) = history.save() {
+        log::error!("Failed to save scrobble history: {}", e);
+    }
+}
+
+/// Write queued Last.fm submissions too old to be accepted to the export file, instead of
+/// letting them fail on every flush
+fn divert_expired(history: &mut history::History, scrobblers: &[Box<dyn Scrobbler>]) {
+    let lastfm: Vec<String> = scrobblers
+        .iter()
+        .filter(|s| s.backend() == scrobbler::lastfm::BACKEND)
+        .map(|s| s.key())
+        .collect();
+    let expired = scheduler::expired(history.entries(), &lastfm, chrono::Utc::now());
+    if expired.is_empty() {
+        return;
+    }
+
+    let path = match export_expired(history, &expired) {
+        Ok(path) => path,
+        Err(e) => {
+            log::error!("Failed to export expired scrobbles: {:#}", e);
+            return;
+        }
+    };
+    let error = format!(
+        "Older than Last.fm accepts ({} days), exported to {}",
+        scheduler::LASTFM_MAX_AGE_DAYS,
+        path.display()
+    );
+    for submission in &expired {
+        let Some(entry) = history.entry_mut(submission.entry_id) else {
+            continue;
+        };
+        if let Some(queued) = entry
+            .submissions
+            .iter_mut()
+            .find(|s| s.service == submission.service)
+        {
+            queued.exported = true;
+            queued.error = Some(error.clone());
+        }
+    }
+
+    log::warn!(
+        "{} queued scrobble(s) were too old for Last.fm and were exported to {} \
+         (other services still get them, see `osx-scrobbler history expired`)",
+        expired.len(),
+        path.display()
+    );
+}
+
+/// Append expired submissions to the export file, returning its path
+fn export_expired(
+    history: &history::History,
+    expired: &[scheduler::PlannedSubmission],
+) -> Result<PathBuf> {
+    use std::io::Write;
+
+    let path = crate::paths::Paths::current()?.expired;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let new = !path.exists();
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+
+    let mut content = String::new();
+    if new {
+        content.push_str(scheduler::EXPORT_HEADER);
+        content.push('\n');
+    }
+    for submission in expired {
+        if let Some(entry) = history
+            .entries()
+            .iter()
+            .find(|e| e.id == submission.entry_id)
+        {
+            content.push_str(&scheduler::export_row(entry, &submission.service));
+            content.push('\n');
+        }
+    }
+    file.write_all(content.as_bytes())
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+
+    Ok(path)
+}
+
+/// Retry failures, except requests over the rate limit budget: those wait in the queue for a
+/// flush window with budget to spare instead
+fn retry_error(error: anyhow::Error) -> backoff::Error<anyhow::Error> {
+    if rate_limit::is_throttled(&error) {
+        backoff::Error::permanent(error)
+    } else {
+        backoff::Error::transient(error)
+    }
+}
+
+/// Submit a scrobble to a single service, retrying transient failures
+pub fn scrobble_with_retries(
+    scrobbler: &dyn Scrobbler,
+    track: &scrobbler::Track,
+    timestamp: chrono::DateTime<chrono::Utc>,
+    app: Option<&str>,
+) -> Result<()> {
+    let backoff = ExponentialBackoff {
+        max_elapsed_time: Some(Duration::from_secs(30)),
+        ..Default::default()
+    };
+
+    retry(backoff, || {
+        scrobbler
+            .scrobble(track, timestamp, app)
+            .map_err(retry_error)
+    })
+    .map_err(|e| match e {
+        backoff::Error::Permanent(e) | backoff::Error::Transient { err: e, .. } => e,
+    })
+}
+
+/// Delete an entry's submitted scrobbles from the services, recording the outcome per service
+///
+/// Services that aren't enabled are reported as failures and left as is.
+pub fn delete_from_services(
+    entry: &mut history::HistoryEntry,
+    scrobblers: &[&dyn Scrobbler],
+) -> Vec<(String, Result<()>)> {
+    let track = entry.track();
+    let mut results = Vec::new();
+
+    for submission in entry.submissions.iter_mut().filter(|s| s.submitted) {
+        let result = match scrobblers.iter().find(|s| s.key() == submission.service) {
+            Some(scrobbler) => scrobbler.delete_scrobble(&track, entry.played_at),
+            None => Err(anyhow::anyhow!("service is not enabled, left as is")),
+        };
+
+        match result {
+            Ok(()) => {
+                submission.deleted = true;
+                submission.error = None;
+            }
+            Err(ref e) => submission.error = Some(format!("Delete failed: {:#}", e)),
+        }
+        results.push((submission.service.clone(), result));
+    }
+
+    results
+}
@@ -0,0 +1,141 @@
+// History export module
+// Writes scrobbles from the local history as CSV or JSON, for `osx-scrobbler history export`
+
+use crate::history::HistoryEntry;
+use anyhow::{Context, Result};
+
+/// Columns of the CSV export
+pub const CSV_HEADER: &str = "played_at,artist,title,album,album_artist,duration,\
+                              listened_seconds,app,private,guest,submitted,failed,tags,note";
+
+/// Entries as CSV, one line per scrobble after the header
+///
+/// `submitted` lists the services that have the scrobble and `failed` the ones whose last
+/// attempt failed, with the error; both are separated by semicolons, like `tags`.
+pub fn to_csv(entries: &[&HistoryEntry]) -> String {
+    let mut csv = format!("{}\n", CSV_HEADER);
+    for entry in entries {
+        let submitted: Vec<&str> = entry
+            .submissions
+            .iter()
+            .filter(|s| s.submitted && !s.deleted)
+            .map(|s| s.service.as_str())
+            .collect();
+        let failed: Vec<String> = entry
+            .submissions
+            .iter()
+            .filter_map(|s| {
+                let error = s.error.as_ref().filter(|_| !s.submitted)?;
+                Some(format!("{}: {}", s.service, error))
+            })
+            .collect();
+
+        let fields = [
+            entry.played_at.to_rfc3339(),
+            entry.artist.clone(),
+            entry.title.clone(),
+            entry.album.clone().unwrap_or_default(),
+            entry.album_artist.clone().unwrap_or_default(),
+            entry.duration.map(|d| d.to_string()).unwrap_or_default(),
+            entry
+                .listened_seconds
+                .map(|s| s.to_string())
+                .unwrap_or_default(),
+            entry.bundle_id.clone().unwrap_or_default(),
+            entry.private.to_string(),
+            entry.guest.to_string(),
+            submitted.join(";"),
+            failed.join(";"),
+            entry.tags.join(";"),
+            entry.note.clone().unwrap_or_default(),
+        ];
+        let row: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Entries as a JSON array, in the history's own format
+pub fn to_json(entries: &[&HistoryEntry]) -> Result<String> {
+    let mut json = serde_json::to_string_pretty(entries).context("Failed to serialize history")?;
+    json.push('\n');
+    Ok(json)
+}
+
+/// Quote a CSV field if it has commas, quotes or line breaks
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::Submission;
+    use chrono::{TimeZone, Utc};
+
+    fn entry() -> HistoryEntry {
+        HistoryEntry {
+            id: 1,
+            artist: "Band, The".to_string(),
+            title: "Say \"Hi\"".to_string(),
+            album: Some("Album".to_string()),
+            duration: Some(200),
+            played_at: Utc.with_ymd_and_hms(2026, 1, 1, 12, 0, 0).unwrap(),
+            bundle_id: Some("com.apple.Music".to_string()),
+            listened_seconds: Some(180),
+            note: Some("First listen".to_string()),
+            tags: vec!["gym".to_string(), "work".to_string()],
+            submissions: vec![
+                Submission {
+                    service: "lastfm".to_string(),
+                    submitted: true,
+                    error: None,
+                    deleted: false,
+                    exported: false,
+                },
+                Submission {
+                    service: "listenbrainz:main".to_string(),
+                    submitted: false,
+                    error: Some("Timed out".to_string()),
+                    deleted: false,
+                    exported: false,
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_csv() {
+        let entry = entry();
+        let csv = to_csv(&[&entry]);
+        let mut lines = csv.lines();
+
+        assert_eq!(lines.next(), Some(CSV_HEADER));
+        assert_eq!(
+            lines.next(),
+            Some(
+                "2026-01-01T12:00:00+00:00,\"Band, The\",\"Say \"\"Hi\"\"\",Album,,200,180,\
+                 com.apple.Music,false,false,lastfm,listenbrainz:main: Timed out,gym;work,\
+                 First listen"
+            )
+        );
+        assert_eq!(lines.next(), None);
+    }
+
+    #[test]
+    fn test_json_round_trips() {
+        let entry = entry();
+        let json = to_json(&[&entry]).unwrap();
+        let parsed: Vec<HistoryEntry> = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].title, entry.title);
+        assert_eq!(parsed[0].submissions.len(), 2);
+    }
+}
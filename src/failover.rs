@@ -0,0 +1,261 @@
+// Failover module
+// Sends scrobbles to a backup service while the primary has been down for too long
+
+use crate::config::FailoverConfig;
+use crate::history::{HistoryEntry, Submission};
+use chrono::{DateTime, Duration, Utc};
+
+/// A primary service with a backup that takes over during long outages
+#[derive(Debug, Clone)]
+pub struct FailoverGroup {
+    pub primary: String,
+    pub backup: String,
+    /// How long the primary has to be failing before the backup is used
+    pub after: Duration,
+}
+
+impl FailoverGroup {
+    pub fn new(config: &FailoverConfig) -> Self {
+        Self {
+            primary: config.primary.clone(),
+            backup: config.backup.clone(),
+            after: Duration::minutes(config.after_minutes as i64),
+        }
+    }
+
+    /// Start of the primary's current outage, if it is down
+    ///
+    /// The outage is the run of most recent entries whose primary submission failed; it
+    /// started when the oldest of them was played. Submissions that were queued but never
+    /// attempted (e.g. by mirroring) don't count either way.
+    pub fn down_since(&self, entries: &[HistoryEntry]) -> Option<DateTime<Utc>> {
+        let mut since = None;
+
+        for entry in entries.iter().rev() {
+            let Some(submission) = entry.submissions.iter().find(|s| s.service == self.primary)
+            else {
+                continue;
+            };
+            if submission.submitted {
+                break;
+            }
+            if submission.error.is_some() {
+                since = Some(entry.played_at);
+            }
+        }
+
+        since
+    }
+
+    /// Check whether the backup should currently receive submissions
+    pub fn is_active(&self, entries: &[HistoryEntry], now: DateTime<Utc>) -> bool {
+        self.active_since(entries, now).is_some()
+    }
+
+    /// Start of the outage the backup is covering for, if it has been down long enough
+    pub fn active_since(
+        &self,
+        entries: &[HistoryEntry],
+        now: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        self.down_since(entries)
+            .filter(|since| now - *since >= self.after)
+    }
+
+    /// Queue entries from the current outage for the backup, returning how many were added
+    ///
+    /// Entries that already have a backup submission (sent or queued) are left alone.
+    pub fn queue_backup(&self, entries: &mut [HistoryEntry]) -> usize {
+        let Some(since) = self.down_since(entries) else {
+            return 0;
+        };
+
+        entries
+            .iter_mut()
+            .map(|entry| self.queue_entry(entry, since))
+            .filter(|queued| *queued)
+            .count()
+    }
+
+    /// Queue one entry for the backup if it was played during an outage that started at `since`
+    /// and has no backup submission yet, returning whether it was
+    pub fn queue_entry(&self, entry: &mut HistoryEntry, since: DateTime<Utc>) -> bool {
+        if entry.played_at < since {
+            return false;
+        }
+        let primary_failed = entry
+            .submissions
+            .iter()
+            .any(|s| s.service == self.primary && !s.submitted);
+        let has_backup = entry.submissions.iter().any(|s| s.service == self.backup);
+
+        if primary_failed && !has_backup {
+            entry.submissions.push(Submission::pending(&self.backup));
+            return true;
+        }
+        false
+    }
+
+    /// Drop backup submissions still queued once the primary is back, returning how many
+    ///
+    /// The primary gets its backlog through the regular retries, so sending the same
+    /// scrobbles to the backup as well would only duplicate them there.
+    pub fn dedupe(&self, entries: &mut [HistoryEntry]) -> usize {
+        entries
+            .iter_mut()
+            .map(|entry| self.dedupe_entry(entry))
+            .sum()
+    }
+
+    /// Drop one entry's backup submissions still queued, returning how many
+    pub fn dedupe_entry(&self, entry: &mut HistoryEntry) -> usize {
+        if !entry.submissions.iter().any(|s| s.service == self.primary) {
+            return 0;
+        }
+
+        let before = entry.submissions.len();
+        entry
+            .submissions
+            .retain(|s| s.service != self.backup || s.submitted);
+        before - entry.submissions.len()
+    }
+}
+
+/// Backup services that should not receive submissions right now
+pub fn standby_services(
+    groups: &[FailoverGroup],
+    entries: &[HistoryEntry],
+    now: DateTime<Utc>,
+) -> Vec<String> {
+    groups
+        .iter()
+        .filter(|g| !g.is_active(entries, now))
+        .map(|g| g.backup.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn group() -> FailoverGroup {
+        FailoverGroup {
+            primary: "listenbrainz:Home".to_string(),
+            backup: "listenbrainz:Primary".to_string(),
+            after: Duration::minutes(60),
+        }
+    }
+
+    fn at(minutes: i64) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 1, 1, 12, 0, 0).unwrap() + Duration::minutes(minutes)
+    }
+
+    fn entry(id: u64, minutes: i64, submissions: Vec<(&str, bool)>) -> HistoryEntry {
+        HistoryEntry {
+            id,
+            artist: "Artist".to_string(),
+            title: "Song".to_string(),
+            duration: Some(200),
+            played_at: at(minutes),
+            submissions: submissions
+                .into_iter()
+                .map(|(service, submitted)| Submission {
+                    service: service.to_string(),
+                    submitted,
+                    error: (!submitted).then(|| "unreachable".to_string()),
+                    deleted: false,
+                    exported: false,
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_down_since_trailing_failures() {
+        let entries = vec![
+            entry(1, 0, vec![("listenbrainz:Home", false)]),
+            entry(2, 10, vec![("listenbrainz:Home", true)]),
+            entry(3, 20, vec![("listenbrainz:Home", false)]),
+            entry(4, 30, vec![]),
+            entry(5, 40, vec![("listenbrainz:Home", false)]),
+        ];
+
+        assert_eq!(group().down_since(&entries), Some(at(20)));
+        assert_eq!(group().down_since(&entries[..2]), None);
+    }
+
+    #[test]
+    fn test_down_since_ignores_unattempted_submissions() {
+        let mut entries = vec![
+            entry(1, 0, vec![("listenbrainz:Home", true)]),
+            entry(2, 10, vec![]),
+        ];
+        entries[1]
+            .submissions
+            .push(Submission::pending("listenbrainz:Home"));
+
+        assert_eq!(group().down_since(&entries), None);
+    }
+
+    #[test]
+    fn test_active_after_threshold() {
+        let entries = vec![entry(1, 0, vec![("listenbrainz:Home", false)])];
+
+        assert!(!group().is_active(&entries, at(59)));
+        assert!(group().is_active(&entries, at(60)));
+        assert_eq!(
+            standby_services(&[group()], &entries, at(30)),
+            vec!["listenbrainz:Primary".to_string()]
+        );
+        assert!(standby_services(&[group()], &entries, at(90)).is_empty());
+    }
+
+    #[test]
+    fn test_queue_backup_only_outage_entries() {
+        let mut entries = vec![
+            entry(1, 0, vec![("listenbrainz:Home", false)]),
+            entry(2, 10, vec![("listenbrainz:Home", true)]),
+            entry(3, 20, vec![("listenbrainz:Home", false)]),
+            entry(
+                4,
+                30,
+                vec![("listenbrainz:Home", false), ("listenbrainz:Primary", true)],
+            ),
+        ];
+
+        assert_eq!(group().queue_backup(&mut entries), 1);
+        assert!(entries[0].needs_retry("listenbrainz:Home"));
+        assert!(!entries[0]
+            .submissions
+            .iter()
+            .any(|s| s.service == "listenbrainz:Primary"));
+        assert!(entries[2].needs_retry("listenbrainz:Primary"));
+
+        // Queuing again doesn't duplicate
+        assert_eq!(group().queue_backup(&mut entries), 0);
+    }
+
+    #[test]
+    fn test_dedupe_drops_only_pending_backup_submissions() {
+        let mut entries = vec![
+            entry(
+                1,
+                0,
+                vec![("listenbrainz:Home", true), ("listenbrainz:Primary", false)],
+            ),
+            entry(
+                2,
+                10,
+                vec![("listenbrainz:Home", false), ("listenbrainz:Primary", true)],
+            ),
+            entry(3, 20, vec![("listenbrainz:Primary", false)]),
+        ];
+
+        assert_eq!(group().dedupe(&mut entries), 1);
+        assert_eq!(entries[0].submissions.len(), 1);
+        assert_eq!(entries[1].submissions.len(), 2);
+        assert_eq!(entries[2].submissions.len(), 1);
+    }
+}
@@ -0,0 +1,141 @@
+// Field mapping module
+// Takes the title and artist from other fields for apps that report them swapped, and spots
+// tracks that look swapped from apps without a mapping
+
+use crate::config::{FieldMappingConfig, TrackField};
+use crate::history::HistoryEntry;
+use crate::scrobbler::Track;
+
+pub struct FieldMappings {
+    mappings: Vec<FieldMappingConfig>,
+}
+
+impl FieldMappings {
+    pub fn new(configs: &[FieldMappingConfig]) -> Self {
+        Self {
+            mappings: configs.to_vec(),
+        }
+    }
+
+    /// The track with its title and artist taken from the fields configured for the app
+    ///
+    /// Fields that would end up empty are left as reported.
+    pub fn apply(&self, bundle_id: Option<&str>, track: Track) -> Track {
+        let Some(mapping) = self.mapping(bundle_id) else {
+            return track;
+        };
+
+        let field = |field: TrackField| {
+            match field {
+                TrackField::Title => Some(track.title.clone()),
+                TrackField::Artist => Some(track.artist.clone()),
+                TrackField::Album => track.album.clone(),
+            }
+            .filter(|value| !value.trim().is_empty())
+        };
+        let title = field(mapping.title_from).unwrap_or_else(|| track.title.clone());
+        let artist = field(mapping.artist_from).unwrap_or_else(|| track.artist.clone());
+
+        Track {
+            title,
+            artist,
+            ..track
+        }
+    }
+
+    fn mapping(&self, bundle_id: Option<&str>) -> Option<&FieldMappingConfig> {
+        let bundle_id = bundle_id?;
+        self.mappings
+            .iter()
+            .find(|mapping| mapping.apps.iter().any(|app| app == bundle_id))
+    }
+}
+
+/// Whether a track's artist and title look swapped, going by the history
+///
+/// That's when its artist was scrobbled as a title and its title as an artist before, ignoring
+/// case. Self-titled tracks don't count.
+pub fn looks_swapped(track: &Track, entries: &[HistoryEntry]) -> bool {
+    let artist = track.artist.trim().to_lowercase();
+    let title = track.title.trim().to_lowercase();
+    if artist.is_empty() || title.is_empty() || artist == title {
+        return false;
+    }
+
+    let entries = || entries.iter().filter(|e| !e.deleted);
+    entries().any(|e| e.title.to_lowercase() == artist)
+        && entries().any(|e| e.artist.to_lowercase() == title)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn track(artist: &str, title: &str, album: Option<&str>) -> Track {
+        Track {
+            title: title.to_string(),
+            artist: artist.to_string(),
+            album: album.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    fn entry(artist: &str, title: &str) -> HistoryEntry {
+        HistoryEntry {
+            id: 1,
+            artist: artist.to_string(),
+            title: title.to_string(),
+            played_at: Utc::now(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_swaps_fields_for_mapped_apps() {
+        let mappings = FieldMappings::new(&[FieldMappingConfig {
+            apps: vec!["com.example.player".to_string()],
+            title_from: TrackField::Artist,
+            artist_from: TrackField::Title,
+        }]);
+        let swapped = track("Song", "Band", Some("Album"));
+
+        let fixed = mappings.apply(Some("com.example.player"), swapped.clone());
+        assert_eq!(
+            (fixed.artist.as_str(), fixed.title.as_str()),
+            ("Band", "Song")
+        );
+        assert_eq!(fixed.album.as_deref(), Some("Album"));
+
+        assert_eq!(mappings.apply(Some("com.other"), swapped.clone()), swapped);
+        assert_eq!(mappings.apply(None, swapped.clone()), swapped);
+    }
+
+    #[test]
+    fn test_empty_fields_are_not_mapped() {
+        let mappings = FieldMappings::new(&[FieldMappingConfig {
+            apps: vec!["com.example.player".to_string()],
+            title_from: TrackField::Title,
+            artist_from: TrackField::Album,
+        }]);
+
+        let fixed = mappings.apply(
+            Some("com.example.player"),
+            track("Unknown", "Song", Some("Band")),
+        );
+        assert_eq!(fixed.artist, "Band");
+        let unchanged = mappings.apply(Some("com.example.player"), track("Band", "Song", None));
+        assert_eq!(unchanged.artist, "Band");
+    }
+
+    #[test]
+    fn test_looks_swapped() {
+        let entries = [entry("Band", "Song"), entry("Weezer", "Buddy Holly")];
+
+        assert!(looks_swapped(&track("song", "Band", None), &entries));
+        assert!(!looks_swapped(&track("Band", "Song", None), &entries));
+        assert!(!looks_swapped(&track("Song", "Other", None), &entries));
+        assert!(!looks_swapped(&track("Weezer", "Weezer", None), &entries));
+        assert!(!looks_swapped(&track("Song", "Band", None), &[]));
+    }
+}
@@ -0,0 +1,443 @@
+// Filters module
+// User-written rules like `app == "com.spotify.client" && duration < 60 -> ignore`, for cases the
+// allow/ignore lists and cleanup patterns can't express
+
+use anyhow::{Context, Result};
+use regex::Regex;
+
+/// What a matching rule does with a track
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterAction {
+    Ignore,
+    /// Scrobble as usual, skipping the rules after this one
+    Scrobble,
+}
+
+/// Metadata a rule is evaluated against (missing values are empty, or 0 for the duration)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FilterInput<'a> {
+    pub app: &'a str,
+    pub app_name: &'a str,
+    pub title: &'a str,
+    pub artist: &'a str,
+    pub album: &'a str,
+    pub duration: u64,
+}
+
+/// Parsed filter rules, checked in order
+#[derive(Debug, Default)]
+pub struct Filters {
+    rules: Vec<Rule>,
+}
+
+impl Filters {
+    pub fn new(rules: &[String]) -> Result<Self> {
+        let rules = rules
+            .iter()
+            .map(|rule| Rule::parse(rule).with_context(|| format!("Invalid filter `{}`", rule)))
+            .collect::<Result<_>>()?;
+
+        Ok(Self { rules })
+    }
+
+    /// Action of the first rule that matches, if any
+    pub fn action(&self, input: &FilterInput) -> Option<FilterAction> {
+        self.rules
+            .iter()
+            .find(|rule| rule.condition.eval(input))
+            .map(|rule| rule.action)
+    }
+}
+
+#[derive(Debug)]
+struct Rule {
+    condition: Expr,
+    action: FilterAction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TextField {
+    App,
+    AppName,
+    Title,
+    Artist,
+    Album,
+}
+
+#[derive(Debug)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    TextEquals(TextField, String),
+    TextMatches(TextField, Regex),
+    Duration(Comparison, f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Expr {
+    fn eval(&self, input: &FilterInput) -> bool {
+        match self {
+            Expr::And(a, b) => a.eval(input) && b.eval(input),
+            Expr::Or(a, b) => a.eval(input) || b.eval(input),
+            Expr::Not(e) => !e.eval(input),
+            Expr::TextEquals(field, value) => text(input, *field) == value,
+            Expr::TextMatches(field, regex) => regex.is_match(text(input, *field)),
+            Expr::Duration(comparison, value) => {
+                let duration = input.duration as f64;
+                match comparison {
+                    Comparison::Eq => duration == *value,
+                    Comparison::Ne => duration != *value,
+                    Comparison::Lt => duration < *value,
+                    Comparison::Le => duration <= *value,
+                    Comparison::Gt => duration > *value,
+                    Comparison::Ge => duration >= *value,
+                }
+            }
+        }
+    }
+}
+
+fn text<'a>(input: &FilterInput<'a>, field: TextField) -> &'a str {
+    match field {
+        TextField::App => input.app,
+        TextField::AppName => input.app_name,
+        TextField::Title => input.title,
+        TextField::Artist => input.artist,
+        TextField::Album => input.album,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Number(f64),
+    /// Operators and punctuation: `==`, `&&`, `(`, `->`, ...
+    Op(&'static str),
+}
+
+const OPERATORS: &[&str] = &[
+    "->", "==", "!=", "=~", "<=", ">=", "&&", "||", "<", ">", "!", "(", ")",
+];
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = input.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        let column = input.len() - rest.len() + 1;
+
+        if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else if c == '"' {
+            let mut value = String::new();
+            let mut chars = rest[1..].char_indices();
+            let end = loop {
+                match chars.next() {
+                    Some((i, '"')) => break i + 2,
+                    // Only quotes and backslashes are escaped, so regexes read as usual
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, escaped @ ('"' | '\\'))) => value.push(escaped),
+                        Some((_, other)) => {
+                            value.push('\\');
+                            value.push(other);
+                        }
+                        None => anyhow::bail!("Unterminated string at column {}", column),
+                    },
+                    Some((_, c)) => value.push(c),
+                    None => anyhow::bail!("Unterminated string at column {}", column),
+                }
+            };
+            tokens.push(Token::Str(value));
+            rest = &rest[end..];
+        } else if c.is_ascii_digit() {
+            let end = rest
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .unwrap_or(rest.len());
+            let number = rest[..end]
+                .parse()
+                .with_context(|| format!("Invalid number at column {}", column))?;
+            tokens.push(Token::Number(number));
+            rest = &rest[end..];
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else {
+            anyhow::bail!("Unexpected `{}` at column {}", c, column);
+        }
+
+        rest = rest.trim_start();
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent parser over the tokens of a rule
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, op: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Op(o)) if *o == op);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut expr = self.and()?;
+        while self.eat("||") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut expr = self.unary()?;
+        while self.eat("&&") {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("(") {
+            let expr = self.or()?;
+            anyhow::ensure!(self.eat(")"), "Missing `)`");
+            return Ok(expr);
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr> {
+        let field = match self.next() {
+            Some(Token::Ident(field)) => field,
+            Some(other) => anyhow::bail!("Expected a field name, found {}", describe(&other)),
+            None => anyhow::bail!("Expected a field name at the end"),
+        };
+        let op = match self.next() {
+            Some(Token::Op(op)) => op,
+            Some(other) => anyhow::bail!("Expected an operator, found {}", describe(&other)),
+            None => anyhow::bail!("Expected an operator after `{}`", field),
+        };
+        let value = self
+            .next()
+            .with_context(|| format!("Expected a value after `{}`", op))?;
+
+        let text_field = match field.as_str() {
+            "app" => TextField::App,
+            "app_name" => TextField::AppName,
+            "title" => TextField::Title,
+            "artist" => TextField::Artist,
+            "album" => TextField::Album,
+            "duration" => {
+                let Token::Number(number) = value else {
+                    anyhow::bail!("`duration` is compared with a number of seconds");
+                };
+                let comparison = match op {
+                    "==" => Comparison::Eq,
+                    "!=" => Comparison::Ne,
+                    "<" => Comparison::Lt,
+                    "<=" => Comparison::Le,
+                    ">" => Comparison::Gt,
+                    ">=" => Comparison::Ge,
+                    _ => anyhow::bail!("`{}` can't be used with `duration`", op),
+                };
+                return Ok(Expr::Duration(comparison, number));
+            }
+            other => anyhow::bail!(
+                "Unknown field `{}` (expected app, app_name, title, artist, album or duration)",
+                other
+            ),
+        };
+
+        let Token::Str(value) = value else {
+            anyhow::bail!("`{}` is compared with a quoted string", field);
+        };
+        match op {
+            "==" => Ok(Expr::TextEquals(text_field, value)),
+            "!=" => Ok(Expr::Not(Box::new(Expr::TextEquals(text_field, value)))),
+            "=~" => {
+                let regex = Regex::new(&value).context("Invalid regex")?;
+                Ok(Expr::TextMatches(text_field, regex))
+            }
+            _ => anyhow::bail!("`{}` can't be used with `{}`", op, field),
+        }
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Ident(ident) => format!("`{}`", ident),
+        Token::Str(value) => format!("\"{}\"", value),
+        Token::Number(number) => number.to_string(),
+        Token::Op(op) => format!("`{}`", op),
+    }
+}
+
+impl Rule {
+    fn parse(rule: &str) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(rule)?,
+            pos: 0,
+        };
+
+        let condition = parser.or()?;
+        anyhow::ensure!(parser.eat("->"), "Expected `-> ignore` or `-> scrobble`");
+        let action = match parser.next() {
+            Some(Token::Ident(action)) if action == "ignore" => FilterAction::Ignore,
+            Some(Token::Ident(action)) if action == "scrobble" => FilterAction::Scrobble,
+            _ => anyhow::bail!("Expected `ignore` or `scrobble` after `->`"),
+        };
+        if let Some(extra) = parser.peek() {
+            anyhow::bail!("Unexpected {} after the action", describe(extra));
+        }
+
+        Ok(Self { condition, action })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filters(rules: &[&str]) -> Filters {
+        Filters::new(&rules.iter().map(|r| r.to_string()).collect::<Vec<_>>()).unwrap()
+    }
+
+    fn spotify(title: &str, duration: u64) -> FilterInput<'_> {
+        FilterInput {
+            app: "com.spotify.client",
+            app_name: "Spotify",
+            title,
+            artist: "Band",
+            album: "",
+            duration,
+        }
+    }
+
+    #[test]
+    fn test_app_and_duration() {
+        let filters = filters(&[r#"app == "com.spotify.client" && duration < 60 -> ignore"#]);
+
+        assert_eq!(
+            filters.action(&spotify("Jingle", 30)),
+            Some(FilterAction::Ignore)
+        );
+        assert_eq!(filters.action(&spotify("Song", 200)), None);
+        assert_eq!(
+            filters.action(&FilterInput {
+                app: "com.apple.Music",
+                ..spotify("Jingle", 30)
+            }),
+            None
+        );
+    }
+
+    #[test]
+    fn test_first_match_wins() {
+        let filters = filters(&[
+            r#"artist == "Band" && title =~ "(?i)live" -> scrobble"#,
+            r#"title =~ "(?i)live|demo" -> ignore"#,
+        ]);
+
+        assert_eq!(
+            filters.action(&spotify("Song (Live)", 200)),
+            Some(FilterAction::Scrobble)
+        );
+        assert_eq!(
+            filters.action(&FilterInput {
+                artist: "Other",
+                ..spotify("Song (Live)", 200)
+            }),
+            Some(FilterAction::Ignore)
+        );
+    }
+
+    #[test]
+    fn test_precedence_and_negation() {
+        // && binds tighter than ||
+        let filters =
+            filters(&[r#"album == "" || duration > 600 && !(app_name != "Spotify") -> ignore"#]);
+
+        assert_eq!(
+            filters.action(&FilterInput {
+                album: "Album",
+                ..spotify("Song", 900)
+            }),
+            Some(FilterAction::Ignore)
+        );
+        assert_eq!(
+            filters.action(&FilterInput {
+                album: "Album",
+                ..spotify("Song", 200)
+            }),
+            None
+        );
+        assert_eq!(
+            filters.action(&spotify("Song", 200)),
+            Some(FilterAction::Ignore)
+        );
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        let quotes = filters(&[r#"title == "Say \"Hi\"" -> ignore"#]);
+        assert_eq!(
+            quotes.action(&spotify("Say \"Hi\"", 200)),
+            Some(FilterAction::Ignore)
+        );
+
+        let regex = filters(&[r#"title =~ "\bLive\b" -> ignore"#]);
+        assert_eq!(
+            regex.action(&spotify("Song (Live)", 200)),
+            Some(FilterAction::Ignore)
+        );
+        assert_eq!(regex.action(&spotify("Lively", 200)), None);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |rule: &str| format!("{:#}", Filters::new(&[rule.to_string()]).unwrap_err());
+
+        assert!(error(r#"app == "x""#).contains("Expected `-> ignore`"));
+        assert!(error(r#"year > 2000 -> ignore"#).contains("Unknown field `year`"));
+        assert!(error(r#"duration == "long" -> ignore"#).contains("number of seconds"));
+        assert!(error(r#"title < "b" -> ignore"#).contains("can't be used"));
+        assert!(error(r#"title == "x -> ignore"#).contains("Unterminated string"));
+        assert!(error(r#"(app == "x" -> ignore"#).contains("Missing `)`"));
+        assert!(error(r#"app == "x" -> skip"#).contains("Expected `ignore` or `scrobble`"));
+        assert!(error(r#"title =~ "(" -> ignore"#).contains("Invalid regex"));
+        assert!(error(r#"app == "x" @ -> ignore"#).contains("Unexpected `@` at column 12"));
+    }
+}
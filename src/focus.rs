@@ -0,0 +1,74 @@
+// Focus module
+// Whether a macOS Focus mode (Do Not Disturb, Work, Sleep, ...) is on, to hold back notifications
+
+use crate::config::NotificationKind;
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+/// Focus modes that are on, as written by the Focus service
+const ASSERTIONS_FILE: &str = "Library/DoNotDisturb/DB/Assertions.json";
+
+/// Whether a Focus mode is on
+///
+/// Read from the Focus database, which macOS may only let apps with Full Disk Access read.
+/// Focus is taken as off when it can't be read.
+pub fn is_focus_on() -> bool {
+    match read_assertions() {
+        Ok(assertions) => has_assertions(&assertions),
+        Err(e) => {
+            log::debug!("Failed to read Focus state: {:#}", e);
+            false
+        }
+    }
+}
+
+/// Whether a notification of this kind should be shown now
+pub fn should_notify(kind: NotificationKind, during_focus: &[NotificationKind]) -> bool {
+    during_focus.contains(&kind) || !is_focus_on()
+}
+
+fn read_assertions() -> Result<Value> {
+    let path = dirs::home_dir()
+        .context("Failed to get home directory")?
+        .join(ASSERTIONS_FILE);
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    serde_json::from_str(&content).with_context(|| format!("Invalid {}", path.display()))
+}
+
+/// A Focus mode is on while it has an assertion record
+fn has_assertions(assertions: &Value) -> bool {
+    assertions["data"].as_array().is_some_and(|data| {
+        data.iter().any(|store| {
+            store["storeAssertionRecords"]
+                .as_array()
+                .is_some_and(|records| !records.is_empty())
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_has_assertions() {
+        let on = json!({"data": [{"storeAssertionRecords": [{
+            "assertionDetails": {
+                "assertionDetailsModeIdentifier": "com.apple.donotdisturb.mode.default",
+                "assertionDetailsReason": "user-action"
+            },
+            "assertionUUID": "0A1B2C3D"
+        }]}]});
+        let off = json!({"data": [{}], "header": {"timestamp": 1}});
+
+        assert!(has_assertions(&on));
+        assert!(!has_assertions(&off));
+        assert!(!has_assertions(
+            &json!({"data": [{"storeAssertionRecords": []}]})
+        ));
+        assert!(!has_assertions(&json!({})));
+    }
+}
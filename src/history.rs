@@ -0,0 +1,1187 @@
+// Scrobble history module
+// Keeps a local record of every scrobble and its per-service submission status
+
+use crate::scrobbler::Track;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use rusqlite::{params, types::Type, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+/// Submission status of a history entry for a single service
+#[derive(Debug, Clone, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Submission {
+    /// Service key (see `Scrobbler::key`)
+    pub service: String,
+    pub submitted: bool,
+    pub error: Option<String>,
+
+    /// The scrobble was removed from the service again
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub deleted: bool,
+
+    /// Too old for the service by the time it was flushed, and written to the export file instead
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub exported: bool,
+}
+
+impl Submission {
+    /// A submission queued for a service but not attempted yet
+    pub fn pending(service: &str) -> Self {
+        Self {
+            service: service.to_string(),
+            submitted: false,
+            error: None,
+            deleted: false,
+            exported: false,
+        }
+    }
+
+    /// Waiting to be (re-)submitted
+    pub fn is_queued(&self) -> bool {
+        !self.submitted && !self.exported
+    }
+}
+
+/// A single scrobble recorded in the history
+#[derive(Debug, Clone, Default, Hash, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub id: u64,
+    pub artist: String,
+    pub title: String,
+    pub album: Option<String>,
+    pub duration: Option<u64>,
+    pub played_at: DateTime<Utc>,
+    pub bundle_id: Option<String>,
+
+    /// Seconds the track was actually played (excluding pauses)
+    #[serde(default)]
+    pub listened_seconds: Option<u64>,
+
+    /// Recorded during a private session (not submitted anywhere)
+    #[serde(default)]
+    pub private: bool,
+
+    /// Played by someone else in guest mode; private, and never submitted, even on retry
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub guest: bool,
+
+    /// Deleted by the user; kept to document what happened on each service
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub deleted: bool,
+
+    /// Page the track was played on, for plays in a browser
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin_url: Option<String>,
+
+    /// Picked by a radio station or autoplay rather than the user
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub radio: bool,
+
+    /// MusicBrainz recording ID, when known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mbid: Option<String>,
+
+    /// Album artist, when it differs from the track artist (e.g. on compilations)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album_artist: Option<String>,
+
+    /// Live recording details moved out of the title, e.g. `Live at Wembley`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub live: Option<String>,
+
+    /// Kept back for review, as confidence that it's music was low; not submitted, even on
+    /// retry, until approved
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub review: bool,
+
+    /// Note added by the user
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+
+    /// Tags added by the user, e.g. `gym` or `work`; lowercase and sorted
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+
+    /// Per-service submission results
+    #[serde(default)]
+    pub submissions: Vec<Submission>,
+}
+
+impl HistoryEntry {
+    /// Reconstruct the track that was scrobbled
+    pub fn track(&self) -> Track {
+        Track {
+            title: self.title.clone(),
+            artist: self.artist.clone(),
+            album: self.album.clone(),
+            duration: self.duration,
+            origin_url: self.origin_url.clone(),
+            radio: self.radio,
+            mbid: self.mbid.clone(),
+            album_artist: self.album_artist.clone(),
+            live: self.live.clone(),
+        }
+    }
+
+    /// Check whether this entry should be re-submitted to the given service
+    pub fn needs_retry(&self, service: &str) -> bool {
+        if self.deleted || self.guest || self.review {
+            return false;
+        }
+
+        match self.submissions.iter().find(|s| s.service == service) {
+            Some(submission) => submission.is_queued(),
+            None => self.private,
+        }
+    }
+
+    /// Record the outcome of a submission to a service
+    pub fn set_submission(&mut self, service: &str, result: &Result<()>) {
+        let submission = Submission {
+            service: service.to_string(),
+            submitted: result.is_ok(),
+            error: result.as_ref().err().map(|e| format!("{:#}", e)),
+            deleted: false,
+            exported: false,
+        };
+
+        match self.submissions.iter_mut().find(|s| s.service == service) {
+            Some(existing) => *existing = submission,
+            None => self.submissions.push(submission),
+        }
+    }
+
+    /// Add a tag, lowercased; tags can't be empty or contain commas or semicolons
+    pub fn add_tag(&mut self, tag: &str) -> Result<()> {
+        let tag = tag.trim().to_lowercase();
+        if tag.is_empty() || tag.contains([',', ';']) {
+            anyhow::bail!("Invalid tag '{}'", tag);
+        }
+
+        if let Err(position) = self.tags.binary_search(&tag) {
+            self.tags.insert(position, tag);
+        }
+        Ok(())
+    }
+
+    /// Remove a tag, ignoring case; returns whether the entry had it
+    pub fn remove_tag(&mut self, tag: &str) -> bool {
+        let tag = tag.trim().to_lowercase();
+        let count = self.tags.len();
+        self.tags.retain(|t| *t != tag);
+        self.tags.len() != count
+    }
+}
+
+/// Schema changes, applied in order; `PRAGMA user_version` counts the ones already applied
+const MIGRATIONS: &[&str] = &[
+    // 1: scrobbles with their per-service submissions
+    "CREATE TABLE scrobbles (
+        id INTEGER PRIMARY KEY,
+        artist TEXT NOT NULL,
+        title TEXT NOT NULL,
+        album TEXT,
+        duration INTEGER,
+        played_at TEXT NOT NULL,
+        bundle_id TEXT,
+        listened_seconds INTEGER,
+        private INTEGER NOT NULL DEFAULT 0,
+        guest INTEGER NOT NULL DEFAULT 0,
+        deleted INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE INDEX scrobbles_played_at ON scrobbles (played_at);
+    CREATE INDEX scrobbles_artist ON scrobbles (artist COLLATE NOCASE);
+    CREATE TABLE submissions (
+        scrobble_id INTEGER NOT NULL REFERENCES scrobbles (id) ON DELETE CASCADE,
+        service TEXT NOT NULL,
+        submitted INTEGER NOT NULL,
+        error TEXT,
+        deleted INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (scrobble_id, service)
+    );",
+    // 2: page browser plays came from
+    "ALTER TABLE scrobbles ADD COLUMN origin_url TEXT;",
+    // 3: plays not chosen by the user
+    "ALTER TABLE scrobbles ADD COLUMN radio INTEGER NOT NULL DEFAULT 0;",
+    // 4: MusicBrainz recording IDs
+    "ALTER TABLE scrobbles ADD COLUMN mbid TEXT;",
+    // 5: submissions diverted to the export file
+    "ALTER TABLE submissions ADD COLUMN exported INTEGER NOT NULL DEFAULT 0;",
+    // 6: album artists of compilation tracks
+    "ALTER TABLE scrobbles ADD COLUMN album_artist TEXT;",
+    // 7: live recording details moved out of titles
+    "ALTER TABLE scrobbles ADD COLUMN live TEXT;",
+    // 8: low-confidence plays held back for review
+    "ALTER TABLE scrobbles ADD COLUMN review INTEGER NOT NULL DEFAULT 0;",
+    // 9: notes and tags added by the user
+    "ALTER TABLE scrobbles ADD COLUMN note TEXT;
+    CREATE TABLE tags (
+        scrobble_id INTEGER NOT NULL REFERENCES scrobbles (id) ON DELETE CASCADE,
+        tag TEXT NOT NULL,
+        PRIMARY KEY (scrobble_id, tag)
+    );",
+];
+
+/// Scrobble counts for status pages
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Totals {
+    /// Scrobbles that weren't deleted
+    pub scrobbles: usize,
+    /// Submissions waiting to be (re-)submitted, by service key
+    pub queued: HashMap<String, usize>,
+}
+
+/// Local scrobble history stored in SQLite
+///
+/// Entries are kept in memory, oldest first; changes are written back by [`History::save`].
+/// New entries get their IDs from the database, so other processes adding entries at the same
+/// time don't take the same ones.
+pub struct History {
+    conn: Connection,
+    entries: Vec<HistoryEntry>,
+    /// Fingerprint of each entry as last written, so saving only writes what changed
+    stored: HashMap<u64, u64>,
+    /// Entries handed out for changes since the last save, the only ones saving looks at
+    dirty: HashSet<u64>,
+}
+
+impl History {
+    /// Get the path to the history database
+    pub fn history_path() -> Result<PathBuf> {
+        Ok(crate::paths::Paths::current()?.history)
+    }
+
+    /// Load history from the database, creating or upgrading it as needed
+    pub fn load() -> Result<Self> {
+        let mut history = Self::open(connect()?)?;
+
+        let legacy_path = crate::paths::Paths::current()?.legacy_history;
+        if legacy_path.exists() && history.entries.is_empty() {
+            history.import_json_lines(&legacy_path)?;
+        }
+
+        Ok(history)
+    }
+
+    /// Read the entries played from `since` until before `until`, oldest first, without loading
+    /// the rest of the history
+    ///
+    /// For commands that only look at the history; the range is found with the index on play
+    /// times, so it stays quick however long the history gets.
+    pub fn between(
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Vec<HistoryEntry>> {
+        let mut conn = connect()?;
+        migrate(&mut conn)?;
+        read_entries(&conn, since, until)
+    }
+
+    /// Read the `limit` latest entries that weren't deleted, newest first, without loading the
+    /// rest of the history
+    pub fn latest(limit: usize) -> Result<Vec<HistoryEntry>> {
+        let mut conn = connect()?;
+        migrate(&mut conn)?;
+        read_latest(&conn, limit)
+    }
+
+    /// Count the scrobbles and queued submissions without loading the history
+    pub fn totals() -> Result<Totals> {
+        let mut conn = connect()?;
+        migrate(&mut conn)?;
+        read_totals(&conn)
+    }
+
+    /// Apply pending migrations and read every entry
+    fn open(mut conn: Connection) -> Result<Self> {
+        migrate(&mut conn)?;
+        let entries = read_entries(&conn, None, None)?;
+
+        let stored = entries.iter().map(|e| (e.id, fingerprint(e))).collect();
+        Ok(Self {
+            conn,
+            entries,
+            stored,
+            dirty: HashSet::new(),
+        })
+    }
+
+    /// Move the JSON lines history of earlier versions into the database
+    fn import_json_lines(&mut self, path: &Path) -> Result<()> {
+        let content = fs::read_to_string(path).context("Failed to read history file")?;
+
+        let mut entries = Vec::new();
+        for (line_no, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<HistoryEntry>(line) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => log::warn!("Skipping malformed history line {}: {}", line_no + 1, e),
+            }
+        }
+        self.replace(entries)?;
+
+        // Kept, renamed, in case anything went wrong
+        let imported = path.with_extension("jsonl.imported");
+        fs::rename(path, &imported).context("Failed to rename imported history file")?;
+        log::info!(
+            "Imported {} scrobble(s) into the history database (old file kept as {})",
+            self.entries.len(),
+            imported.display()
+        );
+
+        Ok(())
+    }
+
+    /// All recorded entries, oldest first
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    /// Mutable access to recorded entries; saving then has to look at all of them
+    pub fn entries_mut(&mut self) -> &mut [HistoryEntry] {
+        self.dirty.extend(self.entries.iter().map(|e| e.id));
+        &mut self.entries
+    }
+
+    /// Run `change` on every entry, only marking those it reports as changed for saving
+    ///
+    /// Returns how many were changed.
+    pub fn update_each(&mut self, mut change: impl FnMut(&mut HistoryEntry) -> bool) -> usize {
+        let mut changed = 0;
+        for entry in &mut self.entries {
+            if change(entry) {
+                self.dirty.insert(entry.id);
+                changed += 1;
+            }
+        }
+        changed
+    }
+
+    /// Find an entry by its ID, to change it
+    pub fn entry_mut(&mut self, id: u64) -> Option<&mut HistoryEntry> {
+        let entry = self.entries.iter_mut().rev().find(|e| e.id == id)?;
+        self.dirty.insert(id);
+        Some(entry)
+    }
+
+    /// Create a new entry for a scrobble (not yet persisted, so without an ID until recorded)
+    pub fn new_entry(
+        &self,
+        track: &Track,
+        played_at: DateTime<Utc>,
+        bundle_id: Option<String>,
+    ) -> HistoryEntry {
+        HistoryEntry {
+            id: 0,
+            artist: track.artist.clone(),
+            title: track.title.clone(),
+            album: track.album.clone(),
+            duration: track.duration,
+            played_at,
+            bundle_id,
+            listened_seconds: None,
+            private: false,
+            guest: false,
+            deleted: false,
+            origin_url: track.origin_url.clone(),
+            radio: track.radio,
+            mbid: track.mbid.clone(),
+            album_artist: track.album_artist.clone(),
+            live: track.live.clone(),
+            review: false,
+            note: None,
+            tags: Vec::new(),
+            submissions: Vec::new(),
+        }
+    }
+
+    /// Add entries played in the past, keeping the history in played order, and save other
+    /// changes
+    ///
+    /// Entries get fresh IDs.
+    pub fn insert(&mut self, entries: Vec<HistoryEntry>) -> Result<()> {
+        self.save()?;
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        let tx = self.conn.transaction().context("Failed to write history")?;
+        let mut inserted = Vec::new();
+        for mut entry in entries {
+            entry.id = 0;
+            entry.id = insert_entry(&tx, &entry)?;
+            inserted.push(entry);
+        }
+        tx.commit().context("Failed to write history")?;
+
+        for entry in inserted {
+            self.stored.insert(entry.id, fingerprint(&entry));
+            let index = self
+                .entries
+                .partition_point(|e| e.played_at <= entry.played_at);
+            self.entries.insert(index, entry);
+        }
+
+        Ok(())
+    }
+
+    /// Add a new entry to the history, returning the ID it was given
+    pub fn record(&mut self, mut entry: HistoryEntry) -> Result<u64> {
+        let tx = self
+            .conn
+            .transaction()
+            .context("Failed to write history entry")?;
+        entry.id = 0;
+        entry.id = insert_entry(&tx, &entry)?;
+        tx.commit().context("Failed to write history entry")?;
+
+        let id = entry.id;
+        self.stored.insert(id, fingerprint(&entry));
+        self.entries.push(entry);
+
+        Ok(id)
+    }
+
+    /// Replace the whole history, e.g. with one restored from a backup
+    pub fn replace(&mut self, mut entries: Vec<HistoryEntry>) -> Result<()> {
+        entries.sort_by_key(|e| e.played_at);
+
+        let tx = self.conn.transaction().context("Failed to write history")?;
+        tx.execute_batch("DELETE FROM submissions; DELETE FROM tags; DELETE FROM scrobbles;")
+            .context("Failed to clear history")?;
+        for entry in &mut entries {
+            entry.id = insert_entry(&tx, entry)?;
+        }
+        tx.commit().context("Failed to write history")?;
+
+        self.stored = entries.iter().map(|e| (e.id, fingerprint(e))).collect();
+        self.dirty.clear();
+        self.entries = entries;
+
+        Ok(())
+    }
+
+    /// Store the final listening time of an entry
+    pub fn set_listened(&mut self, id: u64, seconds: u64) -> Result<()> {
+        if let Some(entry) = self.entry_mut(id) {
+            entry.listened_seconds = Some(seconds);
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// Write entries changed since they were loaded or last saved
+    ///
+    /// Only rows that changed are updated, so entries other processes changed meanwhile are
+    /// left alone.
+    pub fn save(&mut self) -> Result<()> {
+        if self.dirty.is_empty() {
+            return Ok(());
+        }
+        let tx = self.conn.transaction().context("Failed to write history")?;
+
+        let mut written = Vec::new();
+        for entry in self.entries.iter().filter(|e| self.dirty.contains(&e.id)) {
+            let fingerprint = fingerprint(entry);
+            if self.stored.get(&entry.id) != Some(&fingerprint) {
+                update_entry(&tx, entry)?;
+                written.push((entry.id, fingerprint));
+            }
+        }
+
+        tx.commit().context("Failed to write history")?;
+        self.stored.extend(written);
+        self.dirty.clear();
+
+        Ok(())
+    }
+}
+
+/// Open the history database, creating its directory if needed
+fn connect() -> Result<Connection> {
+    let path = History::history_path()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context("Failed to create history directory")?;
+    }
+
+    let conn = Connection::open(&path).context("Failed to open history database")?;
+    // The app and the CLI can use the database at the same time
+    conn.busy_timeout(std::time::Duration::from_secs(5))
+        .context("Failed to configure history database")?;
+    Ok(conn)
+}
+
+/// Bring the database schema up to date
+fn migrate(conn: &mut Connection) -> Result<()> {
+    let version: usize = conn
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .context("Failed to read history database version")?;
+    if version > MIGRATIONS.len() {
+        anyhow::bail!(
+            "History database is from a newer version of OSX Scrobbler (schema {})",
+            version
+        );
+    }
+
+    let tx = conn
+        .transaction()
+        .context("Failed to upgrade history database")?;
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        tx.execute_batch(migration)
+            .with_context(|| format!("Failed to apply history migration {}", index + 1))?;
+        tx.pragma_update(None, "user_version", index + 1)
+            .context("Failed to upgrade history database")?;
+    }
+    tx.commit().context("Failed to upgrade history database")?;
+
+    Ok(())
+}
+
+/// Stored form of a play time; fixed width, so times sort as text
+fn time_key(time: &DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(chrono::SecondsFormat::Nanos, true)
+}
+
+/// Read the entries played from `since` until before `until` with their submissions and tags,
+/// oldest first
+fn read_entries(
+    conn: &Connection,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+) -> Result<Vec<HistoryEntry>> {
+    // Bounds past any stored time when not given, so the index covers every case
+    let since = since.as_ref().map_or_else(|| "0".to_string(), time_key);
+    let until = until.as_ref().map_or_else(|| "9".to_string(), time_key);
+
+    let mut submissions: HashMap<u64, Vec<Submission>> = HashMap::new();
+    {
+        let mut statement = conn
+            .prepare(
+                "SELECT scrobble_id, service, submitted, error, deleted, exported
+                 FROM submissions
+                 WHERE scrobble_id IN
+                     (SELECT id FROM scrobbles WHERE played_at >= ?1 AND played_at < ?2)
+                 ORDER BY rowid",
+            )
+            .context("Failed to read submissions")?;
+        let rows = statement
+            .query_map(params![since, until], |row| {
+                Ok((
+                    row.get::<_, u64>(0)?,
+                    Submission {
+                        service: row.get(1)?,
+                        submitted: row.get(2)?,
+                        error: row.get(3)?,
+                        deleted: row.get(4)?,
+                        exported: row.get(5)?,
+                    },
+                ))
+            })
+            .context("Failed to read submissions")?;
+        for row in rows {
+            let (id, submission) = row.context("Failed to read submission")?;
+            submissions.entry(id).or_default().push(submission);
+        }
+    }
+
+    let mut tags: HashMap<u64, Vec<String>> = HashMap::new();
+    {
+        let mut statement = conn
+            .prepare(
+                "SELECT scrobble_id, tag FROM tags
+                 WHERE scrobble_id IN
+                     (SELECT id FROM scrobbles WHERE played_at >= ?1 AND played_at < ?2)
+                 ORDER BY tag",
+            )
+            .context("Failed to read tags")?;
+        let rows = statement
+            .query_map(params![since, until], |row| {
+                Ok((row.get::<_, u64>(0)?, row.get(1)?))
+            })
+            .context("Failed to read tags")?;
+        for row in rows {
+            let (id, tag) = row.context("Failed to read tag")?;
+            tags.entry(id).or_default().push(tag);
+        }
+    }
+
+    let mut entries = Vec::new();
+    let mut statement = conn
+        .prepare(
+            "SELECT id, artist, title, album, duration, played_at, bundle_id,
+                    listened_seconds, private, guest, deleted, origin_url, radio,
+                    mbid, album_artist, live, review, note
+             FROM scrobbles WHERE played_at >= ?1 AND played_at < ?2
+             ORDER BY played_at, id",
+        )
+        .context("Failed to read history")?;
+    let rows = statement
+        .query_map(params![since, until], |row| {
+            let played_at: String = row.get(5)?;
+            Ok(HistoryEntry {
+                id: row.get(0)?,
+                artist: row.get(1)?,
+                title: row.get(2)?,
+                album: row.get(3)?,
+                duration: row.get(4)?,
+                played_at: DateTime::parse_from_rfc3339(&played_at)
+                    .map_err(|e| {
+                        rusqlite::Error::FromSqlConversionFailure(5, Type::Text, Box::new(e))
+                    })?
+                    .with_timezone(&Utc),
+                bundle_id: row.get(6)?,
+                listened_seconds: row.get(7)?,
+                private: row.get(8)?,
+                guest: row.get(9)?,
+                deleted: row.get(10)?,
+                origin_url: row.get(11)?,
+                radio: row.get(12)?,
+                mbid: row.get(13)?,
+                album_artist: row.get(14)?,
+                live: row.get(15)?,
+                review: row.get(16)?,
+                note: row.get(17)?,
+                tags: Vec::new(),
+                submissions: Vec::new(),
+            })
+        })
+        .context("Failed to read history")?;
+    for row in rows {
+        match row {
+            Ok(mut entry) => {
+                entry.submissions = submissions.remove(&entry.id).unwrap_or_default();
+                entry.tags = tags.remove(&entry.id).unwrap_or_default();
+                entries.push(entry);
+            }
+            Err(e) => log::warn!("Skipping unreadable history entry: {}", e),
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Read the `limit` latest entries that weren't deleted, newest first
+fn read_latest(conn: &Connection, limit: usize) -> Result<Vec<HistoryEntry>> {
+    if limit == 0 {
+        return Ok(Vec::new());
+    }
+
+    // Play time of the oldest of them, so only the entries from there on are read
+    let oldest: Option<String> = conn
+        .query_row(
+            "SELECT played_at FROM scrobbles WHERE NOT deleted
+             ORDER BY played_at DESC LIMIT 1 OFFSET ?1",
+            params![limit - 1],
+            |row| row.get(0),
+        )
+        .optional()
+        .context("Failed to read history")?;
+    let since = oldest
+        .map(|time| DateTime::parse_from_rfc3339(&time).map(|time| time.with_timezone(&Utc)))
+        .transpose()
+        .context("Invalid play time in history")?;
+
+    Ok(read_entries(conn, since, None)?
+        .into_iter()
+        .rev()
+        .filter(|e| !e.deleted)
+        .take(limit)
+        .collect())
+}
+
+/// Count the scrobbles that weren't deleted and the queued submissions of each service
+fn read_totals(conn: &Connection) -> Result<Totals> {
+    let scrobbles = conn
+        .query_row(
+            "SELECT COUNT(*) FROM scrobbles WHERE NOT deleted",
+            [],
+            |row| row.get(0),
+        )
+        .context("Failed to count scrobbles")?;
+
+    // Queued as in `Submission::is_queued`, of scrobbles that can still be submitted
+    let mut statement = conn
+        .prepare(
+            "SELECT service, COUNT(*) FROM submissions
+             JOIN scrobbles ON scrobbles.id = submissions.scrobble_id
+             WHERE NOT submitted AND NOT exported AND NOT private AND NOT scrobbles.deleted
+             GROUP BY service",
+        )
+        .context("Failed to count queued submissions")?;
+    let queued = statement
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .and_then(|rows| rows.collect())
+        .context("Failed to count queued submissions")?;
+
+    Ok(Totals { scrobbles, queued })
+}
+
+/// Insert an entry with its submissions and tags, returning its ID
+///
+/// An entry without an ID (0) gets the next free one from the database.
+fn insert_entry(conn: &Connection, entry: &HistoryEntry) -> Result<u64> {
+    conn.prepare_cached(
+        "INSERT INTO scrobbles (id, artist, title, album, duration, played_at, bundle_id,
+                                listened_seconds, private, guest, deleted, origin_url,
+                                radio, mbid, album_artist, live, review, note)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17,
+                 ?18)",
+    )
+    .and_then(|mut statement| {
+        statement.execute(params![
+            (entry.id != 0).then_some(entry.id),
+            entry.artist,
+            entry.title,
+            entry.album,
+            entry.duration,
+            time_key(&entry.played_at),
+            entry.bundle_id,
+            entry.listened_seconds,
+            entry.private,
+            entry.guest,
+            entry.deleted,
+            entry.origin_url,
+            entry.radio,
+            entry.mbid,
+            entry.album_artist,
+            entry.live,
+            entry.review,
+            entry.note,
+        ])
+    })
+    .with_context(|| {
+        format!(
+            "Failed to write history entry {} - {}",
+            entry.artist, entry.title
+        )
+    })?;
+
+    let id = conn.last_insert_rowid() as u64;
+    write_children(conn, id, entry)?;
+    Ok(id)
+}
+
+/// Update a stored entry with its submissions and tags
+fn update_entry(conn: &Connection, entry: &HistoryEntry) -> Result<()> {
+    conn.prepare_cached(
+        "UPDATE scrobbles SET
+            artist = ?2, title = ?3, album = ?4, duration = ?5, played_at = ?6,
+            bundle_id = ?7, listened_seconds = ?8, private = ?9, guest = ?10, deleted = ?11,
+            origin_url = ?12, radio = ?13, mbid = ?14, album_artist = ?15, live = ?16,
+            review = ?17, note = ?18
+         WHERE id = ?1",
+    )
+    .and_then(|mut statement| {
+        statement.execute(params![
+            entry.id,
+            entry.artist,
+            entry.title,
+            entry.album,
+            entry.duration,
+            time_key(&entry.played_at),
+            entry.bundle_id,
+            entry.listened_seconds,
+            entry.private,
+            entry.guest,
+            entry.deleted,
+            entry.origin_url,
+            entry.radio,
+            entry.mbid,
+            entry.album_artist,
+            entry.live,
+            entry.review,
+            entry.note,
+        ])
+    })
+    .with_context(|| format!("Failed to write history entry {}", entry.id))?;
+
+    write_children(conn, entry.id, entry)
+}
+
+/// Replace the submissions and tags stored for entry `id`
+fn write_children(conn: &Connection, id: u64, entry: &HistoryEntry) -> Result<()> {
+    conn.execute(
+        "DELETE FROM submissions WHERE scrobble_id = ?1",
+        params![id],
+    )
+    .with_context(|| format!("Failed to write submissions of entry {}", id))?;
+    for submission in &entry.submissions {
+        conn.prepare_cached(
+            "INSERT INTO submissions (scrobble_id, service, submitted, error, deleted, exported)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )
+        .and_then(|mut statement| {
+            statement.execute(params![
+                id,
+                submission.service,
+                submission.submitted,
+                submission.error,
+                submission.deleted,
+                submission.exported,
+            ])
+        })
+        .with_context(|| format!("Failed to write submissions of entry {}", id))?;
+    }
+
+    conn.execute("DELETE FROM tags WHERE scrobble_id = ?1", params![id])
+        .with_context(|| format!("Failed to write tags of entry {}", id))?;
+    for tag in &entry.tags {
+        conn.prepare_cached("INSERT INTO tags (scrobble_id, tag) VALUES (?1, ?2)")
+            .and_then(|mut statement| statement.execute(params![id, tag]))
+            .with_context(|| format!("Failed to write tags of entry {}", id))?;
+    }
+
+    Ok(())
+}
+
+fn fingerprint(entry: &HistoryEntry) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    entry.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Check whether a service key matches a user-supplied filter
+///
+/// `lastfm` matches Last.fm, `listenbrainz` matches every ListenBrainz instance
+/// and `listenbrainz:<name>` matches a single instance.
+pub fn service_matches(key: &str, filter: &str) -> bool {
+    key.eq_ignore_ascii_case(filter)
+        || key
+            .to_ascii_lowercase()
+            .starts_with(&format!("{}:", filter.to_ascii_lowercase()))
+}
+
+/// Parse a `--since` value
+///
+/// Accepts RFC 3339 timestamps, `YYYY-MM-DD` dates (midnight UTC) and
+/// relative durations such as `30m`, `12h` or `7d`.
+pub fn parse_since(value: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    let value = value.trim();
+
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+        return Ok(timestamp.with_timezone(&Utc));
+    }
+
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc());
+    }
+
+    if let Some(unit) = value.chars().last() {
+        let amount: i64 = value[..value.len() - unit.len_utf8()]
+            .parse()
+            .with_context(|| format!("Invalid --since value '{}'", value))?;
+        let duration = match unit {
+            'm' => Duration::minutes(amount),
+            'h' => Duration::hours(amount),
+            'd' => Duration::days(amount),
+            'w' => Duration::weeks(amount),
+            _ => anyhow::bail!("Invalid --since unit '{}' (expected m, h, d or w)", unit),
+        };
+        return Ok(now - duration);
+    }
+
+    anyhow::bail!("Invalid --since value '{}'", value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn entry(submissions: Vec<Submission>, private: bool) -> HistoryEntry {
+        HistoryEntry {
+            id: 1,
+            artist: "Artist".to_string(),
+            title: "Song".to_string(),
+            duration: Some(200),
+            played_at: Utc.with_ymd_and_hms(2026, 1, 1, 12, 0, 0).unwrap(),
+            private,
+            submissions,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_failed_submission_needs_retry() {
+        let entry = entry(
+            vec![Submission {
+                service: "lastfm".to_string(),
+                submitted: false,
+                error: Some("timeout".to_string()),
+                deleted: false,
+                exported: false,
+            }],
+            false,
+        );
+
+        assert!(entry.needs_retry("lastfm"));
+        assert!(!entry.needs_retry("listenbrainz:Primary"));
+    }
+
+    #[test]
+    fn test_private_entry_needs_retry_everywhere() {
+        let entry = entry(Vec::new(), true);
+
+        assert!(entry.needs_retry("lastfm"));
+        assert!(entry.needs_retry("listenbrainz:Primary"));
+    }
+
+    #[test]
+    fn test_guest_entry_never_needs_retry() {
+        let mut entry = entry(Vec::new(), true);
+        entry.guest = true;
+
+        assert!(!entry.needs_retry("lastfm"));
+    }
+
+    #[test]
+    fn test_tags() {
+        let mut entry = entry(Vec::new(), false);
+        entry.add_tag(" Work").unwrap();
+        entry.add_tag("gym").unwrap();
+        entry.add_tag("work").unwrap();
+        assert_eq!(entry.tags, ["gym", "work"]);
+
+        assert!(entry.add_tag("").is_err());
+        assert!(entry.add_tag("a;b").is_err());
+
+        assert!(entry.remove_tag("GYM"));
+        assert!(!entry.remove_tag("gym"));
+        assert_eq!(entry.tags, ["work"]);
+    }
+
+    #[test]
+    fn test_entry_held_for_review_needs_approval() {
+        let mut entry = entry(Vec::new(), true);
+        entry.review = true;
+        assert!(!entry.needs_retry("lastfm"));
+
+        entry.review = false;
+        assert!(entry.needs_retry("lastfm"));
+    }
+
+    #[test]
+    fn test_set_submission_replaces_existing() {
+        let mut entry = entry(Vec::new(), false);
+        entry.set_submission("lastfm", &Err(anyhow::anyhow!("boom")));
+        entry.set_submission("lastfm", &Ok(()));
+
+        assert_eq!(entry.submissions.len(), 1);
+        assert!(entry.submissions[0].submitted);
+        assert_eq!(entry.submissions[0].error, None);
+    }
+
+    #[test]
+    fn test_insert_keeps_played_order_and_unique_ids() {
+        let mut history = History::open(Connection::open_in_memory().unwrap()).unwrap();
+        history.record(entry(Vec::new(), false)).unwrap();
+
+        let mut older = entry(Vec::new(), false);
+        older.played_at = Utc.with_ymd_and_hms(2025, 12, 31, 12, 0, 0).unwrap();
+        history.insert(vec![older]).unwrap();
+
+        let ids: Vec<u64> = history.entries().iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec![2, 1]);
+        assert_eq!(history.record(entry(Vec::new(), false)).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_processes_sharing_the_database_keep_each_others_changes() {
+        let path = std::env::temp_dir().join(format!(
+            "osx_scrobbler_history_shared_test_{}.sqlite3",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+
+        // Like the app and a CLI command, each with the history loaded before the other wrote
+        let mut app = History::open(Connection::open(&path).unwrap()).unwrap();
+        let mut cli = History::open(Connection::open(&path).unwrap()).unwrap();
+        let first = app.record(entry(Vec::new(), false)).unwrap();
+        let second = cli.record(entry(Vec::new(), false)).unwrap();
+        assert_ne!(first, second);
+
+        cli.insert(vec![entry(Vec::new(), false)]).unwrap();
+        cli.entry_mut(second).unwrap().add_tag("gym").unwrap();
+        cli.save().unwrap();
+        app.set_listened(first, 180).unwrap();
+        app.entries_mut();
+        app.save().unwrap();
+
+        let reloaded = History::open(Connection::open(&path).unwrap()).unwrap();
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(reloaded.entries().len(), 3);
+        let stored = |id: u64| reloaded.entries().iter().find(|e| e.id == id).unwrap();
+        assert_eq!(stored(first).listened_seconds, Some(180));
+        assert_eq!(stored(second).tags, ["gym"]);
+    }
+
+    #[test]
+    fn test_update_each_marks_only_changed_entries() {
+        let mut history = History::open(Connection::open_in_memory().unwrap()).unwrap();
+        let first = history.record(entry(Vec::new(), false)).unwrap();
+        let second = history.record(entry(Vec::new(), false)).unwrap();
+
+        let changed = history.update_each(|e| e.id == second && e.add_tag("gym").is_ok());
+        assert_eq!(changed, 1);
+        assert_eq!(history.dirty, HashSet::from([second]));
+        assert!(!history.dirty.contains(&first));
+    }
+
+    #[test]
+    fn test_read_entries_in_range() {
+        let mut history = History::open(Connection::open_in_memory().unwrap()).unwrap();
+        for day in 1..=3 {
+            let mut played = entry(Vec::new(), false);
+            played.played_at = Utc.with_ymd_and_hms(2026, 1, day, 12, 0, 0).unwrap();
+            played.set_submission("lastfm", &Ok(()));
+            played.add_tag(&format!("day {}", day)).unwrap();
+            history.record(played).unwrap();
+        }
+
+        let since = Utc.with_ymd_and_hms(2026, 1, 2, 0, 0, 0).unwrap();
+        let entries = read_entries(&history.conn, Some(since), None).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].tags, ["day 2"]);
+        assert_eq!(entries[1].submissions.len(), 1);
+
+        let until = Utc.with_ymd_and_hms(2026, 1, 3, 0, 0, 0).unwrap();
+        let entries = read_entries(&history.conn, Some(since), Some(until)).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].tags, ["day 2"]);
+        assert_eq!(read_entries(&history.conn, None, None).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_latest_and_totals() {
+        let mut history = History::open(Connection::open_in_memory().unwrap()).unwrap();
+        for day in 1..=4 {
+            let mut played = entry(Vec::new(), day == 4);
+            played.played_at = Utc.with_ymd_and_hms(2026, 1, day, 12, 0, 0).unwrap();
+            played.set_submission("lastfm", &Err(anyhow::anyhow!("timeout")));
+            played.set_submission("listenbrainz:Primary", &Ok(()));
+            history.record(played).unwrap();
+        }
+        history.entry_mut(3).unwrap().deleted = true;
+        history.save().unwrap();
+
+        let latest = read_latest(&history.conn, 2).unwrap();
+        let ids: Vec<u64> = latest.iter().map(|e| e.id).collect();
+        assert_eq!(ids, [4, 2]);
+        assert_eq!(read_latest(&history.conn, 10).unwrap().len(), 3);
+        assert!(read_latest(&history.conn, 0).unwrap().is_empty());
+
+        // Neither the deleted nor the private scrobble is waiting for Last.fm
+        let totals = read_totals(&history.conn).unwrap();
+        assert_eq!(totals.scrobbles, 3);
+        assert_eq!(totals.queued, HashMap::from([("lastfm".to_string(), 2)]));
+    }
+
+    #[test]
+    fn test_database_round_trip() {
+        let path = std::env::temp_dir().join(format!(
+            "osx_scrobbler_history_test_{}.sqlite3",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+
+        let mut history = History::open(Connection::open(&path).unwrap()).unwrap();
+        let mut first = entry(Vec::new(), false);
+        first.set_submission("lastfm", &Ok(()));
+        first.set_submission("listenbrainz:Primary", &Err(anyhow::anyhow!("timeout")));
+        history.record(first).unwrap();
+        let mut second = entry(Vec::new(), true);
+        second.album = Some("Album".to_string());
+        second.played_at = Utc.with_ymd_and_hms(2026, 1, 1, 12, 3, 30).unwrap()
+            + Duration::nanoseconds(123_456_789);
+        history.record(second).unwrap();
+        history.set_listened(1, 180).unwrap();
+        history.entry_mut(2).unwrap().guest = true;
+        history.save().unwrap();
+
+        let reloaded = History::open(Connection::open(&path).unwrap()).unwrap();
+        let _ = fs::remove_file(&path);
+
+        let json = |entries: &[HistoryEntry]| serde_json::to_value(entries).unwrap();
+        assert_eq!(json(reloaded.entries()), json(history.entries()));
+        assert_eq!(reloaded.entries()[0].listened_seconds, Some(180));
+        assert_eq!(reloaded.entries()[0].submissions.len(), 2);
+        assert!(reloaded.entries()[1].guest);
+
+        let version: usize = reloaded
+            .conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+    }
+
+    #[test]
+    fn test_replace_drops_tags_of_old_entries() {
+        // Without the cascade, as SQLite builds other than the bundled one default to
+        let conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "foreign_keys", false).unwrap();
+        let mut history = History::open(conn).unwrap();
+        for tag in ["gym", "work"] {
+            let mut tagged = entry(Vec::new(), false);
+            tagged.add_tag(tag).unwrap();
+            history.record(tagged).unwrap();
+        }
+
+        let mut restored = entry(Vec::new(), false);
+        restored.add_tag("restored").unwrap();
+        history.replace(vec![restored]).unwrap();
+
+        // Entry 2's tag would go to the next entry given its ID
+        let reloaded = History::open(history.conn).unwrap();
+        assert_eq!(reloaded.entries()[0].tags, ["restored"]);
+        let rows: u64 = reloaded
+            .conn
+            .query_row("SELECT COUNT(*) FROM tags", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(rows, 1);
+    }
+
+    #[test]
+    fn test_service_matches() {
+        assert!(service_matches("lastfm", "lastfm"));
+        assert!(service_matches("lastfm", "LastFM"));
+        assert!(service_matches("listenbrainz:Primary", "listenbrainz"));
+        assert!(service_matches(
+            "listenbrainz:Primary",
+            "listenbrainz:primary"
+        ));
+        assert!(!service_matches("listenbrainz:Primary", "lastfm"));
+        assert!(!service_matches("listenbrainz:Primary", "listen"));
+    }
+
+    #[test]
+    fn test_parse_since() {
+        let now = Utc.with_ymd_and_hms(2026, 1, 10, 12, 0, 0).unwrap();
+
+        assert_eq!(
+            parse_since("2026-01-01", now).unwrap(),
+            Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap()
+        );
+        assert_eq!(
+            parse_since("2026-01-05T08:30:00Z", now).unwrap(),
+            Utc.with_ymd_and_hms(2026, 1, 5, 8, 30, 0).unwrap()
+        );
+        assert_eq!(
+            parse_since("2d", now).unwrap(),
+            Utc.with_ymd_and_hms(2026, 1, 8, 12, 0, 0).unwrap()
+        );
+        assert_eq!(
+            parse_since("90m", now).unwrap(),
+            Utc.with_ymd_and_hms(2026, 1, 10, 10, 30, 0).unwrap()
+        );
+        assert!(parse_since("yesterday", now).is_err());
+        assert!(parse_since("", now).is_err());
+    }
+}
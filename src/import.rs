@@ -0,0 +1,373 @@
+// Import module
+// Reads scrobbles from Rockbox .scrobbler.log files and Last.fm CSV exports, for
+// `osx-scrobbler import`
+
+use crate::history::HistoryEntry;
+use crate::reconcile::MATCH_TOLERANCE_SECONDS;
+use crate::scrobbler::Track;
+use anyhow::Result;
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use std::collections::HashMap;
+
+/// Kind of file to import
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// Rockbox-style `.scrobbler.log` (Audioscrobbler 1.1)
+    ScrobblerLog,
+    /// CSV export of a Last.fm profile, with or without a header line
+    LastfmCsv,
+}
+
+impl ImportFormat {
+    /// Guess the format from the file's contents
+    pub fn detect(text: &str) -> Self {
+        if text.trim_start().starts_with("#AUDIOSCROBBLER") {
+            Self::ScrobblerLog
+        } else {
+            Self::LastfmCsv
+        }
+    }
+}
+
+/// A play read from an import file
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedScrobble {
+    pub track: Track,
+    pub played_at: DateTime<Utc>,
+}
+
+/// Plays read from an import file
+#[derive(Debug, Default)]
+pub struct ImportedFile {
+    /// Oldest first
+    pub scrobbles: Vec<ImportedScrobble>,
+    /// Lines without an artist, title or valid time, and plays the log marks as skipped
+    pub skipped: usize,
+}
+
+/// Read the plays from an import file
+pub fn parse(text: &str, format: ImportFormat) -> Result<ImportedFile> {
+    let mut file = match format {
+        ImportFormat::ScrobblerLog => parse_scrobbler_log(text),
+        ImportFormat::LastfmCsv => parse_lastfm_csv(text)?,
+    };
+    file.scrobbles.sort_by_key(|s| s.played_at);
+    Ok(file)
+}
+
+/// Plays that aren't in the history yet
+///
+/// A play is in the history when an entry has the same artist and title and was played within
+/// [`MATCH_TOLERANCE_SECONDS`]; each entry accounts for one play, so repeats are kept apart.
+pub fn new_scrobbles(
+    entries: &[HistoryEntry],
+    scrobbles: Vec<ImportedScrobble>,
+) -> Vec<ImportedScrobble> {
+    let key =
+        |artist: &str, title: &str| (artist.trim().to_lowercase(), title.trim().to_lowercase());
+    let mut recorded: HashMap<(String, String), Vec<Option<DateTime<Utc>>>> = HashMap::new();
+    for entry in entries.iter().filter(|e| !e.deleted) {
+        recorded
+            .entry(key(&entry.artist, &entry.title))
+            .or_default()
+            .push(Some(entry.played_at));
+    }
+
+    scrobbles
+        .into_iter()
+        .filter(|scrobble| {
+            let Some(times) = recorded.get_mut(&key(&scrobble.track.artist, &scrobble.track.title))
+            else {
+                return true;
+            };
+            let found = times.iter_mut().find(|time| {
+                time.is_some_and(|time| {
+                    (time - scrobble.played_at).num_seconds().abs() <= MATCH_TOLERANCE_SECONDS
+                })
+            });
+            match found {
+                Some(time) => {
+                    *time = None;
+                    false
+                }
+                None => true,
+            }
+        })
+        .collect()
+}
+
+/// Tab-separated lines of artist, album, title, track number, duration, rating, timestamp and
+/// MBID, after `#` header lines
+///
+/// Timestamps are UTC when the header says `#TZ/UTC`, and local time otherwise.
+fn parse_scrobbler_log(text: &str) -> ImportedFile {
+    let utc = text.lines().any(|line| line.trim() == "#TZ/UTC");
+    let mut file = ImportedFile::default();
+
+    for line in text.lines() {
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split('\t').map(str::trim).collect();
+        let field = |i: usize| fields.get(i).copied().filter(|f| !f.is_empty());
+        let played_at = field(6).and_then(|t| t.parse::<i64>().ok()).and_then(|t| {
+            if utc {
+                DateTime::from_timestamp(t, 0)
+            } else {
+                let local = DateTime::from_timestamp(t, 0)?.naive_utc();
+                Local
+                    .from_local_datetime(&local)
+                    .earliest()
+                    .map(|t| t.with_timezone(&Utc))
+            }
+        });
+        let (Some(artist), Some(title), Some(played_at)) = (field(0), field(2), played_at) else {
+            file.skipped += 1;
+            continue;
+        };
+        if field(5) == Some("S") {
+            file.skipped += 1;
+            continue;
+        }
+
+        file.scrobbles.push(ImportedScrobble {
+            track: Track {
+                title: title.to_string(),
+                artist: artist.to_string(),
+                album: field(1).map(str::to_string),
+                duration: field(4).and_then(|d| d.parse().ok()),
+                origin_url: None,
+                radio: false,
+                mbid: field(7).map(str::to_string),
+                album_artist: None,
+                live: None,
+            },
+            played_at,
+        });
+    }
+    file
+}
+
+/// Columns of a Last.fm CSV export
+struct CsvColumns {
+    artist: usize,
+    title: usize,
+    album: Option<usize>,
+    time: usize,
+    mbid: Option<usize>,
+}
+
+impl CsvColumns {
+    /// Columns named by a header line, if the line is one
+    fn from_header(fields: &[String]) -> Option<Self> {
+        let find = |names: &[&str]| {
+            fields
+                .iter()
+                .position(|f| names.contains(&f.trim().to_lowercase().as_str()))
+        };
+        Some(Self {
+            artist: find(&["artist", "artist_name"])?,
+            title: find(&["track", "title", "name", "track_name"])?,
+            album: find(&["album", "album_name"]),
+            time: find(&["uts", "timestamp", "date", "utc_time", "time", "played_at"])?,
+            mbid: find(&["track_mbid", "mbid"]),
+        })
+    }
+
+    /// Without a header: artist, album, title and date, as most export tools write them
+    fn headerless() -> Self {
+        Self {
+            artist: 0,
+            album: Some(1),
+            title: 2,
+            time: 3,
+            mbid: None,
+        }
+    }
+}
+
+fn parse_lastfm_csv(text: &str) -> Result<ImportedFile> {
+    let mut lines = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .peekable();
+    let Some(first) = lines.peek() else {
+        return Ok(ImportedFile::default());
+    };
+    let columns = match CsvColumns::from_header(&csv_fields(first)) {
+        Some(columns) => {
+            lines.next();
+            columns
+        }
+        None => CsvColumns::headerless(),
+    };
+    if csv_fields(lines.peek().copied().unwrap_or_default()).len() <= columns.title {
+        anyhow::bail!("Not a Last.fm CSV export or .scrobbler.log file");
+    }
+
+    let mut file = ImportedFile::default();
+    for line in lines {
+        let fields = csv_fields(line);
+        let field = |i: usize| fields.get(i).map(|f| f.trim()).filter(|f| !f.is_empty());
+        let played_at = field(columns.time).and_then(parse_time);
+        let (Some(artist), Some(title), Some(played_at)) =
+            (field(columns.artist), field(columns.title), played_at)
+        else {
+            file.skipped += 1;
+            continue;
+        };
+
+        file.scrobbles.push(ImportedScrobble {
+            track: Track {
+                title: title.to_string(),
+                artist: artist.to_string(),
+                album: columns.album.and_then(field).map(str::to_string),
+                duration: None,
+                origin_url: None,
+                radio: false,
+                mbid: columns.mbid.and_then(field).map(str::to_string),
+                album_artist: None,
+                live: None,
+            },
+            played_at,
+        });
+    }
+    Ok(file)
+}
+
+/// A UTC time as export tools write it: Unix seconds or milliseconds, RFC 3339,
+/// `31 Jan 2026 12:34`, `31 Jan 2026, 12:34` or `2026-01-31 12:34:56`
+fn parse_time(text: &str) -> Option<DateTime<Utc>> {
+    if let Ok(number) = text.parse::<i64>() {
+        // Milliseconds from some tools
+        let seconds = if number > 100_000_000_000 {
+            number / 1000
+        } else {
+            number
+        };
+        return DateTime::from_timestamp(seconds, 0);
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Some(time.with_timezone(&Utc));
+    }
+    [
+        "%d %b %Y %H:%M",
+        "%d %b %Y, %H:%M",
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%d %H:%M",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+    .map(|time| time.and_utc())
+}
+
+/// Split a CSV line into its fields, unquoting quoted ones
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(text: &str) -> DateTime<Utc> {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_scrobbler_log() {
+        let log = "#AUDIOSCROBBLER/1.1\n#TZ/UTC\n#CLIENT/Rockbox sansae200 $Revision$\n\
+                   Band\tAlbum\tSong\t1\t215\tL\t1767225600\tb1a9c0e9-d987-4042-ae91-78d6a3267d69\n\
+                   Band\t\tSkipped\t2\t180\tS\t1767225900\t\n\
+                   Band\t\tOther\t3\t180\tL\t1767225000\n";
+        assert_eq!(ImportFormat::detect(log), ImportFormat::ScrobblerLog);
+
+        let file = parse(log, ImportFormat::ScrobblerLog).unwrap();
+        assert_eq!(file.skipped, 1);
+        assert_eq!(file.scrobbles.len(), 2);
+        assert_eq!(file.scrobbles[0].track.title, "Other");
+        assert_eq!(file.scrobbles[0].track.album, None);
+
+        let song = &file.scrobbles[1];
+        assert_eq!(song.played_at, time("2026-01-01T00:00:00Z"));
+        assert_eq!(song.track.album.as_deref(), Some("Album"));
+        assert_eq!(song.track.duration, Some(215));
+        assert_eq!(
+            song.track.mbid.as_deref(),
+            Some("b1a9c0e9-d987-4042-ae91-78d6a3267d69")
+        );
+    }
+
+    #[test]
+    fn test_parse_lastfm_csv() {
+        // Without a header, as written by most export tools
+        let csv = "\"Band, The\",Album,Song,31 Jan 2026 12:34\nBand,,Other,\n";
+        assert_eq!(ImportFormat::detect(csv), ImportFormat::LastfmCsv);
+        let file = parse(csv, ImportFormat::LastfmCsv).unwrap();
+        assert_eq!(file.skipped, 1);
+        assert_eq!(file.scrobbles.len(), 1);
+        assert_eq!(file.scrobbles[0].track.artist, "Band, The");
+        assert_eq!(file.scrobbles[0].played_at, time("2026-01-31T12:34:00Z"));
+
+        // With a header, in any column order
+        let csv = "uts,utc_time,artist,artist_mbid,album,album_mbid,track,track_mbid\n\
+                   1767225600,\"01 Jan 2026, 00:00\",Band,,Album,,Song,abc\n";
+        let file = parse(csv, ImportFormat::LastfmCsv).unwrap();
+        assert_eq!(file.skipped, 0);
+        assert_eq!(file.scrobbles[0].track.title, "Song");
+        assert_eq!(file.scrobbles[0].track.mbid.as_deref(), Some("abc"));
+        assert_eq!(file.scrobbles[0].played_at, time("2026-01-01T00:00:00Z"));
+
+        assert!(parse("just some text\n", ImportFormat::LastfmCsv).is_err());
+    }
+
+    #[test]
+    fn test_new_scrobbles_skips_recorded_plays() {
+        let played_at = time("2026-01-01T00:00:00Z");
+        let scrobble = |title: &str, offset: i64| ImportedScrobble {
+            track: Track {
+                title: title.to_string(),
+                artist: "Band".to_string(),
+                ..Default::default()
+            },
+            played_at: played_at + chrono::Duration::seconds(offset),
+        };
+
+        let recorded = HistoryEntry {
+            id: 1,
+            artist: "band".to_string(),
+            title: "song".to_string(),
+            played_at: played_at + chrono::Duration::seconds(30),
+            ..Default::default()
+        };
+        let new = new_scrobbles(
+            &[recorded],
+            vec![
+                scrobble("Song", 0),
+                scrobble("Song", 60),
+                scrobble("Other", 0),
+            ],
+        );
+
+        // The recorded play accounts for one of the two plays close to it
+        assert_eq!(new, vec![scrobble("Song", 60), scrobble("Other", 0)]);
+    }
+}
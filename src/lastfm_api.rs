@@ -0,0 +1,472 @@
+// Last.fm API client
+// Small client for the Last.fm API, and servers speaking the same protocol like Libre.fm
+
+use crate::http::{self, HttpClient};
+use crate::scrobbler::Track;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+pub const LASTFM_API_URL: &str = "https://ws.audioscrobbler.com/2.0/";
+pub const LASTFM_AUTH_URL: &str = "https://www.last.fm/api/auth/";
+
+/// Last.fm returns a bare object instead of a list when there is a single item
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum OneOrMany<T> {
+    Many(Vec<T>),
+    One(T),
+}
+
+impl<T> OneOrMany<T> {
+    fn into_vec(self) -> Vec<T> {
+        match self {
+            Self::Many(items) => items,
+            Self::One(item) => vec![item],
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: u32,
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct UserInfoResponse {
+    user: UserInfo,
+}
+
+#[derive(Debug, Deserialize)]
+struct UserInfo {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RecentTracksResponse {
+    recenttracks: RecentTracks,
+}
+
+#[derive(Debug, Deserialize)]
+struct RecentTracks {
+    #[serde(default)]
+    track: Option<OneOrMany<RecentTrack>>,
+    #[serde(rename = "@attr")]
+    attr: PageAttr,
+}
+
+#[derive(Debug, Deserialize)]
+struct PageAttr {
+    #[serde(rename = "totalPages")]
+    total_pages: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Text {
+    #[serde(rename = "#text", default)]
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Uts {
+    uts: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RecentTrack {
+    name: String,
+    artist: Text,
+    #[serde(default)]
+    album: Option<Text>,
+    /// Missing for the track that is playing right now
+    #[serde(default)]
+    date: Option<Uts>,
+}
+
+/// A scrobble stored on Last.fm
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scrobbled {
+    pub artist: String,
+    pub title: String,
+    pub album: Option<String>,
+    pub timestamp: i64,
+}
+
+/// One page of a user's scrobbles
+#[derive(Debug)]
+pub struct RecentTracksPage {
+    pub scrobbles: Vec<Scrobbled>,
+    pub total_pages: u32,
+}
+
+/// Client for the Last.fm API
+#[derive(Clone)]
+pub struct LastFmClient {
+    http: HttpClient,
+    api_url: String,
+    api_key: String,
+    api_secret: String,
+    session_key: String,
+}
+
+impl LastFmClient {
+    pub fn new(http: HttpClient, api_key: &str, api_secret: &str, session_key: &str) -> Self {
+        Self {
+            http,
+            api_url: LASTFM_API_URL.to_string(),
+            api_key: api_key.to_string(),
+            api_secret: api_secret.to_string(),
+            session_key: session_key.to_string(),
+        }
+    }
+
+    /// Talk to another server speaking the Last.fm protocol (e.g. Libre.fm)
+    pub fn with_api_url(mut self, api_url: &str) -> Self {
+        self.api_url = api_url.to_string();
+        self
+    }
+
+    /// Get the name of the user the session key belongs to
+    pub fn user_name(&self) -> Result<String> {
+        let mut params = vec![
+            ("method", "user.getInfo".to_string()),
+            ("api_key", self.api_key.clone()),
+            ("sk", self.session_key.clone()),
+        ];
+        let signature = sign(&params, &self.api_secret);
+        params.push(("api_sig", signature));
+
+        let data: UserInfoResponse = self.get(&params)?;
+        Ok(data.user.name)
+    }
+
+    /// Fetch a page of a user's scrobbles played since `from` (Unix time), newest first
+    pub fn recent_tracks(
+        &self,
+        user: &str,
+        from: i64,
+        page: u32,
+        limit: usize,
+    ) -> Result<RecentTracksPage> {
+        let params = vec![
+            ("method", "user.getRecentTracks".to_string()),
+            ("api_key", self.api_key.clone()),
+            ("user", user.to_string()),
+            ("from", from.to_string()),
+            ("page", page.to_string()),
+            ("limit", limit.to_string()),
+        ];
+
+        let data: RecentTracksResponse = self.get(&params)?;
+        Ok(parse_recent_tracks(data))
+    }
+
+    /// Mark a track as loved by the user
+    pub fn love(&self, artist: &str, title: &str) -> Result<()> {
+        self.post(vec![
+            ("method", "track.love".to_string()),
+            ("artist", artist.to_string()),
+            ("track", title.to_string()),
+        ])?;
+        Ok(())
+    }
+
+    /// Tell Last.fm the track is playing now
+    pub fn now_playing(&self, track: &Track) -> Result<()> {
+        let mut params = vec![
+            ("method", "track.updateNowPlaying".to_string()),
+            ("artist", track.artist.clone()),
+            ("track", track.title.clone()),
+        ];
+        params.extend(track_params(track));
+        self.post(params)?;
+        Ok(())
+    }
+
+    /// Scrobble a track played at `timestamp` (Unix time)
+    ///
+    /// Plays the user didn't pick themselves (radio, autoplay) are sent with `chosenByUser=0`.
+    /// Last.fm answers scrobbles it won't record (e.g. older than two weeks) with success, so
+    /// those are turned into errors here.
+    pub fn scrobble(&self, track: &Track, timestamp: i64) -> Result<()> {
+        let body = self.post(scrobble_params(track, timestamp))?;
+        match ignored_reason(&body) {
+            Some(reason) => anyhow::bail!("Last.fm ignored the scrobble: {}", reason),
+            None => Ok(()),
+        }
+    }
+
+    /// Sign and send a write request, returning the response body
+    fn post(&self, mut params: Vec<(&str, String)>) -> Result<String> {
+        params.push(("api_key", self.api_key.clone()));
+        params.push(("sk", self.session_key.clone()));
+        let signature = sign(&params, &self.api_secret);
+        params.push(("api_sig", signature));
+        params.push(("format", "json".to_string()));
+
+        let form: Vec<(&str, &str)> = params
+            .iter()
+            .map(|(name, value)| (*name, value.as_str()))
+            .collect();
+        let response = http::send_form(self.http.post(&self.api_url), &form)
+            .context("Failed to reach Last.fm")?;
+        let status = response.status();
+        let body = response
+            .into_string()
+            .context("Failed to read Last.fm response")?;
+        check_response(status, &body)?;
+
+        Ok(body)
+    }
+
+    fn get<T: serde::de::DeserializeOwned>(&self, params: &[(&str, String)]) -> Result<T> {
+        let mut request = self.http.get(&self.api_url).query("format", "json");
+        for (name, value) in params {
+            request = request.query(name, value);
+        }
+
+        let response = http::send(request, None).context("Failed to reach Last.fm")?;
+        let status = response.status();
+        let body = response
+            .into_string()
+            .context("Failed to read Last.fm response")?;
+
+        check_response(status, &body)?;
+
+        serde_json::from_str(&body).context("Invalid Last.fm response")
+    }
+}
+
+/// Errors come back as JSON, with or without an error status
+pub fn check_response(status: u16, body: &str) -> Result<()> {
+    if let Ok(error) = serde_json::from_str::<ErrorResponse>(body) {
+        anyhow::bail!("Last.fm API error {}: {}", error.error, error.message);
+    }
+    if !(200..300).contains(&status) {
+        anyhow::bail!("Last.fm API error: {}", status);
+    }
+
+    Ok(())
+}
+
+/// Why Last.fm ignored a scrobble, if it did
+///
+/// The `ignoredMessage` text is often empty, so the documented codes are spelled out.
+fn ignored_reason(body: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(body).ok()?;
+    let scrobble = &value["scrobbles"]["scrobble"];
+    let message = &scrobble.get(0).unwrap_or(scrobble)["ignoredMessage"];
+    let code = match &message["code"] {
+        serde_json::Value::String(code) => code.parse().ok()?,
+        code => code.as_u64()?,
+    };
+    let text = message["#text"].as_str().unwrap_or_default().trim();
+
+    let reason = match code {
+        0 => return None,
+        _ if !text.is_empty() => text,
+        1 => "artist was ignored",
+        2 => "track was ignored",
+        3 => "timestamp is too old",
+        4 => "timestamp is too new",
+        5 => "daily scrobble limit exceeded",
+        _ => "unknown reason",
+    };
+    Some(format!("{} (code {})", reason, code))
+}
+
+/// Parameters of a `track.scrobble` request, before authentication
+fn scrobble_params(track: &Track, timestamp: i64) -> Vec<(&'static str, String)> {
+    let mut params = vec![
+        ("method", "track.scrobble".to_string()),
+        ("artist", track.artist.clone()),
+        ("track", track.title.clone()),
+        ("timestamp", timestamp.to_string()),
+    ];
+    params.extend(track_params(track));
+    if track.radio {
+        params.push(("chosenByUser", "0".to_string()));
+    }
+    params
+}
+
+/// Optional track parameters shared by scrobbles and now playing updates
+fn track_params(track: &Track) -> Vec<(&'static str, String)> {
+    let mut params = Vec::new();
+    if let Some(album) = &track.album {
+        params.push(("album", album.clone()));
+    }
+    if let Some(album_artist) = &track.album_artist {
+        params.push(("albumArtist", album_artist.clone()));
+    }
+    if let Some(duration) = track.duration {
+        params.push(("duration", duration.to_string()));
+    }
+    // Lets Last.fm link the right recording instead of matching by name
+    if let Some(mbid) = &track.mbid {
+        params.push(("mbid", mbid.clone()));
+    }
+    params
+}
+
+fn parse_recent_tracks(data: RecentTracksResponse) -> RecentTracksPage {
+    let scrobbles = data
+        .recenttracks
+        .track
+        .map(OneOrMany::into_vec)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|track| {
+            let timestamp = track.date?.uts.parse().ok()?;
+            Some(Scrobbled {
+                artist: track.artist.text,
+                title: track.name,
+                album: track.album.map(|a| a.text).filter(|a| !a.is_empty()),
+                timestamp,
+            })
+        })
+        .collect();
+
+    RecentTracksPage {
+        scrobbles,
+        total_pages: data.recenttracks.attr.total_pages.parse().unwrap_or(1),
+    }
+}
+
+/// Compute the `api_sig` for a signed Last.fm request
+///
+/// Parameters are sorted by name and concatenated as `namevalue`, followed by the secret.
+pub fn sign(params: &[(&str, String)], secret: &str) -> String {
+    let mut sorted: Vec<&(&str, String)> = params.iter().collect();
+    sorted.sort_by_key(|(name, _)| *name);
+
+    let mut sig_string = String::new();
+    for (name, value) in sorted {
+        sig_string.push_str(name);
+        sig_string.push_str(value);
+    }
+    sig_string.push_str(secret);
+
+    format!("{:x}", md5::compute(sig_string.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_sorts_params() {
+        let params = vec![
+            ("method", "auth.gettoken".to_string()),
+            ("api_key", "key".to_string()),
+        ];
+        let expected = format!("{:x}", md5::compute(b"api_keykeymethodauth.gettokensecret"));
+
+        assert_eq!(sign(&params, "secret"), expected);
+    }
+
+    #[test]
+    fn test_ignored_reason() {
+        let accepted = r##"{"scrobbles":{"scrobble":{"ignoredMessage":{"code":"0","#text":""}},
+            "@attr":{"ignored":0,"accepted":1}}}"##;
+        assert_eq!(ignored_reason(accepted), None);
+
+        let too_old = r##"{"scrobbles":{"scrobble":{"ignoredMessage":{"code":"3","#text":""}},
+            "@attr":{"ignored":1,"accepted":0}}}"##;
+        assert_eq!(
+            ignored_reason(too_old).as_deref(),
+            Some("timestamp is too old (code 3)")
+        );
+
+        let batch = r##"{"scrobbles":{"scrobble":[{"ignoredMessage":{"code":"1",
+            "#text":"Artist name failed filter: Unknown"}}]}}"##;
+        assert_eq!(
+            ignored_reason(batch).as_deref(),
+            Some("Artist name failed filter: Unknown (code 1)")
+        );
+        assert_eq!(ignored_reason("{}"), None);
+    }
+
+    #[test]
+    fn test_scrobble_params_flag_radio_plays() {
+        let mut track = Track {
+            title: "Song".to_string(),
+            artist: "Artist".to_string(),
+            duration: Some(200),
+            ..Default::default()
+        };
+        let names = |track: &Track| -> Vec<&str> {
+            scrobble_params(track, 1_700_000_000)
+                .iter()
+                .map(|(name, _)| *name)
+                .collect()
+        };
+
+        assert_eq!(
+            names(&track),
+            vec!["method", "artist", "track", "timestamp", "duration"]
+        );
+        track.radio = true;
+        track.mbid = Some("b1a9c0e9-d987-4042-ae91-78d6a3267d69".to_string());
+        let params = scrobble_params(&track, 1_700_000_000);
+        assert!(params.contains(&("chosenByUser", "0".to_string())));
+        assert!(params.contains(&("mbid", "b1a9c0e9-d987-4042-ae91-78d6a3267d69".to_string())));
+    }
+
+    #[test]
+    fn test_track_params_pass_album_artist() {
+        let track = Track {
+            title: "Song".to_string(),
+            artist: "Band".to_string(),
+            album: Some("Hits".to_string()),
+            album_artist: Some("Various Artists".to_string()),
+            ..Default::default()
+        };
+
+        let params = scrobble_params(&track, 1_700_000_000);
+        assert!(params.contains(&("artist", "Band".to_string())));
+        assert!(params.contains(&("albumArtist", "Various Artists".to_string())));
+    }
+
+    #[test]
+    fn test_parse_recent_tracks_skips_now_playing() {
+        let data: RecentTracksResponse = serde_json::from_str(
+            r##"{"recenttracks": {
+                "track": [
+                    {"name": "Live", "artist": {"#text": "A"}, "album": {"#text": ""},
+                     "@attr": {"nowplaying": "true"}},
+                    {"name": "Song", "artist": {"#text": "A"}, "album": {"#text": "Album"},
+                     "date": {"uts": "1767268800", "#text": "01 Jan 2026, 12:00"}}
+                ],
+                "@attr": {"page": "1", "totalPages": "3"}
+            }}"##,
+        )
+        .unwrap();
+
+        let page = parse_recent_tracks(data);
+        assert_eq!(page.total_pages, 3);
+        assert_eq!(
+            page.scrobbles,
+            vec![Scrobbled {
+                artist: "A".to_string(),
+                title: "Song".to_string(),
+                album: Some("Album".to_string()),
+                timestamp: 1767268800,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_recent_tracks_single_track_object() {
+        let data: RecentTracksResponse = serde_json::from_str(
+            r##"{"recenttracks": {
+                "track": {"name": "Song", "artist": {"#text": "A"},
+                          "date": {"uts": "1767268800"}},
+                "@attr": {"totalPages": "1"}
+            }}"##,
+        )
+        .unwrap();
+
+        assert_eq!(parse_recent_tracks(data).scrobbles.len(), 1);
+    }
+}
@@ -0,0 +1,80 @@
+//! OSX Scrobbler's scrobbling engine
+//!
+//! The `osx-scrobbler` binary is a menu bar app built on this library. Other programs can use the
+//! same pieces to scrobble from their own player or frontend:
+//!
+//! - [`config`] loads and validates `config.toml`
+//! - [`media_monitor`] turns Media Remote's now playing info into cleaned-up tracks and decides
+//!   when a play session becomes a scrobble
+//! - [`text_cleanup`], [`corrections`], [`classical`] and [`apple_music`] are the metadata
+//!   pipeline the monitor runs tracks through
+//! - [`scrobbler`] is the common interface over the Last.fm and ListenBrainz backends
+//! - [`history`] records every scrobble and its per-service submissions
+//! - [`engine`] ties these together: [`engine::Engine`] polls the player, submits now playing
+//!   updates and scrobbles, and flushes queued submissions, leaving only display to frontends
+//!
+//! ```no_run
+//! use osx_scrobbler::{config::Config, engine::Engine};
+//! use std::time::Duration;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let mut engine = Engine::new(Config::load()?);
+//! let refresh_interval = Duration::from_secs(engine.config().refresh_interval);
+//!
+//! loop {
+//!     if let Some(track) = engine.poll().scrobbled {
+//!         println!("Scrobbled {} - {}", track.artist, track.title);
+//!     }
+//!     std::thread::sleep(refresh_interval);
+//! }
+//! # }
+//! ```
+
+pub mod app_import;
+pub mod app_lock;
+pub mod apple_music;
+pub mod arch;
+pub mod artist_aliases;
+#[cfg(feature = "artwork")]
+pub mod artwork;
+pub mod backup;
+pub mod browser;
+pub mod build_info;
+pub mod classical;
+pub mod codesign;
+pub mod confidence;
+pub mod config;
+pub mod control;
+pub mod corrections;
+pub mod energy;
+pub mod engine;
+pub mod export;
+pub mod failover;
+pub mod field_mapping;
+pub mod filters;
+pub mod focus;
+pub mod history;
+pub mod http;
+pub mod import;
+pub mod lastfm_api;
+pub mod listenbrainz_api;
+pub mod long_form;
+pub mod media_monitor;
+pub mod metadata_cache;
+pub mod paths;
+pub mod plugins;
+pub mod rate_limit;
+pub mod reconcile;
+pub mod replay;
+pub mod routing;
+pub mod scheduler;
+pub mod scrobbler;
+pub mod search;
+pub mod session_check;
+pub mod startup;
+pub mod stats;
+pub mod text_cleanup;
+pub mod timeline;
+#[cfg(feature = "web")]
+pub mod web;
+pub mod wrapped;
@@ -0,0 +1,414 @@
+// ListenBrainz API client
+// Minimal client for the submit-listens API that honors the server's rate limit headers
+
+use crate::http::{self, HttpClient};
+use crate::rate_limit::{host_budget, HostBudget};
+use crate::scrobbler::Track;
+use anyhow::{Context, Result};
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Wait this long after a 429 when the server doesn't say how long
+const DEFAULT_RETRY_AFTER_SECONDS: u64 = 10;
+
+#[derive(Debug, Deserialize)]
+struct ValidateTokenResponse {
+    valid: bool,
+    user_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LookupResponse {
+    /// Missing when nothing matched
+    recording_mbid: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListensResponse {
+    payload: ListensPayload,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListensPayload {
+    listens: Vec<Listen>,
+}
+
+/// A listen stored on the server
+#[derive(Debug, Clone, Deserialize)]
+pub struct Listen {
+    pub listened_at: i64,
+    /// MessyBrainz ID, needed to delete the listen
+    #[serde(default)]
+    pub recording_msid: Option<String>,
+    pub track_metadata: TrackMetadata,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TrackMetadata {
+    pub artist_name: String,
+    pub track_name: String,
+    pub release_name: Option<String>,
+}
+
+/// Client for a single ListenBrainz (or compatible) server
+#[derive(Clone)]
+pub struct ListenBrainzClient {
+    http: HttpClient,
+    api_url: String,
+    /// Value of the `Authorization` header
+    authorization: String,
+    /// Extra headers sent with every request
+    headers: Vec<(String, String)>,
+    /// Rate limit budget shared with every other client for the same host
+    budget: Arc<Mutex<HostBudget>>,
+}
+
+impl ListenBrainzClient {
+    pub fn new(http: HttpClient, api_url: &str, token: &str) -> Self {
+        let api_url = api_url.trim_end_matches('/').to_string();
+        let budget = host_budget(&host_of(&api_url));
+
+        Self {
+            http,
+            api_url,
+            authorization: format!("Token {}", token),
+            headers: Vec::new(),
+            budget,
+        }
+    }
+
+    /// Authenticate with basic auth (e.g. for a reverse proxy) instead of the token
+    ///
+    /// Both use the `Authorization` header, so the proxy has to add the ListenBrainz
+    /// token itself when forwarding requests.
+    pub fn with_basic_auth(mut self, username: &str, password: &str) -> Self {
+        let credentials =
+            base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", username, password));
+        self.authorization = format!("Basic {}", credentials);
+        self
+    }
+
+    /// Send extra headers with every request
+    pub fn with_headers(mut self, headers: impl IntoIterator<Item = (String, String)>) -> Self {
+        self.headers.extend(headers);
+        self
+    }
+
+    /// Start a request to an API endpoint with the auth and extra headers applied
+    fn request(&self, method: &str, endpoint: &str) -> ureq::Request {
+        let mut request = self
+            .http
+            .request(method, &format!("{}/1/{}", self.api_url, endpoint))
+            .set("Authorization", &self.authorization);
+        for (name, value) in &self.headers {
+            request = request.set(name, value);
+        }
+        request
+    }
+
+    /// Check that the token is valid, returning the user name
+    pub fn validate_token(&self) -> Result<String> {
+        self.wait_for_budget();
+
+        let response = http::send(self.request("GET", "validate-token"), None)
+            .context("Failed to reach ListenBrainz")?;
+        self.check_response(&response)?;
+
+        let data: ValidateTokenResponse = response
+            .into_json()
+            .context("Invalid validate-token response")?;
+        if !data.valid {
+            anyhow::bail!("ListenBrainz token is invalid");
+        }
+
+        Ok(data.user_name.unwrap_or_default())
+    }
+
+    /// Fetch up to `count` of a user's listens played before `max_ts`, newest first
+    pub fn listens(&self, user: &str, max_ts: Option<i64>, count: usize) -> Result<Vec<Listen>> {
+        self.wait_for_budget();
+
+        let mut request = self
+            .request(
+                "GET",
+                &format!("user/{}/listens", encode_path_segment(user)),
+            )
+            .query("count", &count.to_string());
+        if let Some(max_ts) = max_ts {
+            request = request.query("max_ts", &max_ts.to_string());
+        }
+        let response = http::send(request, None).context("Failed to reach ListenBrainz")?;
+        self.check_response(&response)?;
+
+        let data: ListensResponse = response.into_json().context("Invalid listens response")?;
+        Ok(data.payload.listens)
+    }
+
+    /// Look up the MusicBrainz ID of a recording from its metadata
+    pub fn lookup_recording(
+        &self,
+        artist: &str,
+        track: &str,
+        release: Option<&str>,
+    ) -> Result<Option<String>> {
+        self.wait_for_budget();
+
+        let mut request = self
+            .request("GET", "metadata/lookup/")
+            .query("artist_name", artist)
+            .query("recording_name", track);
+        if let Some(release) = release {
+            request = request.query("release_name", release);
+        }
+        let response = http::send(request, None).context("Failed to reach ListenBrainz")?;
+        self.check_response(&response)?;
+
+        let data: LookupResponse = response.into_json().context("Invalid lookup response")?;
+        Ok(data.recording_mbid)
+    }
+
+    /// Love (`1`), hate (`-1`) or clear (`0`) the user's feedback on a recording
+    pub fn recording_feedback(&self, recording_mbid: &str, score: i8) -> Result<()> {
+        self.wait_for_budget();
+
+        let body = json!({
+            "recording_mbid": recording_mbid,
+            "score": score,
+        });
+        let request = self.request("POST", "feedback/recording-feedback");
+        let response = http::send(request, Some(&body)).context("Failed to reach ListenBrainz")?;
+        self.check_response(&response)
+    }
+
+    /// Delete one of the user's listens
+    pub fn delete_listen(&self, listened_at: i64, recording_msid: &str) -> Result<()> {
+        self.wait_for_budget();
+
+        let body = json!({
+            "listened_at": listened_at,
+            "recording_msid": recording_msid,
+        });
+        let request = self.request("POST", "delete-listen");
+        let response = http::send(request, Some(&body)).context("Failed to reach ListenBrainz")?;
+        self.check_response(&response)
+    }
+
+    /// Submit a "playing now" notification
+    pub fn playing_now(&self, track: &Track) -> Result<()> {
+        self.submit(
+            "playing_now",
+            json!({ "track_metadata": track_metadata(track) }),
+        )
+    }
+
+    /// Submit a single listen with the time it was played
+    pub fn listen(&self, track: &Track, listened_at: i64) -> Result<()> {
+        self.submit(
+            "single",
+            json!({
+                "listened_at": listened_at,
+                "track_metadata": track_metadata(track),
+            }),
+        )
+    }
+
+    fn submit(&self, listen_type: &str, listen: serde_json::Value) -> Result<()> {
+        self.wait_for_budget();
+
+        let body = json!({
+            "listen_type": listen_type,
+            "payload": [listen],
+        });
+
+        let request = self.request("POST", "submit-listens");
+        let response = http::send(request, Some(&body)).context("Failed to reach ListenBrainz")?;
+        self.check_response(&response)
+    }
+
+    /// Block until the shared host budget allows another request
+    fn wait_for_budget(&self) {
+        let delay = self
+            .budget
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .acquire(Instant::now());
+
+        if let Some(delay) = delay {
+            log::warn!(
+                "ListenBrainz rate limit reached for {}, waiting {}s",
+                host_of(&self.api_url),
+                delay.as_secs()
+            );
+            std::thread::sleep(delay);
+        }
+    }
+
+    /// Sync the host budget with the response headers and turn errors into `Err`
+    fn check_response(&self, response: &ureq::Response) -> Result<()> {
+        let header = |name: &str| -> Option<u64> {
+            response.header(name).and_then(|v| v.trim().parse().ok())
+        };
+
+        let remaining = header("X-RateLimit-Remaining");
+        let reset_in = header("X-RateLimit-Reset-In");
+        let now = Instant::now();
+
+        let mut budget = self.budget.lock().unwrap_or_else(|e| e.into_inner());
+        if response.status() == 429 {
+            budget.exhaust(reset_in.unwrap_or(DEFAULT_RETRY_AFTER_SECONDS), now);
+            anyhow::bail!("ListenBrainz rate limit exceeded");
+        }
+        budget.update(remaining, reset_in, now);
+
+        if !(200..300).contains(&response.status()) {
+            anyhow::bail!(
+                "ListenBrainz API error: {} {}",
+                response.status(),
+                response.status_text()
+            );
+        }
+
+        Ok(())
+    }
+}
+
+fn track_metadata(track: &Track) -> serde_json::Value {
+    let mut metadata = json!({
+        "artist_name": track.artist,
+        "track_name": track.title,
+    });
+    if let Some(release) = &track.album {
+        metadata["release_name"] = json!(release);
+    }
+
+    let mut additional_info = serde_json::Map::new();
+    if let Some(origin_url) = &track.origin_url {
+        additional_info.insert("origin_url".to_string(), json!(origin_url));
+    }
+    if let Some(mbid) = &track.mbid {
+        additional_info.insert("recording_mbid".to_string(), json!(mbid));
+    }
+    if let Some(live) = &track.live {
+        additional_info.insert("live".to_string(), json!(live));
+    }
+    if !additional_info.is_empty() {
+        metadata["additional_info"] = additional_info.into();
+    }
+    metadata
+}
+
+/// Percent-encode a value for use as a single URL path segment
+fn encode_path_segment(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// Extract the host (and port) from an API URL
+pub fn host_of(api_url: &str) -> String {
+    let without_scheme = api_url
+        .split_once("://")
+        .map(|(_, rest)| rest)
+        .unwrap_or(api_url);
+    let host = without_scheme.split('/').next().unwrap_or(without_scheme);
+    // Drop any userinfo
+    let host = host.rsplit('@').next().unwrap_or(host);
+
+    host.to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_of() {
+        assert_eq!(
+            host_of("https://api.listenbrainz.org"),
+            "api.listenbrainz.org"
+        );
+        assert_eq!(
+            host_of("https://LB.example.com:8443/api/"),
+            "lb.example.com:8443"
+        );
+        assert_eq!(host_of("http://user@localhost:8100"), "localhost:8100");
+        assert_eq!(host_of("localhost"), "localhost");
+    }
+
+    #[test]
+    fn test_encode_path_segment() {
+        assert_eq!(encode_path_segment("rob_1.x"), "rob_1.x");
+        assert_eq!(encode_path_segment("a b/c"), "a%20b%2Fc");
+    }
+
+    fn track(album: Option<&str>) -> Track {
+        Track {
+            title: "Song".to_string(),
+            artist: "Artist".to_string(),
+            album: album.map(str::to_string),
+            duration: Some(200),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_track_metadata_omits_missing_release() {
+        assert_eq!(
+            track_metadata(&track(None)),
+            json!({ "artist_name": "Artist", "track_name": "Song" })
+        );
+        assert_eq!(
+            track_metadata(&track(Some("Album")))["release_name"],
+            "Album"
+        );
+    }
+
+    #[test]
+    fn test_track_metadata_additional_info() {
+        let url = "https://www.youtube.com/watch?v=5NV6Rdv1a3I";
+        let mbid = "b1a9c0e9-d987-4042-ae91-78d6a3267d69";
+        let track = Track {
+            origin_url: Some(url.to_string()),
+            mbid: Some(mbid.to_string()),
+            live: Some("Live at Wembley".to_string()),
+            ..track(None)
+        };
+
+        assert_eq!(
+            track_metadata(&track)["additional_info"],
+            json!({ "origin_url": url, "recording_mbid": mbid, "live": "Live at Wembley" })
+        );
+    }
+
+    #[test]
+    fn test_clients_for_same_host_share_budget() {
+        let main = ListenBrainzClient::new(http::shared(), "https://lb.example.net", "a");
+        let test = ListenBrainzClient::new(http::shared(), "https://lb.example.net/", "b");
+
+        assert!(Arc::ptr_eq(&main.budget, &test.budget));
+    }
+
+    #[test]
+    fn test_request_applies_auth_and_headers() {
+        let client = ListenBrainzClient::new(http::shared(), "https://lb.example.net/", "tok");
+        let request = client.request("GET", "validate-token");
+        assert_eq!(request.url(), "https://lb.example.net/1/validate-token");
+        assert_eq!(request.header("Authorization"), Some("Token tok"));
+
+        let client = client
+            .with_basic_auth("user", "pass")
+            .with_headers([("X-Proxy-Key".to_string(), "secret".to_string())]);
+        let request = client.request("POST", "submit-listens");
+        assert_eq!(request.header("Authorization"), Some("Basic dXNlcjpwYXNz"));
+        assert_eq!(request.header("X-Proxy-Key"), Some("secret"));
+    }
+}
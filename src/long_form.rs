@@ -0,0 +1,86 @@
+// Long-form content module
+// Detects audiobooks and other long-form content that shouldn't be scrobbled as songs
+
+use crate::apple_music::TrackDetails;
+use crate::config::LongFormConfig;
+
+/// Media kind the Music app reports for audiobooks
+const AUDIOBOOK_MEDIA_KIND: &str = "audiobook";
+
+pub struct LongFormFilter {
+    exclude: bool,
+    max_seconds: u64,
+    apps: Vec<String>,
+}
+
+impl LongFormFilter {
+    pub fn new(config: &LongFormConfig) -> Self {
+        Self {
+            exclude: config.exclude,
+            max_seconds: (config.max_hours * 3600.0) as u64,
+            apps: config.apps.clone(),
+        }
+    }
+
+    /// Whether the Music app's media kind is needed to make a decision
+    pub fn uses_media_kind(&self) -> bool {
+        self.exclude
+    }
+
+    /// Check whether a track is long-form content that should not be scrobbled
+    pub fn is_excluded(
+        &self,
+        bundle_id: Option<&str>,
+        duration: Option<u64>,
+        details: Option<&TrackDetails>,
+    ) -> bool {
+        if !self.exclude {
+            return false;
+        }
+
+        let from_book_app = bundle_id.is_some_and(|id| self.apps.iter().any(|app| app == id));
+        let too_long = duration.is_some_and(|d| d > self.max_seconds);
+        let audiobook = details
+            .and_then(|d| d.media_kind.as_deref())
+            .is_some_and(|kind| kind.eq_ignore_ascii_case(AUDIOBOOK_MEDIA_KIND));
+
+        from_book_app || too_long || audiobook
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter() -> LongFormFilter {
+        LongFormFilter::new(&LongFormConfig::default())
+    }
+
+    #[test]
+    fn test_excludes_book_apps_and_long_tracks() {
+        assert!(filter().is_excluded(Some("com.apple.iBooksX"), Some(300), None));
+        assert!(filter().is_excluded(Some("com.apple.Music"), Some(10 * 3600), None));
+        assert!(!filter().is_excluded(Some("com.apple.Music"), Some(300), None));
+        assert!(!filter().is_excluded(None, None, None));
+    }
+
+    #[test]
+    fn test_excludes_audiobook_media_kind() {
+        let details = TrackDetails {
+            media_kind: Some("Audiobook".to_string()),
+            ..TrackDetails::default()
+        };
+
+        assert!(filter().is_excluded(Some("com.apple.Music"), Some(1200), Some(&details)));
+    }
+
+    #[test]
+    fn test_disabled_excludes_nothing() {
+        let filter = LongFormFilter::new(&LongFormConfig {
+            exclude: false,
+            ..LongFormConfig::default()
+        });
+
+        assert!(!filter.is_excluded(Some("com.apple.iBooksX"), Some(10 * 3600), None));
+    }
+}
@@ -1,25 +1,51 @@
 #[global_allocator]
 static GLOBAL: std::alloc::System = std::alloc::System;
 
-mod config;
-mod media_monitor;
-mod scrobbler;
-mod text_cleanup;
+#[cfg(feature = "tui")]
+mod tui;
 mod ui;
 
-use anyhow::Result;
-use backoff::{retry, ExponentialBackoff};
-use clap::Parser;
-use media_monitor::MediaMonitor;
-use scrobbler::Service;
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand, ValueEnum};
+use osx_scrobbler::config::NotificationKind;
+use osx_scrobbler::energy::{self, Activity};
+use osx_scrobbler::engine::{
+    build_scrobblers, delete_from_services, scrobble_with_retries, Engine,
+};
+use osx_scrobbler::scrobbler::{Scrobbler, Track};
+use osx_scrobbler::{
+    app_import, arch, backup, build_info, codesign, config, export, failover, focus, history,
+    import, media_monitor, paths, rate_limit, reconcile, replay, routing, scheduler, scrobbler,
+    search, session_check, stats, text_cleanup, timeline, wrapped,
+};
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
+use ui::edit_dialog::ReviewChoice;
+use ui::notifications::{NotificationAction, Notifier};
 use ui::tray::TrayManager;
 use winit::event_loop::{ControlFlow, EventLoop};
 
+/// How long a now playing notification waits for the album art to download
+const NOTIFICATION_ARTWORK_WAIT: Duration = Duration::from_secs(3);
+
+/// How long `inspect` waits for Media Remote to report what's playing
+const INSPECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Plays submitted between saves of the history during an import
+const IMPORT_BATCH_SIZE: usize = 50;
+
 /// OSX Scrobbler - Music scrobbling for macOS
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, disable_version_flag = true)]
 struct Args {
+    /// Print version
+    #[arg(short = 'V', long)]
+    version: bool,
+
+    /// With --version, also print the commit, build date, features and file locations
+    #[arg(long, requires = "version")]
+    verbose: bool,
+
     /// Authenticate with Last.fm and obtain session key
     #[arg(long)]
     auth_lastfm: bool,
@@ -28,6 +54,10 @@ struct Args {
     #[arg(long)]
     install_app: bool,
 
+    /// Notarization ticket to staple to the installed app bundle (with --install-app)
+    #[arg(long, value_name = "PATH", requires = "install_app")]
+    notarization_ticket: Option<PathBuf>,
+
     /// Uninstall the app bundle from /Applications/
     #[arg(long)]
     uninstall_app: bool,
@@ -35,10 +65,434 @@ struct Args {
     /// Force console output (show logs in terminal)
     #[arg(long)]
     console: bool,
+
+    /// Count wakeups, AppleScript runs, network requests and menu updates, and print a report
+    /// on quit (also logged every hour)
+    #[arg(long)]
+    profile_energy: bool,
+
+    /// Append what the player reports at each poll to FILE, to replay it later with
+    /// `osx-scrobbler replay`
+    #[arg(long, value_name = "FILE")]
+    record_polls: Option<PathBuf>,
+
+    /// Run in the terminal instead of the menu bar (logs go to the log file)
+    #[cfg(feature = "tui")]
+    #[arg(long)]
+    tui: bool,
+
+    /// Config file to use instead of the default one
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Directory for the history, rate limit state, control socket and lock file
+    #[arg(long, global = true, value_name = "DIR")]
+    state_dir: Option<PathBuf>,
+
+    /// Directory for cached album art
+    #[arg(long, global = true, value_name = "DIR")]
+    cache_dir: Option<PathBuf>,
+
+    /// Directory for the log file
+    #[arg(long, global = true, value_name = "DIR")]
+    log_dir: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Inspect and manage the local scrobble history
+    History {
+        #[command(subcommand)]
+        command: HistoryCommand,
+    },
+
+    /// Show whether the app is running, configured services, queued submissions and API budgets
+    Status,
+
+    /// Pause scrobbling in the running app; plays are recorded as private until resumed
+    Pause,
+
+    /// Resume scrobbling in the running app
+    Resume,
+
+    /// Quit the running app
+    Quit,
+
+    /// Scrobble the track playing in the running app now, without waiting for the threshold
+    ScrobbleNow,
+
+    /// Don't scrobble this play of the track playing in the running app
+    SkipScrobble,
+
+    /// Forget the track the running app shows as playing, when the player is stuck on it
+    ClearNowPlaying,
+
+    /// Show total listening time from the local history
+    Stats {
+        /// Group listening time by day or by artist
+        #[arg(long, value_enum, default_value_t = StatsGrouping::Day)]
+        by: StatsGrouping,
+
+        /// Only include scrobbles played since this time (`2026-01-01`, RFC 3339, or `7d`)
+        #[arg(long)]
+        since: Option<String>,
+
+        /// Maximum number of rows to show
+        #[arg(long, default_value_t = 30)]
+        limit: usize,
+    },
+
+    /// Show a day's plays in order: when each started, how long it played and if it was scrobbled
+    Timeline {
+        /// Day to show (`2026-01-01`, or `yesterday`); today when omitted
+        #[arg(long)]
+        date: Option<String>,
+    },
+
+    /// Summarize a year of listening from the local history: top artists, tracks and albums,
+    /// minutes listened and the busiest day
+    Wrapped {
+        /// Year to summarize; the current year when omitted
+        #[arg(long)]
+        year: Option<i32>,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = WrappedFormat::Markdown)]
+        format: WrappedFormat,
+
+        /// File to write; printed when omitted
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Compare the scrobbles stored on each service with the local history
+    Reconcile {
+        /// Only check this service (`lastfm`, `listenbrainz` or `listenbrainz:<name>`)
+        #[arg(long)]
+        service: Option<String>,
+
+        /// How far back to compare (`2026-01-01`, RFC 3339, or `7d`)
+        #[arg(long, default_value = "7d")]
+        since: String,
+
+        /// Submit scrobbles the service is missing and add its extra scrobbles to the history
+        #[arg(long)]
+        fix: bool,
+    },
+
+    /// Save the config and scrobble history to one file, to move them to another machine
+    Backup {
+        /// File to write (`osx-scrobbler-backup-<date>.json` when omitted)
+        output: Option<PathBuf>,
+
+        /// Leave out credentials and tokens (sign in again after restoring, or keep the ones
+        /// already set up on the other machine)
+        #[arg(long)]
+        no_secrets: bool,
+    },
+
+    /// Replace the config and scrobble history with those from a backup
+    Restore {
+        /// Backup file written by `backup`
+        path: PathBuf,
+
+        /// Replace the history even if it already has scrobbles
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Replace the binary in the installed app bundle with this one, keeping the bundle (and its
+    /// login item) as it is
+    Upgrade,
+
+    /// Show where the config, history and caches are kept
+    Paths {
+        /// Move them from the flat layout of earlier versions (everything in Application
+        /// Support) to ~/.config/osx-scrobbler, Application Support/osx-scrobbler and Caches
+        #[arg(long)]
+        migrate: bool,
+    },
+
+    /// Print everything the player reports about the current track as JSON, to show what
+    /// metadata it provides
+    Inspect,
+
+    /// Show the statistics charts read as JSON from stdin (opened by the menu bar's Statistics)
+    #[cfg(feature = "charts")]
+    #[command(hide = true)]
+    StatsWindow,
+
+    /// Run polls recorded with --record-polls through the media monitor and print what it
+    /// would have sent, without submitting anything
+    Replay {
+        /// File written by --record-polls
+        file: PathBuf,
+    },
+
+    /// Show what the cleanup patterns make of titles, or how often each pattern has matched
+    CleanupTest {
+        /// Titles to clean with the configured patterns
+        #[arg(required_unless_present = "stats")]
+        texts: Vec<String>,
+
+        /// Show how often each pattern has matched in the running app
+        #[arg(long)]
+        stats: bool,
+    },
+
+    /// Submit the plays in a Rockbox .scrobbler.log or a Last.fm CSV export to the services,
+    /// and add them to the local history
+    Import {
+        /// File to import
+        file: PathBuf,
+
+        /// Kind of file; guessed from its contents when omitted
+        #[arg(long, value_enum)]
+        format: Option<ImportFileFormat>,
+
+        /// Only submit to this service (`lastfm`, `listenbrainz` or `listenbrainz:<name>`)
+        #[arg(long)]
+        service: Option<String>,
+
+        /// Show what would be imported without submitting anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Import allowed/ignored apps from another scrobbler's preferences
+    ImportApps {
+        /// Source to import from (`neptunes`, `lastfm`, or a path to a .plist file).
+        /// All known sources are tried when omitted.
+        #[arg(long)]
+        from: Option<String>,
+
+        /// Show what would be imported without changing the config
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum StatsGrouping {
+    Day,
+    Artist,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum WrappedFormat {
+    Markdown,
+    Html,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum ImportFileFormat {
+    /// Rockbox-style .scrobbler.log
+    ScrobblerLog,
+    /// Last.fm CSV export
+    LastfmCsv,
+}
+
+impl From<ImportFileFormat> for import::ImportFormat {
+    fn from(format: ImportFileFormat) -> Self {
+        match format {
+            ImportFileFormat::ScrobblerLog => Self::ScrobblerLog,
+            ImportFileFormat::LastfmCsv => Self::LastfmCsv,
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum ExportFormat {
+    Csv,
+    Json,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum SearchStatus {
+    /// Submitted to every service
+    Submitted,
+    /// Failed on at least one service and queued for a retry
+    Failed,
+    /// Recorded during a private session
+    Private,
+    /// Recorded in guest mode
+    Guest,
+    /// Held back for review for low confidence
+    Review,
+}
+
+impl From<SearchStatus> for timeline::PlayStatus {
+    fn from(status: SearchStatus) -> Self {
+        match status {
+            SearchStatus::Submitted => Self::Scrobbled,
+            SearchStatus::Failed => Self::Queued,
+            SearchStatus::Private => Self::Private,
+            SearchStatus::Guest => Self::Guest,
+            SearchStatus::Review => Self::Review,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum HistoryCommand {
+    /// Show the most recent scrobbles with their IDs and per-service status
+    List {
+        /// Maximum number of scrobbles to show
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+
+    /// Find scrobbles by artist, title or album, forgiving small typos
+    Search {
+        /// Words to look for (every word has to match)
+        #[arg(default_value = "")]
+        query: String,
+
+        /// Only scrobbles played in this app (bundle ID)
+        #[arg(long)]
+        app: Option<String>,
+
+        /// Only scrobbles played since this time (`2026-01-01`, RFC 3339, or `7d`)
+        #[arg(long)]
+        since: Option<String>,
+
+        /// Only scrobbles played before this time (same formats as `--since`)
+        #[arg(long)]
+        until: Option<String>,
+
+        /// Only scrobbles with this status
+        #[arg(long, value_enum)]
+        status: Option<SearchStatus>,
+
+        /// Maximum number of scrobbles to show
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+
+        /// Only scrobbles by this artist (ignoring case)
+        #[arg(long)]
+        artist: Option<String>,
+
+        /// Only scrobbles with this tag (see `history annotate`)
+        #[arg(long)]
+        tag: Option<String>,
+
+        /// Print the matching history entries as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Export scrobbles from the local history, oldest first
+    Export {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+
+        /// Only scrobbles played since this time (`2026-01-01`, RFC 3339, or `7d`)
+        #[arg(long)]
+        since: Option<String>,
+
+        /// Only scrobbles played before this time (same formats as `--since`)
+        #[arg(long)]
+        until: Option<String>,
+
+        /// Only scrobbles by this artist (ignoring case)
+        #[arg(long)]
+        artist: Option<String>,
+
+        /// Only scrobbles played in this app (bundle ID)
+        #[arg(long)]
+        app: Option<String>,
+
+        /// Only scrobbles with this tag (see `history annotate`)
+        #[arg(long)]
+        tag: Option<String>,
+
+        /// File to write; printed when omitted
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Delete a scrobble from the local history
+    Delete {
+        /// ID of the scrobble (see `history list`)
+        id: u64,
+
+        /// Also delete it from the services it was submitted to, where possible
+        #[arg(long)]
+        remote: bool,
+    },
+
+    /// Show the note and tags of a scrobble, or change them
+    Annotate {
+        /// ID of the scrobble (see `history list`)
+        id: u64,
+
+        /// Tags to add, e.g. `gym` or `work`
+        #[arg(long, num_args = 1..)]
+        tag: Vec<String>,
+
+        /// Tags to remove
+        #[arg(long, num_args = 1..)]
+        untag: Vec<String>,
+
+        /// Note to attach, replacing any earlier one; an empty note removes it
+        #[arg(long)]
+        note: Option<String>,
+    },
+
+    /// Show scrobbles exported for being too old for Last.fm (see `[scheduler] timestamps`)
+    Expired,
+
+    /// List scrobbles held back for review, or approve or reject them
+    ///
+    /// Approved scrobbles are submitted by the next `history retry`; rejected ones are deleted.
+    Review {
+        /// IDs of scrobbles to approve
+        #[arg(long, num_args = 1..)]
+        approve: Vec<u64>,
+
+        /// IDs of scrobbles to reject
+        #[arg(long, num_args = 1..)]
+        reject: Vec<u64>,
+    },
+
+    /// Re-submit scrobbles that failed or were recorded during a private session
+    Retry {
+        /// Only retry for this service (`lastfm`, `listenbrainz` or `listenbrainz:<name>`)
+        #[arg(long)]
+        service: Option<String>,
+
+        /// Only retry scrobbles played since this time (`2026-01-01`, RFC 3339, or `7d`)
+        #[arg(long)]
+        since: Option<String>,
+    },
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
+    paths::set_overrides(
+        paths::Overrides {
+            config: args.config.clone(),
+            state_dir: args.state_dir.clone(),
+            cache_dir: args.cache_dir.clone(),
+            log_dir: args.log_dir.clone(),
+        }
+        .or(paths::Overrides::from_env()),
+    );
+
+    if args.version {
+        println!("osx-scrobbler {}", build_info::VERSION);
+        if args.verbose {
+            println!(
+                "\n{}",
+                build_info::details(paths::Paths::current().ok().as_ref())
+            );
+        }
+        return Ok(());
+    }
 
     // Handle Last.fm authentication if requested
     if args.auth_lastfm {
@@ -47,7 +501,7 @@ fn main() -> Result<()> {
 
     // Handle app installation if requested
     if args.install_app {
-        return handle_install_app();
+        return handle_install_app(args.notarization_ticket.as_deref());
     }
 
     // Handle app uninstallation if requested
@@ -55,92 +509,181 @@ fn main() -> Result<()> {
         return handle_uninstall_app();
     }
 
-    // Set up logging based on environment
-    setup_logging(args.console)?;
-
-    // Load configuration (mutable for app filtering updates)
-    let mut config = config::Config::load()?;
-    log::info!("Configuration loaded successfully");
-    log::info!("Refresh interval: {}s", config.refresh_interval);
-    log::info!("Scrobble threshold: {}%", config.scrobble_threshold);
-
-    // Initialize scrobblers
-    let mut scrobblers: Vec<Service> = Vec::new();
-
-    // Initialize Last.fm if enabled
-    if let Some(ref lastfm_config) = config.lastfm {
-        if lastfm_config.enabled {
-            if !lastfm_config.session_key.is_empty() {
-                log::info!("Last.fm scrobbler enabled");
-                let service = Service::lastfm(
-                    lastfm_config.api_key.clone(),
-                    lastfm_config.api_secret.clone(),
-                    lastfm_config.session_key.clone(),
-                );
-                scrobblers.push(service);
-            } else {
-                log::warn!("Last.fm is enabled but session_key is not set. Skipping Last.fm.");
-            }
-        }
+    // Handle subcommands
+    if let Some(command) = args.command {
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+        return match command {
+            Command::History { command } => match command {
+                HistoryCommand::List { limit } => handle_history_list(limit),
+                HistoryCommand::Search {
+                    query,
+                    app,
+                    since,
+                    until,
+                    status,
+                    limit,
+                    artist,
+                    tag,
+                    json,
+                } => {
+                    let now = chrono::Utc::now();
+                    let filter = search::SearchFilter {
+                        app,
+                        artist,
+                        since: since.map(|s| history::parse_since(&s, now)).transpose()?,
+                        until: until.map(|s| history::parse_since(&s, now)).transpose()?,
+                        status: status.map(Into::into),
+                        tag,
+                    };
+                    handle_history_search(&query, &filter, limit, json)
+                }
+                HistoryCommand::Export {
+                    format,
+                    since,
+                    until,
+                    artist,
+                    app,
+                    tag,
+                    output,
+                } => {
+                    let now = chrono::Utc::now();
+                    let filter = search::SearchFilter {
+                        app,
+                        artist,
+                        since: since.map(|s| history::parse_since(&s, now)).transpose()?,
+                        until: until.map(|s| history::parse_since(&s, now)).transpose()?,
+                        status: None,
+                        tag,
+                    };
+                    handle_history_export(format, &filter, output.as_deref())
+                }
+                HistoryCommand::Delete { id, remote } => handle_history_delete(id, remote),
+                HistoryCommand::Annotate {
+                    id,
+                    tag,
+                    untag,
+                    note,
+                } => handle_history_annotate(id, &tag, &untag, note),
+                HistoryCommand::Expired => handle_history_expired(),
+                HistoryCommand::Review { approve, reject } => {
+                    handle_history_review(&approve, &reject)
+                }
+                HistoryCommand::Retry { service, since } => {
+                    handle_history_retry(service.as_deref(), since.as_deref())
+                }
+            },
+            Command::Status => handle_status(),
+            Command::Pause => handle_pause(true),
+            Command::Resume => handle_pause(false),
+            Command::Quit => handle_quit(),
+            Command::ScrobbleNow => handle_scrobble_now(),
+            Command::SkipScrobble => handle_skip_scrobble(),
+            Command::ClearNowPlaying => handle_clear_now_playing(),
+            Command::Stats { by, since, limit } => handle_stats(by, since.as_deref(), limit),
+            Command::Timeline { date } => handle_timeline(date.as_deref()),
+            Command::Wrapped {
+                year,
+                format,
+                output,
+            } => handle_wrapped(year, format, output.as_deref()),
+            Command::Reconcile {
+                service,
+                since,
+                fix,
+            } => handle_reconcile(service.as_deref(), &since, fix),
+            Command::Inspect => handle_inspect(),
+            #[cfg(feature = "charts")]
+            Command::StatsWindow => ui::stats_window::run(
+                serde_json::from_reader(std::io::stdin()).context("Failed to read the charts")?,
+            ),
+            Command::Replay { file } => handle_replay(&file),
+            Command::CleanupTest { texts, stats } => handle_cleanup_test(&texts, stats),
+            Command::Import {
+                file,
+                format,
+                service,
+                dry_run,
+            } => handle_import(&file, format, service.as_deref(), dry_run),
+            Command::ImportApps { from, dry_run } => handle_import_apps(from.as_deref(), dry_run),
+            Command::Backup { output, no_secrets } => handle_backup(output, !no_secrets),
+            Command::Restore { path, force } => handle_restore(&path, force),
+            Command::Upgrade => handle_upgrade(),
+            Command::Paths { migrate } => handle_paths(migrate),
+        };
     }
 
-    // Initialize ListenBrainz instances if enabled
-    for lb_config in &config.listenbrainz {
-        if lb_config.enabled {
-            log::info!("ListenBrainz scrobbler enabled: {}", lb_config.name);
-            let name = lb_config.name.clone();
-            let token = lb_config.token.clone();
-            let api_url = lb_config.api_url.clone();
-
-            let backoff = ExponentialBackoff {
-                max_elapsed_time: Some(Duration::from_secs(30)),
-                ..Default::default()
-            };
-
-            let result = retry(backoff, || {
-                Service::listenbrainz(name.clone(), token.clone(), api_url.clone())
-                    .map_err(backoff::Error::transient)
-            });
-
-            match result {
-                Ok(service) => scrobblers.push(service),
-                Err(e) => log::error!("Failed to initialize ListenBrainz after retries: {}", e),
-            }
-        }
+    if args.profile_energy {
+        energy::enable();
     }
 
-    if scrobblers.is_empty() {
-        log::warn!(
-            "No scrobblers enabled! The app will monitor media but won't scrobble anywhere."
-        );
+    // Terminal UI, which owns the terminal and so always logs to the file
+    #[cfg(feature = "tui")]
+    if args.tui {
+        setup_file_logging()?;
+        return tui::run(Engine::new(config::Config::load()?));
     }
 
-    // Initialize system tray
-    let mut tray = TrayManager::new()?;
-    log::info!("System tray initialized");
+    // Set up logging based on environment
+    setup_logging(args.console)?;
 
-    // Initialize text cleaner
-    let text_cleaner = text_cleanup::TextCleaner::new(&config.cleanup);
-    if config.cleanup.enabled {
-        log::info!(
-            "Text cleanup enabled with {} patterns",
-            config.cleanup.patterns.len()
-        );
+    // Load configuration
+    let config = config::Config::load()?;
+    log::info!("Configuration loaded successfully");
+    log::info!("Refresh interval: {}s", config.refresh_interval);
+    log::info!(
+        "Scrobble threshold: {}% ({:?})",
+        config.scrobble_threshold,
+        config.scrobble_threshold_mode
+    );
+
+    // Initialize the scrobbling engine (services, history and media monitor)
+    let mut engine = Engine::new(config);
+    if let Some(path) = &args.record_polls {
+        engine.record_polls(path)?;
     }
 
-    // Initialize media monitor
-    let mut media_monitor = MediaMonitor::new(config.scrobble_threshold, text_cleaner);
+    // Initialize system tray
+    let profiles: Vec<String> = engine
+        .config()
+        .profiles
+        .iter()
+        .map(|profile| profile.name.clone())
+        .collect();
+    let mut tray = TrayManager::new(&profiles)?;
+    log::info!("System tray initialized");
 
     log::info!("Starting OSX Scrobbler...");
 
     // Setup polling state
-    let refresh_interval = Duration::from_secs(config.refresh_interval);
     let mut next_poll_time = Instant::now();
 
     // Define user events for tray menu actions
-    #[derive(Debug, Clone, Copy)]
+    #[derive(Debug, Clone)]
     enum UserEvent {
         TrayQuit,
+        EditLastScrobble,
+        /// A play held back for review, by its slot in the menu
+        ReviewPlay(usize),
+        SubmitReviews,
+        DiscardReviews,
+        LoveTrack,
+        ScrobbleNow,
+        SkipScrobble,
+        ClearNowPlaying,
+        Statistics,
+        Preferences,
+        About,
+        Pause(Option<u64>),
+        Resume,
+        ToggleGuestMode,
+        SwitchProfile(Option<String>),
+        /// Move the app in this slot of the allowed (true) or ignored apps to the other list
+        MoveApp(bool, usize),
+        /// Take the app in this slot of the allowed (true) or ignored apps off its list
+        RemoveApp(bool, usize),
+        ControlRequest,
+        /// A button clicked on the notification with this ID
+        NotificationAction(String, NotificationAction),
     }
 
     // Run event loop on main thread for tray icon
@@ -151,9 +694,82 @@ fn main() -> Result<()> {
     // Get proxy to send events from other threads
     let event_proxy = event_loop.create_proxy();
 
+    // Wake the event loop for control API requests
+    let control_proxy = event_proxy.clone();
+    engine.set_waker(move || {
+        let _ = control_proxy.send_event(UserEvent::ControlRequest);
+    });
+
+    // Forward notification button clicks to the event loop
+    let notification_proxy = event_proxy.clone();
+    let mut notifier = Notifier::new(move |id, action| {
+        let _ = notification_proxy.send_event(UserEvent::NotificationAction(id, action));
+    });
+    // Asked on the first pass of the event loop, once the app is running
+    let mut notification_onboarding = match (&notifier, engine.config().notifications.enabled) {
+        (Some(notifier), Some(true)) => {
+            notifier.request_permission();
+            false
+        }
+        (Some(_), None) => true,
+        _ => false,
+    };
+    // Quarantined bundle to ask about on the first pass of the event loop
+    let mut quarantined = std::env::current_exe()
+        .ok()
+        .and_then(|exe| codesign::app_bundle(&exe))
+        .map(|bundle| {
+            // A translocated copy is read-only; the original is what login starts
+            if codesign::is_translocated(&bundle) {
+                Path::new("/Applications").join("OSX Scrobbler.app")
+            } else {
+                bundle
+            }
+        })
+        .filter(|bundle| codesign::is_quarantined(bundle));
+    // Track waiting for its album art before being notified
+    let mut pending_notification: Option<(Track, Instant)> = None;
+
     // Spawn minimal thread to forward tray menu events to main event loop
     // This allows event-based wakeup instead of polling
     let quit_item_id = tray.quit_item.id().clone();
+    let edit_last_item_id = tray.edit_last_item.id().clone();
+    let love_item_id = tray.love_item.id().clone();
+    let review_item_ids: Vec<_> = tray.review_items.iter().map(|i| i.id().clone()).collect();
+    let submit_reviews_item_id = tray.submit_reviews_item.id().clone();
+    let discard_reviews_item_id = tray.discard_reviews_item.id().clone();
+    let scrobble_now_item_id = tray.scrobble_now_item.id().clone();
+    let skip_scrobble_item_id = tray.skip_scrobble_item.id().clone();
+    let clear_now_playing_item_id = tray.clear_now_playing_item.id().clone();
+    let guest_mode_item_id = tray.guest_mode_item.id().clone();
+    let resume_item_id = tray.resume_item.id().clone();
+    let pause_item_ids: Vec<_> = tray
+        .pause_items
+        .iter()
+        .map(|(minutes, item)| (item.id().clone(), *minutes))
+        .collect();
+    let stats_item_id = tray.stats_item.id().clone();
+    let preferences_item_id = tray.preferences_item.id().clone();
+    let about_item_id = tray.about_item.id().clone();
+    let profile_item_ids: Vec<_> = tray
+        .profile_items
+        .iter()
+        .map(|(name, item)| (item.id().clone(), name.clone()))
+        .collect();
+    // Move and remove items of each app slot, with its list (allowed or not) and position
+    let app_item_ids: Vec<_> = [(true, &tray.allowed_apps), (false, &tray.ignored_apps)]
+        .into_iter()
+        .flat_map(|(allowed, list)| {
+            list.slots.iter().enumerate().map(move |(slot, app)| {
+                (
+                    app.move_item.id().clone(),
+                    app.remove_item.id().clone(),
+                    allowed,
+                    slot,
+                )
+            })
+        })
+        .collect();
     std::thread::spawn(move || {
         use tray_icon::menu::MenuEvent;
         loop {
@@ -161,6 +777,67 @@ fn main() -> Result<()> {
                 if event.id == quit_item_id {
                     log::info!("Quit menu item clicked");
                     let _ = event_proxy.send_event(UserEvent::TrayQuit);
+                } else if event.id == edit_last_item_id {
+                    log::info!("Edit last scrobble menu item clicked");
+                    let _ = event_proxy.send_event(UserEvent::EditLastScrobble);
+                } else if let Some(slot) = review_item_ids.iter().position(|id| event.id == *id) {
+                    log::info!("Review menu item clicked");
+                    let _ = event_proxy.send_event(UserEvent::ReviewPlay(slot));
+                } else if event.id == submit_reviews_item_id {
+                    log::info!("Submit all reviews menu item clicked");
+                    let _ = event_proxy.send_event(UserEvent::SubmitReviews);
+                } else if event.id == discard_reviews_item_id {
+                    log::info!("Discard all reviews menu item clicked");
+                    let _ = event_proxy.send_event(UserEvent::DiscardReviews);
+                } else if event.id == love_item_id {
+                    log::info!("Love track menu item clicked");
+                    let _ = event_proxy.send_event(UserEvent::LoveTrack);
+                } else if event.id == scrobble_now_item_id {
+                    log::info!("Scrobble now menu item clicked");
+                    let _ = event_proxy.send_event(UserEvent::ScrobbleNow);
+                } else if event.id == skip_scrobble_item_id {
+                    log::info!("Skip scrobble menu item clicked");
+                    let _ = event_proxy.send_event(UserEvent::SkipScrobble);
+                } else if event.id == clear_now_playing_item_id {
+                    log::info!("Clear now playing menu item clicked");
+                    let _ = event_proxy.send_event(UserEvent::ClearNowPlaying);
+                } else if event.id == stats_item_id {
+                    log::info!("Statistics menu item clicked");
+                    let _ = event_proxy.send_event(UserEvent::Statistics);
+                } else if event.id == preferences_item_id {
+                    log::info!("Preferences menu item clicked");
+                    let _ = event_proxy.send_event(UserEvent::Preferences);
+                } else if event.id == about_item_id {
+                    log::info!("About menu item clicked");
+                    let _ = event_proxy.send_event(UserEvent::About);
+                } else if event.id == resume_item_id {
+                    log::info!("Resume scrobbling menu item clicked");
+                    let _ = event_proxy.send_event(UserEvent::Resume);
+                } else if let Some((_, minutes)) =
+                    pause_item_ids.iter().find(|(id, _)| event.id == *id)
+                {
+                    log::info!("Pause scrobbling menu item clicked");
+                    let _ = event_proxy.send_event(UserEvent::Pause(*minutes));
+                } else if event.id == guest_mode_item_id {
+                    log::info!("Guest mode menu item clicked");
+                    let _ = event_proxy.send_event(UserEvent::ToggleGuestMode);
+                } else if let Some((_, name)) =
+                    profile_item_ids.iter().find(|(id, _)| event.id == *id)
+                {
+                    log::info!("Profile menu item clicked");
+                    let _ = event_proxy.send_event(UserEvent::SwitchProfile(name.clone()));
+                } else if let Some((move_id, _, allowed, slot)) =
+                    app_item_ids.iter().find(|(move_id, remove_id, _, _)| {
+                        event.id == *move_id || event.id == *remove_id
+                    })
+                {
+                    if event.id == *move_id {
+                        log::info!("Move app menu item clicked");
+                        let _ = event_proxy.send_event(UserEvent::MoveApp(*allowed, *slot));
+                    } else {
+                        log::info!("Remove app menu item clicked");
+                        let _ = event_proxy.send_event(UserEvent::RemoveApp(*allowed, *slot));
+                    }
                 }
             }
         }
@@ -179,141 +856,375 @@ fn main() -> Result<()> {
 
     #[allow(deprecated)]
     event_loop.run(move |event, elwt| {
+        if let winit::event::Event::NewEvents(_) = event {
+            energy::record(Activity::Wakeup);
+        }
+
         // Handle user events (tray menu actions)
         if let winit::event::Event::UserEvent(UserEvent::TrayQuit) = event {
             log::info!("OSX Scrobbler shutting down");
+            engine.shutdown();
             elwt.exit();
             return;
         }
 
-        let now = Instant::now();
-
-        // Only wake up when we need to poll media
-        elwt.set_control_flow(ControlFlow::WaitUntil(next_poll_time));
+        if let winit::event::Event::UserEvent(UserEvent::ControlRequest) = event {
+            engine.handle_control();
+            if engine.quit_requested() {
+                log::info!("OSX Scrobbler shutting down");
+                engine.shutdown();
+                elwt.exit();
+                return;
+            }
+        }
 
-        // Check if it's time to poll media
-        if now >= next_poll_time {
-            match media_monitor.poll(&config.app_filtering) {
-                Ok(events) => {
-                    // Handle now_playing event
-                    if let Some((ref track, ref bundle_id)) = events.now_playing {
+        if let winit::event::Event::UserEvent(UserEvent::NotificationAction(ref id, action)) = event
+        {
+            // Buttons only act on the latest notification
+            let track = notifier.as_ref().and_then(|n| n.track(id)).cloned();
+            match (track, action) {
+                (Some(track), NotificationAction::Love) => {
+                    let results = engine.love(&track);
+                    tray.show_love_result(track, &results);
+                }
+                (Some(track), NotificationAction::DontScrobble) => {
+                    if !engine.skip_scrobble(&track) {
                         log::info!(
-                            "Now playing: {} - {} (album: {}) from {:?}",
+                            "{} - {} is already scrobbled or no longer playing",
                             track.artist,
-                            track.title,
-                            track.album.as_deref().unwrap_or("Unknown"),
-                            bundle_id
+                            track.title
                         );
+                    }
+                }
+                (None, _) => {}
+            }
+        }
 
-                        // Send to scrobblers immediately with retries
-                        for scrobbler in &scrobblers {
-                            let backoff = ExponentialBackoff {
-                                max_elapsed_time: Some(Duration::from_secs(10)),
-                                ..Default::default()
-                            };
-
-                            let result = retry(backoff, || {
-                                scrobbler
-                                    .now_playing(track)
-                                    .map_err(backoff::Error::transient)
-                            });
-
-                            if let Err(e) = result {
-                                log::error!("Failed to send now playing after retries: {}", e);
-                            }
-                        }
+        if notification_onboarding {
+            notification_onboarding = false;
+            let enabled = ui::notifications::show_onboarding_prompt();
+            if let (true, Some(notifier)) = (enabled, &notifier) {
+                notifier.request_permission();
+            }
+            engine.set_notifications_enabled(enabled);
+        }
+
+        if let Some(bundle) = quarantined.take() {
+            log::warn!("{} is quarantined", bundle.display());
+            if ui::quarantine_dialog::show_quarantine_prompt(&bundle) {
+                match codesign::clear_quarantine(&bundle) {
+                    Ok(()) => log::info!("Cleared the quarantine on {}", bundle.display()),
+                    Err(e) => log::error!("{:#}", e),
+                }
+            } else {
+                log::info!(
+                    "Left the quarantine in place; clear it later with: xattr -dr \
+                     com.apple.quarantine \"{}\"",
+                    bundle.display()
+                );
+            }
+        }
+
+        if let winit::event::Event::UserEvent(UserEvent::EditLastScrobble) = event {
+            match engine.edit_last_scrobble(ui::edit_dialog::show_edit_prompt) {
+                Ok(Some(entry)) => {
+                    let track_str = format!("{} - {}", entry.artist, entry.title);
+                    if let Err(e) = tray.update_last_scrobbled(Some(track_str)) {
+                        log::error!("Failed to update tray last scrobbled: {}", e);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => log::error!("Failed to edit last scrobble: {:#}", e),
+            }
+        }
 
-                        // Update tray immediately
-                        let track_str = format!("{} - {}", track.artist, track.title);
-                        if let Err(e) = tray.update_now_playing(Some(track_str)) {
-                            log::error!("Failed to update tray now playing: {}", e);
+        if let winit::event::Event::UserEvent(UserEvent::ReviewPlay(slot)) = event {
+            let entry = tray.review_id(slot).and_then(|id| {
+                let queue = engine.review_queue();
+                let entry = queue.into_iter().find(|e| e.id == id)?;
+                Some((id, entry.artist.clone(), entry.title.clone()))
+            });
+            if let Some((id, artist, title)) = entry {
+                let result = match ui::edit_dialog::show_review_prompt(&artist, &title) {
+                    Some(ReviewChoice::Submit(artist, title)) => engine
+                        .approve_review(id, Some((artist, title)))
+                        .map(|entry| Some(format!("{} - {}", entry.artist, entry.title))),
+                    Some(ReviewChoice::Discard) => engine.discard_review(&[id]).map(|_| None),
+                    None => Ok(None),
+                };
+                match result {
+                    Ok(Some(track_str)) => {
+                        if let Err(e) = tray.update_last_scrobbled(Some(track_str)) {
+                            log::error!("Failed to update tray last scrobbled: {}", e);
                         }
                     }
+                    Ok(None) => {}
+                    Err(e) => log::error!("Failed to review scrobble: {:#}", e),
+                }
+            }
+        }
 
-                    // Handle scrobble event
-                    if let Some((ref track, timestamp, ref bundle_id)) = events.scrobble {
-                        log::info!(
-                            "Scrobble: {} - {} at {} from {:?}",
-                            track.artist,
-                            track.title,
-                            timestamp.format("%Y-%m-%d %H:%M:%S"),
-                            bundle_id
-                        );
+        if let winit::event::Event::UserEvent(UserEvent::SubmitReviews) = event {
+            let ids: Vec<u64> = engine.review_queue().iter().map(|e| e.id).collect();
+            for id in ids {
+                if let Err(e) = engine.approve_review(id, None) {
+                    log::error!("Failed to submit reviewed scrobble: {:#}", e);
+                }
+            }
+        }
+
+        if let winit::event::Event::UserEvent(UserEvent::DiscardReviews) = event {
+            let ids: Vec<u64> = engine.review_queue().iter().map(|e| e.id).collect();
+            if let Err(e) = engine.discard_review(&ids) {
+                log::error!("Failed to discard reviewed scrobbles: {:#}", e);
+            }
+        }
 
-                        for scrobbler in &scrobblers {
-                            let backoff = ExponentialBackoff {
-                                max_elapsed_time: Some(Duration::from_secs(30)),
-                                ..Default::default()
-                            };
+        if let winit::event::Event::UserEvent(UserEvent::LoveTrack) = event {
+            match engine.love_current() {
+                Ok((track, results)) => tray.show_love_result(track, &results),
+                Err(e) => log::warn!("Failed to love track: {:#}", e),
+            }
+        }
 
-                            let result = retry(backoff, || {
-                                scrobbler
-                                    .scrobble(track, timestamp)
-                                    .map_err(backoff::Error::transient)
-                            });
+        if let winit::event::Event::UserEvent(UserEvent::ScrobbleNow) = event {
+            match engine.scrobble_now() {
+                // Poll right away to show it like any other scrobble
+                Ok(_) => next_poll_time = Instant::now(),
+                Err(e) => log::warn!("Failed to scrobble now: {:#}", e),
+            }
+        }
 
-                            if let Err(e) = result {
-                                log::error!("Failed to scrobble after retries: {}", e);
-                            }
-                        }
+        if let winit::event::Event::UserEvent(UserEvent::SkipScrobble) = event {
+            match engine.skip_current() {
+                Ok(_) => {
+                    if let Err(e) = tray.update_scrobble_countdown(engine.session().as_ref()) {
+                        log::error!("Failed to update tray scrobble countdown: {}", e);
+                    }
+                }
+                Err(e) => log::warn!("Failed to skip scrobble: {:#}", e),
+            }
+        }
 
-                        let track_str = format!("{} - {}", track.artist, track.title);
-                        if let Err(e) = tray.update_last_scrobbled(Some(track_str)) {
-                            log::error!("Failed to update tray last scrobbled: {}", e);
+        if let winit::event::Event::UserEvent(UserEvent::ClearNowPlaying) = event {
+            match engine.clear_now_playing() {
+                // Poll right away to update the menu
+                Ok(_) => next_poll_time = Instant::now(),
+                Err(e) => log::warn!("Failed to clear now playing: {:#}", e),
+            }
+        }
+
+        if let winit::event::Event::UserEvent(UserEvent::Statistics) = event {
+            let entries = engine.history().map(|h| h.entries()).unwrap_or_default();
+            let charts = stats::Charts::new(entries, chrono::Local::now());
+            // Fall back to the text report when the window can't be opened
+            #[cfg(feature = "charts")]
+            let shown = ui::stats_window::open(&charts)
+                .inspect_err(|e| log::warn!("Failed to open the statistics window: {:#}", e))
+                .is_ok();
+            #[cfg(not(feature = "charts"))]
+            let shown = false;
+            if !shown {
+                ui::stats_dialog::show_stats(&stats::report(&charts));
+            }
+        }
+
+        // Reopened with the error until the changes are valid or the dialog is cancelled
+        if let winit::event::Event::UserEvent(UserEvent::Preferences) = event {
+            let mut config = engine.config().clone();
+            let mut error: Option<String> = None;
+            while let Some(result) =
+                ui::preferences_dialog::show_preferences(&config, error.as_deref())
+            {
+                let result = result.and_then(|edited| {
+                    config = edited.clone();
+                    engine.save_config(edited)
+                });
+                match result {
+                    Ok(()) => {
+                        log::info!("Saved preferences");
+                        // Poll right away, with the new settings
+                        next_poll_time = Instant::now();
+                        break;
+                    }
+                    Err(e) => {
+                        log::warn!("Failed to save preferences: {:#}", e);
+                        error = Some(format!("{:#}", e));
+                    }
+                }
+            }
+        }
+
+        if let winit::event::Event::UserEvent(UserEvent::About) = event {
+            let paths = paths::Paths::current().ok();
+            ui::about_dialog::show_about(&build_info::details(paths.as_ref()));
+        }
+
+        if let winit::event::Event::UserEvent(UserEvent::Pause(minutes)) = event {
+            let until = minutes
+                .map(|minutes| chrono::Utc::now() + chrono::Duration::minutes(minutes as i64));
+            engine.pause_until(until);
+        }
+
+        if let winit::event::Event::UserEvent(UserEvent::Resume) = event {
+            engine.set_paused(false);
+        }
+
+        if let winit::event::Event::UserEvent(UserEvent::ToggleGuestMode) = event {
+            engine.set_guest_mode(!engine.is_guest_mode());
+        }
+
+        if let winit::event::Event::UserEvent(UserEvent::MoveApp(allowed, slot)) = event {
+            if let Some(app) = tray.app(allowed, slot) {
+                engine.set_app_allowed(&app, !allowed);
+            }
+        }
+
+        if let winit::event::Event::UserEvent(UserEvent::RemoveApp(allowed, slot)) = event {
+            if let Some(app) = tray.app(allowed, slot) {
+                engine.remove_app(&app);
+            }
+        }
+
+        if let winit::event::Event::UserEvent(UserEvent::SwitchProfile(ref name)) = event {
+            if let Err(e) = engine.switch_profile(name.as_deref()) {
+                log::error!("Failed to switch profile: {:#}", e);
+            }
+        }
+
+        // Pausing and guest mode can also change through the control API or by expiring
+        if let Err(e) = tray.update_paused(engine.is_paused(), engine.paused_until()) {
+            log::error!("Failed to update tray pause: {}", e);
+        }
+        if let Err(e) = tray.update_guest_mode(engine.is_guest_mode(), engine.guest_mode_expires())
+        {
+            log::error!("Failed to update tray guest mode: {}", e);
+        }
+        // And the profile through the control API
+        if let Err(e) = tray.update_profile(engine.active_profile()) {
+            log::error!("Failed to update tray profile: {}", e);
+        }
+        // The config can be reloaded through the control API or the web UI
+        tray.set_menu_bar(&engine.config().menu_bar);
+        // Plays join the review queue when scrobbled and leave it from the menu
+        if let Err(e) = tray.update_review(&engine.review_queue()) {
+            log::error!("Failed to update tray review queue: {}", e);
+        }
+        if let Err(e) = tray.update_apps(&engine.config().app_filtering) {
+            log::error!("Failed to update tray apps: {}", e);
+        }
+
+        let now = Instant::now();
+
+        // Only wake up when we need to poll media
+        elwt.set_control_flow(ControlFlow::WaitUntil(next_poll_time));
+
+        // Check if it's time to poll media
+        if now >= next_poll_time {
+            let update = engine.poll();
+
+            // Update tray immediately
+            if let Some(ref track) = update.now_playing {
+                let track_str = format!("{} - {}", track.artist, track.title);
+                if let Err(e) = tray.update_now_playing(Some(track_str)) {
+                    log::error!("Failed to update tray now playing: {}", e);
+                }
+            } else if update.now_playing_cleared {
+                if let Err(e) = tray.update_now_playing(None) {
+                    log::error!("Failed to update tray now playing: {}", e);
+                }
+                pending_notification = None;
+            }
+
+            if let Some(ref track) = update.scrobbled {
+                let track_str = format!("{} - {}", track.artist, track.title);
+                if let Err(e) = tray.update_last_scrobbled(Some(track_str)) {
+                    log::error!("Failed to update tray last scrobbled: {}", e);
+                }
+            }
+
+            if let Err(e) = tray.update_scrobble_countdown(engine.session().as_ref()) {
+                log::error!("Failed to update tray scrobble countdown: {}", e);
+            }
+
+            // Notifications, held back by Focus modes unless their type is allowed through
+            if let Some(notifier) = notifier.as_mut() {
+                let notifications = &engine.config().notifications;
+                if notifications.enabled == Some(true) {
+                    let allowed = |kind| focus::should_notify(kind, &notifications.during_focus);
+
+                    for service in &update.auth_expired {
+                        if allowed(NotificationKind::AuthExpired) {
+                            notifier.show_auth_expired(service);
                         }
                     }
 
-                    // Handle unknown app event (blocking dialog)
-                    if let Some(ref bundle_id) = events.unknown_app {
-                        use ui::app_dialog::{show_app_prompt, AppChoice};
-
-                        log::info!("Prompting user for app: {}", bundle_id);
-                        let choice = show_app_prompt(bundle_id);
-
-                        match choice {
-                            AppChoice::Allow => {
-                                log::info!("User allowed app: {}", bundle_id);
-                                if !config.app_filtering.allowed_apps.contains(bundle_id) {
-                                    config.app_filtering.allowed_apps.push(bundle_id.clone());
-                                    if let Err(e) = config.save() {
-                                        log::error!("Failed to save config: {}", e);
-                                    } else {
-                                        log::info!("Added {} to allowed apps", bundle_id);
-                                    }
-                                }
+                    if let Some(ref track) = update.scrobbled {
+                        if notifications.scrobbled && allowed(NotificationKind::Scrobbled) {
+                            notifier.show_scrobbled(track);
+                        }
+                        if notifications.failed && allowed(NotificationKind::Failed) {
+                            for (service, error) in &update.failed {
+                                notifier.show_failed(track, service, error);
                             }
-                            AppChoice::Ignore => {
-                                log::info!("User ignored app: {}", bundle_id);
-                                if !config.app_filtering.ignored_apps.contains(bundle_id) {
-                                    config.app_filtering.ignored_apps.push(bundle_id.clone());
-                                    if let Err(e) = config.save() {
-                                        log::error!("Failed to save config: {}", e);
-                                    } else {
-                                        log::info!("Added {} to ignored apps", bundle_id);
-                                    }
-                                }
+                        }
+                    }
+
+                    // A new track is shown once its album art is in or has had time to download
+                    if let Some(ref track) = update.now_playing {
+                        pending_notification = Some((track.clone(), now));
+                    }
+                    if let Some((track, since)) = &pending_notification {
+                        let artwork = engine.artwork();
+                        if artwork.is_some()
+                            || !engine.config().artwork.enabled
+                            || now.duration_since(*since) >= NOTIFICATION_ARTWORK_WAIT
+                        {
+                            if notifications.now_playing && allowed(NotificationKind::NowPlaying) {
+                                notifier.show_now_playing(
+                                    track,
+                                    engine.session().as_ref(),
+                                    artwork.as_deref(),
+                                );
                             }
+                            pending_notification = None;
                         }
                     }
                 }
-                Err(e) => {
-                    log::error!("Error polling media: {}", e);
+            }
+
+            // Handle unknown app event (blocking dialog)
+            if let Some(ref bundle_id) = update.unknown_app {
+                use ui::app_dialog::{show_app_prompt, AppChoice};
+
+                log::info!("Prompting user for app: {}", bundle_id);
+                match show_app_prompt(bundle_id) {
+                    AppChoice::Allow => {
+                        log::info!("User allowed app: {}", bundle_id);
+                        engine.set_app_allowed(bundle_id, true);
+                    }
+                    AppChoice::Ignore => {
+                        log::info!("User ignored app: {}", bundle_id);
+                        engine.set_app_allowed(bundle_id, false);
+                    }
                 }
             }
 
-            // Schedule next poll
-            next_poll_time = now + refresh_interval;
+            // Schedule next poll (the interval can change with the config and playback)
+            next_poll_time = now + engine.poll_interval();
+            energy::log_hourly();
         }
     })?;
 
+    if energy::is_enabled() {
+        println!("{}", energy::report());
+    }
     log::info!("Application exited cleanly");
     Ok(())
 }
 
 /// Set up logging based on whether we're running from a terminal
 fn setup_logging(force_console: bool) -> Result<()> {
-    use std::io::Write;
-
     // Check if stdout is a TTY (terminal)
     let is_terminal = atty::is(atty::Stream::Stdout);
     let use_console = force_console || is_terminal;
@@ -323,39 +1234,44 @@ fn setup_logging(force_console: bool) -> Result<()> {
         env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
     } else {
         // Not running from terminal (e.g., launched via Spotlight)
-        // Log to file instead
-        let log_dir = dirs::home_dir()
-            .ok_or_else(|| anyhow::anyhow!("Could not find home directory"))?
-            .join("Library")
-            .join("Logs");
-
-        std::fs::create_dir_all(&log_dir)?;
-        let log_file = log_dir.join("osx-scrobbler.log");
-
-        let target = Box::new(
-            std::fs::OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open(&log_file)?,
-        );
+        setup_file_logging()?;
+    }
 
-        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
-            .target(env_logger::Target::Pipe(target))
-            .format(|buf, record| {
-                writeln!(
-                    buf,
-                    "[{}] {} - {}",
-                    chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
-                    record.level(),
-                    record.args()
-                )
-            })
-            .init();
+    Ok(())
+}
+
+/// Log to ~/Library/Logs/osx-scrobbler.log (or the `--log-dir` override)
+fn setup_file_logging() -> Result<()> {
+    use std::io::Write;
 
-        // Log where we're logging to (this will go to the file)
-        log::info!("OSX Scrobbler started (logging to {})", log_file.display());
+    let log_file = paths::Paths::current()?.log;
+    if let Some(log_dir) = log_file.parent() {
+        std::fs::create_dir_all(log_dir)?;
     }
 
+    let target = Box::new(
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_file)?,
+    );
+
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
+        .target(env_logger::Target::Pipe(target))
+        .format(|buf, record| {
+            writeln!(
+                buf,
+                "[{}] {} - {}",
+                chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+                record.level(),
+                record.args()
+            )
+        })
+        .init();
+
+    // Log where we're logging to (this will go to the file)
+    log::info!("OSX Scrobbler started (logging to {})", log_file.display());
+
     Ok(())
 }
 
@@ -366,8 +1282,8 @@ fn handle_lastfm_auth() -> Result<()> {
 
     // Check if Last.fm is configured
     let lastfm_config = config
-        .lastfm
-        .as_ref()
+        .credentials()
+        .0
         .ok_or_else(|| anyhow::anyhow!("Last.fm is not configured in config file"))?;
 
     if lastfm_config.api_key.is_empty() || lastfm_config.api_secret.is_empty() {
@@ -382,13 +1298,12 @@ fn handle_lastfm_auth() -> Result<()> {
     println!("API Secret: {}\n", lastfm_config.api_secret);
 
     // Run authentication flow
-    let session_key =
-        scrobbler::lastfm_auth::authenticate(&lastfm_config.api_key, &lastfm_config.api_secret)?;
+    let session_key = scrobbler::lastfm_auth::authenticate(lastfm_config)?;
 
     println!("Session Key: {}\n", session_key);
 
     // Update config with session key
-    if let Some(ref mut lastfm) = config.lastfm {
+    if let Some(lastfm) = config.lastfm_mut() {
         lastfm.session_key = session_key;
         lastfm.enabled = true;
     }
@@ -403,47 +1318,1280 @@ fn handle_lastfm_auth() -> Result<()> {
     Ok(())
 }
 
-/// Info.plist template for macOS app bundle
-const INFO_PLIST_TEMPLATE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
-<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
-<plist version="1.0">
-<dict>
-    <key>CFBundleName</key>
-    <string>OSX Scrobbler</string>
-    <key>CFBundleDisplayName</key>
-    <string>OSX Scrobbler</string>
-    <key>CFBundleIdentifier</key>
-    <string>com.osxscrobbler</string>
-    <key>CFBundleVersion</key>
-    <string>{VERSION}</string>
-    <key>CFBundleShortVersionString</key>
-    <string>{VERSION}</string>
-    <key>CFBundlePackageType</key>
-    <string>APPL</string>
-    <key>CFBundleExecutable</key>
-    <string>osx-scrobbler</string>
-    <key>LSUIElement</key>
-    <true/>
-    <key>LSMinimumSystemVersion</key>
-    <string>10.15</string>
-    <key>NSHighResolutionCapable</key>
-    <true/>
-</dict>
-</plist>"#;
+/// Stop a command that changes the history while the app is running
+///
+/// The app keeps the history in memory, so it wouldn't see the changes and could write its own
+/// copy of the same scrobbles back over them.
+fn ensure_app_not_running(action: &str) -> Result<()> {
+    // The lock file is held whether or not the control API is on
+    if osx_scrobbler::app_lock::is_held()? {
+        anyhow::bail!("Quit OSX Scrobbler before {}", action);
+    }
+    Ok(())
+}
 
-/// Install OSX Scrobbler as a macOS app bundle in /Applications/
-fn handle_install_app() -> Result<()> {
-    use std::fs;
-    use std::io::Write;
-    use std::os::unix::fs::PermissionsExt;
+/// Re-submit failed or private scrobbles from the local history
+fn handle_history_retry(service_filter: Option<&str>, since: Option<&str>) -> Result<()> {
+    ensure_app_not_running("retrying scrobbles")?;
+    let config = config::Config::load()?;
+    let since = since
+        .map(|s| history::parse_since(s, chrono::Utc::now()))
+        .transpose()?;
 
-    println!("OSX Scrobbler App Bundle Installer");
-    println!("===================================\n");
+    let scrobblers: Vec<Box<dyn Scrobbler>> = build_scrobblers(&config)
+        .into_iter()
+        .filter(|s| service_filter.is_none_or(|f| history::service_matches(&s.key(), f)))
+        .collect();
 
-    let app_name = "OSX Scrobbler.app";
-    let app_path = std::path::Path::new("/Applications").join(app_name);
-    let contents_dir = app_path.join("Contents");
+    if scrobblers.is_empty() {
+        anyhow::bail!("No enabled scrobbling service matches the given filter");
+    }
+
+    let routes = routing::Routes::new(&config.routes)?;
+    let mut history = history::History::load()?;
+    let mut retried = 0;
+    let mut failed = 0;
+
+    for entry in history.entries_mut() {
+        if since.is_some_and(|since| entry.played_at < since) {
+            continue;
+        }
+
+        // Private scrobbles go where routes would have sent them at the time
+        let played_at = entry.played_at.with_timezone(&chrono::Local).naive_local();
+        for scrobbler in &scrobblers {
+            let key = scrobbler.key();
+            if !entry.needs_retry(&key)
+                || !routes.allows(&key, entry.bundle_id.as_deref(), played_at)
+            {
+                continue;
+            }
+
+            let result = scrobble_with_retries(
+                scrobbler.as_ref(),
+                &entry.track(),
+                entry.played_at,
+                entry.bundle_id.as_deref(),
+            );
+            match result {
+                Ok(()) => {
+                    println!("✅ {} - {} → {}", entry.artist, entry.title, key);
+                    retried += 1;
+                }
+                Err(ref e) => {
+                    println!("❌ {} - {} → {}: {:#}", entry.artist, entry.title, key, e);
+                    failed += 1;
+                }
+            }
+            entry.set_submission(&key, &result);
+        }
+    }
+
+    history.save()?;
+
+    println!("\nRe-submitted {} scrobble(s), {} failed.", retried, failed);
+
+    Ok(())
+}
+
+/// Print the most recent history entries
+fn handle_history_list(limit: usize) -> Result<()> {
+    let history = history::History::load()?;
+    let entries: Vec<&history::HistoryEntry> = history
+        .entries()
+        .iter()
+        .rev()
+        .filter(|e| !e.deleted)
+        .take(limit)
+        .collect();
+
+    if entries.is_empty() {
+        println!("No scrobbles recorded yet.");
+        return Ok(());
+    }
+
+    for entry in entries.into_iter().rev() {
+        print_history_entry(entry);
+    }
+
+    Ok(())
+}
+
+/// Find scrobbles in the history, printing them like `history list` or as JSON
+fn handle_history_search(
+    query: &str,
+    filter: &search::SearchFilter,
+    limit: usize,
+    json: bool,
+) -> Result<()> {
+    let entries = history::History::between(filter.since, filter.until)?;
+    let results: Vec<&history::HistoryEntry> = search::search(&entries, query, filter)
+        .into_iter()
+        .take(limit)
+        .collect();
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&results).context("Failed to serialize results")?
+        );
+    } else if results.is_empty() {
+        println!("No matching scrobbles.");
+    } else {
+        for entry in results {
+            print_history_entry(entry);
+        }
+    }
+
+    Ok(())
+}
+
+/// Write the history entries the filter lets through as CSV or JSON
+fn handle_history_export(
+    format: ExportFormat,
+    filter: &search::SearchFilter,
+    output: Option<&Path>,
+) -> Result<()> {
+    let entries = history::History::between(filter.since, filter.until)?;
+    let entries: Vec<&history::HistoryEntry> = entries
+        .iter()
+        .filter(|e| !e.deleted && filter.matches(e))
+        .collect();
+    let text = match format {
+        ExportFormat::Csv => export::to_csv(&entries),
+        ExportFormat::Json => export::to_json(&entries)?,
+    };
+
+    match output {
+        Some(path) => {
+            std::fs::write(path, text)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            println!(
+                "Exported {} scrobble(s) to {}",
+                entries.len(),
+                path.display()
+            );
+        }
+        None => print!("{}", text),
+    }
+    Ok(())
+}
+
+/// One line per scrobble: ID, time, track and per-service status
+fn print_history_entry(entry: &history::HistoryEntry) {
+    let services: Vec<String> = entry
+        .submissions
+        .iter()
+        .map(|s| {
+            let status = if s.deleted {
+                "🗑"
+            } else if s.exported {
+                "📤"
+            } else if s.submitted {
+                "✅"
+            } else {
+                "❌"
+            };
+            format!("{} {}", status, s.service)
+        })
+        .collect();
+    println!(
+        "{:>6}  {}  {} - {}  [{}]",
+        entry.id,
+        entry
+            .played_at
+            .with_timezone(&chrono::Local)
+            .format("%Y-%m-%d %H:%M"),
+        entry.artist,
+        entry.title,
+        if entry.guest {
+            "guest".to_string()
+        } else if entry.review {
+            "held for review".to_string()
+        } else if entry.private {
+            "private".to_string()
+        } else {
+            services.join(", ")
+        }
+    );
+    if let Some(url) = &entry.origin_url {
+        println!("        {}", url);
+    }
+    if !entry.tags.is_empty() {
+        println!("        #{}", entry.tags.join(" #"));
+    }
+    if let Some(note) = &entry.note {
+        println!("        {}", note);
+    }
+}
+
+/// Show or change the note and tags of a scrobble
+fn handle_history_annotate(
+    id: u64,
+    tags: &[String],
+    untag: &[String],
+    note: Option<String>,
+) -> Result<()> {
+    if !tags.is_empty() || !untag.is_empty() || note.is_some() {
+        ensure_app_not_running("changing a scrobble")?;
+    }
+    let mut history = history::History::load()?;
+    let entry = history
+        .entry_mut(id)
+        .with_context(|| format!("No scrobble with ID {}", id))?;
+
+    for tag in tags {
+        entry.add_tag(tag)?;
+    }
+    for tag in untag {
+        if !entry.remove_tag(tag) {
+            println!("Scrobble {} isn't tagged '{}'", id, tag);
+        }
+    }
+    if let Some(note) = note {
+        let note = note.trim();
+        entry.note = (!note.is_empty()).then(|| note.to_string());
+    }
+
+    print_history_entry(entry);
+    history.save()
+}
+
+/// List the scrobbles held back for review, or approve or reject some
+fn handle_history_review(approve: &[u64], reject: &[u64]) -> Result<()> {
+    if !approve.is_empty() || !reject.is_empty() {
+        ensure_app_not_running("reviewing scrobbles")?;
+    }
+    let mut history = history::History::load()?;
+
+    if approve.is_empty() && reject.is_empty() {
+        let held: Vec<&history::HistoryEntry> = history
+            .entries()
+            .iter()
+            .filter(|e| e.review && !e.deleted)
+            .collect();
+        if held.is_empty() {
+            println!("No scrobbles are held for review.");
+            return Ok(());
+        }
+        for entry in &held {
+            print_history_entry(entry);
+        }
+        println!(
+            "\n{} scrobble(s) held for review. Approve them with `history review --approve <ID>...` \
+             or reject them with `--reject <ID>...`.",
+            held.len()
+        );
+        return Ok(());
+    }
+
+    for (ids, approved) in [(approve, true), (reject, false)] {
+        for &id in ids {
+            let entry = history
+                .entry_mut(id)
+                .filter(|e| e.review && !e.deleted)
+                .with_context(|| format!("No scrobble with ID {} is held for review", id))?;
+            entry.review = false;
+            entry.deleted = !approved;
+            println!(
+                "{} {} - {}",
+                if approved { "Approved" } else { "Rejected" },
+                entry.artist,
+                entry.title
+            );
+        }
+    }
+    history.save()?;
+
+    if !approve.is_empty() {
+        println!("\nRun `osx-scrobbler history retry` to submit the approved scrobbles.");
+    }
+    Ok(())
+}
+
+/// Report the plays Last.fm was too late for, and where their export went
+fn handle_history_expired() -> Result<()> {
+    let history = history::History::load()?;
+    let report = scheduler::ExpiredReport::of(history.entries());
+    if report.plays.is_empty() {
+        println!("No scrobbles have been exported for being too old for Last.fm.");
+        return Ok(());
+    }
+
+    for entry in &report.plays {
+        print_history_entry(entry);
+    }
+    println!();
+    println!(
+        "{} play(s) were older than Last.fm accepts ({} days) and were exported to:",
+        report.plays.len(),
+        scheduler::LASTFM_MAX_AGE_DAYS
+    );
+    println!("  {}", paths::Paths::current()?.expired.display());
+    println!(
+        "{} of them reached another service, {} are still queued for one",
+        report.submitted_elsewhere, report.queued_elsewhere
+    );
+
+    Ok(())
+}
+
+/// Delete a scrobble from the history, optionally removing it from the services too
+fn handle_history_delete(id: u64, remote: bool) -> Result<()> {
+    ensure_app_not_running("deleting a scrobble")?;
+    let mut history = history::History::load()?;
+    let entry = history
+        .entry_mut(id)
+        .filter(|e| !e.deleted)
+        .with_context(|| format!("No scrobble with ID {} in the history", id))?;
+
+    println!("Deleting {} - {}", entry.artist, entry.title);
+
+    if remote {
+        let config = config::Config::load()?;
+        let scrobblers = build_scrobblers(&config);
+        let scrobblers: Vec<&dyn Scrobbler> = scrobblers.iter().map(|s| s.as_ref()).collect();
+
+        for (service, result) in delete_from_services(entry, &scrobblers) {
+            match result {
+                Ok(()) => println!("✅ {}: deleted", service),
+                Err(e) => println!("❌ {}: {:#}", service, e),
+            }
+        }
+    }
+
+    entry.deleted = true;
+    history.save()?;
+
+    println!("Scrobble {} deleted from the local history.", id);
+    Ok(())
+}
+
+/// Report (and optionally fix) differences between each service and the local history
+fn handle_reconcile(service_filter: Option<&str>, since: &str, fix: bool) -> Result<()> {
+    if fix {
+        ensure_app_not_running("fixing the history")?;
+    }
+    let config = config::Config::load()?;
+    let since = history::parse_since(since, chrono::Utc::now())?;
+
+    let scrobblers: Vec<Box<dyn Scrobbler>> = build_scrobblers(&config)
+        .into_iter()
+        .filter(|s| service_filter.is_none_or(|f| history::service_matches(&s.key(), f)))
+        .collect();
+
+    if scrobblers.is_empty() {
+        anyhow::bail!("No enabled scrobbling service matches the given filter");
+    }
+
+    let mut history = history::History::load()?;
+    let format_time = |t: chrono::DateTime<chrono::Utc>| {
+        t.with_timezone(&chrono::Local)
+            .format("%Y-%m-%d %H:%M")
+            .to_string()
+    };
+
+    for scrobbler in &scrobblers {
+        let key = scrobbler.key();
+        let remote = match scrobbler.recent_listens(since) {
+            Ok(remote) => remote,
+            Err(e) => {
+                println!("❌ {}: {:#}\n", key, e);
+                continue;
+            }
+        };
+
+        let comparison = reconcile::compare(history.entries(), &remote, since);
+        // Only scrobbles that were meant for this service count as missing there
+        let missing_remotely: Vec<u64> = comparison
+            .missing_remotely
+            .iter()
+            .copied()
+            .filter(|id| {
+                history
+                    .entries()
+                    .iter()
+                    .find(|e| e.id == *id)
+                    .is_some_and(|e| e.submissions.iter().any(|s| s.service == key))
+            })
+            .collect();
+
+        println!(
+            "{}: {} scrobble(s) on the service, {} matched",
+            key,
+            remote.len(),
+            comparison.matched.len()
+        );
+
+        if !missing_remotely.is_empty() {
+            println!("  Missing on {} ({}):", key, missing_remotely.len());
+        }
+        for id in &missing_remotely {
+            let Some(entry) = history.entry_mut(*id) else {
+                continue;
+            };
+            let line = format!(
+                "{}  {} - {}",
+                format_time(entry.played_at),
+                entry.artist,
+                entry.title
+            );
+
+            if fix {
+                let result = scrobble_with_retries(
+                    scrobbler.as_ref(),
+                    &entry.track(),
+                    entry.played_at,
+                    entry.bundle_id.as_deref(),
+                );
+                match result {
+                    Ok(()) => println!("    ✅ {}", line),
+                    Err(ref e) => println!("    ❌ {}: {:#}", line, e),
+                }
+                entry.set_submission(&key, &result);
+            } else {
+                println!("    {}", line);
+            }
+        }
+
+        if !comparison.missing_locally.is_empty() {
+            println!(
+                "  Missing from local history ({}):",
+                comparison.missing_locally.len()
+            );
+        }
+        let mut added = Vec::new();
+        for listen in &comparison.missing_locally {
+            println!(
+                "    {}{}  {} - {}",
+                if fix { "➕ " } else { "" },
+                format_time(listen.played_at),
+                listen.artist,
+                listen.title
+            );
+            if fix {
+                let mut entry = history.new_entry(&listen.track(), listen.played_at, None);
+                entry.set_submission(&key, &Ok(()));
+                added.push(entry);
+            }
+        }
+        history.insert(added)?;
+
+        println!();
+    }
+
+    if fix {
+        history.save()?;
+    } else {
+        println!("Run with --fix to submit missing scrobbles and add missing ones to the history.");
+    }
+
+    Ok(())
+}
+
+/// Print a summary of services, queued submissions and rate limit budgets
+fn handle_status() -> Result<()> {
+    let config = config::Config::load()?;
+    let history = history::History::load()?;
+
+    println!("OSX Scrobbler Status");
+    println!("====================\n");
+
+    // The running app, if it answers on the control socket
+    match osx_scrobbler::control::call("status") {
+        Ok(status) => {
+            let paused_until = serde_json::from_value::<chrono::DateTime<chrono::Utc>>(
+                status["paused_until"].clone(),
+            );
+            let state = if status["paused"].as_bool() == Some(true) {
+                match paused_until {
+                    Ok(until) => format!(
+                        "paused until {}",
+                        until.with_timezone(&chrono::Local).format("%H:%M")
+                    ),
+                    Err(_) => "paused".to_string(),
+                }
+            } else if status["guest_mode"].as_bool() == Some(true) {
+                "guest mode".to_string()
+            } else {
+                "scrobbling".to_string()
+            };
+            println!("App: running ({})", state);
+            match serde_json::from_value::<Track>(status["now_playing"]["track"].clone()) {
+                Ok(track) => println!("Now playing: {} - {}\n", track.artist, track.title),
+                Err(_) => println!("Now playing: nothing\n"),
+            }
+        }
+        Err(_) => println!("App: not running\n"),
+    }
+
+    if let Some(profile) = &config.active_profile {
+        println!("Profile: {}\n", profile);
+    }
+
+    // Configured services and their queued submissions
+    let (lastfm, listenbrainz) = config.credentials();
+    let mut services: Vec<(String, bool)> = Vec::new();
+    if let Some(lastfm) = lastfm {
+        services.push((
+            config.service_key("lastfm"),
+            lastfm.enabled && !lastfm.session_key.is_empty(),
+        ));
+    }
+    for lb in listenbrainz {
+        services.push((
+            config.service_key(&format!("listenbrainz:{}", lb.name)),
+            lb.enabled,
+        ));
+    }
+
+    // Weekly credential checks made by the running app
+    let session_checks = session_check::SessionChecks::load();
+    let date = |time: chrono::DateTime<chrono::Utc>| {
+        time.with_timezone(&chrono::Local)
+            .format("%Y-%m-%d")
+            .to_string()
+    };
+
+    println!("Services:");
+    for (key, enabled) in &services {
+        let queued = history
+            .entries()
+            .iter()
+            .filter(|e| !e.private && !e.deleted)
+            .filter(|e| {
+                e.submissions
+                    .iter()
+                    .any(|s| s.service == *key && s.is_queued())
+            })
+            .count();
+        let session = match session_checks.get(key) {
+            None => String::new(),
+            Some(check) if check.rejected => match check.valid_at {
+                Some(valid_at) => format!(
+                    ", session rejected (last valid {}), sign in again",
+                    date(valid_at)
+                ),
+                None => ", session rejected, sign in again".to_string(),
+            },
+            Some(check) if check.error.is_some() => {
+                format!(", session check failed {}", date(check.checked_at))
+            }
+            Some(check) => format!(", session valid ({})", date(check.checked_at)),
+        };
+        println!(
+            "  {:<30} {:<9} {} queued{}",
+            key,
+            if *enabled { "enabled" } else { "disabled" },
+            queued,
+            session
+        );
+    }
+
+    // Failover groups and whether their backup is in use
+    if !config.failover.is_empty() {
+        println!("\nFailover:");
+        let now = chrono::Utc::now();
+        for group in config.failover.iter().map(failover::FailoverGroup::new) {
+            let state = match group.down_since(history.entries()) {
+                Some(since) if group.is_active(history.entries(), now) => format!(
+                    "backup active (primary down since {})",
+                    since.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M")
+                ),
+                Some(since) => format!(
+                    "standby (primary failing since {})",
+                    since.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M")
+                ),
+                None => "standby".to_string(),
+            };
+            println!("  {} → {}: {}", group.primary, group.backup, state);
+        }
+    }
+
+    println!("\nHistory: {} scrobble(s)", history.entries().len());
+
+    // Last.fm request budget (shared with the running app through the state file)
+    if let Some(lastfm) = lastfm {
+        if lastfm.enabled && !lastfm.api_key.is_empty() {
+            let mut budget = rate_limit::RateBudget::lastfm(&lastfm.api_key);
+            let now = chrono::Utc::now();
+            println!(
+                "Last.fm API budget: {}/{} requests in the last {} minutes ({} left before throttling)",
+                budget.used(now),
+                budget.limit(),
+                rate_limit::LASTFM_WINDOW_SECONDS / 60,
+                budget.remaining(now)
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Print listening time totals from the local history
+fn handle_stats(by: StatsGrouping, since: Option<&str>, limit: usize) -> Result<()> {
+    let since = since
+        .map(|s| history::parse_since(s, chrono::Utc::now()))
+        .transpose()?;
+    let entries = history::History::between(since, None)?;
+
+    let totals = match by {
+        StatsGrouping::Day => stats::by_day(&entries, since, &chrono::Local),
+        StatsGrouping::Artist => stats::by_artist(&entries, since),
+    };
+
+    if totals.is_empty() {
+        println!("No scrobbles in history yet.");
+        return Ok(());
+    }
+
+    let total_seconds: u64 = totals.iter().map(|t| t.seconds).sum();
+    let total_scrobbles: usize = totals.iter().map(|t| t.scrobbles).sum();
+
+    // Show the most recent days, or the top artists
+    let rows = match by {
+        StatsGrouping::Day => &totals[totals.len().saturating_sub(limit)..],
+        StatsGrouping::Artist => &totals[..limit.min(totals.len())],
+    };
+
+    for total in rows {
+        println!(
+            "{:<30} {:>10} {:>6} scrobble(s)",
+            total.key,
+            stats::format_duration(total.seconds),
+            total.scrobbles
+        );
+    }
+
+    println!(
+        "\nTotal: {} listened across {} scrobble(s)",
+        stats::format_duration(total_seconds),
+        total_scrobbles
+    );
+
+    Ok(())
+}
+
+/// Summarize a year of the history
+fn handle_wrapped(year: Option<i32>, format: WrappedFormat, output: Option<&Path>) -> Result<()> {
+    use chrono::Datelike;
+
+    let year = year.unwrap_or_else(|| chrono::Local::now().year());
+    // A day either side covers the year in any timezone
+    let start = chrono::NaiveDate::from_ymd_opt(year, 1, 1).context("Invalid year")?;
+    let end = chrono::NaiveDate::from_ymd_opt(year + 1, 1, 1).context("Invalid year")?;
+    let entries = history::History::between(
+        Some(day_start(start - chrono::Duration::days(1))),
+        Some(day_start(end + chrono::Duration::days(1))),
+    )?;
+    let wrapped = wrapped::Wrapped::new(&entries, year, &chrono::Local);
+    let text = match format {
+        WrappedFormat::Markdown => wrapped.to_markdown(),
+        WrappedFormat::Html => wrapped.to_html(),
+    };
+
+    match output {
+        Some(path) => {
+            std::fs::write(path, text)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            println!(
+                "Wrote {}'s summary ({} scrobble(s)) to {}",
+                year,
+                wrapped.scrobbles,
+                path.display()
+            );
+        }
+        None => print!("{}", text),
+    }
+    Ok(())
+}
+
+/// Write the config and history to a backup file
+fn handle_backup(output: Option<PathBuf>, include_secrets: bool) -> Result<()> {
+    let config = config::Config::load()?;
+    let history = history::History::load()?;
+    let path = output.unwrap_or_else(|| {
+        PathBuf::from(format!(
+            "osx-scrobbler-backup-{}.json",
+            chrono::Local::now().format("%Y-%m-%d")
+        ))
+    });
+
+    backup::Backup::new(&config, history.entries(), include_secrets).write(&path)?;
+
+    println!(
+        "Backed up the config{} and {} scrobble(s) to {}",
+        if include_secrets {
+            ""
+        } else {
+            " (without credentials)"
+        },
+        history.entries().len(),
+        path.display()
+    );
+    if include_secrets {
+        println!("The backup includes your credentials; keep it somewhere safe.");
+    }
+    Ok(())
+}
+
+/// Replace the config and history with a backup's
+fn handle_restore(path: &Path, force: bool) -> Result<()> {
+    ensure_app_not_running("restoring a backup")?;
+
+    let backup = backup::Backup::read(path)?;
+    let mut history = history::History::load()?;
+    if !history.entries().is_empty() && !force {
+        anyhow::bail!(
+            "The history already has {} scrobble(s); use --force to replace it",
+            history.entries().len()
+        );
+    }
+
+    let mut config = backup.config;
+    if !backup.secrets {
+        let current = config::Config::load().unwrap_or_default();
+        for service in backup::restore_secrets(&mut config, &current) {
+            println!("{} has no credentials here and was turned off", service);
+        }
+    }
+    config
+        .validate()
+        .context("The backup's config is invalid")?;
+    config.save()?;
+    history.replace(backup.history)?;
+
+    println!(
+        "Restored the config and {} scrobble(s) from a backup made {} by version {}",
+        history.entries().len(),
+        backup
+            .created_at
+            .with_timezone(&chrono::Local)
+            .format("%Y-%m-%d %H:%M"),
+        backup.app_version
+    );
+    Ok(())
+}
+
+/// Print where files are kept, moving them to the XDG layout first if asked
+fn handle_paths(migrate: bool) -> Result<()> {
+    if migrate {
+        // The app would keep writing to the old locations
+        if osx_scrobbler::control::call("status").is_ok() {
+            anyhow::bail!("Quit OSX Scrobbler before moving its files");
+        }
+        // Makes sure there's a config to move, so the new layout is picked up
+        config::Config::load()?;
+
+        for (from, to) in paths::migrate()? {
+            println!("Moved {} to {}", from.display(), to.display());
+        }
+        println!();
+    }
+
+    let paths = paths::Paths::current()?;
+    println!("Layout:     {}", paths.layout.label());
+    println!("Config:     {}", paths.config.display());
+    println!("History:    {}", paths.history.display());
+    println!("Rate limit: {}", paths.rate_limit.display());
+    println!("Sessions:   {}", paths.session_checks.display());
+    println!("Expired:    {}", paths.expired.display());
+    println!("Socket:     {}", paths.socket.display());
+    println!("Artwork:    {}", paths.artwork.display());
+    println!("Aliases:    {}", paths.artist_aliases.display());
+    println!("Log:        {}", paths.log.display());
+    Ok(())
+}
+
+/// Midnight UTC at the start of `day`, to look up plays around it
+fn day_start(day: chrono::NaiveDate) -> chrono::DateTime<chrono::Utc> {
+    day.and_time(chrono::NaiveTime::MIN).and_utc()
+}
+
+/// Print a day's plays from the local history as a timeline
+fn handle_timeline(date: Option<&str>) -> Result<()> {
+    let today = chrono::Local::now().date_naive();
+    let day = match date {
+        None => today,
+        Some("yesterday") => today - chrono::Duration::days(1),
+        Some(date) => chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .with_context(|| format!("Invalid date '{}' (expected YYYY-MM-DD)", date))?,
+    };
+    // A day either side covers the day in any timezone
+    let entries = history::History::between(
+        Some(day_start(day - chrono::Duration::days(1))),
+        Some(day_start(day + chrono::Duration::days(2))),
+    )?;
+    let plays = timeline::plays_on(&entries, day, &chrono::Local);
+
+    println!("{}\n", day.format("%A, %Y-%m-%d"));
+    if plays.is_empty() {
+        println!("No plays recorded.");
+        return Ok(());
+    }
+
+    for play in &plays {
+        let played = play
+            .listened_seconds
+            .map_or_else(|| "-".to_string(), ui::format_seconds);
+        println!(
+            "{}  {:<50} {:>6}  {}",
+            play.played_at.with_timezone(&chrono::Local).format("%H:%M"),
+            format!("{} - {}", play.artist, play.title),
+            played,
+            timeline::PlayStatus::of(play).label()
+        );
+    }
+
+    let listened: u64 = plays.iter().filter_map(|p| p.listened_seconds).sum();
+    println!(
+        "\n{} play(s), {} listened",
+        plays.len(),
+        stats::format_duration(listened)
+    );
+
+    Ok(())
+}
+
+/// Ask the running app to pause or resume scrobbling
+fn handle_pause(pause: bool) -> Result<()> {
+    let result = osx_scrobbler::control::call(if pause { "pause" } else { "resume" })?;
+    if result["paused"].as_bool() == Some(true) {
+        println!("Scrobbling paused; plays are recorded as private until resumed");
+    } else {
+        println!("Scrobbling resumed");
+    }
+    Ok(())
+}
+
+/// Ask the running app to quit, and wait until it has
+fn handle_quit() -> Result<()> {
+    osx_scrobbler::control::call("quit")?;
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while osx_scrobbler::control::call("status").is_ok() {
+        if Instant::now() >= deadline {
+            anyhow::bail!("OSX Scrobbler is still running");
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    println!("OSX Scrobbler quit");
+    Ok(())
+}
+
+/// Ask the running app to scrobble its current track
+fn handle_scrobble_now() -> Result<()> {
+    let result = osx_scrobbler::control::call("scrobble_now")?;
+    let track: Track =
+        serde_json::from_value(result["track"].clone()).context("Invalid scrobble_now result")?;
+
+    if result["private"].as_bool() == Some(true) {
+        println!(
+            "Recorded {} - {} as private (scrobbling is paused)",
+            track.artist, track.title
+        );
+    } else {
+        println!("Scrobbled {} - {}", track.artist, track.title);
+    }
+    Ok(())
+}
+
+/// Ask the running app not to scrobble this play of its current track
+fn handle_skip_scrobble() -> Result<()> {
+    let result = osx_scrobbler::control::call("skip_scrobble")?;
+    let track: Track =
+        serde_json::from_value(result["track"].clone()).context("Invalid skip_scrobble result")?;
+
+    println!(
+        "Not scrobbling this play of {} - {}",
+        track.artist, track.title
+    );
+    Ok(())
+}
+
+/// Ask the running app to forget the track its player is stuck on
+fn handle_clear_now_playing() -> Result<()> {
+    let result = osx_scrobbler::control::call("clear_now_playing")?;
+    let track: Track = serde_json::from_value(result["track"].clone())
+        .context("Invalid clear_now_playing result")?;
+
+    println!(
+        "Cleared {} - {}; it's ignored until the player reports something else",
+        track.artist, track.title
+    );
+    Ok(())
+}
+
+/// Print Media Remote's now playing info as is, plus the Music app's details of its track
+fn handle_inspect() -> Result<()> {
+    let info = media_monitor::inspect(INSPECT_TIMEOUT).context("Nothing is playing")?;
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&info).context("Failed to serialize now playing info")?
+    );
+    Ok(())
+}
+
+/// Print the events the recorded polls lead to with the current config
+fn handle_replay(path: &Path) -> Result<()> {
+    let config = config::Config::load()?;
+    let snapshots = replay::load(path)?;
+    let Some(first) = snapshots.first() else {
+        println!("No polls recorded in {}", path.display());
+        return Ok(());
+    };
+
+    println!(
+        "Replaying {} polls from {} (nothing is submitted)\n",
+        snapshots.len(),
+        first
+            .at
+            .with_timezone(&chrono::Local)
+            .format("%Y-%m-%d %H:%M:%S")
+    );
+    let events = replay::replay(&config, &snapshots);
+    for (at, event) in &events {
+        println!(
+            "{:>6}s  {}",
+            (*at - first.at).num_seconds(),
+            replay::describe_event(event)
+        );
+    }
+    if events.is_empty() {
+        println!("No events");
+    }
+    Ok(())
+}
+
+/// Clean titles with the configured patterns, and show per-pattern stats from the running app
+fn handle_cleanup_test(texts: &[String], show_stats: bool) -> Result<()> {
+    let config = config::Config::load()?;
+    if !config.cleanup.enabled {
+        println!("Text cleanup is disabled (cleanup.enabled = false)\n");
+    }
+
+    for text in texts {
+        // A cleaner per title, so its stats are the patterns that matched this one
+        let cleaner = text_cleanup::TextCleaner::new(&config.cleanup);
+        let (title, live) = cleaner.live(cleaner.clean_title(text));
+        println!("{}\n  → {}", text, title);
+        if let Some(live) = live {
+            println!("  live: {}", live);
+        }
+        for stat in cleaner.stats().iter().filter(|s| s.matches > 0) {
+            println!("  matched {} {}", stat.kind, stat.pattern);
+        }
+        if title.is_empty() {
+            println!("  ⚠ nothing is left of the title");
+        }
+    }
+
+    if !show_stats {
+        return Ok(());
+    }
+    if !texts.is_empty() {
+        println!();
+    }
+    let response = osx_scrobbler::control::call("cleanup_stats")
+        .context("Pattern stats are counted by the app, which isn't answering")?;
+    let stats: Vec<text_cleanup::PatternStats> =
+        serde_json::from_value(response["patterns"].clone())
+            .context("Invalid cleanup_stats answer")?;
+    if stats.is_empty() {
+        println!("No cleanup patterns are configured");
+        return Ok(());
+    }
+
+    println!("Pattern matches since the app started or its config was reloaded:\n");
+    println!("  {:>7}  {:>7}  Pattern", "Matches", "Emptied");
+    for stat in &stats {
+        let kind = if stat.kind == "suffix" {
+            "suffix: "
+        } else {
+            ""
+        };
+        println!(
+            "  {:>7}  {:>7}  {}{}",
+            stat.matches, stat.emptied, kind, stat.pattern
+        );
+    }
+
+    let unused = stats.iter().filter(|s| s.matches == 0).count();
+    if unused > 0 {
+        println!(
+            "\n{} pattern(s) never matched; they may be safe to remove",
+            unused
+        );
+    }
+    let greedy = stats.iter().filter(|s| s.emptied > 0).count();
+    if greedy > 0 {
+        println!(
+            "{} pattern(s) left names empty; they may be too greedy",
+            greedy
+        );
+    }
+    Ok(())
+}
+
+/// Submit the plays in an import file and add them to the history, a batch at a time
+fn handle_import(
+    path: &Path,
+    format: Option<ImportFileFormat>,
+    service_filter: Option<&str>,
+    dry_run: bool,
+) -> Result<()> {
+    if !dry_run {
+        ensure_app_not_running("importing scrobbles")?;
+    }
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let format = format.map_or_else(|| import::ImportFormat::detect(&text), Into::into);
+    let file = import::parse(&text, format)?;
+
+    let mut history = history::History::load()?;
+    let read = file.scrobbles.len();
+    let scrobbles = import::new_scrobbles(history.entries(), file.scrobbles);
+    println!(
+        "Read {} play(s) from {}: {} new, {} already in the history, {} skipped",
+        read,
+        path.display(),
+        scrobbles.len(),
+        read - scrobbles.len(),
+        file.skipped
+    );
+
+    if dry_run {
+        for scrobble in &scrobbles {
+            println!(
+                "  {}  {} - {}",
+                scrobble
+                    .played_at
+                    .with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M"),
+                scrobble.track.artist,
+                scrobble.track.title
+            );
+        }
+        return Ok(());
+    }
+    if scrobbles.is_empty() {
+        return Ok(());
+    }
+
+    let config = config::Config::load()?;
+    let scrobblers: Vec<Box<dyn Scrobbler>> = build_scrobblers(&config)
+        .into_iter()
+        .filter(|s| service_filter.is_none_or(|f| history::service_matches(&s.key(), f)))
+        .collect();
+    if scrobblers.is_empty() {
+        anyhow::bail!("No enabled scrobbling service matches the given filter");
+    }
+
+    // Last.fm ignores plays older than two weeks, so they aren't sent there at all
+    let lastfm_cutoff = chrono::Utc::now() - chrono::Duration::days(scheduler::LASTFM_MAX_AGE_DAYS);
+    let mut submitted = 0;
+    let mut failed = 0;
+    let mut too_old = 0;
+
+    // Each batch is saved as soon as it's done, so an interrupted import can be run again
+    for batch in scrobbles.chunks(IMPORT_BATCH_SIZE) {
+        let mut entries = Vec::new();
+        for scrobble in batch {
+            let mut entry = history.new_entry(&scrobble.track, scrobble.played_at, None);
+            for scrobbler in &scrobblers {
+                let key = scrobbler.key();
+                if scrobbler.backend() == scrobbler::lastfm::BACKEND
+                    && scrobble.played_at < lastfm_cutoff
+                {
+                    too_old += 1;
+                    continue;
+                }
+
+                let result = scrobble_with_retries(
+                    scrobbler.as_ref(),
+                    &scrobble.track,
+                    scrobble.played_at,
+                    None,
+                );
+                match result {
+                    Ok(()) => submitted += 1,
+                    Err(ref e) => {
+                        println!(
+                            "❌ {} - {} → {}: {:#}",
+                            scrobble.track.artist, scrobble.track.title, key, e
+                        );
+                        failed += 1;
+                    }
+                }
+                entry.set_submission(&key, &result);
+            }
+            entries.push(entry);
+        }
+        history.insert(entries)?;
+        println!("Imported {} play(s)...", batch.len());
+    }
+
+    println!(
+        "\nSubmitted {} scrobble(s), {} failed (queued for `history retry`).",
+        submitted, failed
+    );
+    if too_old > 0 {
+        println!(
+            "{} play(s) older than {} days weren't sent to Last.fm, which doesn't accept them.",
+            too_old,
+            scheduler::LASTFM_MAX_AGE_DAYS
+        );
+    }
+    Ok(())
+}
+
+/// Import app filtering decisions from other scrobblers
+fn handle_import_apps(from: Option<&str>, dry_run: bool) -> Result<()> {
+    let mut config = config::Config::load()?;
+
+    // Resolve which preference files to read
+    let mut files: Vec<(String, std::path::PathBuf)> = Vec::new();
+    match from {
+        Some(from) => match app_import::find_source(from) {
+            Some(source) => match app_import::locate(source) {
+                Some(path) => files.push((source.name.to_string(), path)),
+                None => anyhow::bail!("No {} preferences found", source.name),
+            },
+            None => {
+                let path = std::path::PathBuf::from(from);
+                if !path.exists() {
+                    anyhow::bail!(
+                        "Unknown source '{}' (expected one of: {}, or a path to a .plist file)",
+                        from,
+                        app_import::SOURCES
+                            .iter()
+                            .map(|s| s.id)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    );
+                }
+                files.push((path.display().to_string(), path));
+            }
+        },
+        None => {
+            for source in app_import::SOURCES {
+                if let Some(path) = app_import::locate(source) {
+                    files.push((source.name.to_string(), path));
+                }
+            }
+            if files.is_empty() {
+                println!("No preferences from other scrobblers found.");
+                return Ok(());
+            }
+        }
+    }
+
+    let mut decisions = Vec::new();
+    for (name, path) in &files {
+        println!("Reading {} ({})", name, path.display());
+        decisions.extend(app_import::read_decisions(path)?);
+    }
+
+    let summary = app_import::merge(&mut config.app_filtering, &decisions);
+
+    for bundle_id in &summary.allowed {
+        println!("  + allow  {}", bundle_id);
+    }
+    for bundle_id in &summary.ignored {
+        println!("  + ignore {}", bundle_id);
+    }
+    for bundle_id in &summary.skipped {
+        println!("  = keep   {} (already configured)", bundle_id);
+    }
+
+    if summary.allowed.is_empty() && summary.ignored.is_empty() {
+        println!("\nNothing new to import.");
+        return Ok(());
+    }
+
+    if dry_run {
+        println!("\nDry run, config not changed.");
+        return Ok(());
+    }
+
+    config.validate()?;
+    config.save()?;
+    println!(
+        "\nImported {} allowed and {} ignored app(s).",
+        summary.allowed.len(),
+        summary.ignored.len()
+    );
+
+    Ok(())
+}
+
+/// Info.plist template for macOS app bundle
+const INFO_PLIST_TEMPLATE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>CFBundleName</key>
+    <string>OSX Scrobbler</string>
+    <key>CFBundleDisplayName</key>
+    <string>OSX Scrobbler</string>
+    <key>CFBundleIdentifier</key>
+    <string>com.osxscrobbler</string>
+    <key>CFBundleVersion</key>
+    <string>{VERSION}</string>
+    <key>CFBundleShortVersionString</key>
+    <string>{VERSION}</string>
+    <key>CFBundlePackageType</key>
+    <string>APPL</string>
+    <key>CFBundleExecutable</key>
+    <string>osx-scrobbler</string>
+    <key>LSUIElement</key>
+    <true/>
+    <key>LSMinimumSystemVersion</key>
+    <string>10.15</string>
+    <key>NSHighResolutionCapable</key>
+    <true/>
+</dict>
+</plist>"#;
+
+/// Install OSX Scrobbler as a macOS app bundle in /Applications/
+fn handle_install_app(notarization_ticket: Option<&Path>) -> Result<()> {
+    use std::fs;
+    use std::io::Write;
+    use std::os::unix::fs::PermissionsExt;
+
+    println!("OSX Scrobbler App Bundle Installer");
+    println!("===================================\n");
+
+    let app_name = "OSX Scrobbler.app";
+    let app_path = std::path::Path::new("/Applications").join(app_name);
+    let contents_dir = app_path.join("Contents");
     let macos_dir = contents_dir.join("MacOS");
+    let current_exe = std::env::current_exe()?;
+
+    // Say up front what Gatekeeper will make of this build
+    let signature = codesign::signature(&current_exe)?;
+    println!("Binary is {}", signature.describe());
+    if signature.is_developer_id() {
+        if let Err(e) = codesign::verify(&current_exe) {
+            println!(
+                "⚠️  The signature doesn't verify, the binary may have been modified: {}",
+                e
+            );
+        }
+    } else {
+        println!("⚠️  This build isn't signed with a Developer ID.");
+        println!("   That's fine on the Mac it was built on, but copied to another Mac the app");
+        println!("   will be blocked by Gatekeeper (right-click → Open to allow it once).");
+        if notarization_ticket.is_some() {
+            anyhow::bail!("Only builds signed with a Developer ID can be notarized");
+        }
+    }
+
+    if !confirm_architecture(&current_exe)? {
+        println!("Installation cancelled.");
+        return Ok(());
+    }
+    println!();
 
     // Check if app already exists
     if app_path.exists() {
@@ -478,8 +2626,6 @@ fn handle_install_app() -> Result<()> {
         Err(e) => return Err(e.into()),
     }
 
-    // Get current binary path
-    let current_exe = std::env::current_exe()?;
     let target_binary = macos_dir.join("osx-scrobbler");
 
     // Copy binary
@@ -499,6 +2645,26 @@ fn handle_install_app() -> Result<()> {
     let plist_path = contents_dir.join("Info.plist");
     fs::write(&plist_path, info_plist)?;
 
+    if let Some(ticket) = notarization_ticket {
+        println!("Stapling notarization ticket...");
+        codesign::staple(&app_path, ticket)?;
+    }
+    if signature.is_developer_id() {
+        match codesign::assess(&app_path) {
+            Ok(assessment) if assessment.is_notarized() => println!("Gatekeeper: notarized"),
+            Ok(assessment) => println!(
+                "⚠️  Gatekeeper: {} ({}); other Macs will warn before opening it",
+                if assessment.accepted {
+                    "accepted"
+                } else {
+                    "rejected"
+                },
+                assessment.source.as_deref().unwrap_or("no reason given")
+            ),
+            Err(e) => println!("⚠️  Couldn't check notarization: {}", e),
+        }
+    }
+
     println!("\n✅ Successfully installed OSX Scrobbler!");
     println!("\nApp bundle location:");
     println!("  {}", app_path.display());
@@ -511,6 +2677,148 @@ fn handle_install_app() -> Result<()> {
     Ok(())
 }
 
+/// Warn when `binary` isn't built for this Mac's processor and ask whether to go on
+fn confirm_architecture(binary: &Path) -> Result<bool> {
+    use std::io::Write;
+
+    // An Intel-only build runs on Apple Silicon through Rosetta, but media keys, the menu bar
+    // and the Music app scripting are flaky there
+    let machine = arch::machine();
+    let archs = arch::binary(binary)?;
+    let names: Vec<String> = archs.iter().map(ToString::to_string).collect();
+    println!("Built for {}, this Mac is {}", names.join(" + "), machine);
+    if !archs.contains(&machine) {
+        println!("⚠️  This build isn't made for this Mac's processor.");
+        if arch::is_translated() {
+            println!("   It's running under Rosetta, which is known to cause crashes.");
+        }
+        println!("   Get the {} or universal build from", machine);
+        println!("     {}/releases", env!("CARGO_PKG_REPOSITORY"));
+        println!("   or build one here with:");
+        println!("     cargo install osx-scrobbler --force\n");
+        print!("Use this build anyway? [y/N] ");
+        std::io::stdout().flush()?;
+
+        let mut input = String::new();
+        std::io::stdin().read_line(&mut input)?;
+
+        return Ok(input.trim().eq_ignore_ascii_case("y"));
+    }
+    Ok(true)
+}
+
+/// Replace the binary in the installed app bundle with the one running now
+///
+/// Unlike reinstalling, this leaves the rest of the bundle alone: edits to Info.plist other than
+/// the version, and the login item, which points at the bundle.
+fn handle_upgrade() -> Result<()> {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    let app_path = Path::new("/Applications").join("OSX Scrobbler.app");
+    let contents_dir = app_path.join("Contents");
+    let target_binary = contents_dir.join("MacOS").join("osx-scrobbler");
+    if !target_binary.exists() {
+        println!("❌ App bundle not found at {}", app_path.display());
+        println!("\nInstall it with:");
+        println!("  osx-scrobbler --install-app");
+        return Ok(());
+    }
+
+    let current_exe = std::env::current_exe()?.canonicalize()?;
+    if current_exe == target_binary.canonicalize()? {
+        println!("This is the binary in the app bundle already.");
+        println!("Run `upgrade` from the newly installed binary, e.g. ~/.cargo/bin/osx-scrobbler.");
+        return Ok(());
+    }
+    if !confirm_architecture(&current_exe)? {
+        println!("Upgrade cancelled.");
+        return Ok(());
+    }
+
+    // Copy next to the old binary, then swap it in, so a failed copy leaves the app working
+    let staged = target_binary.with_extension("new");
+    let copied = fs::copy(&current_exe, &staged)
+        .and_then(|_| fs::set_permissions(&staged, fs::Permissions::from_mode(0o755)))
+        .and_then(|_| fs::rename(&staged, &target_binary));
+    if let Err(e) = copied {
+        let _ = fs::remove_file(&staged);
+        if e.kind() == std::io::ErrorKind::PermissionDenied {
+            eprintln!("\n❌ Permission denied replacing the binary.");
+            eprintln!("\nTry running with sudo:");
+            eprintln!("  sudo osx-scrobbler upgrade\n");
+        }
+        return Err(e).context("Failed to replace the binary in the app bundle");
+    }
+
+    let version = env!("CARGO_PKG_VERSION");
+    let plist_path = contents_dir.join("Info.plist");
+    let info_plist = match fs::read_to_string(&plist_path) {
+        Ok(plist) => ["CFBundleVersion", "CFBundleShortVersionString"]
+            .iter()
+            .fold(plist, |plist, key| set_plist_string(&plist, key, version)),
+        Err(_) => INFO_PLIST_TEMPLATE.replace("{VERSION}", version),
+    };
+    fs::write(&plist_path, info_plist)
+        .with_context(|| format!("Failed to write {}", plist_path.display()))?;
+
+    // A signed bundle seals the binary and Info.plist, so swapping them broke its signature
+    if contents_dir.join("_CodeSignature").exists() {
+        check_upgraded_signature(&app_path, &current_exe)?;
+    }
+
+    println!("\n✅ Upgraded {} to {}", app_path.display(), version);
+    if contents_dir.join("CodeResources").exists() {
+        println!(
+            "⚠️  The stapled notarization ticket was for the old build; install the new one's"
+        );
+        println!("   with --install-app --notarization-ticket.");
+    }
+    println!("\nQuit OSX Scrobbler from the menu bar and open it again to use the new version.");
+
+    Ok(())
+}
+
+/// Re-seal an upgraded bundle ad hoc, unless the new binary has a Developer ID (whose bundle
+/// signature only its owner can redo, so a broken one is reported like `--install-app` does)
+fn check_upgraded_signature(app_path: &Path, new_binary: &Path) -> Result<()> {
+    let Err(e) = codesign::verify(app_path) else {
+        return Ok(());
+    };
+
+    if codesign::signature(new_binary)?.is_developer_id() {
+        println!(
+            "⚠️  The bundle's signature doesn't verify after the upgrade: {}",
+            e
+        );
+        println!("   Sign the bundle again with your identity, or reinstall with --install-app.");
+        return Ok(());
+    }
+
+    codesign::sign_ad_hoc(app_path).context("Failed to re-sign the app bundle")?;
+    codesign::verify(app_path).context("The re-signed app bundle doesn't verify")?;
+    println!("Re-signed the app bundle (ad hoc)");
+    Ok(())
+}
+
+/// Set the `<string>` value following `<key>key</key>` in an Info.plist, if the key is there
+fn set_plist_string(plist: &str, key: &str, value: &str) -> String {
+    let key_tag = format!("<key>{}</key>", key);
+    let Some(key_end) = plist.find(&key_tag).map(|i| i + key_tag.len()) else {
+        return plist.to_string();
+    };
+    let rest = &plist[key_end..];
+    let (Some(start), Some(end)) = (rest.find("<string>"), rest.find("</string>")) else {
+        return plist.to_string();
+    };
+    if !rest[..start].trim().is_empty() {
+        return plist.to_string();
+    }
+    let start = key_end + start + "<string>".len();
+    let end = key_end + end;
+    format!("{}{}{}", &plist[..start], value, &plist[end..])
+}
+
 /// Uninstall the app bundle from /Applications/
 fn handle_uninstall_app() -> Result<()> {
     use std::fs;
@@ -1,17 +1,37 @@
 // Media monitoring module
 // Polls macOS media remote for now playing information
 
-use crate::config::AppFilteringConfig;
+use crate::apple_music::{
+    self, CompilationHandler, RadioHandler, TrackDetails, APPLE_MUSIC_BUNDLE_ID,
+};
+use crate::artist_aliases::ArtistAliases;
+use crate::browser;
+use crate::classical::ClassicalFormatter;
+use crate::confidence::{self, ConfidenceScorer, Signals};
+use crate::config::{AppFilteringConfig, ChapterPolicy, Config, PlayerPolicy, ThresholdMode};
+use crate::corrections::Corrections;
+use crate::field_mapping::FieldMappings;
+use crate::filters::{FilterAction, FilterInput, Filters};
+use crate::long_form::LongFormFilter;
+use crate::metadata_cache::MetadataCache;
+use crate::plugins::Plugins;
+use crate::replay::{PollSnapshot, Recorder};
 use crate::scrobbler::Track;
-use crate::text_cleanup::TextCleaner;
+use crate::text_cleanup::{PatternStats, TextCleaner};
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 use media_remote::prelude::*;
 use media_remote::NowPlayingInfo;
-use std::time::SystemTime;
+use serde::{Deserialize, Serialize};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
 
 const MIN_TRACK_DURATION: u64 = 30; // Minimum track duration in seconds to scrobble
 const SCROBBLE_TIME_THRESHOLD: u64 = 240; // 4 minutes in seconds
+const METADATA_CACHE_SIZE: usize = 500; // Tracks whose processed metadata is kept
+const CHAPTER_MIN_POSITION: f64 = 5.0; // Playback position past which a title change can't be a new file
+const PARKED_SESSION_TIMEOUT: Duration = Duration::from_secs(30 * 60); // How long another app's session is kept
 
 /// Action to take based on app filtering
 #[derive(Debug, PartialEq)]
@@ -21,6 +41,24 @@ enum AppFilterAction {
     PromptUser,
 }
 
+/// When tracks are scrobbled, from the config
+#[derive(Debug, Clone, Copy)]
+struct Threshold {
+    percent: u8,
+    mode: ThresholdMode,
+    seconds: u64,
+}
+
+impl Threshold {
+    fn new(config: &Config) -> Self {
+        Self {
+            percent: config.scrobble_threshold,
+            mode: config.scrobble_threshold_mode,
+            seconds: config.scrobble_threshold_seconds,
+        }
+    }
+}
+
 /// Represents the current play session state
 #[derive(Debug, Clone)]
 struct PlaySession {
@@ -32,6 +70,17 @@ struct PlaySession {
     scrobbled: bool,
     now_playing_sent: bool,
     info_update_time: Option<SystemTime>,
+    listened_seconds: f64, // Time actually spent playing (excludes pauses)
+    last_tick: Option<Instant>,
+    tick_position: Option<f64>, // Position at the last tick, when the player reports one
+    persistent_id: Option<String>, // Music app track ID, when known
+    file_duration: u64,         // Duration reported by the player (the whole file for chapters)
+    chapter: bool,              // A chapter of a longer file, with unknown duration
+    confident: bool,            // Confident enough that this is music to scrobble it
+    review: bool,               // Not confident enough to submit it without the user's review
+    skipped: bool,              // The user asked not to scrobble it
+    stuck_polls: u32,           // Polls in a row the position stood still or was past the end
+    away_polls: u32,            // Polls in a row another app was reported instead
 }
 
 impl PlaySession {
@@ -41,67 +90,614 @@ impl PlaySession {
         duration: u64,
         info_update_time: Option<SystemTime>,
         position: Option<f64>,
+        clock: &Clock,
     ) -> Self {
         Self {
             track,
             bundle_id,
-            started_at: Utc::now(),
+            started_at: clock.utc(),
             duration,
             scrobbled: false,
             now_playing_sent: false,
             info_update_time,
             position,
+            listened_seconds: 0.0,
+            last_tick: Some(clock.instant()),
+            tick_position: position,
+            persistent_id: None,
+            file_duration: duration,
+            chapter: false,
+            confident: true,
+            review: false,
+            skipped: false,
+            stuck_polls: 0,
+            away_polls: 0,
         }
     }
 
-    /// Calculate elapsed play time in seconds
-    fn elapsed_seconds(&self) -> u64 {
-        let elapsed = Utc::now().signed_duration_since(self.started_at);
-        elapsed.num_seconds().max(0) as u64
+    /// Accumulate listening time since the last poll
+    /// Time is only counted while the previous poll saw the track playing. When the player
+    /// reports its position, how far it moved is counted instead, up to the time since the last
+    /// poll: a stalled stream doesn't count, and neither does seeking ahead.
+    fn tick(&mut self, now: Instant, is_playing: bool, position: Option<f64>) {
+        if let Some(last_tick) = self.last_tick {
+            let since_tick = now.duration_since(last_tick).as_secs_f64();
+            self.listened_seconds += match (self.tick_position, position) {
+                (Some(previous), Some(current)) => (current - previous).clamp(0.0, since_tick),
+                _ => since_tick,
+            };
+        }
+        self.last_tick = if is_playing { Some(now) } else { None };
+        self.tick_position = position;
     }
 
-    /// Check if track should be scrobbled based on Last.fm rules
-    fn should_scrobble(&self, threshold_percent: u8) -> bool {
-        if self.scrobbled {
-            return false;
+    /// Stop counting listening time when a poll finds `bundle_id` paused
+    ///
+    /// Another app being paused says nothing about this session, which may still be playing.
+    fn reported_paused(&mut self, now: Instant, bundle_id: &Option<String>, position: Option<f64>) {
+        if self.bundle_id == *bundle_id {
+            self.tick(now, false, position);
+        }
+    }
+
+    /// Stop counting, the track having changed `ago` seconds before now
+    ///
+    /// A track that changed between polls only played until the next one started, so the time
+    /// since then isn't counted.
+    fn close(&mut self, now: Instant, ago: f64) {
+        if let Some(last_tick) = self.last_tick.take() {
+            let since_tick = now.duration_since(last_tick).as_secs_f64();
+            self.listened_seconds += (since_tick - ago).max(0.0);
+        }
+        self.tick_position = None;
+    }
+
+    /// Record the position reported while playing, returning for how many polls in a row the
+    /// player has looked stuck
+    ///
+    /// Media Remote moves the position of a track reported as playing along by itself, so a
+    /// player that stopped updating it runs past the end of the track rather than standing
+    /// still. Both count as stuck; players that don't report a position never do.
+    fn update_position(&mut self, position: Option<f64>) -> u32 {
+        let standing_still = position.is_some() && position == self.position;
+        let past_end =
+            self.duration > 0 && position.is_some_and(|position| position > self.duration as f64);
+        if standing_still || past_end {
+            self.stuck_polls += 1;
+        } else {
+            self.stuck_polls = 0;
+        }
+        self.position = position;
+        self.stuck_polls
+    }
+
+    /// Seconds into the session at which the track is scrobbled, if it can be
+    fn scrobble_at(&self, threshold: &Threshold) -> Option<u64> {
+        if !self.confident || self.skipped {
+            return None;
+        }
+
+        // Chapter lengths aren't known, so only a time rule applies
+        if self.chapter {
+            return Some(match threshold.mode {
+                ThresholdMode::FixedSeconds => threshold.seconds,
+                _ => SCROBBLE_TIME_THRESHOLD,
+            });
         }
 
         // Track must be at least 30 seconds long
         if self.duration < MIN_TRACK_DURATION {
-            return false;
+            return None;
         }
 
-        let elapsed = self.elapsed_seconds();
+        // Scrobble after 50% (configurable) of the track OR 4 minutes, whichever comes first,
+        // unless configured otherwise
+        let percent_time = (self.duration * threshold.percent as u64) / 100;
+        Some(match threshold.mode {
+            ThresholdMode::LastfmStandard => percent_time.min(SCROBBLE_TIME_THRESHOLD),
+            ThresholdMode::PercentOnly => percent_time,
+            ThresholdMode::FixedSeconds => threshold.seconds,
+        })
+    }
+
+    /// Check if track should be scrobbled based on Last.fm rules
+    fn should_scrobble(&self, threshold: &Threshold) -> bool {
+        !self.scrobbled
+            && self
+                .scrobble_at(threshold)
+                .is_some_and(|at| self.listened_seconds >= at as f64)
+    }
 
-        // Scrobble after 50% (configurable) of the track OR 4 minutes, whichever comes first
-        let threshold_time = (self.duration * threshold_percent as u64) / 100;
-        let scrobble_at = threshold_time.min(SCROBBLE_TIME_THRESHOLD);
+    /// Listening time including the time since the last poll, if still playing
+    fn listened_so_far(&self, now: Instant) -> f64 {
+        let since_tick = self
+            .last_tick
+            .map_or(0.0, |last_tick| now.duration_since(last_tick).as_secs_f64());
+        self.listened_seconds + since_tick
+    }
 
-        elapsed >= scrobble_at
+    /// Whether the session keeps playing from its app while `bundle_id` is reported instead
+    fn holds_against(
+        &self,
+        bundle_id: &Option<String>,
+        app_filtering: &AppFilteringConfig,
+    ) -> bool {
+        if self.away_polls >= app_filtering.player_switch_polls {
+            return false;
+        }
+        let allowed = |id: &Option<String>| {
+            id.as_ref()
+                .is_some_and(|id| app_filtering.allowed_apps.contains(id))
+        };
+        match app_filtering.multiple_players {
+            PlayerPolicy::MostRecent => false,
+            PlayerPolicy::PreferAllowed => allowed(&self.bundle_id) && !allowed(bundle_id),
+            PlayerPolicy::Lock => true,
+        }
+    }
+
+    fn scrobble_event(&self) -> MediaEvent {
+        MediaEvent::Scrobble {
+            track: self.track.clone(),
+            timestamp: self.started_at,
+            bundle_id: self.bundle_id.clone(),
+            review: self.review,
+        }
     }
 
     /// Check if we should send "now playing" update
     fn should_send_now_playing(&self) -> bool {
-        !self.now_playing_sent
+        !self.now_playing_sent && self.confident
+    }
+
+    /// Check whether a reported track is the one this session is playing
+    fn is_playing(&self, track: &Track) -> bool {
+        if self.chapter {
+            // Chapters carry the file's duration, which the session drops
+            self.track.title == track.title
+                && self.track.artist == track.artist
+                && self.track.album == track.album
+        } else {
+            self.track == *track
+        }
+    }
+
+    /// Check whether a title change is the next chapter of the file being played
+    ///
+    /// The file is the same if the Music app's persistent ID matches, or otherwise if the
+    /// duration does, and playback carried on instead of starting over.
+    fn is_next_chapter(
+        &self,
+        bundle_id: &Option<String>,
+        duration: u64,
+        persistent_id: Option<&str>,
+        position: Option<f64>,
+    ) -> bool {
+        if self.bundle_id != *bundle_id {
+            return false;
+        }
+
+        let same_file = match (self.persistent_id.as_deref(), persistent_id) {
+            (Some(current), Some(new)) => current == new,
+            _ => self.file_duration > 0 && duration == self.file_duration,
+        };
+        let continues = match (self.position, position) {
+            (Some(prev), Some(cur)) => cur >= prev && cur >= CHAPTER_MIN_POSITION,
+            _ => false,
+        };
+
+        same_file && continues
     }
 }
 
+/// Identifies a track as reported by a player
+///
+/// Media Remote doesn't expose a persistent track ID, so the raw metadata stands in for it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct TrackKey {
+    bundle_id: Option<String>,
+    title: String,
+    artist: String,
+    album: Option<String>,
+    duration: Option<u64>,
+}
+
+impl TrackKey {
+    fn new(info: &PlayerInfo) -> Option<Self> {
+        Some(Self {
+            bundle_id: info.bundle_id.clone(),
+            title: info.title.clone()?,
+            artist: info.artist.clone()?,
+            album: info.album.clone(),
+            duration: info.duration.map(|d| d as u64),
+        })
+    }
+
+    /// Check whether the player still reports this track, without copying its metadata
+    fn matches(&self, info: &PlayerInfo) -> bool {
+        info.title.as_deref() == Some(self.title.as_str())
+            && info.artist.as_deref() == Some(self.artist.as_str())
+            && info.album == self.album
+            && info.bundle_id == self.bundle_id
+            && info.duration.map(|d| d as u64) == self.duration
+    }
+}
+
+/// What the player reported at a poll: Media Remote's now playing info without the artwork
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerInfo {
+    pub is_playing: Option<bool>,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub elapsed_time: Option<f64>,
+    pub duration: Option<f64>,
+    pub info_update_time: Option<SystemTime>,
+    pub bundle_id: Option<String>,
+    pub bundle_name: Option<String>,
+}
+
+impl From<&NowPlayingInfo> for PlayerInfo {
+    fn from(info: &NowPlayingInfo) -> Self {
+        Self {
+            is_playing: info.is_playing,
+            title: info.title.clone(),
+            artist: info.artist.clone(),
+            album: info.album.clone(),
+            elapsed_time: info.elapsed_time,
+            duration: info.duration,
+            info_update_time: info.info_update_time,
+            bundle_id: info.bundle_id.clone(),
+            bundle_name: info.bundle_name.clone(),
+        }
+    }
+}
+
+/// Where the monitor takes the time from
+#[derive(Debug, Clone, Copy)]
+enum Clock {
+    System,
+    /// The time of the poll being replayed, with the instant the replay started standing in
+    /// for the first poll's time
+    Replay {
+        first: DateTime<Utc>,
+        started: Instant,
+        at: DateTime<Utc>,
+    },
+}
+
+impl Clock {
+    fn instant(&self) -> Instant {
+        match *self {
+            Clock::System => Instant::now(),
+            Clock::Replay { first, started, at } => {
+                started + (at - first).to_std().unwrap_or_default()
+            }
+        }
+    }
+
+    fn utc(&self) -> DateTime<Utc> {
+        match *self {
+            Clock::System => Utc::now(),
+            Clock::Replay { at, .. } => at,
+        }
+    }
+
+    /// The clock set to a replayed poll's time
+    fn replaying(self, at: DateTime<Utc>) -> Self {
+        match self {
+            Clock::System => Clock::Replay {
+                first: at,
+                started: Instant::now(),
+                at,
+            },
+            Clock::Replay { first, started, .. } => Clock::Replay { first, started, at },
+        }
+    }
+}
+
+/// Result of processing a track's metadata
+#[derive(Debug, Clone)]
+struct ProcessedTrack {
+    /// `None` if the track isn't scrobbled at all
+    track: Option<Track>,
+    details: Option<TrackDetails>,
+    /// Share of the reported title and artist that cleanup rewrote
+    rewritten: f64,
+}
+
+/// Media Remote's now playing info as reported, without the artwork
+fn describe_info(info: &PlayerInfo) -> String {
+    format!(
+        "is_playing: {:?}, title: {:?}, artist: {:?}, album: {:?}, elapsed_time: {:?}, \
+         duration: {:?}, info_update_time: {:?}, bundle_id: {:?}, bundle_name: {:?}",
+        info.is_playing,
+        info.title,
+        info.artist,
+        info.album,
+        info.elapsed_time,
+        info.duration,
+        info.info_update_time,
+        info.bundle_id,
+        info.bundle_name
+    )
+}
+
+/// Every field of Media Remote's now playing info, with only whether there's artwork
+fn info_json(info: &NowPlayingInfo) -> serde_json::Value {
+    serde_json::json!({
+        "is_playing": info.is_playing,
+        "title": info.title,
+        "artist": info.artist,
+        "album": info.album,
+        "elapsed_time": info.elapsed_time,
+        "duration": info.duration,
+        "info_update_time": info.info_update_time.map(DateTime::<Utc>::from),
+        "bundle_id": info.bundle_id,
+        "bundle_name": info.bundle_name,
+        "has_album_cover": info.album_cover.is_some(),
+    })
+}
+
+/// What the player reports about the current track, as JSON, for `osx-scrobbler inspect`
+///
+/// Waits up to `timeout` for Media Remote's first report, and returns `None` if nothing is
+/// playing by then. For the Music app, the details it's asked for are added as `apple_music`.
+pub fn inspect(timeout: Duration) -> Option<serde_json::Value> {
+    let now_playing = NowPlayingPerl::new();
+    let started = Instant::now();
+    let info = loop {
+        let info = now_playing.get_info().as_ref().cloned();
+        if let Some(info) = info {
+            break info;
+        }
+        if started.elapsed() >= timeout {
+            return None;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    };
+
+    let mut json = info_json(&info);
+    if info.bundle_id.as_deref() == Some(APPLE_MUSIC_BUNDLE_ID) {
+        json["apple_music"] = match apple_music::current_track_details() {
+            Ok(details) => serde_json::to_value(details).unwrap_or_default(),
+            Err(e) => serde_json::json!({ "error": format!("{:#}", e) }),
+        };
+    }
+    Some(json)
+}
+
+/// Filter rules from the config, without any if they don't parse
+fn build_filters(config: &Config) -> Filters {
+    Filters::new(&config.filters).unwrap_or_else(|e| {
+        log::error!("Ignoring filters: {:#}", e);
+        Filters::default()
+    })
+}
+
+/// Fingerprint of the settings that affect processed metadata
+fn metadata_fingerprint(config: &Config) -> u64 {
+    let settings = serde_json::to_string(&(
+        &config.field_mapping,
+        &config.cleanup,
+        &config.apple_music,
+        &config.classical,
+        &config.long_form,
+        &config.corrections,
+        &config.artist_aliases,
+        &config.plugins,
+    ))
+    .unwrap_or_default();
+
+    let mut hasher = DefaultHasher::new();
+    settings.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Progress of the current play session, for frontends to display
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionProgress {
+    pub track: Track,
+    pub bundle_id: Option<String>,
+    pub listened_seconds: u64,
+    /// Track duration in seconds (0 for chapters and streams)
+    pub duration: u64,
+    /// Seconds into the session at which it's scrobbled, `None` if it won't be
+    pub scrobble_at: Option<u64>,
+    pub scrobbled: bool,
+}
+
 /// Media monitor that polls macOS media remote
 pub struct MediaMonitor {
-    now_playing: NowPlayingPerl,
-    scrobble_threshold: u8,
+    /// Media Remote, or `None` when replaying recorded polls
+    now_playing: Option<NowPlayingPerl>,
+    clock: Clock,
+    /// Where each poll is recorded, see [`MediaMonitor::record_polls`]
+    recorder: Option<Recorder>,
+    threshold: Threshold,
+    /// Polls a playing session's position can stand still before it's expired (0 for never)
+    stalled_polls: u32,
     current_session: Option<PlaySession>,
+    /// Sessions of other apps, kept from when they were last reported so each carries on
+    /// where it left off instead of restarting its track
+    ///
+    /// They don't tick: Media Remote only reports one app, so there's no telling whether theirs
+    /// are still playing.
+    parked_sessions: Vec<(Instant, PlaySession)>,
+    field_mappings: FieldMappings,
     text_cleaner: TextCleaner,
+    radio: RadioHandler,
+    compilations: CompilationHandler,
+    classical: ClassicalFormatter,
+    long_form: LongFormFilter,
+    chapter_policy: ChapterPolicy,
+    confidence: ConfidenceScorer,
+    corrections: Corrections,
+    artist_aliases: ArtistAliases,
+    filters: Filters,
+    plugins: Plugins,
+    /// Paths `plugins` were loaded from, to reload them only when they change
+    plugin_paths: Vec<PathBuf>,
+    /// Processed metadata of recently played tracks
+    metadata_cache: MetadataCache<TrackKey, ProcessedTrack>,
+    /// Music app details of the track last processed
+    current_details: Option<TrackDetails>,
+    /// Share of the track last processed that cleanup rewrote
+    current_rewritten: f64,
+    /// Track reported on the last poll, with its processed metadata
+    last_track: Option<(TrackKey, Option<Track>)>,
+    /// Look up the page browser plays come from
+    capture_urls: bool,
+    /// Apps whose plays are picked by a station or autoplay
+    radio_apps: Vec<String>,
+    /// Log the Media Remote info each new play came from, see `[debug] log_payloads`
+    log_payloads: bool,
+    /// Track cleared as stuck, ignored for as long as the player keeps reporting it
+    cleared: Option<TrackKey>,
 }
 
 impl MediaMonitor {
-    pub fn new(scrobble_threshold: u8, text_cleaner: TextCleaner) -> Self {
+    pub fn new(config: &Config) -> Self {
+        Self::build(config, Some(NowPlayingPerl::new()))
+    }
+
+    /// A monitor that only handles the polls given to [`MediaMonitor::replay_poll`]
+    ///
+    /// Apps aren't asked about what they played, so the Music app's track details and browser
+    /// page URLs are left out.
+    pub fn for_replay(config: &Config) -> Self {
+        Self::build(config, None)
+    }
+
+    fn build(config: &Config, now_playing: Option<NowPlayingPerl>) -> Self {
         Self {
-            now_playing: NowPlayingPerl::new(),
-            scrobble_threshold,
+            clock: Clock::System,
+            now_playing,
+            recorder: None,
+            threshold: Threshold::new(config),
+            stalled_polls: config.stalled_polls,
             current_session: None,
-            text_cleaner,
+            parked_sessions: Vec::new(),
+            field_mappings: FieldMappings::new(&config.field_mapping),
+            text_cleaner: TextCleaner::new(&config.cleanup),
+            radio: RadioHandler::new(&config.apple_music),
+            compilations: CompilationHandler::new(&config.apple_music),
+            classical: ClassicalFormatter::new(&config.classical),
+            long_form: LongFormFilter::new(&config.long_form),
+            chapter_policy: config.chapters.policy,
+            confidence: ConfidenceScorer::new(&config.confidence),
+            corrections: Corrections::new(&config.corrections),
+            artist_aliases: ArtistAliases::new(&config.artist_aliases),
+            filters: build_filters(config),
+            plugins: Plugins::load(&config.plugins),
+            plugin_paths: config.plugins.clone(),
+            metadata_cache: MetadataCache::new(METADATA_CACHE_SIZE, metadata_fingerprint(config)),
+            current_details: None,
+            current_rewritten: 0.0,
+            last_track: None,
+            capture_urls: config.browser.capture_url,
+            radio_apps: config.app_filtering.radio_apps.clone(),
+            log_payloads: config.debug.log_payloads,
+            cleared: None,
+        }
+    }
+
+    /// Apply changed settings (cleanup, corrections, ...) to new tracks
+    ///
+    /// Cached metadata is dropped if anything that affects it changed.
+    pub fn reconfigure(&mut self, config: &Config) {
+        self.threshold = Threshold::new(config);
+        self.stalled_polls = config.stalled_polls;
+        self.chapter_policy = config.chapters.policy;
+        self.capture_urls = config.browser.capture_url;
+        self.radio_apps = config.app_filtering.radio_apps.clone();
+        self.log_payloads = config.debug.log_payloads;
+        self.confidence = ConfidenceScorer::new(&config.confidence);
+        self.field_mappings = FieldMappings::new(&config.field_mapping);
+        self.text_cleaner = TextCleaner::new(&config.cleanup);
+        self.radio = RadioHandler::new(&config.apple_music);
+        self.compilations = CompilationHandler::new(&config.apple_music);
+        self.classical = ClassicalFormatter::new(&config.classical);
+        self.long_form = LongFormFilter::new(&config.long_form);
+        self.corrections = Corrections::new(&config.corrections);
+        self.artist_aliases = ArtistAliases::new(&config.artist_aliases);
+        self.filters = build_filters(config);
+        if self.plugin_paths != config.plugins {
+            self.plugins = Plugins::load(&config.plugins);
+            self.plugin_paths = config.plugins.clone();
         }
+        self.metadata_cache.invalidate(metadata_fingerprint(config));
+        self.last_track = None;
+    }
+
+    /// How often each cleanup pattern changed a text since the config was loaded
+    ///
+    /// Cleaned tracks are cached, so repeated plays of a track usually count once.
+    pub fn cleanup_stats(&self) -> Vec<PatternStats> {
+        self.text_cleaner.stats()
+    }
+
+    /// Progress of the track being played, if any
+    pub fn current_session(&self) -> Option<SessionProgress> {
+        let session = self.current_session.as_ref()?;
+        Some(SessionProgress {
+            track: session.track.clone(),
+            bundle_id: session.bundle_id.clone(),
+            listened_seconds: session.listened_so_far(self.clock.instant()).round() as u64,
+            duration: session.duration,
+            scrobble_at: session.scrobble_at(&self.threshold),
+            scrobbled: session.scrobbled,
+        })
+    }
+
+    /// Don't scrobble the current session, if it's still playing `track` and isn't scrobbled yet
+    pub fn skip_scrobble(&mut self, track: &Track) -> bool {
+        match self.current_session.as_mut() {
+            Some(session) if session.is_playing(track) && !session.scrobbled => {
+                session.skipped = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// End the current session and ignore its track until the player reports something else
+    ///
+    /// For a player that crashed while Media Remote keeps reporting its last track. Returns the
+    /// track cleared and its app, or `None` when nothing is playing.
+    pub fn clear_now_playing(&mut self) -> Option<(Track, Option<String>)> {
+        let (track, bundle_id) = self.clear_session()?;
+        log::info!("Cleared now playing: {} - {}", track.artist, track.title);
+        Some((track, bundle_id))
+    }
+
+    /// Drop the current session, ignoring the track reported for it from now on
+    fn clear_session(&mut self) -> Option<(Track, Option<String>)> {
+        let session = self.current_session.take()?;
+        self.cleared = self.last_track.as_ref().map(|(key, _)| key.clone());
+        Some((session.track, session.bundle_id))
+    }
+
+    /// Scrobble the current session now, without waiting for the threshold
+    ///
+    /// Returns `None` when nothing is playing or it was already scrobbled.
+    pub fn force_scrobble(&mut self) -> Option<(Track, DateTime<Utc>, Option<String>)> {
+        let now = self.clock.instant();
+        let session = self.current_session.as_mut().filter(|s| !s.scrobbled)?;
+
+        log::info!(
+            "Scrobbling now: {} - {} (played {:.0}s)",
+            session.track.artist,
+            session.track.title,
+            session.listened_so_far(now)
+        );
+        session.scrobbled = true;
+
+        Some((
+            session.track.clone(),
+            session.started_at,
+            session.bundle_id.clone(),
+        ))
     }
 
     /// Check if an app should be scrobbled based on filtering config
@@ -132,8 +728,9 @@ impl MediaMonitor {
                 if app_filtering.allowed_apps.contains(id) {
                     return AppFilterAction::Allow;
                 }
-                // Check ignored list
-                if app_filtering.ignored_apps.contains(id) {
+                // Check ignored list, and the system processes that aren't worth asking about
+                if app_filtering.ignored_apps.contains(id) || app_filtering.system_apps.contains(id)
+                {
                     return AppFilterAction::Ignore;
                 }
                 // Unknown app - prompt if enabled
@@ -147,50 +744,265 @@ impl MediaMonitor {
         }
     }
 
-    /// Convert media_remote NowPlayingInfo to our Track structure
-    fn media_info_to_track(&self, info: &NowPlayingInfo) -> Option<Track> {
-        let title = info.title.clone()?;
-        let artist = info.artist.clone()?;
-        let album = info.album.clone();
+    /// Look up details of an Apple Music track if anything needs them
+    fn music_app_details(&self, bundle_id: Option<&str>) -> Option<TrackDetails> {
+        let needed = self.classical.is_enabled()
+            || self.long_form.uses_media_kind()
+            || self.compilations.is_enabled();
+        // A replay can't ask about what was playing back then
+        if !needed || bundle_id != Some(APPLE_MUSIC_BUNDLE_ID) || self.now_playing.is_none() {
+            return None;
+        }
 
-        // Apply text cleanup
-        let title = self.text_cleaner.clean(&title);
-        let artist = self.text_cleaner.clean(&artist);
-        let album = self.text_cleaner.clean_option(album);
+        let details = apple_music::current_track_details().unwrap_or_else(|e| {
+            log::warn!("Failed to get track details from Music: {:#}", e);
+            TrackDetails::default()
+        });
+        Some(details)
+    }
 
-        Some(Track {
-            title,
-            artist,
-            album,
-            duration: info.duration.map(|d| d as u64),
+    /// Music app persistent ID of the track last processed, if it came from the Music app
+    fn persistent_id(&self) -> Option<String> {
+        self.current_details
+            .as_ref()
+            .and_then(|details| details.persistent_id.clone())
+    }
+
+    /// Convert the player's info to our Track structure
+    ///
+    /// Processed metadata is cached, so repeated plays skip the cleanup and Music app queries.
+    fn media_info_to_track(&mut self, info: &PlayerInfo) -> Option<Track> {
+        // Steady state: the same track as on the last poll
+        if let Some((key, track)) = &self.last_track {
+            if key.matches(info) {
+                return track.clone();
+            }
+        }
+
+        let key = TrackKey::new(info)?;
+
+        // Artists renamed by a lookup since are processed again
+        if self.artist_aliases.take_updated() {
+            self.metadata_cache.clear();
+        }
+        let processed = match self.metadata_cache.get(&key) {
+            Some(processed) => processed,
+            None => {
+                let processed = self.process_track(&key);
+                self.metadata_cache.insert(key.clone(), processed.clone());
+                processed
+            }
+        };
+
+        // Looked up on every new play rather than cached, the same track can be on another page
+        let mut track = processed.track;
+        if let Some(track) = track.as_mut() {
+            track.origin_url = self.origin_url(&key);
+            track.radio |= key
+                .bundle_id
+                .as_ref()
+                .is_some_and(|id| self.radio_apps.contains(id));
+        }
+
+        self.current_details = processed.details;
+        self.current_rewritten = processed.rewritten;
+        self.last_track = Some((key, track.clone()));
+        track
+    }
+
+    /// Page a browser is playing the track from, if capturing URLs is on
+    fn origin_url(&self, key: &TrackKey) -> Option<String> {
+        let bundle_id = key.bundle_id.as_deref().filter(|id| {
+            self.capture_urls && browser::is_supported(id) && self.now_playing.is_some()
+        })?;
+
+        browser::origin_url(bundle_id, &key.title).unwrap_or_else(|e| {
+            log::warn!("Failed to find the page playing in the browser: {:#}", e);
+            None
         })
     }
 
-    /// Check for track changes and return events (now playing, scrobble)
-    pub fn poll(&mut self, app_filtering: &AppFilteringConfig) -> Result<MediaEvents> {
-        // Clone media info to avoid holding the guard
-        let media_info = {
-            let guard = self.now_playing.get_info();
-            guard.as_ref().cloned()
+    /// Run reported metadata through radio handling, filters, cleanup, plugins, artist aliases and
+    /// corrections
+    fn process_track(&self, key: &TrackKey) -> ProcessedTrack {
+        let bundle_id = key.bundle_id.as_deref();
+        let track = Track {
+            title: key.title.clone(),
+            artist: key.artist.clone(),
+            album: key.album.clone(),
+            duration: key.duration,
+            origin_url: None,
+            radio: false,
+            mbid: None,
+            album_artist: None,
+            live: None,
+        };
+
+        // Apps that swap fields are set straight before anything looks at them
+        let track = self.field_mappings.apply(bundle_id, track);
+
+        // Radio stations may need the real artist/title parsed out first
+        let Some(track) = self.radio.resolve(bundle_id, track) else {
+            return ProcessedTrack {
+                track: None,
+                details: None,
+                rewritten: 0.0,
+            };
+        };
+        let radio = track.radio;
+
+        let details = self.music_app_details(bundle_id);
+        if self
+            .long_form
+            .is_excluded(bundle_id, track.duration, details.as_ref())
+        {
+            log::debug!("Not scrobbling long-form content: {}", track.title);
+            return ProcessedTrack {
+                track: None,
+                details,
+                rewritten: 0.0,
+            };
+        }
+
+        // Station metadata isn't the library's, so radio tracks keep what the station reported
+        let track = match details.as_ref().filter(|_| !track.radio) {
+            Some(details) => self.compilations.apply(track, details),
+            None => track,
+        };
+
+        let classical = details.as_ref().filter(|d| self.classical.is_classical(d));
+        let track = match &classical {
+            Some(details) => self.classical.apply(track, details),
+            None => track,
+        };
+
+        // Apply text cleanup (classical titles are kept away from the patterns)
+        let title = match classical {
+            Some(_) => track.title.clone(),
+            None => self.text_cleaner.clean_title(&track.title),
+        };
+        let artist = self.text_cleaner.clean(&track.artist);
+        // Live details are moved out by choice, so they don't count as rewritten
+        let rewritten =
+            confidence::rewritten_share(&[&track.title, &track.artist], &[&title, &artist]);
+        let (title, live) = self.text_cleaner.live(title);
+        let track = Track {
+            title,
+            artist,
+            album: self.text_cleaner.clean_option(track.album),
+            duration: track.duration,
+            origin_url: track.origin_url,
+            radio: track.radio,
+            mbid: track.mbid,
+            album_artist: self.text_cleaner.clean_option(track.album_artist),
+            live,
+        };
+
+        // Plugins come before corrections, which stay the user's final say
+        let Some(mut track) = self.plugins.apply(bundle_id, track) else {
+            return ProcessedTrack {
+                track: None,
+                details,
+                rewritten,
+            };
         };
+        self.artist_aliases.apply(&mut track);
+        self.corrections.apply(&mut track);
+        // Plugins don't have to send the flag back
+        track.radio |= radio;
+
+        ProcessedTrack {
+            track: Some(track),
+            details,
+            rewritten,
+        }
+    }
 
-        let mut events = MediaEvents::default();
+    /// Check for track changes and return what happened since the last poll, in order
+    pub fn poll(&mut self, app_filtering: &AppFilteringConfig) -> Result<Vec<MediaEvent>> {
+        // Copy media info to avoid holding the guard
+        let info = self.now_playing.as_ref().and_then(|now_playing| {
+            let guard = now_playing.get_info();
+            guard.as_ref().map(PlayerInfo::from)
+        });
+
+        if let Some(recorder) = self.recorder.as_mut() {
+            let snapshot = PollSnapshot {
+                at: Utc::now(),
+                info: info.clone(),
+            };
+            if let Err(e) = recorder.record(&snapshot) {
+                log::error!("Stopped recording polls: {:#}", e);
+                self.recorder = None;
+            }
+        }
+
+        self.handle_poll(info, app_filtering)
+    }
 
+    /// Handle a recorded poll as if the player reported it at the time it was recorded
+    ///
+    /// For monitors made with [`MediaMonitor::for_replay`], given polls in the order they
+    /// were recorded.
+    pub fn replay_poll(
+        &mut self,
+        snapshot: &PollSnapshot,
+        app_filtering: &AppFilteringConfig,
+    ) -> Vec<MediaEvent> {
+        self.clock = self.clock.replaying(snapshot.at);
+        self.handle_poll(snapshot.info.clone(), app_filtering)
+            .unwrap_or_else(|e| {
+                log::error!("Error replaying poll: {}", e);
+                Vec::new()
+            })
+    }
+
+    /// Append what the player reports at each poll to a file, for `osx-scrobbler replay`
+    pub fn record_polls(&mut self, path: &Path) -> Result<()> {
+        self.recorder = Some(Recorder::create(path)?);
+        log::info!("Recording polls to {}", path.display());
+        Ok(())
+    }
+
+    fn handle_poll(
+        &mut self,
+        info: Option<PlayerInfo>,
+        app_filtering: &AppFilteringConfig,
+    ) -> Result<Vec<MediaEvent>> {
+        let mut events = Vec::new();
+        self.poll_player(info, app_filtering, &mut events)?;
+        self.expire_parked_sessions(&mut events);
+        Ok(events)
+    }
+
+    fn poll_player(
+        &mut self,
+        media_info: Option<PlayerInfo>,
+        app_filtering: &AppFilteringConfig,
+        events: &mut Vec<MediaEvent>,
+    ) -> Result<()> {
+        let now = self.clock.instant();
         if let Some(info) = media_info {
+            if let Some(cleared) = &self.cleared {
+                if cleared.matches(&info) {
+                    return Ok(());
+                }
+                log::debug!("Player moved on from the cleared track");
+                self.cleared = None;
+            }
+
             // Check if media is playing (not paused)
             let is_playing = info.is_playing.unwrap_or(false);
 
-            log::debug!(
-                    "is_playing: {0:?}, title: {1:?}, artist: {2:?}, album: {3:?}, elapsed_time: {4:?}, duration: {5:?}, bundle_id: {6:?}, bundle_name: {7:?}",
-                    info.is_playing,
-                    info.title,
-                    info.artist, info.album, info.elapsed_time, info.duration, info.bundle_id, info.bundle_name
-                );
+            log::debug!("{}", describe_info(&info));
 
             if !is_playing {
                 // Media is paused or stopped - don't start new session
                 // but keep existing session in case playback resumes
-                return Ok(events);
+                if let Some(session) = self.current_session.as_mut() {
+                    session.reported_paused(now, &info.bundle_id, info.elapsed_time);
+                }
+                return Ok(());
             }
 
             if let Some(track) = self.media_info_to_track(&info) {
@@ -203,26 +1015,58 @@ impl MediaMonitor {
                 match self.should_scrobble_app(&bundle_id, app_filtering) {
                     AppFilterAction::Ignore => {
                         log::debug!("Ignoring playback from {:?}", bundle_id);
-                        return Ok(events);
+                        return Ok(());
                     }
                     AppFilterAction::PromptUser => {
                         // Emit event to prompt user
                         if let Some(ref id) = bundle_id {
-                            events.unknown_app = Some(id.clone());
+                            events.push(MediaEvent::UnknownApp(id.clone()));
                         }
-                        return Ok(events);
+                        return Ok(());
                     }
                     AppFilterAction::Allow => {
                         // Continue with normal processing
                     }
                 }
 
+                let filter_input = FilterInput {
+                    app: bundle_id.as_deref().unwrap_or(""),
+                    app_name: info.bundle_name.as_deref().unwrap_or(""),
+                    title: &track.title,
+                    artist: &track.artist,
+                    album: track.album.as_deref().unwrap_or(""),
+                    duration,
+                };
+                if self.filters.action(&filter_input) == Some(FilterAction::Ignore) {
+                    log::debug!("Ignoring {} - {} (filter)", track.artist, track.title);
+                    return Ok(());
+                }
+
+                // Media Remote can switch back and forth between two apps playing at once
+                if let Some(session) = self.current_session.as_mut() {
+                    if session.bundle_id == bundle_id {
+                        session.away_polls = 0;
+                    } else if session.holds_against(&bundle_id, app_filtering) {
+                        session.away_polls += 1;
+                        session.tick(now, false, None);
+                        log::debug!(
+                            "Ignoring {:?} while {:?} holds the session",
+                            bundle_id,
+                            session.bundle_id
+                        );
+                        return Ok(());
+                    }
+                }
+                if self.current_session.as_ref().map(|s| &s.bundle_id) != Some(&bundle_id) {
+                    self.switch_session(&bundle_id);
+                }
+
                 // Check if this is a new track or continuation
-                let is_new_track = match &self.current_session {
+                let mut is_new_track = match &self.current_session {
                     None => true,
                     Some(session) => {
                         // New track
-                        if session.track != track {
+                        if !session.is_playing(&track) {
                             true
                         } else {
                             // Same track, let's see if we can detect if it is a new playback or same track playing
@@ -239,7 +1083,36 @@ impl MediaMonitor {
                     }
                 };
 
+                // A title change partway through the same file is a new chapter
+                let persistent_id = self.persistent_id();
+                let is_chapter = is_new_track
+                    && self.current_session.as_ref().is_some_and(|session| {
+                        session.is_next_chapter(
+                            &bundle_id,
+                            duration,
+                            persistent_id.as_deref(),
+                            info.elapsed_time,
+                        )
+                    });
+                if is_chapter && self.chapter_policy == ChapterPolicy::Ignore {
+                    // Carry on with the session the file started with
+                    is_new_track = false;
+                }
+
                 if is_new_track {
+                    if is_chapter {
+                        events.extend(self.finished_chapter_scrobble());
+                    }
+
+                    // Previous session (if any) is over. A new track reports how long it has
+                    // played; a chapter carries the file's position instead
+                    let new_track_played = if is_chapter {
+                        0.0
+                    } else {
+                        info.elapsed_time.unwrap_or(0.0)
+                    };
+                    self.end_session(new_track_played, events);
+
                     // New track started
                     log::info!(
                         "New track: {} - {} ({}s) from {:?}",
@@ -248,61 +1121,492 @@ impl MediaMonitor {
                         duration,
                         bundle_id
                     );
+                    if self.log_payloads {
+                        log::debug!("Reported as {}", describe_info(&info));
+                    }
+
+                    // Chapters are scrobbled without the file's duration
+                    let track = if is_chapter {
+                        Track {
+                            duration: None,
+                            ..track
+                        }
+                    } else {
+                        track
+                    };
+
+                    // Game and app audio often comes with odd metadata
+                    let score = self.confidence.score(&Signals {
+                        bundle_id: bundle_id.as_deref(),
+                        bundle_name: info.bundle_name.as_deref(),
+                        title: &track.title,
+                        artist: &track.artist,
+                        album: track.album.as_deref(),
+                        duration: (duration > 0).then_some(duration),
+                        rewritten: self.current_rewritten,
+                    });
+                    let confident = self.confidence.accepts(score);
+                    let review = confident && self.confidence.needs_review(score);
 
                     let mut new_session = PlaySession::new(
                         track.clone(),
                         bundle_id.clone(),
-                        duration,
+                        track.duration.unwrap_or(0),
                         info.info_update_time,
                         info.elapsed_time,
+                        &self.clock,
                     );
                     new_session.now_playing_sent = true; // Mark as sent immediately
+                    new_session.persistent_id = persistent_id;
+                    new_session.file_duration = duration;
+                    new_session.chapter = is_chapter;
+                    new_session.confident = confident;
+                    new_session.review = review;
                     self.current_session = Some(new_session);
 
-                    // Send now playing update
-                    events.now_playing = Some((track, bundle_id));
+                    if review {
+                        log::info!(
+                            "Holding {} - {} for review: confidence {:.2} is below the review \
+                             threshold",
+                            track.artist,
+                            track.title,
+                            score
+                        );
+                    }
+                    if confident {
+                        // Send now playing update
+                        events.push(MediaEvent::NowPlaying { track, bundle_id });
+                    } else {
+                        log::info!(
+                            "Not scrobbling {} - {}: confidence {:.2} is below the threshold",
+                            track.artist,
+                            track.title,
+                            score
+                        );
+                    }
                 } else if let Some(session) = self.current_session.as_mut() {
+                    // Some apps keep being reported as playing after they crash
+                    let stuck_polls = session.update_position(info.elapsed_time);
+                    if self.stalled_polls > 0 && stuck_polls >= self.stalled_polls {
+                        log::info!(
+                            "{} - {} has looked stuck for {} polls, expiring its session",
+                            session.track.artist,
+                            session.track.title,
+                            stuck_polls
+                        );
+                        if let Some((track, bundle_id)) = self.clear_session() {
+                            events.push(MediaEvent::Stalled { track, bundle_id });
+                        }
+                        return Ok(());
+                    }
+
+                    session.tick(now, true, info.elapsed_time);
+
                     // Same track, check if we should scrobble
-                    if session.should_scrobble(self.scrobble_threshold) {
+                    if session.should_scrobble(&self.threshold) {
                         log::info!(
-                            "Scrobbling: {} - {} (played {}s / {}s)",
+                            "Scrobbling: {} - {} (played {:.0}s / {}s)",
                             session.track.artist,
                             session.track.title,
-                            session.elapsed_seconds(),
+                            session.listened_seconds,
                             session.duration
                         );
 
-                        events.scrobble = Some((
-                            session.track.clone(),
-                            session.started_at,
-                            session.bundle_id.clone(),
-                        ));
+                        events.push(session.scrobble_event());
                         session.scrobbled = true;
                     } else if session.should_send_now_playing() {
                         // Send now playing update if not sent yet
-                        events.now_playing =
-                            Some((session.track.clone(), session.bundle_id.clone()));
+                        events.push(MediaEvent::NowPlaying {
+                            track: session.track.clone(),
+                            bundle_id: session.bundle_id.clone(),
+                        });
                         session.now_playing_sent = true;
                     }
-                    session.position = info.elapsed_time;
                 }
+            } else if let Some(session) = self.current_session.as_mut() {
+                // Playing something that isn't scrobbled (e.g. skipped radio), which doesn't
+                // count as listening to the previous track
+                session.tick(now, false, None);
             }
         } else {
+            self.cleared = None;
             // No media playing, clear session
             if self.current_session.is_some() {
                 log::info!("Media stopped, clearing session");
-                self.current_session = None;
+                self.end_session(0.0, events);
             }
         }
 
-        Ok(events)
+        Ok(())
+    }
+
+    /// Set the current session aside and carry on with the one `bundle_id` left off, if any
+    fn switch_session(&mut self, bundle_id: &Option<String>) {
+        if let Some(mut session) = self.current_session.take() {
+            log::debug!(
+                "Keeping {} - {} from {:?} while {:?} plays",
+                session.track.artist,
+                session.track.title,
+                session.bundle_id,
+                bundle_id
+            );
+            let now = self.clock.instant();
+            session.tick(now, false, None);
+            self.parked_sessions.push((now, session));
+        }
+
+        let parked = self
+            .parked_sessions
+            .iter()
+            .position(|(_, session)| session.bundle_id == *bundle_id);
+        if let Some(index) = parked {
+            let (_, mut session) = self.parked_sessions.swap_remove(index);
+            log::debug!(
+                "Back to {} - {} from {:?}",
+                session.track.artist,
+                session.track.title,
+                bundle_id
+            );
+            session.away_polls = 0;
+            self.current_session = Some(session);
+        }
+    }
+
+    /// End the sessions of apps that haven't been reported for a while
+    fn expire_parked_sessions(&mut self, events: &mut Vec<MediaEvent>) {
+        let now = self.clock.instant();
+        let (expired, kept) = std::mem::take(&mut self.parked_sessions)
+            .into_iter()
+            .partition(|(parked_at, _)| now.duration_since(*parked_at) >= PARKED_SESSION_TIMEOUT);
+        self.parked_sessions = kept;
+        for (_, session) in expired {
+            self.finish(session, 0.0, events);
+        }
+    }
+
+    /// Scrobble for a chapter that just ended, if it played long enough and wasn't scrobbled
+    fn finished_chapter_scrobble(&mut self) -> Option<MediaEvent> {
+        let now = self.clock.instant();
+        let session = self.current_session.as_mut()?;
+        if session.scrobbled
+            || !session.confident
+            || session.listened_so_far(now) < MIN_TRACK_DURATION as f64
+        {
+            return None;
+        }
+
+        log::info!(
+            "Scrobbling finished chapter: {} - {} (played {:.0}s)",
+            session.track.artist,
+            session.track.title,
+            session.listened_so_far(now)
+        );
+        session.scrobbled = true;
+
+        // The first chapter still carries the whole file's duration
+        Some(MediaEvent::Scrobble {
+            track: Track {
+                duration: None,
+                ..session.track.clone()
+            },
+            timestamp: session.started_at,
+            bundle_id: session.bundle_id.clone(),
+            review: session.review,
+        })
+    }
+
+    /// Finish the current session, which stopped playing `ago` seconds before now, and report
+    /// how long it was listened to
+    fn end_session(&mut self, ago: f64, events: &mut Vec<MediaEvent>) {
+        if let Some(session) = self.current_session.take() {
+            self.finish(session, ago, events);
+        }
+    }
+
+    /// Report how long a session was listened to, scrobbling it first if it crossed the
+    /// threshold since the last poll (the track changed before a poll could see it)
+    fn finish(&self, mut session: PlaySession, ago: f64, events: &mut Vec<MediaEvent>) {
+        session.close(self.clock.instant(), ago);
+
+        if session.should_scrobble(&self.threshold) {
+            log::info!(
+                "Scrobbling ended session: {} - {} (played {:.0}s / {}s)",
+                session.track.artist,
+                session.track.title,
+                session.listened_seconds,
+                session.duration
+            );
+            events.push(session.scrobble_event());
+        }
+
+        log::debug!(
+            "Session ended: {} - {} (listened {:.0}s)",
+            session.track.artist,
+            session.track.title,
+            session.listened_seconds
+        );
+
+        events.push(MediaEvent::SessionEnded {
+            track: session.track,
+            listened_seconds: session.listened_seconds.round() as u64,
+            bundle_id: session.bundle_id,
+        });
     }
 }
 
-/// Events generated by media monitoring
-#[derive(Debug, Default)]
-pub struct MediaEvents {
-    pub now_playing: Option<(Track, Option<String>)>,
-    pub scrobble: Option<(Track, DateTime<Utc>, Option<String>)>,
-    pub unknown_app: Option<String>,
+/// Something that happened during a poll
+///
+/// A poll can report several, e.g. the scrobble of a track that just ended, then the end of its
+/// session, then the next track playing.
+#[derive(Debug, Clone)]
+pub enum MediaEvent {
+    NowPlaying {
+        track: Track,
+        bundle_id: Option<String>,
+    },
+    Scrobble {
+        track: Track,
+        timestamp: DateTime<Utc>,
+        bundle_id: Option<String>,
+        /// Confidence was too low to submit it without the user's review
+        review: bool,
+    },
+    /// An app that isn't allowed or ignored yet started playing
+    UnknownApp(String),
+    /// A session finished, with the seconds it was actually played
+    SessionEnded {
+        track: Track,
+        listened_seconds: u64,
+        bundle_id: Option<String>,
+    },
+    /// Session expired because the player stopped moving while reported as playing
+    Stalled {
+        track: Track,
+        bundle_id: Option<String>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const STANDARD: Threshold = Threshold {
+        percent: 50,
+        mode: ThresholdMode::LastfmStandard,
+        seconds: 240,
+    };
+
+    fn session(title: &str, duration: u64, position: f64) -> PlaySession {
+        let track = Track {
+            title: title.to_string(),
+            artist: "Podcast".to_string(),
+            duration: Some(duration),
+            ..Default::default()
+        };
+        PlaySession::new(
+            track,
+            Some("com.apple.podcasts".to_string()),
+            duration,
+            None,
+            Some(position),
+            &Clock::System,
+        )
+    }
+
+    #[test]
+    fn test_skipped_session_is_not_scrobbled() {
+        let mut session = session("Episode", 600, 0.0);
+        assert_eq!(session.scrobble_at(&STANDARD), Some(240));
+
+        session.skipped = true;
+        assert_eq!(session.scrobble_at(&STANDARD), None);
+        assert!(!session.should_scrobble(&STANDARD));
+    }
+
+    #[test]
+    fn test_threshold_modes() {
+        let long = session("Epic", 1200, 0.0);
+        let short = session("Jingle", 40, 0.0);
+        assert_eq!(long.scrobble_at(&STANDARD), Some(240));
+        assert_eq!(short.scrobble_at(&STANDARD), Some(20));
+
+        let percent_only = Threshold {
+            mode: ThresholdMode::PercentOnly,
+            ..STANDARD
+        };
+        assert_eq!(long.scrobble_at(&percent_only), Some(600));
+        assert_eq!(short.scrobble_at(&percent_only), Some(20));
+
+        let fixed = Threshold {
+            mode: ThresholdMode::FixedSeconds,
+            seconds: 90,
+            ..STANDARD
+        };
+        assert_eq!(long.scrobble_at(&fixed), Some(90));
+        assert_eq!(short.scrobble_at(&fixed), Some(90));
+
+        // Too short to scrobble, whatever the mode
+        let blip = session("Blip", 20, 0.0);
+        assert_eq!(blip.scrobble_at(&fixed), None);
+    }
+
+    #[test]
+    fn test_listening_time_follows_position() {
+        let mut session = session("Song", 600, 0.0);
+        let ten_seconds_ago = || Some(Instant::now() - Duration::from_secs(10));
+
+        // Buffering: only 4 of the last 10 seconds played
+        session.last_tick = ten_seconds_ago();
+        session.tick(Instant::now(), true, Some(4.0));
+        assert_eq!(session.listened_seconds.round(), 4.0);
+
+        // Seeking ahead counts as no more than the time since the last poll
+        session.last_tick = ten_seconds_ago();
+        session.tick(Instant::now(), true, Some(300.0));
+        assert_eq!(session.listened_seconds.round(), 14.0);
+
+        // Paused for a long time, then resumed
+        session.tick(Instant::now(), false, Some(301.0));
+        session.tick(Instant::now(), true, Some(301.0));
+        session.last_tick = ten_seconds_ago();
+        session.tick(Instant::now(), true, Some(311.0));
+        assert_eq!(session.listened_seconds.round(), 24.0);
+        assert!(!session.should_scrobble(&STANDARD));
+
+        // Without a position, wall clock time is counted
+        session.tick_position = None;
+        session.last_tick = Some(Instant::now() - Duration::from_secs(300));
+        session.tick(Instant::now(), true, None);
+        assert!(session.should_scrobble(&STANDARD));
+    }
+
+    #[test]
+    fn test_threshold_crossed_between_polls() {
+        let mut session = session("Song", 200, 0.0);
+        session.listened_seconds = 95.0;
+        session.last_tick = Some(Instant::now() - Duration::from_secs(10));
+        assert!(!session.should_scrobble(&STANDARD));
+
+        // The next track has played for 3 of the 10 seconds since the last poll
+        let mut closed = session.clone();
+        closed.close(Instant::now(), 3.0);
+        assert_eq!(closed.listened_seconds.round(), 102.0);
+        assert!(closed.should_scrobble(&STANDARD));
+
+        // ... or for 8, so this one stopped short of its threshold
+        session.close(Instant::now(), 8.0);
+        assert_eq!(session.listened_seconds.round(), 97.0);
+        assert!(!session.should_scrobble(&STANDARD));
+        assert!(session.last_tick.is_none());
+    }
+
+    #[test]
+    fn test_player_policy() {
+        let mut session = session("Song", 200, 10.0);
+        session.bundle_id = Some("com.apple.Music".to_string());
+        let safari = Some("com.apple.Safari".to_string());
+        let mut app_filtering = AppFilteringConfig::default();
+        assert!(!session.holds_against(&safari, &app_filtering));
+
+        app_filtering.multiple_players = PlayerPolicy::PreferAllowed;
+        assert!(!session.holds_against(&safari, &app_filtering));
+        app_filtering.allowed_apps = vec!["com.apple.Music".to_string()];
+        assert!(session.holds_against(&safari, &app_filtering));
+        app_filtering.allowed_apps.extend(safari.clone());
+        assert!(!session.holds_against(&safari, &app_filtering));
+
+        app_filtering.multiple_players = PlayerPolicy::Lock;
+        assert!(session.holds_against(&safari, &app_filtering));
+        // Until the other app has been reported long enough
+        session.away_polls = app_filtering.player_switch_polls;
+        assert!(!session.holds_against(&safari, &app_filtering));
+    }
+
+    #[test]
+    fn test_paused_app_does_not_stop_another() {
+        let mut session = session("Song", 200, 0.0);
+        session.bundle_id = Some("com.apple.Music".to_string());
+        let spotify = Some("com.spotify.client".to_string());
+        let music = session.bundle_id.clone();
+
+        // Music plays on while Media Remote also reports a paused Spotify every other poll
+        for poll in 1..=5 {
+            session.last_tick = Some(Instant::now() - Duration::from_secs(10));
+            session.reported_paused(Instant::now(), &spotify, Some(30.0));
+            assert!(session.last_tick.is_some());
+            session.tick(Instant::now(), true, Some(poll as f64 * 10.0));
+        }
+        assert_eq!(session.listened_seconds.round(), 50.0);
+
+        // Its own pause does stop the clock
+        session.reported_paused(Instant::now(), &music, Some(50.0));
+        assert!(session.last_tick.is_none());
+    }
+
+    #[test]
+    fn test_position_standing_still() {
+        let mut session = session("Song", 200, 10.0);
+
+        assert_eq!(session.update_position(Some(15.0)), 0);
+        assert_eq!(session.update_position(Some(15.0)), 1);
+        assert_eq!(session.update_position(Some(15.0)), 2);
+        assert_eq!(session.update_position(Some(20.0)), 0);
+        // No position reported at all
+        assert_eq!(session.update_position(None), 0);
+        assert_eq!(session.update_position(None), 0);
+        // Moving, but past the end of the track
+        assert_eq!(session.update_position(Some(201.0)), 1);
+        assert_eq!(session.update_position(Some(206.0)), 2);
+    }
+
+    #[test]
+    fn test_crashed_player_expires() {
+        let mut session = session("Song", 60, 0.0);
+        let stalled_polls = 3;
+
+        // A player that crashed at the start of the track, whose last report Media Remote keeps
+        // moving along as if it were still playing, read every 5 seconds
+        let stalled_at = (5..=120)
+            .step_by(5)
+            .find(|second| session.update_position(Some(*second as f64)) >= stalled_polls);
+
+        // Three polls past the end of the track
+        assert_eq!(stalled_at, Some(75));
+    }
+
+    #[test]
+    fn test_next_chapter_same_file_continuing() {
+        let bundle_id = Some("com.apple.podcasts".to_string());
+        let session = session("Intro", 3600, 290.0);
+
+        assert!(session.is_next_chapter(&bundle_id, 3600, None, Some(300.0)));
+        // Started over, a different file, or a different player
+        assert!(!session.is_next_chapter(&bundle_id, 3600, None, Some(1.0)));
+        assert!(!session.is_next_chapter(&bundle_id, 200, None, Some(300.0)));
+        assert!(!session.is_next_chapter(&None, 3600, None, Some(300.0)));
+    }
+
+    #[test]
+    fn test_next_chapter_by_persistent_id() {
+        let bundle_id = Some("com.apple.podcasts".to_string());
+        let mut session = session("Intro", 3600, 290.0);
+        session.persistent_id = Some("A1".to_string());
+
+        assert!(session.is_next_chapter(&bundle_id, 0, Some("A1"), Some(300.0)));
+        assert!(!session.is_next_chapter(&bundle_id, 3600, Some("B2"), Some(300.0)));
+    }
+
+    #[test]
+    fn test_chapter_session_ignores_file_duration() {
+        let mut session = session("Part 2", 3600, 300.0);
+        session.chapter = true;
+        session.track.duration = None;
+
+        let mut reported = session.track.clone();
+        reported.duration = Some(3600);
+        assert!(session.is_playing(&reported));
+        // Only the 4 minute rule applies to chapters
+        assert!(!session.should_scrobble(&STANDARD));
+    }
 }
@@ -0,0 +1,103 @@
+// Metadata cache module
+// Remembers processed metadata per track so repeated plays skip the cleanup pipeline
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Least recently used cache, cleared whenever the settings it was filled with change
+pub struct MetadataCache<K, V> {
+    capacity: usize,
+    /// Fingerprint of the settings the cached values were computed with
+    fingerprint: u64,
+    entries: HashMap<K, (V, u64)>,
+    /// Incremented on every access, so the smallest stamp is the least recently used
+    clock: u64,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> MetadataCache<K, V> {
+    pub fn new(capacity: usize, fingerprint: u64) -> Self {
+        Self {
+            capacity,
+            fingerprint,
+            entries: HashMap::new(),
+            clock: 0,
+        }
+    }
+
+    /// Look up a value, marking it as recently used
+    pub fn get(&mut self, key: &K) -> Option<V> {
+        self.clock += 1;
+        let (value, used) = self.entries.get_mut(key)?;
+        *used = self.clock;
+        Some(value.clone())
+    }
+
+    /// Store a value, evicting the least recently used one if the cache is full
+    pub fn insert(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        self.clock += 1;
+        self.entries.insert(key, (value, self.clock));
+    }
+
+    /// Drop everything
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Drop everything if the settings changed
+    pub fn invalidate(&mut self, fingerprint: u64) {
+        if fingerprint != self.fingerprint {
+            self.entries.clear();
+            self.fingerprint = fingerprint;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = MetadataCache::new(2, 0);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        assert_eq!(cache.get(&"a"), Some(1));
+
+        cache.insert("c", 3);
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.get(&"a"), Some(1));
+        assert_eq!(cache.get(&"c"), Some(3));
+
+        // Replacing a value doesn't evict anything
+        cache.insert("c", 4);
+        assert_eq!(cache.get(&"a"), Some(1));
+        assert_eq!(cache.get(&"c"), Some(4));
+    }
+
+    #[test]
+    fn test_invalidate_only_on_change() {
+        let mut cache = MetadataCache::new(2, 7);
+        cache.insert("a", 1);
+
+        cache.invalidate(7);
+        assert_eq!(cache.get(&"a"), Some(1));
+
+        cache.invalidate(8);
+        assert_eq!(cache.get(&"a"), None);
+    }
+}
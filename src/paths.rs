@@ -0,0 +1,400 @@
+// Paths module
+// Where the app keeps its files: next to each other in the system directories (the layout of
+// earlier versions), or split the way Homebrew and other command line tools do it, with any
+// location overridable from the command line or environment. Everything that persists data asks
+// here for its path.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// How files are laid out on disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// `osx_scrobbler*` files directly in Application Support, art in Caches/osx_scrobbler
+    Flat,
+    /// Config in ~/.config/osx-scrobbler, state in Application Support/osx-scrobbler and art in
+    /// Caches/osx-scrobbler
+    Xdg,
+}
+
+impl Layout {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Flat => "flat",
+            Self::Xdg => "XDG",
+        }
+    }
+}
+
+/// System directories the layouts are built on
+#[derive(Debug, Clone)]
+pub struct BaseDirs {
+    /// Where the flat layout's config lives (Application Support on macOS)
+    pub config: PathBuf,
+    /// Application Support on macOS
+    pub data: PathBuf,
+    /// ~/Library/Caches on macOS
+    pub cache: PathBuf,
+    /// `$XDG_CONFIG_HOME`, or ~/.config
+    pub xdg_config: PathBuf,
+    /// ~/Library/Logs
+    pub logs: PathBuf,
+}
+
+impl BaseDirs {
+    pub fn system() -> Result<Self> {
+        let home = dirs::home_dir().context("Failed to get home directory")?;
+        let xdg_config = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .unwrap_or_else(|| home.join(".config"));
+
+        Ok(Self {
+            config: dirs::config_dir().context("Failed to get config directory")?,
+            data: dirs::data_dir().context("Failed to get data directory")?,
+            cache: dirs::cache_dir().context("Failed to get cache directory")?,
+            xdg_config,
+            logs: home.join("Library").join("Logs"),
+        })
+    }
+}
+
+/// Locations set on the command line or in the environment, used instead of the layout's
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Overrides {
+    /// Config file (`--config`, `OSX_SCROBBLER_CONFIG`)
+    pub config: Option<PathBuf>,
+    /// Directory for the history (and its submission queue), rate limit state, export of expired
+    /// scrobbles, control socket and lock file
+    /// (`--state-dir`, `OSX_SCROBBLER_STATE_DIR`)
+    pub state_dir: Option<PathBuf>,
+    /// Directory for album art (`--cache-dir`, `OSX_SCROBBLER_CACHE_DIR`)
+    pub cache_dir: Option<PathBuf>,
+    /// Directory for the log file (`--log-dir`, `OSX_SCROBBLER_LOG_DIR`)
+    pub log_dir: Option<PathBuf>,
+}
+
+impl Overrides {
+    pub fn from_env() -> Self {
+        let var = |name| {
+            std::env::var_os(name)
+                .filter(|v| !v.is_empty())
+                .map(PathBuf::from)
+        };
+
+        Self {
+            config: var("OSX_SCROBBLER_CONFIG"),
+            state_dir: var("OSX_SCROBBLER_STATE_DIR"),
+            cache_dir: var("OSX_SCROBBLER_CACHE_DIR"),
+            log_dir: var("OSX_SCROBBLER_LOG_DIR"),
+        }
+    }
+
+    /// These overrides, with the ones not set taken from `other`
+    pub fn or(self, other: Self) -> Self {
+        Self {
+            config: self.config.or(other.config),
+            state_dir: self.state_dir.or(other.state_dir),
+            cache_dir: self.cache_dir.or(other.cache_dir),
+            log_dir: self.log_dir.or(other.log_dir),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+static OVERRIDES: OnceLock<Overrides> = OnceLock::new();
+
+/// Use these overrides for the rest of the process, instead of reading the environment
+///
+/// Only the first call has an effect, so call it before anything looks up a path.
+pub fn set_overrides(overrides: Overrides) {
+    if OVERRIDES.set(overrides).is_err() {
+        log::warn!("Path overrides were already set");
+    }
+}
+
+fn overrides() -> &'static Overrides {
+    OVERRIDES.get_or_init(Overrides::from_env)
+}
+
+/// Locations of every file the app writes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Paths {
+    pub layout: Layout,
+    pub config: PathBuf,
+    pub history: PathBuf,
+    /// History file of versions before the database, imported into it once
+    pub legacy_history: PathBuf,
+    pub rate_limit: PathBuf,
+    /// Results of the weekly credential checks
+    pub session_checks: PathBuf,
+    /// Queued scrobbles too old for Last.fm, see `[scheduler] timestamps`
+    pub expired: PathBuf,
+    pub socket: PathBuf,
+    /// Locked by the running app, see `app_lock`
+    pub lock: PathBuf,
+    pub artwork: PathBuf,
+    /// MusicBrainz artist lookups, see `[artist_aliases]`
+    pub artist_aliases: PathBuf,
+    pub log: PathBuf,
+}
+
+impl Paths {
+    /// Paths of the layout in use (XDG once its config file exists, flat otherwise), with the
+    /// overrides applied
+    pub fn current() -> Result<Self> {
+        Ok(Self::detect(&BaseDirs::system()?).with_overrides(overrides()))
+    }
+
+    fn with_overrides(mut self, overrides: &Overrides) -> Self {
+        if let Some(config) = &overrides.config {
+            self.config = config.clone();
+        }
+        if let Some(state) = &overrides.state_dir {
+            self.history = state.join("history.sqlite3");
+            self.rate_limit = state.join("ratelimit.json");
+            self.session_checks = state.join("sessions.json");
+            self.expired = state.join("expired.csv");
+            self.socket = state.join("control.sock");
+            self.lock = state.join("app.lock");
+        }
+        if let Some(cache) = &overrides.cache_dir {
+            self.artwork = cache.join("artwork");
+            self.artist_aliases = cache.join("artist_aliases.json");
+        }
+        if let Some(logs) = &overrides.log_dir {
+            self.log = logs.join("osx-scrobbler.log");
+        }
+        self
+    }
+
+    fn detect(base: &BaseDirs) -> Self {
+        let xdg = Self::new(Layout::Xdg, base);
+        if xdg.config.exists() {
+            xdg
+        } else {
+            Self::new(Layout::Flat, base)
+        }
+    }
+
+    pub fn new(layout: Layout, base: &BaseDirs) -> Self {
+        let legacy_history = base.data.join("osx_scrobbler_history.jsonl");
+        let log = base.logs.join("osx-scrobbler.log");
+
+        match layout {
+            Layout::Flat => Self {
+                layout,
+                config: base.config.join("osx_scrobbler.conf"),
+                history: base.data.join("osx_scrobbler_history.sqlite3"),
+                legacy_history,
+                rate_limit: base.data.join("osx_scrobbler_ratelimit.json"),
+                session_checks: base.data.join("osx_scrobbler_sessions.json"),
+                expired: base.data.join("osx_scrobbler_expired.csv"),
+                socket: base.data.join("osx_scrobbler.sock"),
+                lock: base.data.join("osx_scrobbler.lock"),
+                artwork: base.cache.join("osx_scrobbler").join("artwork"),
+                artist_aliases: base.cache.join("osx_scrobbler").join("artist_aliases.json"),
+                log,
+            },
+            Layout::Xdg => {
+                let state = base.data.join("osx-scrobbler");
+                Self {
+                    layout,
+                    config: base.xdg_config.join("osx-scrobbler").join("config.toml"),
+                    history: state.join("history.sqlite3"),
+                    legacy_history,
+                    rate_limit: state.join("ratelimit.json"),
+                    session_checks: state.join("sessions.json"),
+                    expired: state.join("expired.csv"),
+                    socket: state.join("control.sock"),
+                    lock: state.join("app.lock"),
+                    artwork: base.cache.join("osx-scrobbler").join("artwork"),
+                    artist_aliases: base.cache.join("osx-scrobbler").join("artist_aliases.json"),
+                    log,
+                }
+            }
+        }
+    }
+
+    /// Files that are kept across restarts, in the order they're moved (config last, since it
+    /// decides the layout)
+    fn persistent(&self) -> [&Path; 7] {
+        [
+            &self.history,
+            &self.rate_limit,
+            &self.session_checks,
+            &self.expired,
+            &self.artwork,
+            &self.artist_aliases,
+            &self.config,
+        ]
+    }
+}
+
+/// Move the flat layout's files to where the XDG layout keeps them
+///
+/// The app must not be running. Nothing is moved if a file is already at its new location, and
+/// files moved before a failure are put back. Returns the moves made.
+pub fn migrate() -> Result<Vec<(PathBuf, PathBuf)>> {
+    anyhow::ensure!(
+        overrides().is_empty(),
+        "Locations are overridden on the command line or in the environment; remove the overrides to migrate"
+    );
+    migrate_in(&BaseDirs::system()?)
+}
+
+fn migrate_in(base: &BaseDirs) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let flat = Paths::new(Layout::Flat, base);
+    let xdg = Paths::new(Layout::Xdg, base);
+    anyhow::ensure!(!xdg.config.exists(), "Files are already in the XDG layout");
+
+    let moves: Vec<(PathBuf, PathBuf)> = flat
+        .persistent()
+        .into_iter()
+        .zip(xdg.persistent())
+        .filter(|(from, _)| from.exists())
+        .map(|(from, to)| (from.to_path_buf(), to.to_path_buf()))
+        .collect();
+    for (_, to) in &moves {
+        anyhow::ensure!(!to.exists(), "{} already exists", to.display());
+    }
+
+    for (i, (from, to)) in moves.iter().enumerate() {
+        if let Err(e) = move_path(from, to) {
+            for (from, to) in moves[..i].iter().rev() {
+                if let Err(e) = fs::rename(to, from) {
+                    log::warn!("Failed to move {} back: {}", to.display(), e);
+                }
+            }
+            return Err(e);
+        }
+    }
+
+    // The flat layout's cache folder only held the art and artist lookups
+    if let Some(parent) = flat.artwork.parent() {
+        let _ = fs::remove_dir(parent);
+    }
+
+    Ok(moves)
+}
+
+fn move_path(from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    fs::rename(from, to)
+        .with_context(|| format!("Failed to move {} to {}", from.display(), to.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base(root: &Path) -> BaseDirs {
+        BaseDirs {
+            config: root.join("Application Support"),
+            data: root.join("Application Support"),
+            cache: root.join("Caches"),
+            xdg_config: root.join(".config"),
+            logs: root.join("Logs"),
+        }
+    }
+
+    fn write(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn test_migrate_to_xdg() {
+        let root =
+            std::env::temp_dir().join(format!("osx_scrobbler_paths_test_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let base = base(&root);
+        let flat = Paths::new(Layout::Flat, &base);
+        write(&flat.config, "refresh_interval = 5");
+        write(&flat.history, "db");
+        write(&flat.artwork.join("cover.jpg"), "jpeg");
+        assert_eq!(Paths::detect(&base).layout, Layout::Flat);
+
+        let moves = migrate_in(&base).unwrap();
+        let xdg = Paths::detect(&base);
+        assert_eq!(xdg.layout, Layout::Xdg);
+        // No rate limit state to move
+        assert_eq!(moves.len(), 3);
+        assert_eq!(
+            fs::read_to_string(&xdg.config).unwrap(),
+            "refresh_interval = 5"
+        );
+        assert_eq!(xdg.config, root.join(".config/osx-scrobbler/config.toml"));
+        assert!(xdg.history.exists() && xdg.artwork.join("cover.jpg").exists());
+        assert!(!flat.config.exists() && !root.join("Caches/osx_scrobbler").exists());
+
+        assert!(migrate_in(&base).is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_migrate_refuses_to_overwrite() {
+        let root = std::env::temp_dir().join(format!(
+            "osx_scrobbler_paths_overwrite_test_{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&root);
+        let base = base(&root);
+        let flat = Paths::new(Layout::Flat, &base);
+        let xdg = Paths::new(Layout::Xdg, &base);
+        write(&flat.config, "");
+        write(&flat.history, "old");
+        write(&xdg.history, "new");
+
+        let error = migrate_in(&base).unwrap_err().to_string();
+        assert!(error.contains("already exists"), "{}", error);
+        assert!(flat.config.exists());
+        assert_eq!(fs::read_to_string(&xdg.history).unwrap(), "new");
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_overrides() {
+        let root = Path::new("/Users/me/Library");
+        let paths = Paths::new(Layout::Flat, &base(root)).with_overrides(&Overrides {
+            config: Some(PathBuf::from("/etc/scrobbler.toml")),
+            state_dir: Some(PathBuf::from("/var/scrobbler")),
+            ..Overrides::default()
+        });
+
+        assert_eq!(paths.config, Path::new("/etc/scrobbler.toml"));
+        assert_eq!(paths.history, Path::new("/var/scrobbler/history.sqlite3"));
+        assert_eq!(paths.socket, Path::new("/var/scrobbler/control.sock"));
+        assert_eq!(paths.lock, Path::new("/var/scrobbler/app.lock"));
+        assert_eq!(paths.expired, Path::new("/var/scrobbler/expired.csv"));
+        assert_eq!(paths.artwork, root.join("Caches/osx_scrobbler/artwork"));
+        assert_eq!(paths.log, root.join("Logs/osx-scrobbler.log"));
+    }
+
+    #[test]
+    fn test_command_line_overrides_win() {
+        let cli = Overrides {
+            state_dir: Some(PathBuf::from("/cli")),
+            ..Overrides::default()
+        };
+        let env = Overrides {
+            state_dir: Some(PathBuf::from("/env")),
+            log_dir: Some(PathBuf::from("/env/logs")),
+            ..Overrides::default()
+        };
+
+        let merged = cli.or(env);
+        assert_eq!(merged.state_dir, Some(PathBuf::from("/cli")));
+        assert_eq!(merged.log_dir, Some(PathBuf::from("/env/logs")));
+        assert!(Overrides::default().is_empty());
+        assert!(!merged.is_empty());
+    }
+}
@@ -0,0 +1,238 @@
+// Plugins module
+// Metadata transforms loaded from dynamic libraries, for cleanup logic beyond regex
+//
+// A plugin exports three C functions:
+//
+//   uint32_t osx_scrobbler_plugin_abi_version(void);
+//   char *osx_scrobbler_transform(const char *request);
+//   void osx_scrobbler_free(char *response);
+//
+// The request is a JSON object `{"abi_version", "app", "track"}`; the response is a JSON object
+// with an `action` of `keep`, `rewrite` (with a new `track`) or `reject`, or NULL to keep the
+// track. Responses are handed back to the plugin's `osx_scrobbler_free`.
+
+use crate::scrobbler::Track;
+use anyhow::{Context, Result};
+use libloading::Library;
+use serde::Deserialize;
+use serde_json::json;
+use std::ffi::{c_char, CStr, CString};
+use std::path::{Path, PathBuf};
+
+/// Version of the plugin ABI, bumped on incompatible changes to the functions or JSON
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+
+type AbiVersionFn = unsafe extern "C" fn() -> u32;
+type TransformFn = unsafe extern "C" fn(*const c_char) -> *mut c_char;
+type FreeFn = unsafe extern "C" fn(*mut c_char);
+
+/// What a plugin wants done with a track
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum PluginResponse {
+    Keep,
+    Rewrite { track: Track },
+    Reject,
+}
+
+struct Plugin {
+    path: PathBuf,
+    transform: TransformFn,
+    free: FreeFn,
+    // Keeps the functions above loaded
+    _library: Library,
+}
+
+impl Plugin {
+    fn load(path: &Path) -> Result<Self> {
+        // SAFETY: plugins are libraries the user configured; their initializers run on load
+        let library = unsafe { Library::new(path) }.context("Failed to load library")?;
+
+        // SAFETY: the symbols are declared with the signatures of the plugin ABI above
+        let (abi_version, transform, free) = unsafe {
+            let abi_version = *library
+                .get::<AbiVersionFn>(b"osx_scrobbler_plugin_abi_version\0")
+                .context("Missing osx_scrobbler_plugin_abi_version")?;
+            let transform = *library
+                .get::<TransformFn>(b"osx_scrobbler_transform\0")
+                .context("Missing osx_scrobbler_transform")?;
+            let free = *library
+                .get::<FreeFn>(b"osx_scrobbler_free\0")
+                .context("Missing osx_scrobbler_free")?;
+            (abi_version(), transform, free)
+        };
+
+        if abi_version != PLUGIN_ABI_VERSION {
+            anyhow::bail!(
+                "Built for plugin ABI version {}, expected {}",
+                abi_version,
+                PLUGIN_ABI_VERSION
+            );
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            transform,
+            free,
+            _library: library,
+        })
+    }
+
+    fn call(&self, request: &str) -> Result<PluginResponse> {
+        let request = CString::new(request).context("Request contains a NUL byte")?;
+
+        // SAFETY: the request outlives the call, and the response is only freed by the plugin
+        let response = unsafe {
+            let response = (self.transform)(request.as_ptr());
+            if response.is_null() {
+                return Ok(PluginResponse::Keep);
+            }
+            let text = CStr::from_ptr(response).to_string_lossy().into_owned();
+            (self.free)(response);
+            text
+        };
+
+        parse_response(&response)
+    }
+}
+
+/// Loaded plugins, applied in order
+#[derive(Default)]
+pub struct Plugins {
+    plugins: Vec<Plugin>,
+}
+
+impl Plugins {
+    /// Load plugins from the given paths, skipping (and logging) those that fail
+    pub fn load(paths: &[PathBuf]) -> Self {
+        let plugins = paths
+            .iter()
+            .filter_map(|path| match Plugin::load(path) {
+                Ok(plugin) => {
+                    log::info!("Loaded plugin {}", path.display());
+                    Some(plugin)
+                }
+                Err(e) => {
+                    log::error!("Failed to load plugin {}: {:#}", path.display(), e);
+                    None
+                }
+            })
+            .collect();
+
+        Self { plugins }
+    }
+
+    /// Run a track through every plugin; `None` if one rejects it
+    ///
+    /// A plugin that returns something invalid leaves the track as it was.
+    pub fn apply(&self, bundle_id: Option<&str>, mut track: Track) -> Option<Track> {
+        for plugin in &self.plugins {
+            match plugin.call(&request(bundle_id, &track)) {
+                Ok(PluginResponse::Keep) => {}
+                Ok(PluginResponse::Rewrite { track: rewritten }) => {
+                    log::debug!(
+                        "Plugin {} rewrote {:?} to {:?}",
+                        plugin.path.display(),
+                        track,
+                        rewritten
+                    );
+                    track = rewritten;
+                }
+                Ok(PluginResponse::Reject) => {
+                    log::debug!(
+                        "Plugin {} rejected {} - {}",
+                        plugin.path.display(),
+                        track.artist,
+                        track.title
+                    );
+                    return None;
+                }
+                Err(e) => log::warn!("Plugin {}: {:#}", plugin.path.display(), e),
+            }
+        }
+
+        Some(track)
+    }
+}
+
+fn request(bundle_id: Option<&str>, track: &Track) -> String {
+    json!({
+        "abi_version": PLUGIN_ABI_VERSION,
+        "app": bundle_id,
+        "track": track,
+    })
+    .to_string()
+}
+
+fn parse_response(response: &str) -> Result<PluginResponse> {
+    serde_json::from_str(response).context("Invalid response")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track() -> Track {
+        Track {
+            title: "Song - Remastered 2011".to_string(),
+            artist: "Band".to_string(),
+            duration: Some(200),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_request() {
+        let request: serde_json::Value =
+            serde_json::from_str(&request(Some("com.spotify.client"), &track())).unwrap();
+
+        assert_eq!(
+            request,
+            json!({
+                "abi_version": PLUGIN_ABI_VERSION,
+                "app": "com.spotify.client",
+                "track": {
+                    "title": "Song - Remastered 2011",
+                    "artist": "Band",
+                    "album": null,
+                    "duration": 200,
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_response() {
+        assert_eq!(
+            parse_response(r#"{"action": "keep"}"#).unwrap(),
+            PluginResponse::Keep
+        );
+        assert_eq!(
+            parse_response(r#"{"action": "reject"}"#).unwrap(),
+            PluginResponse::Reject
+        );
+        assert_eq!(
+            parse_response(
+                r#"{"action": "rewrite", "track": {"title": "Song", "artist": "Band", "album": null, "duration": 200}}"#
+            )
+            .unwrap(),
+            PluginResponse::Rewrite {
+                track: Track {
+                    title: "Song".to_string(),
+                    ..track()
+                }
+            }
+        );
+
+        assert!(parse_response("not json").is_err());
+        assert!(parse_response(r#"{"action": "rewrite"}"#).is_err());
+        assert!(parse_response(r#"{"action": "skip"}"#).is_err());
+    }
+
+    #[test]
+    fn test_missing_plugin_is_skipped() {
+        let plugins = Plugins::load(&[PathBuf::from("/nonexistent/libplugin.dylib")]);
+
+        assert_eq!(plugins.apply(None, track()), Some(track()));
+    }
+}
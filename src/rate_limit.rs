@@ -0,0 +1,383 @@
+// Rate limit budgeting module
+// Tracks API requests in a sliding window so we can throttle before hitting service limits
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+
+/// Last.fm allows 5 requests per second per API key, averaged over 5 minutes
+pub const LASTFM_WINDOW_SECONDS: i64 = 5 * 60;
+pub const LASTFM_REQUESTS_PER_WINDOW: usize = 5 * 5 * 60;
+
+/// Start throttling once this share of the budget is used
+const THROTTLE_AT_PERCENT: usize = 90;
+
+/// Recorded requests are written out at most this often (and when the app quits), since only
+/// other processes read the file
+const SAVE_INTERVAL_SECONDS: i64 = 60;
+
+/// A request turned away because the budget is used up; it should be queued and retried later
+#[derive(Debug, Clone, PartialEq)]
+pub struct Throttled {
+    /// When the budget has room again
+    pub retry_in: std::time::Duration,
+}
+
+impl std::fmt::Display for Throttled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Request budget used up, room again in {}s",
+            self.retry_in.as_secs()
+        )
+    }
+}
+
+impl std::error::Error for Throttled {}
+
+/// Whether an error is a request turned away by [`Throttled`]
+pub fn is_throttled(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| cause.is::<Throttled>())
+}
+
+/// Sliding-window request budget for a single API key
+#[derive(Debug, Clone)]
+pub struct RateBudget {
+    key: String,
+    limit: usize,
+    window: Duration,
+    requests: VecDeque<DateTime<Utc>>,
+    /// Requests were recorded since the last save
+    unsaved: bool,
+    saved_at: Option<DateTime<Utc>>,
+}
+
+impl RateBudget {
+    pub fn new(key: &str, limit: usize, window: Duration) -> Self {
+        Self {
+            key: key.to_string(),
+            limit,
+            window,
+            requests: VecDeque::new(),
+            unsaved: false,
+            saved_at: None,
+        }
+    }
+
+    /// Budget for a Last.fm API key, restoring requests recorded by earlier runs
+    pub fn lastfm(api_key: &str) -> Self {
+        let mut budget = Self::new(
+            api_key,
+            LASTFM_REQUESTS_PER_WINDOW,
+            Duration::seconds(LASTFM_WINDOW_SECONDS),
+        );
+
+        match load_requests() {
+            Ok(mut stored) => {
+                if let Some(requests) = stored.remove(api_key) {
+                    budget.requests = requests.into();
+                }
+            }
+            Err(e) => log::warn!("Failed to load rate limit state: {}", e),
+        }
+
+        budget
+    }
+
+    /// Maximum requests per window
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Drop requests that fell out of the window
+    fn prune(&mut self, now: DateTime<Utc>) {
+        while self
+            .requests
+            .front()
+            .is_some_and(|&t| now - t >= self.window)
+        {
+            self.requests.pop_front();
+        }
+    }
+
+    /// Record a request made at `now`
+    pub fn record(&mut self, now: DateTime<Utc>) {
+        self.prune(now);
+        self.requests.push_back(now);
+        self.unsaved = true;
+    }
+
+    /// Count a request about to be made at `now`, or turn it away if the budget is used up
+    pub fn try_record(&mut self, now: DateTime<Utc>) -> Result<(), Throttled> {
+        if let Some(retry_in) = self.throttle_delay(now) {
+            return Err(Throttled { retry_in });
+        }
+        self.record(now);
+        Ok(())
+    }
+
+    /// Copy of the budget to save, if requests were recorded and the last save was a while ago
+    ///
+    /// The copy can be saved without holding on to this one (or the lock around it).
+    pub fn save_due(&mut self, now: DateTime<Utc>) -> Option<Self> {
+        let recent = self
+            .saved_at
+            .is_some_and(|saved_at| now - saved_at < Duration::seconds(SAVE_INTERVAL_SECONDS));
+        if !self.unsaved || recent {
+            return None;
+        }
+        self.unsaved = false;
+        self.saved_at = Some(now);
+        Some(self.clone())
+    }
+
+    /// Whether requests were recorded since the last save
+    pub fn is_unsaved(&self) -> bool {
+        self.unsaved
+    }
+
+    /// Requests made within the current window
+    pub fn used(&mut self, now: DateTime<Utc>) -> usize {
+        self.prune(now);
+        self.requests.len()
+    }
+
+    /// Requests that can be made before throttling kicks in
+    pub fn remaining(&mut self, now: DateTime<Utc>) -> usize {
+        let soft_limit = self.limit * THROTTLE_AT_PERCENT / 100;
+        soft_limit.saturating_sub(self.used(now))
+    }
+
+    /// How long to wait before the next request to stay under the soft limit
+    pub fn throttle_delay(&mut self, now: DateTime<Utc>) -> Option<std::time::Duration> {
+        if self.remaining(now) > 0 {
+            return None;
+        }
+
+        // Wait until enough old requests expire to get back under the soft limit
+        let soft_limit = self.limit * THROTTLE_AT_PERCENT / 100;
+        let excess = self.requests.len() + 1 - soft_limit;
+        let expires_at = *self.requests.get(excess - 1)? + self.window;
+
+        (expires_at - now).to_std().ok()
+    }
+
+    /// Persist recorded requests so other processes (e.g. `status`) can see them
+    pub fn save(&mut self) -> Result<()> {
+        self.unsaved = false;
+        let mut stored = load_requests().unwrap_or_default();
+        stored.insert(self.key.clone(), self.requests.iter().copied().collect());
+
+        let path = state_path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context("Failed to create rate limit state directory")?;
+        }
+        let content =
+            serde_json::to_string(&stored).context("Failed to serialize rate limit state")?;
+        fs::write(&path, content).context("Failed to write rate limit state")?;
+
+        Ok(())
+    }
+}
+
+/// Server-reported rate limit for a single host
+///
+/// Mirrors the `X-RateLimit-Remaining` / `X-RateLimit-Reset-In` headers sent by
+/// ListenBrainz: requests spend tokens locally and every response resyncs the bucket.
+#[derive(Debug, Clone, Default)]
+pub struct HostBudget {
+    remaining: Option<u64>,
+    reset_at: Option<Instant>,
+}
+
+impl HostBudget {
+    /// Take a token for the next request, returning how long to wait first (if at all)
+    pub fn acquire(&mut self, now: Instant) -> Option<std::time::Duration> {
+        // Window is over (or unknown) - the server will tell us the new budget
+        if self.reset_at.is_none_or(|reset_at| now >= reset_at) {
+            self.remaining = None;
+            self.reset_at = None;
+            return None;
+        }
+
+        match self.remaining {
+            Some(0) => {
+                let delay = self.reset_at.map(|reset_at| reset_at - now);
+                // The request goes out once the window resets
+                self.remaining = None;
+                self.reset_at = None;
+                delay
+            }
+            Some(remaining) => {
+                self.remaining = Some(remaining - 1);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Update the budget from a response's rate limit headers
+    pub fn update(&mut self, remaining: Option<u64>, reset_in: Option<u64>, now: Instant) {
+        if let (Some(remaining), Some(reset_in)) = (remaining, reset_in) {
+            self.remaining = Some(remaining);
+            self.reset_at = Some(now + std::time::Duration::from_secs(reset_in));
+        }
+    }
+
+    /// Mark the budget as exhausted (e.g. after an HTTP 429)
+    pub fn exhaust(&mut self, reset_in: u64, now: Instant) {
+        self.remaining = Some(0);
+        self.reset_at = Some(now + std::time::Duration::from_secs(reset_in));
+    }
+}
+
+/// Get the budget shared by every client talking to `host`
+pub fn host_budget(host: &str) -> Arc<Mutex<HostBudget>> {
+    static BUDGETS: OnceLock<Mutex<HashMap<String, Arc<Mutex<HostBudget>>>>> = OnceLock::new();
+
+    let budgets = BUDGETS.get_or_init(|| Mutex::new(HashMap::new()));
+    let mut budgets = budgets.lock().unwrap_or_else(|e| e.into_inner());
+    budgets
+        .entry(host.to_ascii_lowercase())
+        .or_default()
+        .clone()
+}
+
+/// Get the path to the persisted rate limit state
+fn state_path() -> Result<PathBuf> {
+    Ok(crate::paths::Paths::current()?.rate_limit)
+}
+
+fn load_requests() -> Result<HashMap<String, Vec<DateTime<Utc>>>> {
+    let path = state_path()?;
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+
+    let content = fs::read_to_string(&path).context("Failed to read rate limit state")?;
+    serde_json::from_str(&content).context("Failed to parse rate limit state")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn start() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 1, 1, 12, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn test_requests_expire_after_window() {
+        let mut budget = RateBudget::new("key", 100, Duration::seconds(300));
+        budget.record(start());
+        budget.record(start() + Duration::seconds(100));
+
+        assert_eq!(budget.used(start() + Duration::seconds(200)), 2);
+        assert_eq!(budget.used(start() + Duration::seconds(300)), 1);
+        assert_eq!(budget.used(start() + Duration::seconds(400)), 0);
+    }
+
+    #[test]
+    fn test_remaining_keeps_headroom() {
+        let mut budget = RateBudget::new("key", 100, Duration::seconds(300));
+        for _ in 0..50 {
+            budget.record(start());
+        }
+
+        assert_eq!(budget.remaining(start()), 40);
+        assert_eq!(budget.throttle_delay(start()), None);
+    }
+
+    #[test]
+    fn test_try_record_turns_requests_away_when_used_up() {
+        let mut budget = RateBudget::new("key", 10, Duration::seconds(300));
+        for _ in 0..9 {
+            assert_eq!(budget.try_record(start()), Ok(()));
+        }
+
+        assert_eq!(
+            budget.try_record(start() + Duration::seconds(100)),
+            Err(Throttled {
+                retry_in: std::time::Duration::from_secs(200)
+            })
+        );
+        assert_eq!(budget.used(start() + Duration::seconds(100)), 9);
+        assert!(is_throttled(
+            &anyhow::Error::new(Throttled {
+                retry_in: std::time::Duration::from_secs(1)
+            })
+            .context("Failed to scrobble")
+        ));
+    }
+
+    #[test]
+    fn test_save_due_at_most_once_per_interval() {
+        let mut budget = RateBudget::new("key", 100, Duration::seconds(300));
+        assert!(budget.save_due(start()).is_none());
+
+        budget.record(start());
+        assert!(budget.save_due(start()).is_some());
+        budget.record(start() + Duration::seconds(10));
+        assert!(budget.save_due(start() + Duration::seconds(10)).is_none());
+        assert!(budget.is_unsaved());
+        assert!(budget
+            .save_due(start() + Duration::seconds(SAVE_INTERVAL_SECONDS))
+            .is_some());
+        assert!(!budget.is_unsaved());
+    }
+
+    #[test]
+    fn test_host_budget_unknown_allows_requests() {
+        let mut budget = HostBudget::default();
+        assert_eq!(budget.acquire(Instant::now()), None);
+    }
+
+    #[test]
+    fn test_host_budget_waits_when_exhausted() {
+        let now = Instant::now();
+        let mut budget = HostBudget::default();
+        budget.update(Some(1), Some(10), now);
+
+        // One token left, then we must wait for the reset
+        assert_eq!(budget.acquire(now), None);
+        assert_eq!(
+            budget.acquire(now + std::time::Duration::from_secs(4)),
+            Some(std::time::Duration::from_secs(6))
+        );
+        // After the reset the budget is unknown again
+        assert_eq!(
+            budget.acquire(now + std::time::Duration::from_secs(11)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_host_budget_shared_per_host() {
+        let a = host_budget("api.example.org");
+        let b = host_budget("API.example.org");
+        a.lock().unwrap().exhaust(60, Instant::now());
+
+        assert!(b.lock().unwrap().acquire(Instant::now()).is_some());
+        assert!(!Arc::ptr_eq(&a, &host_budget("other.example.org")));
+    }
+
+    #[test]
+    fn test_throttle_delay_waits_for_oldest_request() {
+        let mut budget = RateBudget::new("key", 10, Duration::seconds(300));
+        for i in 0..9 {
+            budget.record(start() + Duration::seconds(i * 10));
+        }
+
+        let now = start() + Duration::seconds(100);
+        assert_eq!(budget.remaining(now), 0);
+        assert_eq!(
+            budget.throttle_delay(now),
+            Some(std::time::Duration::from_secs(200))
+        );
+    }
+}
@@ -0,0 +1,204 @@
+// Reconciliation module
+// Compares listens stored on a service with the local history to find gaps
+
+use crate::history::{HistoryEntry, Submission};
+use crate::scrobbler::Track;
+use chrono::{DateTime, Duration, Utc};
+
+/// Listens this close together (in seconds) are treated as the same scrobble
+pub const MATCH_TOLERANCE_SECONDS: i64 = 120;
+
+/// A listen as stored on a scrobbling service
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteListen {
+    pub artist: String,
+    pub title: String,
+    pub album: Option<String>,
+    pub played_at: DateTime<Utc>,
+}
+
+impl RemoteListen {
+    pub fn track(&self) -> Track {
+        Track {
+            title: self.title.clone(),
+            artist: self.artist.clone(),
+            album: self.album.clone(),
+            duration: None,
+            origin_url: None,
+            radio: false,
+            mbid: None,
+            album_artist: None,
+            live: None,
+        }
+    }
+}
+
+/// Result of comparing remote listens with the local history
+#[derive(Debug, Default)]
+pub struct Comparison<'a> {
+    /// IDs of local entries found on the service
+    pub matched: Vec<u64>,
+    /// Remote listens with no local entry
+    pub missing_locally: Vec<&'a RemoteListen>,
+    /// IDs of local entries played since the start of the window that the service lacks
+    pub missing_remotely: Vec<u64>,
+}
+
+fn same_listen(entry: &HistoryEntry, listen: &RemoteListen) -> bool {
+    entry
+        .artist
+        .trim()
+        .eq_ignore_ascii_case(listen.artist.trim())
+        && entry.title.trim().eq_ignore_ascii_case(listen.title.trim())
+        && (entry.played_at - listen.played_at).num_seconds().abs() <= MATCH_TOLERANCE_SECONDS
+}
+
+/// Match remote listens played since `since` against local entries
+///
+/// Each local entry matches at most one remote listen, so repeated plays of a track are
+/// counted separately. Private entries are ignored.
+pub fn compare<'a>(
+    entries: &[HistoryEntry],
+    remote: &'a [RemoteListen],
+    since: DateTime<Utc>,
+) -> Comparison<'a> {
+    let window_start = since - Duration::seconds(MATCH_TOLERANCE_SECONDS);
+    let candidates: Vec<&HistoryEntry> = entries
+        .iter()
+        .filter(|e| !e.private && e.played_at >= window_start)
+        .collect();
+    let mut used = vec![false; candidates.len()];
+
+    let mut comparison = Comparison::default();
+    for listen in remote.iter().filter(|l| l.played_at >= since) {
+        let found = candidates
+            .iter()
+            .enumerate()
+            .find(|(i, entry)| !used[*i] && same_listen(entry, listen));
+
+        match found {
+            Some((i, entry)) => {
+                used[i] = true;
+                // Deleted entries still account for the listen, but aren't acted on
+                if !entry.deleted {
+                    comparison.matched.push(entry.id);
+                }
+            }
+            None => comparison.missing_locally.push(listen),
+        }
+    }
+
+    comparison.missing_remotely = candidates
+        .iter()
+        .zip(&used)
+        .filter(|(entry, used)| !**used && !entry.deleted && entry.played_at >= since)
+        .map(|(entry, _)| entry.id)
+        .collect();
+
+    comparison
+}
+
+/// Mark an entry as stored on the canonical service and queue it for targets that lack it
+///
+/// Returns the number of submissions queued. Targets that already have a submission
+/// (sent, failed or queued) are left alone.
+pub fn queue_mirror(entry: &mut HistoryEntry, canonical: &str, targets: &[String]) -> usize {
+    entry.set_submission(canonical, &Ok(()));
+
+    let mut queued = 0;
+    for target in targets {
+        if !entry.submissions.iter().any(|s| s.service == *target) {
+            entry.submissions.push(Submission::pending(target));
+            queued += 1;
+        }
+    }
+
+    queued
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(minutes: i64) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 1, 1, 12, 0, 0).unwrap() + Duration::minutes(minutes)
+    }
+
+    fn entry(id: u64, title: &str, played_at: DateTime<Utc>) -> HistoryEntry {
+        HistoryEntry {
+            id,
+            artist: "Artist".to_string(),
+            title: title.to_string(),
+            duration: Some(200),
+            played_at,
+            ..Default::default()
+        }
+    }
+
+    fn listen(title: &str, played_at: DateTime<Utc>) -> RemoteListen {
+        RemoteListen {
+            artist: "artist".to_string(),
+            title: title.to_string(),
+            album: None,
+            played_at,
+        }
+    }
+
+    #[test]
+    fn test_compare_matches_within_tolerance() {
+        let entries = vec![
+            entry(1, "One", at(0)),
+            entry(2, "Two", at(5)),
+            entry(3, "Old", at(-600)),
+        ];
+        let remote = vec![
+            listen("One", at(0) + Duration::seconds(30)),
+            listen("Two", at(10)),
+            listen("Three", at(15)),
+        ];
+
+        let comparison = compare(&entries, &remote, at(-60));
+        assert_eq!(comparison.matched, vec![1]);
+        assert_eq!(comparison.missing_locally, vec![&remote[1], &remote[2]]);
+        assert_eq!(comparison.missing_remotely, vec![2]);
+    }
+
+    #[test]
+    fn test_compare_repeated_plays_match_once_each() {
+        let entries = vec![entry(1, "Loop", at(0))];
+        let remote = vec![listen("Loop", at(0)), listen("Loop", at(1))];
+
+        let comparison = compare(&entries, &remote, at(-60));
+        assert_eq!(comparison.matched, vec![1]);
+        assert_eq!(comparison.missing_locally, vec![&remote[1]]);
+    }
+
+    #[test]
+    fn test_compare_deleted_entries_absorb_listens() {
+        let mut entries = vec![entry(1, "Oops", at(0)), entry(2, "Gone", at(5))];
+        entries[0].deleted = true;
+        entries[1].deleted = true;
+        let remote = vec![listen("Oops", at(0))];
+
+        let comparison = compare(&entries, &remote, at(-60));
+        assert!(comparison.matched.is_empty());
+        assert!(comparison.missing_locally.is_empty());
+        assert!(comparison.missing_remotely.is_empty());
+    }
+
+    #[test]
+    fn test_queue_mirror_skips_existing_submissions() {
+        let mut entry = entry(1, "One", at(0));
+        entry.set_submission("lastfm", &Err(anyhow::anyhow!("down")));
+
+        let targets = vec!["lastfm".to_string(), "listenbrainz:Home".to_string()];
+        assert_eq!(
+            queue_mirror(&mut entry, "listenbrainz:Primary", &targets),
+            1
+        );
+        assert!(!entry.needs_retry("listenbrainz:Primary"));
+        assert!(entry.needs_retry("listenbrainz:Home"));
+        assert_eq!(entry.submissions.len(), 3);
+    }
+}
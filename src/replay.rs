@@ -0,0 +1,402 @@
+// Replay module
+// Records what the player reported at each poll, and feeds a recording back through the media
+// monitor to reproduce how the plays were handled, without submitting anything
+
+use crate::config::Config;
+use crate::media_monitor::{MediaEvent, MediaMonitor, PlayerInfo};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+/// What the player reported at one poll (`None` when nothing was playing)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PollSnapshot {
+    pub at: DateTime<Utc>,
+    pub info: Option<PlayerInfo>,
+}
+
+/// Appends a snapshot of every poll to a file, one JSON object per line
+pub struct Recorder {
+    file: File,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        Ok(Self { file })
+    }
+
+    pub fn record(&mut self, snapshot: &PollSnapshot) -> Result<()> {
+        let line = serde_json::to_string(snapshot).context("Failed to serialize poll")?;
+        writeln!(self.file, "{}", line).context("Failed to write poll")
+    }
+}
+
+/// Read a recording, skipping blank lines
+pub fn load(path: &Path) -> Result<Vec<PollSnapshot>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut snapshots = Vec::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.with_context(|| format!("Failed to read {}", path.display()))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let snapshot = serde_json::from_str(&line)
+            .with_context(|| format!("Invalid poll on line {}", number + 1))?;
+        snapshots.push(snapshot);
+    }
+    Ok(snapshots)
+}
+
+/// Run the polls through a media monitor with the config, returning the events of each
+///
+/// The monitor takes each poll's time as the current time, so thresholds play out as they did.
+pub fn replay(config: &Config, snapshots: &[PollSnapshot]) -> Vec<(DateTime<Utc>, MediaEvent)> {
+    let mut monitor = MediaMonitor::for_replay(config);
+    let mut events = Vec::new();
+    for snapshot in snapshots {
+        for event in monitor.replay_poll(snapshot, &config.app_filtering) {
+            events.push((snapshot.at, event));
+        }
+    }
+    events
+}
+
+/// One line describing an event, for `osx-scrobbler replay`
+pub fn describe_event(event: &MediaEvent) -> String {
+    let app =
+        |bundle_id: &Option<String>| bundle_id.as_deref().unwrap_or("unknown app").to_string();
+    match event {
+        MediaEvent::NowPlaying { track, bundle_id } => format!(
+            "now playing {} - {} ({})",
+            track.artist,
+            track.title,
+            app(bundle_id)
+        ),
+        MediaEvent::Scrobble {
+            track,
+            timestamp,
+            bundle_id,
+            review,
+        } => format!(
+            "scrobble {} - {} started at {} ({}){}",
+            track.artist,
+            track.title,
+            timestamp.format("%H:%M:%S"),
+            app(bundle_id),
+            if *review { ", held for review" } else { "" }
+        ),
+        MediaEvent::UnknownApp(bundle_id) => format!("unknown app {}", bundle_id),
+        MediaEvent::SessionEnded {
+            track,
+            listened_seconds,
+            ..
+        } => format!(
+            "ended {} - {} after {}s",
+            track.artist, track.title, listened_seconds
+        ),
+        MediaEvent::Stalled { track, .. } => {
+            format!("stalled {} - {}", track.artist, track.title)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use proptest::prelude::*;
+
+    fn first_poll() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 1, 1, 12, 0, 0).unwrap()
+    }
+
+    fn playing(position: f64) -> Option<PlayerInfo> {
+        Some(PlayerInfo {
+            is_playing: Some(true),
+            title: Some("Song".to_string()),
+            artist: Some("Band".to_string()),
+            album: Some("Album".to_string()),
+            elapsed_time: Some(position),
+            duration: Some(200.0),
+            info_update_time: None,
+            bundle_id: Some("com.apple.Music".to_string()),
+            bundle_name: Some("Music".to_string()),
+        })
+    }
+
+    /// A poll every 10 seconds for `seconds`, then nothing playing
+    fn recording(seconds: u32) -> Vec<PollSnapshot> {
+        let start = first_poll();
+        (0..=seconds)
+            .step_by(10)
+            .map(|second| PollSnapshot {
+                at: start + chrono::Duration::seconds(second as i64),
+                info: playing(second as f64),
+            })
+            .chain(std::iter::once(PollSnapshot {
+                at: start + chrono::Duration::seconds(seconds as i64 + 10),
+                info: None,
+            }))
+            .collect()
+    }
+
+    fn config() -> Config {
+        let mut config = Config::default();
+        config.app_filtering.allowed_apps = vec!["com.apple.Music".to_string()];
+        config
+    }
+
+    /// Longest time between polls of the simulated player
+    const MAX_POLL_INTERVAL: u32 = 20;
+
+    /// Something the user does with the player, right before a poll
+    #[derive(Debug, Clone)]
+    enum Action {
+        Wait,
+        Play,
+        Pause,
+        /// Seek to a fraction of the track
+        Seek(f64),
+        /// Start another track of the given length
+        Next(u64),
+        /// Quit the player, so nothing is reported until it plays again
+        Stop,
+    }
+
+    fn action() -> impl Strategy<Value = Action> {
+        prop_oneof![
+            4 => Just(Action::Wait),
+            2 => Just(Action::Play),
+            1 => Just(Action::Pause),
+            1 => (0.0..1.0).prop_map(Action::Seek),
+            1 => (10u64..400).prop_map(Action::Next),
+            1 => Just(Action::Stop),
+        ]
+    }
+
+    /// A play of a track by the simulated player, and how long it actually played
+    ///
+    /// A track the player picks up again after quitting, or goes back in, is played again.
+    struct Played {
+        title: String,
+        duration: u64,
+        /// Seconds from the first poll to the one the play started at
+        started: i64,
+        seconds: f64,
+    }
+
+    /// Polls of a player going through `steps`, each an action followed by `dt` seconds
+    ///
+    /// Actions take effect right before a poll, so each poll reports how the player was until
+    /// the next one.
+    fn simulate(first: u64, steps: &[(Action, u32)]) -> (Vec<PollSnapshot>, Vec<Played>) {
+        let start = first_poll();
+        let mut plays = vec![Played {
+            title: "Track 0".to_string(),
+            duration: first,
+            started: 0,
+            seconds: 0.0,
+        }];
+        let (mut position, mut playing, mut stopped) = (0.0, true, false);
+        // Position at the last poll that saw the track playing
+        let mut reported = None;
+        let mut now = 0;
+        let mut snapshots = Vec::new();
+
+        for (action, dt) in steps {
+            match *action {
+                Action::Wait => {}
+                Action::Play if stopped => {
+                    let last = plays.last().unwrap();
+                    plays.push(Played {
+                        title: last.title.clone(),
+                        duration: last.duration,
+                        started: now,
+                        seconds: 0.0,
+                    });
+                    (playing, stopped, reported) = (true, false, None);
+                }
+                Action::Play => playing = true,
+                Action::Pause => playing = false,
+                Action::Seek(fraction) => {
+                    position = (plays.last().unwrap().duration as f64 * fraction).floor()
+                }
+                Action::Next(duration) => {
+                    plays.push(Played {
+                        title: format!("Track {}", plays.len()),
+                        duration,
+                        started: now,
+                        seconds: 0.0,
+                    });
+                    (position, playing, stopped, reported) = (0.0, true, false, None);
+                }
+                Action::Stop => (playing, stopped) = (false, true),
+            }
+
+            // Having gone back since the last poll that saw it playing is playing it again
+            if playing && !stopped {
+                if reported.is_some_and(|reported| position < reported) {
+                    let last = plays.last().unwrap();
+                    plays.push(Played {
+                        title: last.title.clone(),
+                        duration: last.duration,
+                        started: now,
+                        seconds: 0.0,
+                    });
+                }
+                reported = Some(position);
+            }
+
+            let track = plays.last_mut().unwrap();
+            snapshots.push(PollSnapshot {
+                at: start + chrono::Duration::seconds(now),
+                info: (!stopped).then(|| PlayerInfo {
+                    is_playing: Some(playing),
+                    title: Some(track.title.clone()),
+                    artist: Some("Band".to_string()),
+                    album: None,
+                    elapsed_time: Some(position),
+                    duration: Some(track.duration as f64),
+                    info_update_time: None,
+                    bundle_id: Some("com.example.Player".to_string()),
+                    bundle_name: Some("Player".to_string()),
+                }),
+            });
+
+            now += *dt as i64;
+            let mut left = if playing { *dt as f64 } else { 0.0 };
+            while left > 0.0 {
+                let track = plays.last_mut().unwrap();
+                let played = left.min(track.duration as f64 - position);
+                track.seconds += played;
+                position += played;
+                left -= played;
+                if left > 0.0 {
+                    // Players go on to the next track at the end of one, which is first
+                    // reported at the next poll
+                    let duration = track.duration;
+                    plays.push(Played {
+                        title: format!("Track {}", plays.len()),
+                        duration,
+                        started: now,
+                        seconds: 0.0,
+                    });
+                    (position, reported) = (0.0, None);
+                }
+            }
+        }
+        snapshots.push(PollSnapshot {
+            at: start + chrono::Duration::seconds(now),
+            info: None,
+        });
+
+        (snapshots, plays)
+    }
+
+    proptest! {
+        #[test]
+        fn test_scrobbles_follow_the_rules(
+            first in 10u64..400,
+            steps in prop::collection::vec((action(), 1..=MAX_POLL_INTERVAL), 1..120),
+        ) {
+            let mut config = Config::default();
+            config.app_filtering.allowed_apps = vec!["com.example.Player".to_string()];
+            let start = first_poll();
+            let (snapshots, plays) = simulate(first, &steps);
+            let events = replay(&config, &snapshots);
+
+            let mut scrobbled = Vec::new();
+            let mut last_started = None;
+            for (at, event) in &events {
+                let MediaEvent::Scrobble { track, timestamp, .. } = event else {
+                    continue;
+                };
+                // The play the scrobble is of, the last of its track started by then
+                let (index, played) = plays
+                    .iter()
+                    .enumerate()
+                    .rfind(|(_, play)| {
+                        play.title == track.title
+                            && start + chrono::Duration::seconds(play.started) <= *timestamp
+                    })
+                    .unwrap();
+
+                prop_assert!(!scrobbled.contains(&index), "{} scrobbled twice", track.title);
+                scrobbled.push(index);
+                prop_assert!(played.duration >= 30, "{}s track scrobbled", played.duration);
+                // Listening is overcounted by at most the poll the track ended in, as the
+                // monitor can't see a track that started and was skipped between polls
+                let needed = (played.duration / 2).min(240) as f64;
+                prop_assert!(
+                    played.seconds + MAX_POLL_INTERVAL as f64 >= needed,
+                    "{} scrobbled after playing {}s of {}s",
+                    track.title,
+                    played.seconds,
+                    played.duration
+                );
+                // Timestamps are when the track started, in order
+                prop_assert!(timestamp <= at);
+                prop_assert!(last_started.is_none_or(|last| last <= *timestamp));
+                last_started = Some(*timestamp);
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let path =
+            std::env::temp_dir().join(format!("osx-scrobbler-polls-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let snapshots = recording(20);
+
+        let mut recorder = Recorder::create(&path).unwrap();
+        for snapshot in &snapshots {
+            recorder.record(snapshot).unwrap();
+        }
+        let loaded = load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, snapshots);
+    }
+
+    #[test]
+    fn test_replay_follows_the_recorded_time() {
+        // Half of the 200s track is played by the poll at 100s
+        let events = replay(&config(), &recording(120));
+        let scrobbles: Vec<_> = events
+            .iter()
+            .filter(|(_, event)| matches!(event, MediaEvent::Scrobble { .. }))
+            .collect();
+
+        assert_eq!(scrobbles.len(), 1);
+        assert_eq!(
+            scrobbles[0].0,
+            Utc.with_ymd_and_hms(2026, 1, 1, 12, 1, 40).unwrap()
+        );
+        // Played until the poll that saw it stopped
+        assert!(matches!(
+            events.last(),
+            Some((
+                _,
+                MediaEvent::SessionEnded {
+                    listened_seconds: 130,
+                    ..
+                }
+            ))
+        ));
+
+        // Stopping before the threshold isn't scrobbled
+        let events = replay(&config(), &recording(60));
+        assert!(!events
+            .iter()
+            .any(|(_, event)| matches!(event, MediaEvent::Scrobble { .. })));
+    }
+}
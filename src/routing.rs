@@ -0,0 +1,189 @@
+// Routing module
+// Sends plays to a subset of the services depending on the app and the time they're played at
+
+use crate::config::RouteConfig;
+use crate::history::service_matches;
+use anyhow::{Context, Result};
+use chrono::{Datelike, NaiveDateTime, NaiveTime, Weekday};
+
+/// Parsed routes, checked in order
+#[derive(Debug, Default)]
+pub struct Routes {
+    routes: Vec<Route>,
+}
+
+#[derive(Debug)]
+struct Route {
+    apps: Vec<String>,
+    /// Local time of day the route applies from and until; wraps past midnight if `from > until`
+    window: Option<(NaiveTime, NaiveTime)>,
+    days: Vec<Weekday>,
+    services: Vec<String>,
+}
+
+impl Routes {
+    pub fn new(routes: &[RouteConfig]) -> Result<Self> {
+        let routes = routes
+            .iter()
+            .enumerate()
+            .map(|(i, route)| {
+                Route::parse(route).with_context(|| format!("Invalid route {}", i + 1))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { routes })
+    }
+
+    /// Whether a play from `bundle_id` at local time `at` goes to the service with this key
+    ///
+    /// The first matching route decides; plays no route matches go to every service.
+    pub fn allows(&self, service: &str, bundle_id: Option<&str>, at: NaiveDateTime) -> bool {
+        match self
+            .routes
+            .iter()
+            .find(|route| route.matches(bundle_id, at))
+        {
+            Some(route) => route
+                .services
+                .iter()
+                .any(|filter| service_matches(service, filter)),
+            None => true,
+        }
+    }
+}
+
+impl Route {
+    fn parse(config: &RouteConfig) -> Result<Self> {
+        anyhow::ensure!(!config.services.is_empty(), "services can't be empty");
+
+        let window = match (&config.from, &config.until) {
+            (Some(from), Some(until)) => Some((parse_time(from)?, parse_time(until)?)),
+            (None, None) => None,
+            _ => anyhow::bail!("from and until must be set together"),
+        };
+        let days = config
+            .days
+            .iter()
+            .map(|day| {
+                day.parse::<Weekday>()
+                    .map_err(|_| anyhow::anyhow!("Unknown day '{}' (expected mon, tue, ...)", day))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            apps: config.apps.clone(),
+            window,
+            days,
+            services: config.services.clone(),
+        })
+    }
+
+    fn matches(&self, bundle_id: Option<&str>, at: NaiveDateTime) -> bool {
+        let app = self.apps.is_empty()
+            || bundle_id.is_some_and(|bundle_id| self.apps.iter().any(|app| app == bundle_id));
+        let day = self.days.is_empty() || self.days.contains(&at.weekday());
+        let time = self.window.is_none_or(|(from, until)| {
+            let time = at.time();
+            if from <= until {
+                from <= time && time < until
+            } else {
+                time >= from || time < until
+            }
+        });
+
+        app && day && time
+    }
+}
+
+fn parse_time(value: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(value, "%H:%M")
+        .with_context(|| format!("Invalid time '{}' (expected HH:MM)", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn route(apps: &[&str], hours: Option<(&str, &str)>, services: &[&str]) -> RouteConfig {
+        RouteConfig {
+            apps: apps.iter().map(|a| a.to_string()).collect(),
+            from: hours.map(|(from, _)| from.to_string()),
+            until: hours.map(|(_, until)| until.to_string()),
+            days: Vec::new(),
+            services: services.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    /// 2026-01-05 is a Monday
+    fn monday(hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 1, 5)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_work_hours_go_to_self_hosted() {
+        let mut work = route(&[], Some(("09:00", "17:00")), &["listenbrainz:Self-hosted"]);
+        work.days = ["mon", "tue", "wed", "thu", "fri"]
+            .iter()
+            .map(|d| d.to_string())
+            .collect();
+        let routes = Routes::new(&[work]).unwrap();
+
+        assert!(routes.allows("listenbrainz:Self-hosted", None, monday(10, 0)));
+        assert!(!routes.allows("lastfm", None, monday(10, 0)));
+        assert!(!routes.allows("lastfm", None, monday(16, 59)));
+        // Evenings and weekends go everywhere
+        assert!(routes.allows("lastfm", None, monday(17, 0)));
+        assert!(routes.allows("lastfm", None, monday(10, 0) + chrono::Duration::days(5)));
+    }
+
+    #[test]
+    fn test_apps_and_first_match_wins() {
+        let routes = Routes::new(&[
+            route(&["com.spotify.client"], None, &["lastfm"]),
+            route(&[], None, &["listenbrainz"]),
+        ])
+        .unwrap();
+
+        assert!(routes.allows("lastfm", Some("com.spotify.client"), monday(12, 0)));
+        assert!(!routes.allows(
+            "listenbrainz:Primary",
+            Some("com.spotify.client"),
+            monday(12, 0)
+        ));
+        assert!(routes.allows(
+            "listenbrainz:Primary",
+            Some("com.apple.Music"),
+            monday(12, 0)
+        ));
+        assert!(!routes.allows("lastfm", None, monday(12, 0)));
+    }
+
+    #[test]
+    fn test_window_past_midnight() {
+        let routes = Routes::new(&[route(&[], Some(("22:00", "06:00")), &["lastfm"])]).unwrap();
+
+        assert!(!routes.allows("listenbrainz:Primary", None, monday(23, 30)));
+        assert!(!routes.allows("listenbrainz:Primary", None, monday(5, 59)));
+        assert!(routes.allows("listenbrainz:Primary", None, monday(6, 0)));
+    }
+
+    #[test]
+    fn test_invalid_routes() {
+        let error = |route: RouteConfig| format!("{:#}", Routes::new(&[route]).unwrap_err());
+
+        assert!(error(route(&[], None, &[])).contains("services can't be empty"));
+        assert!(error(route(&[], Some(("9am", "17:00")), &["lastfm"])).contains("Invalid time"));
+
+        let mut half_window = route(&[], None, &["lastfm"]);
+        half_window.from = Some("09:00".to_string());
+        assert!(error(half_window).contains("set together"));
+
+        let mut bad_day = route(&[], None, &["lastfm"]);
+        bad_day.days = vec!["someday".to_string()];
+        assert!(error(bad_day).contains("Unknown day"));
+    }
+}
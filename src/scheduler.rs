@@ -0,0 +1,377 @@
+// Submission scheduler module
+// Batches non-urgent submissions (retries, backfills) into periodic flush windows
+
+use crate::config::{SchedulerConfig, TimestampPolicy};
+use crate::export::csv_field;
+use crate::history::HistoryEntry;
+use chrono::{DateTime, Duration, Local, TimeZone, Timelike, Utc};
+use std::collections::HashMap;
+
+const MINUTES_PER_DAY: i64 = 24 * 60;
+
+/// Last.fm refuses scrobbles of plays older than this
+pub const LASTFM_MAX_AGE_DAYS: i64 = 14;
+
+/// First line of the file expired submissions are exported to
+pub const EXPORT_HEADER: &str = "artist,title,album,played_at,service";
+
+/// A queued submission picked for the current flush window
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedSubmission {
+    pub entry_id: u64,
+    pub service: String,
+}
+
+/// Decides when queued submissions are flushed and how many go out at once
+pub struct SubmissionScheduler {
+    enabled: bool,
+    interval_minutes: i64,
+    max_per_flush: usize,
+    timestamps: TimestampPolicy,
+    next_flush: DateTime<Utc>,
+}
+
+impl SubmissionScheduler {
+    pub fn new(config: &SchedulerConfig) -> Self {
+        let interval_minutes = config.flush_interval_minutes as i64;
+        Self {
+            enabled: config.enabled,
+            interval_minutes,
+            max_per_flush: config.max_per_flush,
+            timestamps: config.timestamps,
+            next_flush: next_window(Local::now(), interval_minutes).with_timezone(&Utc),
+        }
+    }
+
+    pub fn timestamps(&self) -> TimestampPolicy {
+        self.timestamps
+    }
+
+    /// Check whether a flush window has opened
+    pub fn is_due(&self, now: DateTime<Utc>) -> bool {
+        self.enabled && now >= self.next_flush
+    }
+
+    /// Move on to the next flush window
+    pub fn advance(&mut self) {
+        self.next_flush = next_window(Local::now(), self.interval_minutes).with_timezone(&Utc);
+        log::debug!("Next submission flush at {}", self.next_flush);
+    }
+
+    /// Pick queued submissions for this window, oldest first, capped per service
+    ///
+    /// `services` pairs each service key with its remaining rate limit budget, if it has one.
+    pub fn plan(
+        &self,
+        entries: &[HistoryEntry],
+        services: &[(String, Option<usize>)],
+    ) -> Vec<PlannedSubmission> {
+        let caps: Vec<(String, usize)> = services
+            .iter()
+            .map(|(key, budget)| {
+                let cap = budget.map_or(self.max_per_flush, |b| b.min(self.max_per_flush));
+                (key.clone(), cap)
+            })
+            .collect();
+        plan(entries, &caps)
+    }
+}
+
+/// Start of the next flush window strictly after `now`
+///
+/// Windows are aligned to local midnight, so a 60 minute interval flushes at the top of every hour.
+pub fn next_window<Tz: TimeZone>(now: DateTime<Tz>, interval_minutes: i64) -> DateTime<Tz> {
+    let interval = interval_minutes.clamp(1, MINUTES_PER_DAY);
+    let minutes_since_midnight = (now.hour() * 60 + now.minute()) as i64;
+    let next = (minutes_since_midnight / interval + 1) * interval;
+
+    let offset = Duration::minutes(next.min(MINUTES_PER_DAY) - minutes_since_midnight)
+        - Duration::seconds(now.second() as i64)
+        - Duration::nanoseconds(now.nanosecond() as i64);
+
+    now + offset
+}
+
+/// Pick queued submissions, at most the given cap for each service
+///
+/// A submission is queued when the history has a not-yet-successful submission for that
+/// service. Entries from private sessions and deleted entries are never picked automatically.
+pub fn plan(entries: &[HistoryEntry], services: &[(String, usize)]) -> Vec<PlannedSubmission> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    let mut planned = Vec::new();
+
+    for entry in entries.iter().filter(|e| !e.private && !e.deleted) {
+        for submission in entry.submissions.iter().filter(|s| s.is_queued()) {
+            let Some((service, cap)) = services.iter().find(|(s, _)| *s == submission.service)
+            else {
+                continue;
+            };
+
+            let count = counts.entry(service.as_str()).or_insert(0);
+            if *count >= *cap {
+                continue;
+            }
+            *count += 1;
+
+            planned.push(PlannedSubmission {
+                entry_id: entry.id,
+                service: service.clone(),
+            });
+        }
+    }
+
+    planned
+}
+
+/// Queued submissions to the given (Last.fm) services of plays too old for Last.fm to accept
+///
+/// A minute of slack keeps plays right at the limit from being refused mid-flush.
+pub fn expired(
+    entries: &[HistoryEntry],
+    services: &[String],
+    now: DateTime<Utc>,
+) -> Vec<PlannedSubmission> {
+    let cutoff = now - Duration::days(LASTFM_MAX_AGE_DAYS) + Duration::minutes(1);
+
+    entries
+        .iter()
+        .filter(|e| !e.private && !e.deleted && e.played_at < cutoff)
+        .flat_map(|entry| {
+            entry
+                .submissions
+                .iter()
+                .filter(|s| s.is_queued() && services.contains(&s.service))
+                .map(|s| PlannedSubmission {
+                    entry_id: entry.id,
+                    service: s.service.clone(),
+                })
+        })
+        .collect()
+}
+
+/// Plays exported for being too old for Last.fm, and how they fared with the other services
+#[derive(Debug, Default)]
+pub struct ExpiredReport<'a> {
+    /// Oldest first
+    pub plays: Vec<&'a HistoryEntry>,
+    /// Plays that reached at least one other service (e.g. ListenBrainz)
+    pub submitted_elsewhere: usize,
+    /// Plays still queued for another service
+    pub queued_elsewhere: usize,
+}
+
+impl<'a> ExpiredReport<'a> {
+    pub fn of(entries: &'a [HistoryEntry]) -> Self {
+        let mut report = Self::default();
+        for entry in entries.iter().filter(|e| !e.deleted) {
+            if !entry.submissions.iter().any(|s| s.exported) {
+                continue;
+            }
+            let mut others = entry.submissions.iter().filter(|s| !s.exported);
+            if others.clone().any(|s| s.submitted) {
+                report.submitted_elsewhere += 1;
+            }
+            if others.any(|s| s.is_queued()) {
+                report.queued_elsewhere += 1;
+            }
+            report.plays.push(entry);
+        }
+        report.plays.sort_by_key(|e| e.played_at);
+        report
+    }
+}
+
+/// A line of the export file (see [`EXPORT_HEADER`]) for a play that couldn't be submitted
+pub fn export_row(entry: &HistoryEntry, service: &str) -> String {
+    [
+        entry.artist.as_str(),
+        entry.title.as_str(),
+        entry.album.as_deref().unwrap_or_default(),
+        &entry.played_at.to_rfc3339(),
+        service,
+    ]
+    .iter()
+    .map(|field| csv_field(field))
+    .collect::<Vec<_>>()
+    .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::Submission;
+
+    fn entry(id: u64, submissions: Vec<(&str, bool)>, private: bool) -> HistoryEntry {
+        HistoryEntry {
+            id,
+            artist: "Artist".to_string(),
+            title: "Song".to_string(),
+            duration: Some(200),
+            played_at: Utc.with_ymd_and_hms(2026, 1, 1, 12, 0, 0).unwrap(),
+            private,
+            submissions: submissions
+                .into_iter()
+                .map(|(service, submitted)| Submission {
+                    service: service.to_string(),
+                    submitted,
+                    error: None,
+                    deleted: false,
+                    exported: false,
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_expired_lastfm_submissions() {
+        let now = Utc.with_ymd_and_hms(2026, 1, 16, 12, 0, 0).unwrap();
+        let mut recent = entry(2, vec![("lastfm", false)], false);
+        recent.played_at = now - Duration::days(3);
+        let entries = vec![
+            entry(
+                1,
+                vec![("lastfm", false), ("listenbrainz:Primary", false)],
+                false,
+            ),
+            recent,
+            entry(3, vec![("lastfm", true)], false),
+            entry(4, vec![("lastfm", false)], true),
+        ];
+
+        assert_eq!(
+            expired(&entries, &["lastfm".to_string()], now),
+            vec![PlannedSubmission {
+                entry_id: 1,
+                service: "lastfm".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_expired_report() {
+        let mut exported = entry(
+            1,
+            vec![("lastfm", false), ("listenbrainz:Primary", true)],
+            false,
+        );
+        exported.submissions[0].exported = true;
+        let mut waiting = entry(
+            2,
+            vec![("lastfm", false), ("listenbrainz:Primary", false)],
+            false,
+        );
+        waiting.submissions[0].exported = true;
+        waiting.played_at -= Duration::days(1);
+        let mut deleted = exported.clone();
+        deleted.id = 3;
+        deleted.deleted = true;
+        let entries = vec![
+            exported,
+            waiting,
+            deleted,
+            entry(4, vec![("lastfm", false)], false),
+        ];
+
+        let report = ExpiredReport::of(&entries);
+        let ids: Vec<u64> = report.plays.iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec![2, 1]);
+        assert_eq!(report.submitted_elsewhere, 1);
+        assert_eq!(report.queued_elsewhere, 1);
+    }
+
+    #[test]
+    fn test_export_row_quotes_fields() {
+        let mut played = entry(1, Vec::new(), false);
+        played.artist = "Crosby, Stills & Nash".to_string();
+        played.title = "Song \"Live\"".to_string();
+
+        assert_eq!(
+            export_row(&played, "lastfm"),
+            "\"Crosby, Stills & Nash\",\"Song \"\"Live\"\"\",,2026-01-01T12:00:00+00:00,lastfm"
+        );
+    }
+
+    #[test]
+    fn test_next_window_top_of_hour() {
+        let now = Utc.with_ymd_and_hms(2026, 1, 1, 12, 34, 56).unwrap();
+        assert_eq!(
+            next_window(now, 60),
+            Utc.with_ymd_and_hms(2026, 1, 1, 13, 0, 0).unwrap()
+        );
+
+        // Exactly on a window boundary moves to the following one
+        let now = Utc.with_ymd_and_hms(2026, 1, 1, 13, 0, 0).unwrap();
+        assert_eq!(
+            next_window(now, 60),
+            Utc.with_ymd_and_hms(2026, 1, 1, 14, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_next_window_wraps_to_midnight() {
+        let now = Utc.with_ymd_and_hms(2026, 1, 1, 23, 50, 0).unwrap();
+        assert_eq!(
+            next_window(now, 15),
+            Utc.with_ymd_and_hms(2026, 1, 2, 0, 0, 0).unwrap()
+        );
+        assert_eq!(
+            next_window(now, 7 * 60),
+            Utc.with_ymd_and_hms(2026, 1, 2, 0, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_plan_caps_per_service() {
+        let entries = vec![
+            entry(1, vec![("lastfm", false), ("listenbrainz:A", false)], false),
+            entry(2, vec![("lastfm", false), ("listenbrainz:A", true)], false),
+            entry(3, vec![("lastfm", false)], false),
+        ];
+        let services = vec![("lastfm".to_string(), 2), ("listenbrainz:A".to_string(), 2)];
+
+        let planned = plan(&entries, &services);
+        assert_eq!(
+            planned,
+            vec![
+                PlannedSubmission {
+                    entry_id: 1,
+                    service: "lastfm".to_string()
+                },
+                PlannedSubmission {
+                    entry_id: 1,
+                    service: "listenbrainz:A".to_string()
+                },
+                PlannedSubmission {
+                    entry_id: 2,
+                    service: "lastfm".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scheduler_caps_by_rate_limit_budget() {
+        let entries: Vec<HistoryEntry> = (1..=5)
+            .map(|id| entry(id, vec![("lastfm", false)], false))
+            .collect();
+        let scheduler = SubmissionScheduler::new(&SchedulerConfig::default());
+
+        let planned = scheduler.plan(&entries, &[("lastfm".to_string(), Some(3))]);
+        assert_eq!(planned.len(), 3);
+
+        let planned = scheduler.plan(&entries, &[("lastfm".to_string(), None)]);
+        assert_eq!(planned.len(), 5);
+    }
+
+    #[test]
+    fn test_plan_skips_private_and_unknown_services() {
+        let entries = vec![
+            entry(1, vec![("lastfm", false)], true),
+            entry(2, vec![("listenbrainz:Gone", false)], false),
+        ];
+        let services = vec![("lastfm".to_string(), 10)];
+
+        assert!(plan(&entries, &services).is_empty());
+    }
+}
@@ -0,0 +1,217 @@
+// Last.fm backend
+
+use super::{Scrobbler, Track, LISTENS_PER_PAGE, MAX_LISTEN_PAGES};
+use crate::config::Config;
+use crate::http::{self, HttpClient};
+use crate::lastfm_api::LastFmClient;
+use crate::rate_limit::RateBudget;
+use crate::reconcile::RemoteListen;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use std::sync::Mutex;
+
+pub const BACKEND: &str = "lastfm";
+
+pub struct LastFm {
+    api: LastFmClient,
+    budget: Mutex<RateBudget>,
+    /// Host of the server, when it isn't Last.fm itself (e.g. libre.fm)
+    server: Option<String>,
+}
+
+impl LastFm {
+    pub fn new(http: HttpClient, api_key: &str, api_secret: &str, session_key: &str) -> Self {
+        Self {
+            api: LastFmClient::new(http, api_key, api_secret, session_key),
+            budget: Mutex::new(RateBudget::lastfm(api_key)),
+            server: None,
+        }
+    }
+
+    /// Talk to another server speaking the Last.fm protocol
+    pub fn with_api_url(mut self, api_url: &str) -> Self {
+        self.api = self.api.clone().with_api_url(api_url);
+        self.server = api_url
+            .split_once("://")
+            .and_then(|(_, rest)| rest.split('/').next())
+            .map(str::to_string);
+        self
+    }
+
+    /// Count the upcoming request, or fail with [`Throttled`](crate::rate_limit::Throttled) if
+    /// the request budget is nearly exhausted, so the submission is queued instead of waited for
+    fn throttle(&self) -> Result<()> {
+        let Ok(mut budget) = self.budget.lock() else {
+            return Ok(());
+        };
+
+        let now = Utc::now();
+        if let Err(throttled) = budget.try_record(now) {
+            log::warn!("Last.fm: {}", throttled);
+            return Err(throttled.into());
+        }
+
+        // Written outside the lock, so other requests don't wait on the file
+        let due = budget.save_due(now);
+        drop(budget);
+        if let Some(mut snapshot) = due {
+            if let Err(e) = snapshot.save() {
+                log::warn!("Failed to save rate limit state: {}", e);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for LastFm {
+    fn drop(&mut self) {
+        self.save_state();
+    }
+}
+
+/// Last.fm, if it's enabled and signed in
+pub fn from_config(config: &Config) -> Vec<Box<dyn Scrobbler>> {
+    let (Some(lastfm_config), _) = config.credentials() else {
+        return Vec::new();
+    };
+    if !lastfm_config.enabled {
+        return Vec::new();
+    }
+    if lastfm_config.session_key.is_empty() {
+        log::warn!("Last.fm is enabled but session_key is not set. Skipping Last.fm.");
+        return Vec::new();
+    }
+
+    log::info!("Last.fm scrobbler enabled");
+    let mut lastfm = LastFm::new(
+        http::shared(),
+        &lastfm_config.api_key,
+        &lastfm_config.api_secret,
+        &lastfm_config.session_key,
+    );
+    if lastfm_config.is_custom_server() {
+        log::info!(
+            "Scrobbling to {} with the Last.fm protocol",
+            lastfm_config.api_url
+        );
+        lastfm = lastfm.with_api_url(&lastfm_config.api_url);
+    }
+    vec![Box::new(lastfm)]
+}
+
+impl Scrobbler for LastFm {
+    fn backend(&self) -> &'static str {
+        BACKEND
+    }
+
+    fn key(&self) -> String {
+        BACKEND.to_string()
+    }
+
+    fn display_name(&self) -> String {
+        match &self.server {
+            Some(server) => format!("Last.fm ({})", server),
+            None => "Last.fm".to_string(),
+        }
+    }
+
+    fn remaining_budget(&self) -> Option<usize> {
+        self.budget.lock().ok().map(|mut b| b.remaining(Utc::now()))
+    }
+
+    fn save_state(&self) {
+        let Ok(mut budget) = self.budget.lock() else {
+            return;
+        };
+        if budget.is_unsaved() {
+            if let Err(e) = budget.save() {
+                log::warn!("Failed to save rate limit state: {}", e);
+            }
+        }
+    }
+
+    fn now_playing(&self, track: &Track, _app: Option<&str>) -> Result<()> {
+        self.throttle()?;
+        self.api
+            .now_playing(track)
+            .context("Failed to update now playing on Last.fm")?;
+        log::info!("Last.fm: Now playing updated");
+        Ok(())
+    }
+
+    fn scrobble(&self, track: &Track, timestamp: DateTime<Utc>, _app: Option<&str>) -> Result<()> {
+        self.throttle()?;
+        self.api
+            .scrobble(track, timestamp.timestamp())
+            .context("Failed to scrobble to Last.fm")?;
+        log::info!("Last.fm: Scrobbled successfully");
+        Ok(())
+    }
+
+    fn can_love(&self) -> bool {
+        true
+    }
+
+    fn love(&self, track: &Track) -> Result<()> {
+        self.throttle()?;
+        self.api
+            .love(&track.artist, &track.title)
+            .context("Failed to love track on Last.fm")?;
+        log::info!("Last.fm: Loved {} - {}", track.artist, track.title);
+        Ok(())
+    }
+
+    fn can_verify(&self) -> bool {
+        true
+    }
+
+    fn verify_session(&self) -> Result<()> {
+        self.throttle()?;
+        // Signed with the session key, so it fails once the session is revoked
+        let user_name = self
+            .api
+            .user_name()
+            .context("Failed to verify the Last.fm session")?;
+        log::info!("Last.fm: Session of {} is valid", user_name);
+        Ok(())
+    }
+
+    /// Last.fm has no API for deleting scrobbles, so this always fails
+    fn delete_scrobble(&self, _track: &Track, _timestamp: DateTime<Utc>) -> Result<()> {
+        anyhow::bail!(
+            "Last.fm has no API for deleting scrobbles; delete it from your library on last.fm"
+        )
+    }
+
+    fn recent_listens(&self, since: DateTime<Utc>) -> Result<Vec<RemoteListen>> {
+        self.throttle()?;
+        let user = self
+            .api
+            .user_name()
+            .context("Failed to look up the Last.fm user")?;
+
+        let mut listens = Vec::new();
+        for page in 1..=MAX_LISTEN_PAGES as u32 {
+            self.throttle()?;
+            let result = self
+                .api
+                .recent_tracks(&user, since.timestamp(), page, LISTENS_PER_PAGE)
+                .context("Failed to fetch scrobbles from Last.fm")?;
+
+            listens.extend(result.scrobbles.into_iter().filter_map(|scrobbled| {
+                Some(RemoteListen {
+                    played_at: DateTime::from_timestamp(scrobbled.timestamp, 0)?,
+                    artist: scrobbled.artist,
+                    title: scrobbled.title,
+                    album: scrobbled.album,
+                })
+            }));
+
+            if page >= result.total_pages {
+                break;
+            }
+        }
+
+        Ok(listens)
+    }
+}
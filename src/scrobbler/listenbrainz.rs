@@ -0,0 +1,238 @@
+// ListenBrainz backend, for listenbrainz.org and compatible servers
+
+use super::{Scrobbler, Track, LISTENS_PER_PAGE, MAX_LISTEN_PAGES};
+use crate::config::{Config, ListenBrainzConfig};
+use crate::http;
+use crate::listenbrainz_api::ListenBrainzClient;
+use crate::reconcile::RemoteListen;
+use anyhow::{Context, Result};
+use backoff::{retry, ExponentialBackoff};
+use chrono::{DateTime, Utc};
+use std::time::Duration;
+
+pub const BACKEND: &str = "listenbrainz";
+
+pub struct ListenBrainz {
+    name: String,
+    client: ListenBrainzClient,
+    /// User the token belongs to
+    user_name: String,
+}
+
+impl ListenBrainz {
+    /// Create a ListenBrainz service, checking the client's token
+    pub fn new(name: String, client: ListenBrainzClient) -> Result<Self> {
+        let user_name = client
+            .validate_token()
+            .with_context(|| format!("Failed to authenticate with ListenBrainz ({})", name))?;
+        log::info!("ListenBrainz ({}): Authenticated as {}", name, user_name);
+
+        Ok(Self {
+            name,
+            client,
+            user_name,
+        })
+    }
+}
+
+/// Every enabled ListenBrainz instance whose token checks out
+pub fn from_config(config: &Config) -> Vec<Box<dyn Scrobbler>> {
+    let (_, listenbrainz) = config.credentials();
+    listenbrainz
+        .iter()
+        .filter(|lb_config| lb_config.enabled)
+        .filter_map(|lb_config| {
+            log::info!("ListenBrainz scrobbler enabled: {}", lb_config.name);
+            connect(lb_config).map(|service| Box::new(service) as Box<dyn Scrobbler>)
+        })
+        .collect()
+}
+
+/// Set up the client for an instance and check its token, retrying while the server is down
+fn connect(lb_config: &ListenBrainzConfig) -> Option<ListenBrainz> {
+    let name = lb_config.name.clone();
+
+    // Instances with custom TLS settings get their own client
+    let http = if lb_config.ca_cert.is_some() || lb_config.client_cert.is_some() {
+        let client_identity = lb_config
+            .client_cert
+            .as_deref()
+            .zip(lb_config.client_key.as_deref());
+        match http::with_tls(lb_config.ca_cert.as_deref(), client_identity) {
+            Ok(client) => client,
+            Err(e) => {
+                log::error!("Failed to set up TLS for ListenBrainz ({}): {:#}", name, e);
+                return None;
+            }
+        }
+    } else {
+        http::shared()
+    };
+
+    let mut client = ListenBrainzClient::new(http, &lb_config.api_url, &lb_config.token)
+        .with_headers(lb_config.headers.clone());
+    if let Some(username) = &lb_config.username {
+        let password = lb_config.password.as_deref().unwrap_or_default();
+        client = client.with_basic_auth(username, password);
+    }
+
+    let backoff = ExponentialBackoff {
+        max_elapsed_time: Some(Duration::from_secs(30)),
+        ..Default::default()
+    };
+
+    let result = retry(backoff, || {
+        ListenBrainz::new(name.clone(), client.clone()).map_err(backoff::Error::transient)
+    });
+
+    match result {
+        Ok(service) => Some(service),
+        Err(e) => {
+            log::error!("Failed to initialize ListenBrainz after retries: {}", e);
+            None
+        }
+    }
+}
+
+impl Scrobbler for ListenBrainz {
+    fn backend(&self) -> &'static str {
+        BACKEND
+    }
+
+    fn key(&self) -> String {
+        format!("{}:{}", BACKEND, self.name)
+    }
+
+    fn display_name(&self) -> String {
+        format!("ListenBrainz ({})", self.name)
+    }
+
+    fn now_playing(&self, track: &Track, _app: Option<&str>) -> Result<()> {
+        self.client.playing_now(track).with_context(|| {
+            format!(
+                "Failed to update now playing on ListenBrainz ({})",
+                self.name
+            )
+        })?;
+        log::info!("ListenBrainz ({}): Now playing updated", self.name);
+        Ok(())
+    }
+
+    fn scrobble(&self, track: &Track, timestamp: DateTime<Utc>, _app: Option<&str>) -> Result<()> {
+        self.client
+            .listen(track, timestamp.timestamp())
+            .with_context(|| format!("Failed to scrobble to ListenBrainz ({})", self.name))?;
+        log::info!("ListenBrainz ({}): Scrobbled successfully", self.name);
+        Ok(())
+    }
+
+    fn can_love(&self) -> bool {
+        true
+    }
+
+    fn love(&self, track: &Track) -> Result<()> {
+        let name = &self.name;
+        // Feedback is given on MusicBrainz recordings, so find the track's first unless known
+        let recording_mbid = match &track.mbid {
+            Some(mbid) => mbid.clone(),
+            None => self
+                .client
+                .lookup_recording(&track.artist, &track.title, track.album.as_deref())
+                .with_context(|| format!("Failed to look up track on ListenBrainz ({})", name))?
+                .with_context(|| {
+                    format!(
+                        "Track not found in MusicBrainz, can't love it on ListenBrainz ({})",
+                        name
+                    )
+                })?,
+        };
+        self.client
+            .recording_feedback(&recording_mbid, 1)
+            .with_context(|| format!("Failed to love track on ListenBrainz ({})", name))?;
+        log::info!(
+            "ListenBrainz ({}): Loved {} - {}",
+            name,
+            track.artist,
+            track.title
+        );
+        Ok(())
+    }
+
+    fn can_verify(&self) -> bool {
+        true
+    }
+
+    fn verify_session(&self) -> Result<()> {
+        self.client
+            .validate_token()
+            .with_context(|| format!("Failed to verify the ListenBrainz token ({})", self.name))?;
+        log::info!("ListenBrainz ({}): Token is valid", self.name);
+        Ok(())
+    }
+
+    fn delete_scrobble(&self, track: &Track, timestamp: DateTime<Utc>) -> Result<()> {
+        let name = &self.name;
+        let listened_at = timestamp.timestamp();
+        // The listen's MessyBrainz ID is only known to the server, so look it up
+        let listens = self
+            .client
+            .listens(&self.user_name, Some(listened_at + 1), LISTENS_PER_PAGE)
+            .with_context(|| format!("Failed to fetch listens from ListenBrainz ({})", name))?;
+        let recording_msid = listens
+            .iter()
+            .filter(|l| l.listened_at == listened_at)
+            .find(|l| {
+                l.track_metadata
+                    .track_name
+                    .eq_ignore_ascii_case(&track.title)
+                    && l.track_metadata
+                        .artist_name
+                        .eq_ignore_ascii_case(&track.artist)
+            })
+            .and_then(|l| l.recording_msid.clone())
+            .with_context(|| format!("Listen not found on ListenBrainz ({})", name))?;
+
+        self.client
+            .delete_listen(listened_at, &recording_msid)
+            .with_context(|| format!("Failed to delete listen on ListenBrainz ({})", name))?;
+        log::info!("ListenBrainz ({}): Listen deleted", name);
+        Ok(())
+    }
+
+    fn recent_listens(&self, since: DateTime<Utc>) -> Result<Vec<RemoteListen>> {
+        let mut listens = Vec::new();
+        let mut max_ts = None;
+
+        for _ in 0..MAX_LISTEN_PAGES {
+            let page = self
+                .client
+                .listens(&self.user_name, max_ts, LISTENS_PER_PAGE)
+                .with_context(|| {
+                    format!("Failed to fetch listens from ListenBrainz ({})", self.name)
+                })?;
+            let full_page = page.len() == LISTENS_PER_PAGE;
+
+            for listen in page {
+                max_ts = Some(listen.listened_at);
+                let Some(played_at) = DateTime::from_timestamp(listen.listened_at, 0) else {
+                    continue;
+                };
+                if played_at < since {
+                    return Ok(listens);
+                }
+                listens.push(RemoteListen {
+                    artist: listen.track_metadata.artist_name,
+                    title: listen.track_metadata.track_name,
+                    album: listen.track_metadata.release_name,
+                    played_at,
+                });
+            }
+
+            if !full_page {
+                break;
+            }
+        }
+
+        Ok(listens)
+    }
+}
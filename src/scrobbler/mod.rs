@@ -0,0 +1,424 @@
+// Scrobbler module
+// The interface every scrobbling backend implements, and the registry they're set up from
+
+pub mod lastfm;
+pub mod listenbrainz;
+pub mod scrobbler_log;
+pub mod webhook;
+
+use crate::config::Config;
+use crate::reconcile::RemoteListen;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Listens fetched per request when reading a service's history
+const LISTENS_PER_PAGE: usize = 100;
+/// Upper bound on pages fetched in one go
+const MAX_LISTEN_PAGES: usize = 20;
+
+/// Last.fm authentication helper
+///
+/// Works with any server speaking the Last.fm protocol, e.g. Libre.fm, given its URLs.
+pub mod lastfm_auth {
+    use crate::config::LastFmConfig;
+    use crate::http;
+    use crate::lastfm_api::{check_response, sign};
+    use anyhow::{Context, Result};
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize)]
+    struct LastFmResponse {
+        token: Option<String>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct SessionResponse {
+        session: Session,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Session {
+        key: String,
+    }
+
+    /// Get an authentication token from Last.fm
+    fn get_token(api_url: &str, api_key: &str, api_secret: &str) -> Result<String> {
+        // Create API signature for getToken request
+        let signature = sign(
+            &[
+                ("api_key", api_key.to_string()),
+                ("method", "auth.gettoken".to_string()),
+            ],
+            api_secret,
+        );
+
+        let response = http::send_form(
+            http::shared().post(api_url),
+            &[
+                ("method", "auth.gettoken"),
+                ("api_key", api_key),
+                ("api_sig", &signature),
+                ("format", "json"),
+            ],
+        )
+        .context("Failed to get token from Last.fm")?;
+
+        if response.status() != 200 {
+            anyhow::bail!("Last.fm API error: {}", response.status());
+        }
+
+        let data: LastFmResponse = response.into_json()?;
+        data.token
+            .ok_or_else(|| anyhow::anyhow!("No token in Last.fm response"))
+    }
+
+    /// Exchange a token the user authorized for a session key
+    fn get_session(api_url: &str, api_key: &str, api_secret: &str, token: &str) -> Result<String> {
+        let signature = sign(
+            &[
+                ("api_key", api_key.to_string()),
+                ("method", "auth.getSession".to_string()),
+                ("token", token.to_string()),
+            ],
+            api_secret,
+        );
+
+        let response = http::send_form(
+            http::shared().post(api_url),
+            &[
+                ("method", "auth.getSession"),
+                ("api_key", api_key),
+                ("token", token),
+                ("api_sig", &signature),
+                ("format", "json"),
+            ],
+        )
+        .context("Failed to get session from Last.fm")?;
+        let status = response.status();
+        let body = response
+            .into_string()
+            .context("Failed to read Last.fm response")?;
+        check_response(status, &body)?;
+
+        let data: SessionResponse =
+            serde_json::from_str(&body).context("No session in Last.fm response")?;
+        Ok(data.session.key)
+    }
+
+    /// Perform the complete Last.fm authentication flow using token-based auth
+    /// Returns the session key on success
+    pub fn authenticate(config: &LastFmConfig) -> Result<String> {
+        let LastFmConfig {
+            api_key,
+            api_secret,
+            api_url,
+            ..
+        } = config;
+        println!("Starting Last.fm authentication...\n");
+        if config.is_custom_server() {
+            println!("Server: {}\n", api_url);
+        }
+
+        // Step 1: Get authentication token
+        println!("Getting authorization token...");
+        let token = get_token(api_url, api_key, api_secret)?;
+        println!("Token obtained: {}\n", token);
+
+        // Step 2: Direct user to authorize
+        let auth_url = format!("{}?api_key={}&token={}", config.auth_url(), api_key, token);
+        println!("Please authorize this application:");
+        println!("  {}\n", auth_url);
+        println!("Opening authorization URL in your browser...");
+
+        let _ = std::process::Command::new("open").arg(&auth_url).spawn();
+
+        println!("\nAfter authorizing, press Enter to continue...");
+
+        // Wait for user to press Enter
+        let mut input = String::new();
+        std::io::stdin().read_line(&mut input)?;
+
+        // Step 3: Exchange token for session key
+        println!("\nExchanging token for session key...");
+        let session_key = get_session(api_url, api_key, api_secret, &token)?;
+        println!("Session key obtained successfully!\n");
+
+        Ok(session_key)
+    }
+}
+
+/// Represents a music track
+///
+/// More fields may be added, so other crates build one with [`Track::new`] and set the rest.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Track {
+    pub title: String,
+    pub artist: String,
+    pub album: Option<String>,
+    pub duration: Option<u64>,
+    /// Page the track was played on, for plays in a browser
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin_url: Option<String>,
+    /// Picked by a radio station or autoplay rather than the user (Last.fm's `chosenByUser=0`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub radio: bool,
+    /// MusicBrainz recording ID, when known (plugins can set it)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mbid: Option<String>,
+    /// Album artist, when it differs from the track artist (e.g. "Various Artists" on a
+    /// compilation)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album_artist: Option<String>,
+    /// Live recording details moved out of the title (`Live at Wembley`), see `[cleanup] live`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub live: Option<String>,
+}
+
+impl Track {
+    /// A track with just an artist and title
+    pub fn new(artist: impl Into<String>, title: impl Into<String>) -> Self {
+        Self {
+            artist: artist.into(),
+            title: title.into(),
+            ..Self::default()
+        }
+    }
+}
+
+/// A scrobbling service
+///
+/// Backends implement this and register in [`BACKENDS`] to be set up from the config.
+pub trait Scrobbler {
+    /// Name of the backend in [`BACKENDS`], e.g. `lastfm`
+    fn backend(&self) -> &'static str;
+
+    /// Stable key identifying this service in history and CLI filters
+    fn key(&self) -> String;
+
+    /// Name shown to the user
+    fn display_name(&self) -> String;
+
+    /// Requests left before this service starts throttling (None if not rate limited)
+    fn remaining_budget(&self) -> Option<usize> {
+        None
+    }
+
+    /// Write out state kept in memory between saves (e.g. the rate limit budget), before the app
+    /// exits
+    fn save_state(&self) {}
+
+    /// Submit a "now playing" update for a track playing in `app` (a bundle ID, when known)
+    fn now_playing(&self, track: &Track, app: Option<&str>) -> Result<()>;
+
+    /// Scrobble a track played in `app`
+    fn scrobble(&self, track: &Track, timestamp: DateTime<Utc>, app: Option<&str>) -> Result<()>;
+
+    /// Whether the service can mark tracks as loved
+    fn can_love(&self) -> bool {
+        false
+    }
+
+    /// Mark a track as loved
+    fn love(&self, _track: &Track) -> Result<()> {
+        anyhow::bail!("{} can't love tracks", self.display_name())
+    }
+
+    /// Whether the service has credentials to verify
+    fn can_verify(&self) -> bool {
+        false
+    }
+
+    /// Check that the service still accepts the credentials, with a cheap authenticated request
+    fn verify_session(&self) -> Result<()> {
+        anyhow::bail!("{} has no credentials to verify", self.display_name())
+    }
+
+    /// Remove a scrobble from the service
+    fn delete_scrobble(&self, _track: &Track, _timestamp: DateTime<Utc>) -> Result<()> {
+        anyhow::bail!("{} can't delete scrobbles", self.display_name())
+    }
+
+    /// Fetch listens stored on the service since the given time, newest first
+    fn recent_listens(&self, _since: DateTime<Utc>) -> Result<Vec<RemoteListen>> {
+        anyhow::bail!("{} can't list scrobbles", self.display_name())
+    }
+}
+
+/// A kind of scrobbling service
+pub struct Backend {
+    pub name: &'static str,
+    /// Set up the services of this kind enabled in the config, with the active credentials
+    pub build: fn(&Config) -> Vec<Box<dyn Scrobbler>>,
+}
+
+/// Every backend services can be set up from
+pub const BACKENDS: &[Backend] = &[
+    Backend {
+        name: lastfm::BACKEND,
+        build: lastfm::from_config,
+    },
+    Backend {
+        name: listenbrainz::BACKEND,
+        build: listenbrainz::from_config,
+    },
+    Backend {
+        name: webhook::BACKEND,
+        build: webhook::from_config,
+    },
+    Backend {
+        name: scrobbler_log::BACKEND,
+        build: scrobbler_log::from_config,
+    },
+];
+
+/// Set up every enabled service, marked with the active profile if there is one
+pub fn build_all(config: &Config) -> Vec<Box<dyn Scrobbler>> {
+    let scrobblers = BACKENDS.iter().flat_map(|backend| (backend.build)(config));
+    match &config.active_profile {
+        Some(profile) => scrobblers
+            .map(|inner| {
+                Box::new(Profiled {
+                    inner,
+                    profile: profile.clone(),
+                }) as Box<dyn Scrobbler>
+            })
+            .collect(),
+        None => scrobblers.collect(),
+    }
+}
+
+/// A service belonging to a credential profile
+///
+/// Its key is `profile/service`, so its history and queues stay apart from other profiles'.
+struct Profiled {
+    inner: Box<dyn Scrobbler>,
+    profile: String,
+}
+
+impl Scrobbler for Profiled {
+    fn backend(&self) -> &'static str {
+        self.inner.backend()
+    }
+
+    fn key(&self) -> String {
+        format!("{}/{}", self.profile, self.inner.key())
+    }
+
+    fn display_name(&self) -> String {
+        format!("{} [{}]", self.inner.display_name(), self.profile)
+    }
+
+    fn remaining_budget(&self) -> Option<usize> {
+        self.inner.remaining_budget()
+    }
+
+    fn save_state(&self) {
+        self.inner.save_state()
+    }
+
+    fn now_playing(&self, track: &Track, app: Option<&str>) -> Result<()> {
+        self.inner.now_playing(track, app)
+    }
+
+    fn scrobble(&self, track: &Track, timestamp: DateTime<Utc>, app: Option<&str>) -> Result<()> {
+        self.inner.scrobble(track, timestamp, app)
+    }
+
+    fn can_love(&self) -> bool {
+        self.inner.can_love()
+    }
+
+    fn love(&self, track: &Track) -> Result<()> {
+        self.inner.love(track)
+    }
+
+    fn can_verify(&self) -> bool {
+        self.inner.can_verify()
+    }
+
+    fn verify_session(&self) -> Result<()> {
+        self.inner.verify_session()
+    }
+
+    fn delete_scrobble(&self, track: &Track, timestamp: DateTime<Utc>) -> Result<()> {
+        self.inner.delete_scrobble(track, timestamp)
+    }
+
+    fn recent_listens(&self, since: DateTime<Utc>) -> Result<Vec<RemoteListen>> {
+        self.inner.recent_listens(since)
+    }
+}
+
+/// Whether an error means the service rejected its credentials, e.g. a revoked session key
+pub fn is_auth_error(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        let message = cause.to_string();
+        // Last.fm error 9, or a ListenBrainz 401 or token it no longer knows
+        message.contains("Invalid session key")
+            || message.contains("API error: 401")
+            || message.contains("ListenBrainz token is invalid")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Dummy;
+
+    impl Scrobbler for Dummy {
+        fn backend(&self) -> &'static str {
+            "dummy"
+        }
+
+        fn key(&self) -> String {
+            "dummy:Test".to_string()
+        }
+
+        fn display_name(&self) -> String {
+            "Dummy (Test)".to_string()
+        }
+
+        fn now_playing(&self, _track: &Track, _app: Option<&str>) -> Result<()> {
+            Ok(())
+        }
+
+        fn scrobble(
+            &self,
+            _track: &Track,
+            _timestamp: DateTime<Utc>,
+            _app: Option<&str>,
+        ) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_profiled() {
+        let profiled = Profiled {
+            inner: Box::new(Dummy),
+            profile: "work".to_string(),
+        };
+        assert_eq!(profiled.backend(), "dummy");
+        assert_eq!(profiled.key(), "work/dummy:Test");
+        assert_eq!(profiled.display_name(), "Dummy (Test) [work]");
+        assert_eq!(profiled.remaining_budget(), None);
+        let track = Track {
+            title: "Song".to_string(),
+            artist: "Band".to_string(),
+            duration: Some(200),
+            ..Default::default()
+        };
+        assert!(profiled.delete_scrobble(&track, Utc::now()).is_err());
+    }
+
+    #[test]
+    fn test_backend_names_are_unique() {
+        let mut names: Vec<_> = BACKENDS.iter().map(|b| b.name).collect();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), BACKENDS.len());
+    }
+}
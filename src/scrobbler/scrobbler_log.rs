@@ -0,0 +1,168 @@
+// Scrobbler log backend
+// Appends scrobbles to a Rockbox-style .scrobbler.log file, for a portable archive
+
+use super::{Scrobbler, Track};
+use crate::build_info::VERSION;
+use crate::config::Config;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+
+pub const BACKEND: &str = "scrobbler_log";
+
+/// Rating written for every entry: the track was listened to, not skipped
+const RATING_LISTENED: &str = "L";
+
+pub struct ScrobblerLog {
+    path: PathBuf,
+}
+
+impl ScrobblerLog {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+/// The log file, if it's enabled
+pub fn from_config(config: &Config) -> Vec<Box<dyn Scrobbler>> {
+    config
+        .scrobbler_log
+        .iter()
+        .filter(|log| log.enabled)
+        .map(|log| {
+            let path = expand_home(&log.path);
+            log::info!("Scrobbler log enabled: {}", path.display());
+            Box::new(ScrobblerLog::new(path)) as Box<dyn Scrobbler>
+        })
+        .collect()
+}
+
+impl Scrobbler for ScrobblerLog {
+    fn backend(&self) -> &'static str {
+        BACKEND
+    }
+
+    fn key(&self) -> String {
+        BACKEND.to_string()
+    }
+
+    fn display_name(&self) -> String {
+        "Scrobbler log".to_string()
+    }
+
+    /// The log only records finished plays
+    fn now_playing(&self, _track: &Track, _app: Option<&str>) -> Result<()> {
+        Ok(())
+    }
+
+    fn scrobble(&self, track: &Track, timestamp: DateTime<Utc>, _app: Option<&str>) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).with_context(|| {
+                format!("Failed to create directory for {}", self.path.display())
+            })?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+
+        let mut text = String::new();
+        if file.metadata().map(|meta| meta.len() == 0).unwrap_or(true) {
+            text.push_str(&header());
+        }
+        text.push_str(&entry(track, timestamp));
+        file.write_all(text.as_bytes())
+            .with_context(|| format!("Failed to write to {}", self.path.display()))
+    }
+
+    /// The format has no loves
+    fn love(&self, _track: &Track) -> Result<()> {
+        Ok(())
+    }
+
+    /// Entries are never rewritten, the log is append-only
+    fn delete_scrobble(&self, _track: &Track, _timestamp: DateTime<Utc>) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Lines starting a new log file
+fn header() -> String {
+    format!(
+        "#AUDIOSCROBBLER/1.1\n#TZ/UTC\n#CLIENT/osx-scrobbler {}\n",
+        VERSION
+    )
+}
+
+/// One tab-separated line: artist, album, title, track number, duration, rating, timestamp, MBID
+fn entry(track: &Track, timestamp: DateTime<Utc>) -> String {
+    let fields = [
+        field(&track.artist),
+        field(track.album.as_deref().unwrap_or_default()),
+        field(&track.title),
+        String::new(),
+        track.duration.map(|d| d.to_string()).unwrap_or_default(),
+        RATING_LISTENED.to_string(),
+        timestamp.timestamp().to_string(),
+        field(track.mbid.as_deref().unwrap_or_default()),
+    ];
+    format!("{}\n", fields.join("\t"))
+}
+
+/// Text with the separators the format can't hold replaced by spaces
+fn field(value: &str) -> String {
+    value.replace(['\t', '\n', '\r'], " ")
+}
+
+/// Resolve a leading `~/` to the home directory
+fn expand_home(path: &std::path::Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track() -> Track {
+        Track {
+            title: "Song\tTitle".to_string(),
+            artist: "Band".to_string(),
+            duration: Some(215),
+            mbid: Some("0d3f2d8e-1c4a-4b5e-9c1d-2e3f4a5b6c7d".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_entry() {
+        let played_at = DateTime::from_timestamp(1767225600, 0).unwrap();
+        assert_eq!(
+            entry(&track(), played_at),
+            "Band\t\tSong Title\t\t215\tL\t1767225600\t0d3f2d8e-1c4a-4b5e-9c1d-2e3f4a5b6c7d\n"
+        );
+    }
+
+    #[test]
+    fn test_scrobble_writes_header_once() {
+        let dir = std::env::temp_dir().join(format!(
+            "osx_scrobbler_scrobbler_log_test_{}",
+            std::process::id()
+        ));
+        let path = dir.join("logs").join(".scrobbler.log");
+        let log = ScrobblerLog::new(path.clone());
+        let played_at = DateTime::from_timestamp(1767225600, 0).unwrap();
+        log.scrobble(&track(), played_at, None).unwrap();
+        log.scrobble(&track(), played_at, None).unwrap();
+
+        let text = std::fs::read_to_string(path).unwrap();
+        assert_eq!(text.matches("#AUDIOSCROBBLER/1.1").count(), 1);
+        assert_eq!(text.lines().count(), 5);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,240 @@
+// Webhook backend
+// Posts now playing updates and scrobbles to a URL, with a JSON body filled in from a template
+
+use super::{Scrobbler, Track};
+use crate::config::{Config, WebhookConfig, WebhookEvent};
+use crate::http::{self, HttpClient};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+pub const BACKEND: &str = "webhook";
+
+/// Body posted when a webhook doesn't set its own
+pub const DEFAULT_BODY: &str = r#"{"event": "{{event}}", "artist": "{{artist}}", "title": "{{title}}", "album": "{{album}}", "duration": {{duration}}, "timestamp": {{timestamp}}, "bundle_id": "{{bundle_id}}"}"#;
+
+pub struct Webhook {
+    http: HttpClient,
+    config: WebhookConfig,
+}
+
+impl Webhook {
+    pub fn new(http: HttpClient, config: WebhookConfig) -> Self {
+        Self { http, config }
+    }
+
+    fn post(
+        &self,
+        event: WebhookEvent,
+        track: &Track,
+        timestamp: Option<DateTime<Utc>>,
+        app: Option<&str>,
+    ) -> Result<()> {
+        if !self.config.events.contains(&event) {
+            return Ok(());
+        }
+
+        let body = render(&self.config.body, event, track, timestamp, app)?;
+        let mut request = self.http.post(&self.config.url);
+        for (name, value) in &self.config.headers {
+            request = request.set(name, value);
+        }
+
+        let response = http::send(request, Some(&body))
+            .with_context(|| format!("Failed to reach webhook {}", self.config.name))?;
+        let status = response.status();
+        if !(200..300).contains(&status) {
+            anyhow::bail!("Webhook {} answered with HTTP {}", self.config.name, status);
+        }
+        Ok(())
+    }
+}
+
+/// Every enabled webhook
+pub fn from_config(config: &Config) -> Vec<Box<dyn Scrobbler>> {
+    config
+        .webhook
+        .iter()
+        .filter(|webhook| webhook.enabled)
+        .map(|webhook| {
+            log::info!("Webhook enabled: {}", webhook.name);
+            Box::new(Webhook::new(http::shared(), webhook.clone())) as Box<dyn Scrobbler>
+        })
+        .collect()
+}
+
+impl Scrobbler for Webhook {
+    fn backend(&self) -> &'static str {
+        BACKEND
+    }
+
+    fn key(&self) -> String {
+        format!("{}:{}", BACKEND, self.config.name)
+    }
+
+    fn display_name(&self) -> String {
+        format!("Webhook ({})", self.config.name)
+    }
+
+    fn now_playing(&self, track: &Track, app: Option<&str>) -> Result<()> {
+        self.post(WebhookEvent::NowPlaying, track, None, app)
+    }
+
+    fn scrobble(&self, track: &Track, timestamp: DateTime<Utc>, app: Option<&str>) -> Result<()> {
+        self.post(WebhookEvent::Scrobble, track, Some(timestamp), app)
+    }
+
+    /// Loves aren't posted, so loving a track everywhere doesn't fail on webhooks
+    fn love(&self, _track: &Track) -> Result<()> {
+        Ok(())
+    }
+
+    /// There's nothing to take back from a webhook
+    fn delete_scrobble(&self, _track: &Track, _timestamp: DateTime<Utc>) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Check that a body template only uses known placeholders and fills in to valid JSON
+pub fn check_body(template: &str) -> Result<()> {
+    let track = Track {
+        title: "Title \"quoted\"".to_string(),
+        artist: "Artist".to_string(),
+        album: None,
+        duration: None,
+        origin_url: None,
+        radio: false,
+        mbid: None,
+        album_artist: None,
+        live: None,
+    };
+    render(
+        template,
+        WebhookEvent::Scrobble,
+        &track,
+        Some(Utc::now()),
+        None,
+    )?;
+    Ok(())
+}
+
+/// Fill in a body template
+///
+/// Text placeholders are replaced with JSON-escaped text, to go between quotes, and are empty
+/// when unknown. Number placeholders are replaced with a number, or `null` when unknown.
+fn render(
+    template: &str,
+    event: WebhookEvent,
+    track: &Track,
+    timestamp: Option<DateTime<Utc>>,
+    app: Option<&str>,
+) -> Result<Value> {
+    let mut body = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        body.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find("}}")
+            .context("Unclosed {{ placeholder")?;
+        let name = rest[start + 2..start + end].trim();
+        let value = match name {
+            "event" => text(match event {
+                WebhookEvent::NowPlaying => "now_playing",
+                WebhookEvent::Scrobble => "scrobble",
+            }),
+            "artist" => text(&track.artist),
+            "title" => text(&track.title),
+            "album" => text(track.album.as_deref().unwrap_or_default()),
+            "album_artist" => text(track.album_artist.as_deref().unwrap_or_default()),
+            "live" => text(track.live.as_deref().unwrap_or_default()),
+            "bundle_id" => text(app.unwrap_or_default()),
+            "mbid" => text(track.mbid.as_deref().unwrap_or_default()),
+            "url" => text(track.origin_url.as_deref().unwrap_or_default()),
+            "played_at" => text(&timestamp.map(|t| t.to_rfc3339()).unwrap_or_default()),
+            "duration" => number(track.duration.map(|d| d as i64)),
+            "timestamp" => number(timestamp.map(|t| t.timestamp())),
+            _ => anyhow::bail!("Unknown placeholder {{{{{}}}}}", name),
+        };
+        body.push_str(&value);
+        rest = &rest[start + end + 2..];
+    }
+    body.push_str(rest);
+
+    serde_json::from_str(&body).context("The body isn't valid JSON once filled in")
+}
+
+fn text(value: &str) -> String {
+    let quoted = Value::String(value.to_string()).to_string();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+fn number(value: Option<i64>) -> String {
+    value.map_or_else(|| "null".to_string(), |n| n.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn track() -> Track {
+        Track {
+            title: "Say \"Hi\"".to_string(),
+            artist: "Band".to_string(),
+            album: Some("Album".to_string()),
+            duration: Some(200),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_render_default_body() {
+        let played_at = DateTime::from_timestamp(1767225600, 0).unwrap();
+        let body = render(
+            DEFAULT_BODY,
+            WebhookEvent::Scrobble,
+            &track(),
+            Some(played_at),
+            Some("com.apple.Music"),
+        )
+        .unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "event": "scrobble",
+                "artist": "Band",
+                "title": "Say \"Hi\"",
+                "album": "Album",
+                "duration": 200,
+                "timestamp": 1767225600,
+                "bundle_id": "com.apple.Music",
+            })
+        );
+
+        let body = render(DEFAULT_BODY, WebhookEvent::NowPlaying, &track(), None, None).unwrap();
+        assert_eq!(body["event"], "now_playing");
+        assert_eq!(body["timestamp"], Value::Null);
+        assert_eq!(body["bundle_id"], "");
+    }
+
+    #[test]
+    fn test_render_custom_body() {
+        let body = render(
+            r#"{"text": "{{ artist }} - {{title}}"}"#,
+            WebhookEvent::NowPlaying,
+            &track(),
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(body, json!({ "text": "Band - Say \"Hi\"" }));
+    }
+
+    #[test]
+    fn test_check_body() {
+        assert!(check_body(DEFAULT_BODY).is_ok());
+        assert!(check_body(r#"{"who": "{{singer}}"}"#).is_err());
+        assert!(check_body(r#"{"artist": "{{artist"}"#).is_err());
+        assert!(check_body(r#"{"artist": {{artist}}}"#).is_err());
+    }
+}
@@ -0,0 +1,234 @@
+// Search module
+// Finds scrobbles in the local history by artist, title or album, forgiving small typos
+
+use crate::history::HistoryEntry;
+use crate::timeline::PlayStatus;
+use chrono::{DateTime, Utc};
+
+/// Narrows down which entries a search looks at
+#[derive(Debug, Clone, Default)]
+pub struct SearchFilter {
+    /// Bundle ID of the app the track was played in
+    pub app: Option<String>,
+    /// Artist, ignoring case
+    pub artist: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub status: Option<PlayStatus>,
+    /// Tag added with `history annotate`, ignoring case
+    pub tag: Option<String>,
+}
+
+impl SearchFilter {
+    pub fn matches(&self, entry: &HistoryEntry) -> bool {
+        self.app
+            .as_ref()
+            .is_none_or(|app| entry.bundle_id.as_ref() == Some(app))
+            && self
+                .artist
+                .as_ref()
+                .is_none_or(|artist| entry.artist.to_lowercase() == artist.to_lowercase())
+            && self.since.is_none_or(|since| entry.played_at >= since)
+            && self.until.is_none_or(|until| entry.played_at < until)
+            && self
+                .status
+                .is_none_or(|status| PlayStatus::of(entry) == status)
+            && self
+                .tag
+                .as_ref()
+                .is_none_or(|tag| entry.tags.contains(&tag.to_lowercase()))
+    }
+}
+
+/// Entries matching every word of `query`, best matches first, then most recent first
+///
+/// A word matches when it's part of the artist, title or album, or is at most one typo away
+/// from one of their words (for words of four letters or more). An empty query matches
+/// everything the filter lets through. Deleted scrobbles are left out.
+pub fn search<'a>(
+    entries: &'a [HistoryEntry],
+    query: &str,
+    filter: &SearchFilter,
+) -> Vec<&'a HistoryEntry> {
+    let terms: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+
+    let mut results: Vec<(u32, &HistoryEntry)> = entries
+        .iter()
+        .filter(|e| !e.deleted && filter.matches(e))
+        .filter_map(|e| score(e, &terms).map(|score| (score, e)))
+        .collect();
+    results.sort_by(|(a_score, a), (b_score, b)| {
+        b_score
+            .cmp(a_score)
+            .then_with(|| b.played_at.cmp(&a.played_at))
+    });
+
+    results.into_iter().map(|(_, e)| e).collect()
+}
+
+/// Sum of how well each term matches (2 for a substring, 1 for a near miss), or None if one doesn't
+fn score(entry: &HistoryEntry, terms: &[String]) -> Option<u32> {
+    let text = format!(
+        "{} {} {}",
+        entry.artist,
+        entry.title,
+        entry.album.as_deref().unwrap_or_default()
+    )
+    .to_lowercase();
+    let words: Vec<&str> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect();
+
+    terms
+        .iter()
+        .map(|term| {
+            if text.contains(term.as_str()) {
+                Some(2)
+            } else if term.chars().count() >= 4 && words.iter().any(|w| edit_distance(w, term) <= 1)
+            {
+                Some(1)
+            } else {
+                None
+            }
+        })
+        .sum()
+}
+
+/// Levenshtein distance between two strings, by character
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::Submission;
+    use chrono::TimeZone;
+
+    fn entry(id: u64, artist: &str, title: &str, album: Option<&str>) -> HistoryEntry {
+        HistoryEntry {
+            id,
+            artist: artist.to_string(),
+            title: title.to_string(),
+            album: album.map(str::to_string),
+            duration: Some(200),
+            played_at: Utc.with_ymd_and_hms(2026, 1, 1, 12, id as u32, 0).unwrap(),
+            bundle_id: Some("com.apple.Music".to_string()),
+            listened_seconds: Some(200),
+            submissions: vec![Submission {
+                service: "lastfm".to_string(),
+                submitted: true,
+                error: None,
+                deleted: false,
+                exported: false,
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn ids(results: Vec<&HistoryEntry>) -> Vec<u64> {
+        results.iter().map(|e| e.id).collect()
+    }
+
+    #[test]
+    fn test_search_terms_and_typos() {
+        let entries = vec![
+            entry(1, "Radiohead", "Karma Police", Some("OK Computer")),
+            entry(2, "Massive Attack", "Teardrop", Some("Mezzanine")),
+            entry(3, "Radiohead", "Airbag", Some("OK Computer")),
+        ];
+        let filter = SearchFilter::default();
+
+        assert_eq!(ids(search(&entries, "radiohead", &filter)), vec![3, 1]);
+        assert_eq!(ids(search(&entries, "radiohead karma", &filter)), vec![1]);
+        assert_eq!(ids(search(&entries, "mezanine", &filter)), vec![2]);
+        assert_eq!(ids(search(&entries, "computer", &filter)), vec![3, 1]);
+        assert!(search(&entries, "radiohead teardrop", &filter).is_empty());
+        // Short words have to match exactly
+        assert!(search(&entries, "oj", &filter).is_empty());
+    }
+
+    #[test]
+    fn test_exact_matches_rank_first() {
+        let entries = vec![
+            entry(1, "Portishead", "Roads", None),
+            entry(2, "Band", "Portishead Cover", None),
+            entry(3, "Band", "Partishead", None),
+        ];
+
+        assert_eq!(
+            ids(search(&entries, "portishead", &SearchFilter::default())),
+            vec![2, 1, 3]
+        );
+    }
+
+    #[test]
+    fn test_filters() {
+        let mut failed = entry(2, "Band", "Song", None);
+        failed.submissions[0].submitted = false;
+        let mut spotify = entry(3, "Band", "Song", None);
+        spotify.bundle_id = Some("com.spotify.client".to_string());
+        let mut deleted = entry(4, "Band", "Song", None);
+        deleted.deleted = true;
+        let entries = vec![entry(1, "Band", "Song", None), failed, spotify, deleted];
+
+        let status = SearchFilter {
+            status: Some(PlayStatus::Queued),
+            ..SearchFilter::default()
+        };
+        assert_eq!(ids(search(&entries, "song", &status)), vec![2]);
+
+        let app = SearchFilter {
+            app: Some("com.spotify.client".to_string()),
+            ..SearchFilter::default()
+        };
+        assert_eq!(ids(search(&entries, "", &app)), vec![3]);
+
+        let artist = SearchFilter {
+            artist: Some("band".to_string()),
+            ..SearchFilter::default()
+        };
+        let entries = vec![
+            entry(1, "Band", "Song", None),
+            entry(2, "Other Band", "Song", None),
+        ];
+        assert_eq!(ids(search(&entries, "", &artist)), vec![1]);
+
+        let range = SearchFilter {
+            since: Some(Utc.with_ymd_and_hms(2026, 1, 1, 12, 2, 0).unwrap()),
+            until: Some(Utc.with_ymd_and_hms(2026, 1, 1, 12, 3, 0).unwrap()),
+            ..SearchFilter::default()
+        };
+        assert_eq!(ids(search(&entries, "band", &range)), vec![2]);
+
+        let tag = SearchFilter {
+            tag: Some("Gym".to_string()),
+            ..SearchFilter::default()
+        };
+        let mut entries = entries;
+        entries[0].add_tag("gym").unwrap();
+        assert_eq!(ids(search(&entries, "", &tag)), vec![1]);
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("teardrop", "teardrop"), 0);
+        assert_eq!(edit_distance("teardrop", "tearsdrop"), 1);
+        assert_eq!(edit_distance("mezzanine", "mezanine"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+}
@@ -0,0 +1,157 @@
+// Session check module
+// Verifies each service's credentials about once a week with a cheap authenticated request, so a
+// revoked session is noticed before scrobbles go missing; results are kept for `status`
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Days between checks of the same service
+pub const CHECK_INTERVAL_DAYS: i64 = 7;
+
+/// Hours before a check that couldn't reach the service is tried again
+pub const RETRY_INTERVAL_HOURS: i64 = 1;
+
+/// Outcome of the latest check of a service
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionCheck {
+    pub checked_at: DateTime<Utc>,
+    /// Why the check failed, if it did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Whether the failure was the service rejecting the credentials
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub rejected: bool,
+    /// Last time the credentials were accepted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub valid_at: Option<DateTime<Utc>>,
+}
+
+/// Check results by service key, shared with `status` through a state file
+#[derive(Debug, Clone, Default)]
+pub struct SessionChecks {
+    path: PathBuf,
+    checks: BTreeMap<String, SessionCheck>,
+}
+
+impl SessionChecks {
+    /// Load the results from the state file, starting over if it's missing or unreadable
+    pub fn load() -> Self {
+        match crate::paths::Paths::current() {
+            Ok(paths) => Self::load_from(&paths.session_checks),
+            Err(e) => {
+                log::warn!("Failed to locate session check state: {:#}", e);
+                Self::default()
+            }
+        }
+    }
+
+    fn load_from(path: &Path) -> Self {
+        let checks = fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        Self {
+            path: path.to_path_buf(),
+            checks,
+        }
+    }
+
+    pub fn get(&self, service: &str) -> Option<&SessionCheck> {
+        self.checks.get(service)
+    }
+
+    /// Whether the service hasn't been checked for a week, or for an hour if the last check
+    /// didn't get an answer
+    pub fn is_due(&self, service: &str, now: DateTime<Utc>) -> bool {
+        self.checks.get(service).is_none_or(|check| {
+            let interval = if check.error.is_some() && !check.rejected {
+                Duration::hours(RETRY_INTERVAL_HOURS)
+            } else {
+                Duration::days(CHECK_INTERVAL_DAYS)
+            };
+            now - check.checked_at >= interval
+        })
+    }
+
+    /// Record the result of checking a service
+    pub fn record(
+        &mut self,
+        service: &str,
+        result: &Result<()>,
+        rejected: bool,
+        now: DateTime<Utc>,
+    ) {
+        let valid_at = match result {
+            Ok(()) => Some(now),
+            Err(_) => self.checks.get(service).and_then(|check| check.valid_at),
+        };
+        self.checks.insert(
+            service.to_string(),
+            SessionCheck {
+                checked_at: now,
+                error: result.as_ref().err().map(|e| format!("{:#}", e)),
+                rejected,
+                valid_at,
+            },
+        );
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).context("Failed to create session check state directory")?;
+        }
+        let content =
+            serde_json::to_string(&self.checks).context("Failed to serialize session checks")?;
+        fs::write(&self.path, content).context("Failed to write session check state")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_checks_are_due_weekly() {
+        let path = std::env::temp_dir().join(format!("session_checks_{}.json", std::process::id()));
+        let now = Utc.with_ymd_and_hms(2026, 1, 1, 12, 0, 0).unwrap();
+        let mut checks = SessionChecks::load_from(&path);
+        assert!(checks.is_due("lastfm", now));
+
+        checks.record("lastfm", &Ok(()), false, now);
+        assert!(!checks.is_due("lastfm", now + Duration::days(6)));
+        assert!(checks.is_due("lastfm", now + Duration::days(7)));
+
+        checks.record("lastfm", &Err(anyhow::anyhow!("Timed out")), false, now);
+        assert!(checks.is_due("lastfm", now + Duration::hours(1)));
+
+        checks.save().unwrap();
+        let loaded = SessionChecks::load_from(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.get("lastfm"), checks.get("lastfm"));
+    }
+
+    #[test]
+    fn test_failed_check_keeps_last_valid_time() {
+        let now = Utc.with_ymd_and_hms(2026, 1, 1, 12, 0, 0).unwrap();
+        let later = now + Duration::days(7);
+        let mut checks = SessionChecks::default();
+        checks.record("lastfm", &Ok(()), false, now);
+        checks.record(
+            "lastfm",
+            &Err(anyhow::anyhow!("Invalid session key")),
+            true,
+            later,
+        );
+
+        let check = checks.get("lastfm").unwrap();
+        assert_eq!(check.checked_at, later);
+        assert_eq!(check.valid_at, Some(now));
+        assert!(check.rejected);
+        assert_eq!(check.error.as_deref(), Some("Invalid session key"));
+    }
+}
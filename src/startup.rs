@@ -0,0 +1,192 @@
+// Startup module
+// Holds off polling right after a login launch, until the network and Media Remote are up, so the
+// first polls don't fail and report errors
+
+use crate::config::{Config, StartupConfig};
+use std::net::ToSocketAddrs;
+use std::process::Command;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Launches this soon after boot are taken to be login launches
+const LOGIN_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+/// Decides when polling can start
+#[derive(Debug)]
+pub struct StartupGate {
+    started: Instant,
+    grace_period: Duration,
+    ready_timeout: Duration,
+    /// `host:port` of the enabled services, any of which resolving means the network is up
+    hosts: Vec<String>,
+}
+
+impl StartupGate {
+    /// A gate for this launch, or `None` when it isn't soon after boot (or waiting is off)
+    pub fn new(config: &Config) -> Option<Self> {
+        let StartupConfig {
+            grace_period,
+            ready_timeout,
+        } = config.startup;
+        if grace_period == 0 && ready_timeout == 0 {
+            return None;
+        }
+        let uptime = uptime()?;
+        if uptime > LOGIN_WINDOW {
+            return None;
+        }
+
+        log::info!(
+            "Launched {}s after boot, waiting for the network and Media Remote before polling",
+            uptime.as_secs()
+        );
+        Some(Self {
+            started: Instant::now(),
+            grace_period: Duration::from_secs(grace_period),
+            ready_timeout: Duration::from_secs(ready_timeout),
+            hosts: service_hosts(config),
+        })
+    }
+
+    /// Whether polling can start: the grace period is over, and everything is ready or waiting
+    /// for it timed out
+    pub fn is_open(&self) -> bool {
+        let waited = self.started.elapsed();
+        if waited < self.grace_period {
+            return false;
+        }
+        if waited >= self.grace_period + self.ready_timeout {
+            log::warn!(
+                "Still not ready after {}s, polling anyway",
+                waited.as_secs()
+            );
+            return true;
+        }
+
+        let media_remote = media_remote_running();
+        let network = self.hosts.is_empty() || self.hosts.iter().any(|h| resolves(h));
+        log::debug!(
+            "Startup readiness: Media Remote {}, network {}",
+            media_remote,
+            network
+        );
+        if media_remote && network {
+            log::info!("Ready after {}s, starting to poll", waited.as_secs());
+        }
+        media_remote && network
+    }
+}
+
+/// Time since the Mac booted
+fn uptime() -> Option<Duration> {
+    let output = Command::new("sysctl")
+        .args(["-n", "kern.boottime"])
+        .output()
+        .ok()?;
+    let booted = parse_boottime(&String::from_utf8_lossy(&output.stdout))?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+    Some(Duration::from_secs(now.saturating_sub(booted)))
+}
+
+/// Seconds since the epoch from `{ sec = 1767225600, usec = 0 } Thu Jan  1 00:00:00 2026`
+fn parse_boottime(output: &str) -> Option<u64> {
+    let rest = output.split("sec =").nth(1)?;
+    rest.split(',').next()?.trim().parse().ok()
+}
+
+fn media_remote_running() -> bool {
+    Command::new("pgrep")
+        .args(["-x", "mediaremoted"])
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+fn resolves(host: &str) -> bool {
+    host.to_socket_addrs()
+        .is_ok_and(|mut addrs| addrs.next().is_some())
+}
+
+/// `host:port` of the enabled Last.fm and ListenBrainz services
+fn service_hosts(config: &Config) -> Vec<String> {
+    let lastfm = config
+        .lastfm
+        .as_ref()
+        .filter(|lastfm| lastfm.enabled)
+        .map(|lastfm| lastfm.api_url.as_str());
+    let listenbrainz = config
+        .listenbrainz
+        .iter()
+        .filter(|lb| lb.enabled)
+        .map(|lb| lb.api_url.as_str());
+
+    let mut hosts: Vec<String> = lastfm
+        .into_iter()
+        .chain(listenbrainz)
+        .filter_map(host)
+        .collect();
+    hosts.dedup();
+    hosts
+}
+
+/// `host:port` of a URL, with the scheme's default port
+fn host(url: &str) -> Option<String> {
+    let (scheme, rest) = url.split_once("://")?;
+    let authority = rest.split('/').next()?;
+    let authority = authority.rsplit('@').next()?;
+    if authority.is_empty() {
+        return None;
+    }
+    if authority.contains(':') {
+        return Some(authority.to_string());
+    }
+    let port = if scheme == "http" { 80 } else { 443 };
+    Some(format!("{}:{}", authority, port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lastfm_api::LASTFM_API_URL;
+
+    #[test]
+    fn test_parse_boottime() {
+        assert_eq!(
+            parse_boottime("{ sec = 1767225600, usec = 123456 } Thu Jan  1 00:00:00 2026\n"),
+            Some(1767225600)
+        );
+        assert_eq!(parse_boottime(""), None);
+    }
+
+    #[test]
+    fn test_host() {
+        assert_eq!(
+            host(LASTFM_API_URL),
+            Some("ws.audioscrobbler.com:443".to_string())
+        );
+        assert_eq!(
+            host("http://user@localhost:8100/apis/listenbrainz"),
+            Some("localhost:8100".to_string())
+        );
+        assert_eq!(
+            host("http://maloja.local"),
+            Some("maloja.local:80".to_string())
+        );
+        assert_eq!(host("not a url"), None);
+    }
+
+    #[test]
+    fn test_service_hosts() {
+        let mut config = Config::default();
+        assert!(service_hosts(&config).is_empty());
+
+        config.listenbrainz[0].enabled = true;
+        assert_eq!(service_hosts(&config), vec!["api.listenbrainz.org:443"]);
+
+        let lastfm = config.lastfm.as_mut().unwrap();
+        lastfm.enabled = true;
+        lastfm.api_url = "https://libre.fm/2.0/".to_string();
+        assert_eq!(
+            service_hosts(&config),
+            vec!["libre.fm:443", "api.listenbrainz.org:443"]
+        );
+    }
+}
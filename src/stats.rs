@@ -0,0 +1,411 @@
+// Listening statistics module
+// Aggregates scrobble history into listening time per day or artist, and charts it
+
+use crate::history::HistoryEntry;
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Days covered by the per day chart and top artists of the statistics report
+pub const REPORT_DAYS: i64 = 7;
+/// Days covered by the per hour chart
+pub const REPORT_HOUR_DAYS: i64 = 30;
+/// Artists listed in the statistics report
+const REPORT_ARTISTS: usize = 5;
+/// Characters in the longest bar
+const BAR_WIDTH: usize = 24;
+/// Sparkline levels, lowest first
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Total listening time for a single group (a day or an artist)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ListeningTotal {
+    pub key: String,
+    pub seconds: u64,
+    pub scrobbles: usize,
+}
+
+/// Sum listening time per group, using `key` to assign entries to groups
+fn aggregate<F>(
+    entries: &[HistoryEntry],
+    since: Option<DateTime<Utc>>,
+    key: F,
+) -> Vec<ListeningTotal>
+where
+    F: Fn(&HistoryEntry) -> String,
+{
+    let mut totals: HashMap<String, ListeningTotal> = HashMap::new();
+
+    // Guest listens aren't the user's listening time
+    for entry in entries.iter().filter(|e| !e.deleted && !e.guest) {
+        if since.is_some_and(|since| entry.played_at < since) {
+            continue;
+        }
+
+        let key = key(entry);
+        let total = totals.entry(key.clone()).or_insert(ListeningTotal {
+            key,
+            seconds: 0,
+            scrobbles: 0,
+        });
+        total.seconds += entry.listened_seconds.unwrap_or(0);
+        total.scrobbles += 1;
+    }
+
+    totals.into_values().collect()
+}
+
+/// Listening time per calendar day in the given timezone, oldest day first
+pub fn by_day<Tz: TimeZone>(
+    entries: &[HistoryEntry],
+    since: Option<DateTime<Utc>>,
+    tz: &Tz,
+) -> Vec<ListeningTotal> {
+    let mut totals = aggregate(entries, since, |e| {
+        e.played_at
+            .with_timezone(tz)
+            .date_naive()
+            .format("%Y-%m-%d")
+            .to_string()
+    });
+    totals.sort_by(|a, b| a.key.cmp(&b.key));
+    totals
+}
+
+/// Listening time per artist, most listened first
+pub fn by_artist(entries: &[HistoryEntry], since: Option<DateTime<Utc>>) -> Vec<ListeningTotal> {
+    let mut totals = aggregate(entries, since, |e| e.artist.clone());
+    totals.sort_by(|a, b| b.seconds.cmp(&a.seconds).then_with(|| a.key.cmp(&b.key)));
+    totals
+}
+
+/// Format seconds as a compact duration (e.g. `2h 05m`, `12m 30s`)
+pub fn format_duration(seconds: u64) -> String {
+    let hours = seconds / 3600;
+    let minutes = (seconds % 3600) / 60;
+    let secs = seconds % 60;
+
+    if hours > 0 {
+        format!("{}h {:02}m", hours, minutes)
+    } else {
+        format!("{}m {:02}s", minutes, secs)
+    }
+}
+
+/// Scrobbles per day for the `days` days up to `today`, oldest first, including empty days
+pub fn daily_counts<Tz: TimeZone>(
+    entries: &[HistoryEntry],
+    today: NaiveDate,
+    days: i64,
+    tz: &Tz,
+) -> Vec<(NaiveDate, usize)> {
+    let totals: HashMap<String, usize> = by_day(entries, None, tz)
+        .into_iter()
+        .map(|total| (total.key, total.scrobbles))
+        .collect();
+    (0..days)
+        .rev()
+        .map(|ago| {
+            let day = today - Duration::days(ago);
+            let key = day.format("%Y-%m-%d").to_string();
+            (day, totals.get(&key).copied().unwrap_or(0))
+        })
+        .collect()
+}
+
+/// Scrobbles per hour of the day in the given timezone, midnight first
+pub fn hourly_counts<Tz: TimeZone>(
+    entries: &[HistoryEntry],
+    since: Option<DateTime<Utc>>,
+    tz: &Tz,
+) -> [usize; 24] {
+    let mut counts = [0; 24];
+    let totals = aggregate(entries, since, |e| {
+        e.played_at.with_timezone(tz).hour().to_string()
+    });
+    for total in totals {
+        if let Ok(hour) = total.key.parse::<usize>() {
+            counts[hour] = total.scrobbles;
+        }
+    }
+    counts
+}
+
+/// Horizontal bar for `value` out of `max`, at least one character for anything above zero
+pub fn bar(value: usize, max: usize) -> String {
+    if value == 0 || max == 0 {
+        return "·".to_string();
+    }
+    let width = (value * BAR_WIDTH).div_ceil(max);
+    "█".repeat(width)
+}
+
+/// One character per value, scaled to the largest
+pub fn sparkline(values: &[usize]) -> String {
+    let max = values.iter().copied().max().unwrap_or(0);
+    values
+        .iter()
+        .map(|&value| {
+            let level = (value * (SPARKS.len() - 1) + max / 2).checked_div(max);
+            SPARKS[level.unwrap_or(0)]
+        })
+        .collect()
+}
+
+/// Data behind the statistics charts, serializable so another process can draw them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Charts {
+    /// Scrobbles on each of the last [`REPORT_DAYS`] days, oldest first
+    pub days: Vec<(NaiveDate, usize)>,
+    /// Scrobbles per hour of the day over the last [`REPORT_HOUR_DAYS`] days, midnight first
+    pub hours: [usize; 24],
+    /// Most listened artists of the last [`REPORT_DAYS`] days
+    pub artists: Vec<ListeningTotal>,
+}
+
+impl Charts {
+    /// Compute the charts of recent listening as seen at `now`, in its timezone
+    pub fn new<Tz: TimeZone>(entries: &[HistoryEntry], now: DateTime<Tz>) -> Self {
+        let tz = now.timezone();
+        let now_utc = now.with_timezone(&Utc);
+        let mut artists = by_artist(entries, Some(now_utc - Duration::days(REPORT_DAYS)));
+        artists.truncate(REPORT_ARTISTS);
+        Self {
+            days: daily_counts(entries, now.date_naive(), REPORT_DAYS, &tz),
+            hours: hourly_counts(
+                entries,
+                Some(now_utc - Duration::days(REPORT_HOUR_DAYS)),
+                &tz,
+            ),
+            artists,
+        }
+    }
+}
+
+/// Plain text charts of recent listening: scrobbles per day and per hour, and the top artists
+pub fn report(charts: &Charts) -> String {
+    let mut lines = Vec::new();
+
+    lines.push(format!("Scrobbles per day, last {} days", REPORT_DAYS));
+    let max = charts
+        .days
+        .iter()
+        .map(|(_, count)| *count)
+        .max()
+        .unwrap_or(0);
+    for (day, count) in &charts.days {
+        lines.push(format!(
+            "{} {}  {}",
+            bar(*count, max),
+            count,
+            day.format("%a %e %b")
+        ));
+    }
+
+    lines.push(String::new());
+    lines.push(format!(
+        "Scrobbles by hour, last {} days (midnight to 11 pm)",
+        REPORT_HOUR_DAYS
+    ));
+    lines.push(sparkline(&charts.hours));
+
+    lines.push(String::new());
+    lines.push(format!("Top artists, last {} days", REPORT_DAYS));
+    if charts.artists.is_empty() {
+        lines.push("No scrobbles yet".to_string());
+    }
+    for (rank, artist) in charts.artists.iter().enumerate() {
+        lines.push(format!(
+            "{}. {} - {} ({} scrobble{})",
+            rank + 1,
+            artist.key,
+            format_duration(artist.seconds),
+            artist.scrobbles,
+            if artist.scrobbles == 1 { "" } else { "s" }
+        ));
+    }
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(artist: &str, played_at: DateTime<Utc>, listened: Option<u64>) -> HistoryEntry {
+        HistoryEntry {
+            id: 1,
+            artist: artist.to_string(),
+            title: "Song".to_string(),
+            duration: Some(200),
+            played_at,
+            listened_seconds: listened,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_by_day_groups_and_sorts() {
+        let entries = vec![
+            entry(
+                "B",
+                Utc.with_ymd_and_hms(2026, 1, 2, 10, 0, 0).unwrap(),
+                Some(100),
+            ),
+            entry(
+                "A",
+                Utc.with_ymd_and_hms(2026, 1, 1, 10, 0, 0).unwrap(),
+                Some(200),
+            ),
+            entry(
+                "A",
+                Utc.with_ymd_and_hms(2026, 1, 1, 23, 0, 0).unwrap(),
+                Some(50),
+            ),
+        ];
+
+        assert_eq!(
+            by_day(&entries, None, &Utc),
+            vec![
+                ListeningTotal {
+                    key: "2026-01-01".to_string(),
+                    seconds: 250,
+                    scrobbles: 2
+                },
+                ListeningTotal {
+                    key: "2026-01-02".to_string(),
+                    seconds: 100,
+                    scrobbles: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_by_artist_sorted_by_time_and_filtered_by_since() {
+        let entries = vec![
+            entry(
+                "Old",
+                Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap(),
+                Some(9999),
+            ),
+            entry(
+                "A",
+                Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap(),
+                Some(100),
+            ),
+            entry(
+                "B",
+                Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap(),
+                Some(300),
+            ),
+            entry(
+                "A",
+                Utc.with_ymd_and_hms(2026, 1, 2, 0, 0, 0).unwrap(),
+                None,
+            ),
+        ];
+        let since = Some(Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap());
+
+        let totals = by_artist(&entries, since);
+        assert_eq!(totals.len(), 2);
+        assert_eq!(totals[0].key, "B");
+        assert_eq!(totals[1].key, "A");
+        assert_eq!(totals[1].seconds, 100);
+        assert_eq!(totals[1].scrobbles, 2);
+    }
+
+    #[test]
+    fn test_daily_and_hourly_counts() {
+        let entries = vec![
+            entry(
+                "A",
+                Utc.with_ymd_and_hms(2026, 1, 3, 9, 0, 0).unwrap(),
+                None,
+            ),
+            entry(
+                "A",
+                Utc.with_ymd_and_hms(2026, 1, 3, 9, 30, 0).unwrap(),
+                None,
+            ),
+            entry(
+                "B",
+                Utc.with_ymd_and_hms(2026, 1, 1, 22, 0, 0).unwrap(),
+                None,
+            ),
+        ];
+        let today = NaiveDate::from_ymd_opt(2026, 1, 3).unwrap();
+
+        let counts: Vec<usize> = daily_counts(&entries, today, 3, &Utc)
+            .into_iter()
+            .map(|(_, count)| count)
+            .collect();
+        assert_eq!(counts, vec![1, 0, 2]);
+
+        let hours = hourly_counts(&entries, None, &Utc);
+        assert_eq!(hours[9], 2);
+        assert_eq!(hours[22], 1);
+        assert_eq!(hours.iter().sum::<usize>(), 3);
+    }
+
+    #[test]
+    fn test_bars() {
+        assert_eq!(bar(0, 10), "·");
+        assert_eq!(bar(10, 10).chars().count(), BAR_WIDTH);
+        assert_eq!(bar(1, 1000), "█");
+        assert_eq!(sparkline(&[0, 2, 4]), "▁▅█");
+        assert_eq!(sparkline(&[0, 0]), "▁▁");
+    }
+
+    #[test]
+    fn test_report() {
+        let entries = vec![entry(
+            "A",
+            Utc.with_ymd_and_hms(2026, 1, 3, 9, 0, 0).unwrap(),
+            Some(200),
+        )];
+        let report = report(&Charts::new(
+            &entries,
+            Utc.with_ymd_and_hms(2026, 1, 3, 12, 0, 0).unwrap(),
+        ));
+        assert!(report.contains("█ 1  Sat  3 Jan"));
+        assert!(report.contains("1. A - 3m 20s (1 scrobble)"));
+    }
+
+    #[test]
+    fn test_charts_keep_top_artists_and_survive_json() {
+        let now = Utc.with_ymd_and_hms(2026, 1, 3, 12, 0, 0).unwrap();
+        let entries: Vec<HistoryEntry> = (0..REPORT_ARTISTS + 2)
+            .map(|n| entry(&format!("Artist {}", n), now, Some(100 + n as u64)))
+            .chain(std::iter::once(entry(
+                "Last month",
+                now - Duration::days(20) - Duration::hours(12),
+                Some(9999),
+            )))
+            .collect();
+
+        let charts = Charts::new(&entries, now);
+        assert_eq!(charts.days.len(), REPORT_DAYS as usize);
+        assert_eq!(
+            charts.days.last(),
+            Some(&(now.date_naive(), REPORT_ARTISTS + 2))
+        );
+        assert_eq!(charts.hours[12], REPORT_ARTISTS + 2);
+        assert_eq!(charts.hours[0], 1);
+        assert_eq!(charts.artists.len(), REPORT_ARTISTS);
+        assert_eq!(
+            charts.artists[0].key,
+            format!("Artist {}", REPORT_ARTISTS + 1)
+        );
+
+        let json = serde_json::to_string(&charts).unwrap();
+        assert_eq!(serde_json::from_str::<Charts>(&json).unwrap(), charts);
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(0), "0m 00s");
+        assert_eq!(format_duration(750), "12m 30s");
+        assert_eq!(format_duration(7500), "2h 05m");
+    }
+}
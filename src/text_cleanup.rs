@@ -1,12 +1,136 @@
 // Text cleanup module
-// Applies regex patterns to clean up track/album/artist names
+// Applies regex patterns to clean up track/album/artist names, picks one script for names given
+// in two, and handles live recording details and version suffixes in titles
 
-use crate::config::CleanupConfig;
-use regex::Regex;
+use crate::config::{CleanupConfig, DualScript, LivePolicy, SuffixConfig};
+use regex::{Regex, RegexSet};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
 
 pub struct TextCleaner {
     enabled: bool,
-    patterns: Vec<Regex>,
+    patterns: PatternSet,
+    dual_script: DualScript,
+    live: LivePolicy,
+    suffixes: Option<Suffixes>,
+    /// How often each of `patterns` changed a text
+    hits: Vec<Hits>,
+}
+
+/// Patterns in one automaton, to find the few that apply to a text in a single pass
+struct PatternSet {
+    regexes: Vec<Regex>,
+    /// `None` when the patterns are too big to combine, so each is tried in turn instead
+    set: Option<RegexSet>,
+}
+
+impl PatternSet {
+    fn new(regexes: Vec<Regex>) -> Self {
+        let set = RegexSet::new(regexes.iter().map(Regex::as_str))
+            .map_err(|e| {
+                log::warn!(
+                    "Cleanup patterns are too big to combine, trying them one by one: {}",
+                    e
+                )
+            })
+            .ok();
+        Self { regexes, set }
+    }
+
+    /// Whether each of the patterns matches `text`
+    fn matches(&self, text: &str) -> Vec<bool> {
+        match &self.set {
+            Some(set) => {
+                let matches = set.matches(text);
+                (0..self.regexes.len())
+                    .map(|i| matches.matched(i))
+                    .collect()
+            }
+            None => self.regexes.iter().map(|re| re.is_match(text)).collect(),
+        }
+    }
+
+    /// Whether any of the patterns matches `text`
+    fn is_match(&self, text: &str) -> bool {
+        match &self.set {
+            Some(set) => set.is_match(text),
+            None => self.regexes.iter().any(|re| re.is_match(text)),
+        }
+    }
+}
+
+/// Compiled `[cleanup.suffixes]` patterns
+struct Suffixes {
+    strip: PatternSet,
+    /// The `strip` patterns as configured, for the stats
+    strip_patterns: Vec<String>,
+    preserve: PatternSet,
+    /// How often each `strip` pattern removed a suffix
+    hits: Vec<Hits>,
+}
+
+#[derive(Default)]
+struct Hits {
+    matches: AtomicU64,
+    emptied: AtomicU64,
+}
+
+/// How often a cleanup pattern changed a text, for `osx-scrobbler cleanup-test --stats`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PatternStats {
+    /// `pattern` for `[cleanup] patterns`, `suffix` for `[cleanup.suffixes] strip`
+    pub kind: String,
+    pub pattern: String,
+    pub matches: u64,
+    /// Texts the pattern left empty
+    pub emptied: u64,
+}
+
+impl Suffixes {
+    fn new(config: &SuffixConfig) -> Self {
+        let (strip, strip_patterns) = regex_set(&config.strip, |p| format!("(?i)^(?:{})$", p));
+        let (preserve, _) = regex_set(&config.preserve, |p| format!("(?i){}", p));
+        Self {
+            hits: strip_patterns.iter().map(|_| Hits::default()).collect(),
+            strip,
+            strip_patterns,
+            preserve,
+        }
+    }
+
+    /// Remove the suffix if it's to be, counting the `strip` patterns that matched it
+    fn strips(&self, suffix: &str) -> bool {
+        let matches = self.strip.matches(suffix);
+        if !matches.contains(&true) || self.preserve.is_match(suffix) {
+            return false;
+        }
+        for (hits, _) in self
+            .hits
+            .iter()
+            .zip(matches)
+            .filter(|(_, matched)| *matched)
+        {
+            hits.matches.fetch_add(1, Ordering::Relaxed);
+        }
+        true
+    }
+}
+
+/// A set of the valid patterns after wrapping them, warning about the others, and the valid
+/// patterns as given
+fn regex_set(patterns: &[String], wrap: impl Fn(&str) -> String) -> (PatternSet, Vec<String>) {
+    let (regexes, valid): (Vec<Regex>, Vec<String>) = patterns
+        .iter()
+        .filter_map(|pattern| match Regex::new(&wrap(pattern)) {
+            Ok(re) => Some((re, pattern.clone())),
+            Err(e) => {
+                log::warn!("Invalid suffix pattern '{}': {}", pattern, e);
+                None
+            }
+        })
+        .unzip();
+    (PatternSet::new(regexes), valid)
 }
 
 impl TextCleaner {
@@ -30,7 +154,12 @@ impl TextCleaner {
 
         Self {
             enabled: config.enabled,
-            patterns,
+            hits: patterns.iter().map(|_| Hits::default()).collect(),
+            patterns: PatternSet::new(patterns),
+            dual_script: config.dual_script,
+            live: config.live,
+            suffixes: (config.enabled && config.suffixes.enabled)
+                .then(|| Suffixes::new(&config.suffixes)),
         }
     }
 
@@ -40,19 +169,201 @@ impl TextCleaner {
             return text.to_string();
         }
 
+        // Only patterns that match are applied. A replacement can make later patterns match
+        // (or stop matching), so the set is re-checked whenever the text changes.
         let mut result = text.to_string();
-        for pattern in &self.patterns {
-            result = pattern.replace_all(&result, "").to_string();
+        let mut matches = self.patterns.matches(&result);
+        let mut last_applied = None;
+        for (i, pattern) in self.patterns.regexes.iter().enumerate() {
+            if !matches[i] {
+                continue;
+            }
+
+            let replaced = pattern.replace_all(&result, "");
+            if replaced != result {
+                result = replaced.into_owned();
+                matches = self.patterns.matches(&result);
+                self.hits[i].matches.fetch_add(1, Ordering::Relaxed);
+                last_applied = Some(i);
+            }
         }
 
         // Trim any extra whitespace
-        result.trim().to_string()
+        let result = result.trim();
+        if let Some(i) = last_applied.filter(|_| result.is_empty() && !text.trim().is_empty()) {
+            self.hits[i].emptied.fetch_add(1, Ordering::Relaxed);
+        }
+        match (self.dual_script, split_dual_script(result)) {
+            (DualScript::Original, Some((original, _))) => original.to_string(),
+            (DualScript::Latin, Some((_, latin))) => latin.to_string(),
+            _ => result.to_string(),
+        }
+    }
+
+    /// Clean a track title: the patterns, then the version suffixes
+    ///
+    /// Suffixes at the end of the title, like `(Radio Edit)`, `[Original Mix]` or
+    /// `- Radio Edit`, are removed when a `strip` pattern matches them entirely and no `preserve`
+    /// pattern matches them, so `Song (Artist B Remix) [Radio Edit]` becomes
+    /// `Song (Artist B Remix)`.
+    pub fn clean_title(&self, title: &str) -> String {
+        let title = self.clean(title);
+        let Some(suffixes) = &self.suffixes else {
+            return title;
+        };
+
+        static SUFFIX: OnceLock<Regex> = OnceLock::new();
+        let suffix = SUFFIX.get_or_init(|| {
+            Regex::new(r"\s*(?:\(([^()]*)\)|\[([^\[\]]*)\]|\s[-–—]\s+([^()\[\]]+?))\s*$").unwrap()
+        });
+
+        // Suffixes are taken off the end one by one, keeping the ones that stay
+        let mut rest = title.as_str();
+        let mut kept = Vec::new();
+        while let Some(captures) = suffix.captures(rest) {
+            let whole = captures.get(0).expect("group 0 always matches");
+            let head = rest[..whole.start()].trim_end();
+            let Some(text) = (1..=3).find_map(|i| captures.get(i)) else {
+                break;
+            };
+            if head.is_empty() {
+                break;
+            }
+            if !suffixes.strips(text.as_str().trim()) {
+                kept.push(whole.as_str());
+            }
+            rest = head;
+        }
+
+        kept.iter()
+            .rev()
+            .fold(rest.to_string(), |mut title, suffix| {
+                title.push_str(suffix);
+                title
+            })
+    }
+
+    /// How often each pattern changed a text since the cleaner was created
+    ///
+    /// A text left empty counts against the pattern applied last.
+    pub fn stats(&self) -> Vec<PatternStats> {
+        let stats = |kind: &str, pattern: &str, hits: &Hits| PatternStats {
+            kind: kind.to_string(),
+            pattern: pattern.to_string(),
+            matches: hits.matches.load(Ordering::Relaxed),
+            emptied: hits.emptied.load(Ordering::Relaxed),
+        };
+
+        let patterns = self
+            .patterns
+            .regexes
+            .iter()
+            .zip(&self.hits)
+            .map(|(pattern, hits)| stats("pattern", pattern.as_str(), hits));
+        let suffixes = self.suffixes.iter().flat_map(|suffixes| {
+            suffixes
+                .strip_patterns
+                .iter()
+                .zip(&suffixes.hits)
+                .map(|(pattern, hits)| stats("suffix", pattern, hits))
+        });
+        patterns.chain(suffixes).collect()
     }
 
     /// Clean an optional string
     pub fn clean_option(&self, text: Option<String>) -> Option<String> {
         text.map(|s| self.clean(&s))
     }
+
+    /// Apply the live recording policy to a title
+    ///
+    /// Returns the title to scrobble, and with the `tag` policy, the live details taken out of it.
+    pub fn live(&self, title: String) -> (String, Option<String>) {
+        if !self.enabled || self.live == LivePolicy::Keep {
+            return (title, None);
+        }
+        let Some((rest, live)) = split_live(&title) else {
+            return (title, None);
+        };
+
+        let live = (self.live == LivePolicy::Tag).then(|| live.to_string());
+        (rest.to_string(), live)
+    }
+}
+
+/// Split live recording details off the end of a title
+///
+/// Recognizes `Song (Live)`, `Song [Live at Wembley, 1986]` and `Song - Live in Berlin`, returning
+/// the title and the details (`Live at Wembley, 1986`). Titles that merely end in a word starting
+/// with "live", like `Song (Live Forever)`, are left alone.
+pub fn split_live(title: &str) -> Option<(&str, &str)> {
+    static LIVE: OnceLock<Regex> = OnceLock::new();
+    let live = LIVE.get_or_init(|| {
+        let details = r"live(?:\s+(?:at|in|from|on|@|version|recording|session)\b[^)\]]*|\s*[,/]?\s*\d{4}[^)\]]*)?";
+        Regex::new(&format!(
+            r"(?i)\s*(?:[(\[]\s*({details})\s*[)\]]|\s[-–—]\s+({details}))$"
+        ))
+        .unwrap()
+    });
+
+    let captures = live.captures(title)?;
+    let details = captures.get(1).or_else(|| captures.get(2))?.as_str().trim();
+    let rest = title[..captures.get(0)?.start()].trim_end();
+    (!rest.is_empty()).then_some((rest, details))
+}
+
+/// Split a name given in two scripts into its original-script and Latin parts
+///
+/// Recognizes `宇多田ヒカル (Hikaru Utada)`, `Hikaru Utada (宇多田ヒカル)` (also with full-width
+/// parentheses) and `宇多田ヒカル / Hikaru Utada`. One part has to be written in another script
+/// and the other only in Latin letters, and version tags like `(Live)` or `(Japanese Ver.)` aren't
+/// taken for a Latin name.
+pub fn split_dual_script(text: &str) -> Option<(&str, &str)> {
+    static FORMATS: OnceLock<[Regex; 2]> = OnceLock::new();
+    static VERSION_TAG: OnceLock<Regex> = OnceLock::new();
+    let formats = FORMATS.get_or_init(|| {
+        [
+            Regex::new(r"^(.+?)\s*[(（]([^()（）]+)[)）]$").unwrap(),
+            Regex::new(r"^(.+?)\s+/\s+(.+)$").unwrap(),
+        ]
+    });
+    let version_tag = VERSION_TAG.get_or_init(|| {
+        Regex::new(
+            r"(?i)\b(ver|version|remix|mix|live|edit|feat|ft|prod|inst|instrumental|acoustic|remaster(ed)?|demo|cover|bonus|ost|tv size)\b",
+        )
+        .unwrap()
+    });
+
+    let captures = formats.iter().find_map(|format| format.captures(text))?;
+    let (first, second) = (
+        captures.get(1)?.as_str().trim(),
+        captures.get(2)?.as_str().trim(),
+    );
+    let (original, latin) = match (is_latin(first), is_latin(second)) {
+        (false, true) => (first, second),
+        (true, false) => (second, first),
+        _ => return None,
+    };
+    if !has_other_script(original) || version_tag.is_match(latin) {
+        return None;
+    }
+    Some((original, latin))
+}
+
+/// Whether every letter is Latin, with at least one letter
+fn is_latin(text: &str) -> bool {
+    let mut letters = text.chars().filter(|c| c.is_alphabetic()).peekable();
+    letters.peek().is_some() && letters.all(is_latin_letter)
+}
+
+/// Whether any letter is from another script than Latin
+fn has_other_script(text: &str) -> bool {
+    text.chars()
+        .any(|c| c.is_alphabetic() && !is_latin_letter(c))
+}
+
+fn is_latin_letter(c: char) -> bool {
+    c.is_ascii_alphabetic() || matches!(c, '\u{00C0}'..='\u{024F}' | '\u{1E00}'..='\u{1EFF}')
 }
 
 #[cfg(test)]
@@ -64,6 +375,9 @@ mod tests {
         let config = CleanupConfig {
             enabled: false,
             patterns: vec![r"\s*\[Explicit\]".to_string()],
+            dual_script: DualScript::Keep,
+            live: LivePolicy::Keep,
+            suffixes: SuffixConfig::default(),
         };
         let cleaner = TextCleaner::new(&config);
 
@@ -78,6 +392,9 @@ mod tests {
                 r"\s*\[Explicit\]".to_string(),
                 r"\s*\(Explicit\)".to_string(),
             ],
+            dual_script: DualScript::Keep,
+            live: LivePolicy::Keep,
+            suffixes: SuffixConfig::default(),
         };
         let cleaner = TextCleaner::new(&config);
 
@@ -91,6 +408,9 @@ mod tests {
         let config = CleanupConfig {
             enabled: true,
             patterns: vec![r"\s*\[Clean\]".to_string()],
+            dual_script: DualScript::Keep,
+            live: LivePolicy::Keep,
+            suffixes: SuffixConfig::default(),
         };
         let cleaner = TextCleaner::new(&config);
 
@@ -102,6 +422,9 @@ mod tests {
         let config = CleanupConfig {
             enabled: true,
             patterns: vec![r"\s*\[Explicit\]".to_string()],
+            dual_script: DualScript::Keep,
+            live: LivePolicy::Keep,
+            suffixes: SuffixConfig::default(),
         };
         let cleaner = TextCleaner::new(&config);
 
@@ -116,6 +439,9 @@ mod tests {
                 r"\s*\[Explicit\]".to_string(),
                 r"\s*- Remastered.*".to_string(),
             ],
+            dual_script: DualScript::Keep,
+            live: LivePolicy::Keep,
+            suffixes: SuffixConfig::default(),
         };
         let cleaner = TextCleaner::new(&config);
 
@@ -127,6 +453,9 @@ mod tests {
         let config = CleanupConfig {
             enabled: true,
             patterns: vec![r"\s*\[Explicit\]".to_string()],
+            dual_script: DualScript::Keep,
+            live: LivePolicy::Keep,
+            suffixes: SuffixConfig::default(),
         };
         let cleaner = TextCleaner::new(&config);
 
@@ -141,12 +470,82 @@ mod tests {
         let config = CleanupConfig {
             enabled: true,
             patterns: vec![r"\s*\[Explicit\]".to_string()],
+            dual_script: DualScript::Keep,
+            live: LivePolicy::Keep,
+            suffixes: SuffixConfig::default(),
         };
         let cleaner = TextCleaner::new(&config);
 
         assert_eq!(cleaner.clean_option(None), None);
     }
 
+    #[test]
+    fn test_replacement_enables_later_pattern() {
+        let config = CleanupConfig {
+            enabled: true,
+            patterns: vec![r"\s*\[Explicit\]".to_string(), r"\s*- Live$".to_string()],
+            dual_script: DualScript::Keep,
+            live: LivePolicy::Keep,
+            suffixes: SuffixConfig::default(),
+        };
+        let cleaner = TextCleaner::new(&config);
+
+        // "- Live" is only at the end once "[Explicit]" is gone
+        assert_eq!(cleaner.clean("Song - Live [Explicit]"), "Song");
+    }
+
+    /// Compares against applying every pattern in turn; run with
+    /// `cargo test --release -- --ignored --nocapture bench_many_patterns`
+    #[test]
+    #[ignore]
+    fn bench_many_patterns() {
+        use std::time::Instant;
+
+        let patterns: Vec<String> = (0..150)
+            .map(|i| format!(r"\s*\[(Tag{}|Variant {})\]", i, i))
+            .chain([r"\s*\[Explicit\]".to_string()])
+            .collect();
+        let config = CleanupConfig {
+            enabled: true,
+            patterns,
+            dual_script: DualScript::Keep,
+            live: LivePolicy::Keep,
+            suffixes: SuffixConfig::default(),
+        };
+        let cleaner = TextCleaner::new(&config);
+        let titles = ["Song [Explicit]", "Another Song (Remastered 2011)", "Plain"];
+        let rounds = 2000;
+
+        let start = Instant::now();
+        for _ in 0..rounds {
+            for title in titles {
+                std::hint::black_box(cleaner.clean(title));
+            }
+        }
+        let with_set = start.elapsed();
+
+        let start = Instant::now();
+        for _ in 0..rounds {
+            for title in titles {
+                let mut result = title.to_string();
+                for pattern in &cleaner.patterns.regexes {
+                    result = pattern.replace_all(&result, "").to_string();
+                }
+                std::hint::black_box(result.trim());
+            }
+        }
+        let sequential = start.elapsed();
+
+        println!(
+            "{} patterns, {} cleanups: RegexSet {:?}, sequential {:?}",
+            cleaner.patterns.regexes.len(),
+            rounds * titles.len(),
+            with_set,
+            sequential
+        );
+        assert_eq!(cleaner.clean("Song [Explicit]"), "Song");
+    }
+
     #[test]
     fn test_invalid_pattern_is_skipped() {
         let config = CleanupConfig {
@@ -155,10 +554,241 @@ mod tests {
                 r"[invalid(".to_string(), // Invalid regex
                 r"\s*\[Explicit\]".to_string(),
             ],
+            dual_script: DualScript::Keep,
+            live: LivePolicy::Keep,
+            suffixes: SuffixConfig::default(),
         };
         let cleaner = TextCleaner::new(&config);
 
         // Should still clean with the valid pattern
         assert_eq!(cleaner.clean("Song [Explicit]"), "Song");
     }
+
+    #[test]
+    fn test_patterns_too_big_to_combine_are_tried_one_by_one() {
+        let mut config = CleanupConfig {
+            enabled: true,
+            ..CleanupConfig::default()
+        };
+        config.suffixes.enabled = true;
+        let mut cleaner = TextCleaner::new(&config);
+        let title = "Song (Remastered 2011) [Explicit] - Radio Edit";
+        let expected = cleaner.clean_title(title);
+
+        // What's left when RegexSet::new fails
+        cleaner.patterns.set = None;
+        if let Some(suffixes) = cleaner.suffixes.as_mut() {
+            suffixes.strip.set = None;
+            suffixes.preserve.set = None;
+        }
+        assert_eq!(cleaner.clean_title(title), expected);
+        assert_ne!(expected, title);
+    }
+
+    #[test]
+    fn test_split_dual_script() {
+        // J-pop artists, in both orders and with full-width parentheses
+        assert_eq!(
+            split_dual_script("宇多田ヒカル (Hikaru Utada)"),
+            Some(("宇多田ヒカル", "Hikaru Utada"))
+        );
+        assert_eq!(
+            split_dual_script("Hikaru Utada (宇多田ヒカル)"),
+            Some(("宇多田ヒカル", "Hikaru Utada"))
+        );
+        assert_eq!(
+            split_dual_script("米津玄師（Kenshi Yonezu）"),
+            Some(("米津玄師", "Kenshi Yonezu"))
+        );
+        // K-pop artists and titles
+        assert_eq!(
+            split_dual_script("방탄소년단 (BTS)"),
+            Some(("방탄소년단", "BTS"))
+        );
+        assert_eq!(
+            split_dual_script("봄날 (Spring Day)"),
+            Some(("봄날", "Spring Day"))
+        );
+        assert_eq!(split_dual_script("아이유 / IU"), Some(("아이유", "IU")));
+        assert_eq!(
+            split_dual_script("Mötley Crüe (Мотли Крю)").map(|s| s.1),
+            Some("Mötley Crüe")
+        );
+
+        assert_eq!(
+            split_dual_script("에드 시런 (Ed Sheeran)"),
+            Some(("에드 시런", "Ed Sheeran"))
+        );
+
+        // Not names in two scripts
+        assert_eq!(split_dual_script("Song (Live)"), None);
+        assert_eq!(split_dual_script("紅蓮華 (TV Size)"), None);
+        assert_eq!(split_dual_script("夜に駆ける"), None);
+        assert_eq!(split_dual_script("Lemon (Japanese Ver.)"), None);
+        assert_eq!(split_dual_script("夜に駆ける (YOASOBI Remix)"), None);
+        assert_eq!(split_dual_script("宇多田ヒカル / 椎名林檎"), None);
+        assert_eq!(split_dual_script("(G)I-DLE"), None);
+    }
+
+    #[test]
+    fn test_dual_script_preference() {
+        let cleaner = |dual_script| {
+            TextCleaner::new(&CleanupConfig {
+                enabled: true,
+                patterns: vec![r"\s*\[Explicit\]".to_string()],
+                dual_script,
+                live: LivePolicy::Keep,
+                suffixes: SuffixConfig::default(),
+            })
+        };
+
+        let name = "宇多田ヒカル (Hikaru Utada) [Explicit]";
+        assert_eq!(
+            cleaner(DualScript::Keep).clean(name),
+            "宇多田ヒカル (Hikaru Utada)"
+        );
+        assert_eq!(cleaner(DualScript::Original).clean(name), "宇多田ヒカル");
+        assert_eq!(cleaner(DualScript::Latin).clean(name), "Hikaru Utada");
+        assert_eq!(
+            cleaner(DualScript::Latin).clean("Song (Live)"),
+            "Song (Live)"
+        );
+    }
+
+    #[test]
+    fn test_split_live() {
+        assert_eq!(split_live("Song (Live)"), Some(("Song", "Live")));
+        assert_eq!(
+            split_live("Song [Live at Wembley, 1986]"),
+            Some(("Song", "Live at Wembley, 1986"))
+        );
+        assert_eq!(
+            split_live("Song - Live in Berlin"),
+            Some(("Song", "Live in Berlin"))
+        );
+        assert_eq!(split_live("Song (live 2003)"), Some(("Song", "live 2003")));
+        assert_eq!(
+            split_live("Song (Live From Abbey Road)"),
+            Some(("Song", "Live From Abbey Road"))
+        );
+
+        assert_eq!(split_live("Live Forever"), None);
+        assert_eq!(split_live("Song (Live Forever)"), None);
+        assert_eq!(split_live("Live and Let Die"), None);
+        assert_eq!(split_live("(Live)"), None);
+        assert_eq!(split_live("Song (Live) (Remix)"), None);
+    }
+
+    #[test]
+    fn test_live_policy() {
+        let cleaner = |live| {
+            TextCleaner::new(&CleanupConfig {
+                enabled: true,
+                patterns: Vec::new(),
+                dual_script: DualScript::Keep,
+                live,
+                suffixes: SuffixConfig::default(),
+            })
+        };
+        let title = || "Song (Live at Wembley)".to_string();
+
+        assert_eq!(cleaner(LivePolicy::Keep).live(title()), (title(), None));
+        assert_eq!(
+            cleaner(LivePolicy::Strip).live(title()),
+            ("Song".to_string(), None)
+        );
+        assert_eq!(
+            cleaner(LivePolicy::Tag).live(title()),
+            ("Song".to_string(), Some("Live at Wembley".to_string()))
+        );
+        assert_eq!(
+            cleaner(LivePolicy::Tag).live("Song".to_string()),
+            ("Song".to_string(), None)
+        );
+    }
+
+    fn suffix_cleaner(strip: &[&str], preserve: &[&str]) -> TextCleaner {
+        TextCleaner::new(&CleanupConfig {
+            enabled: true,
+            patterns: vec![r"\s*\[Explicit\]".to_string()],
+            dual_script: DualScript::Keep,
+            live: LivePolicy::Keep,
+            suffixes: SuffixConfig {
+                enabled: true,
+                strip: strip.iter().map(|s| s.to_string()).collect(),
+                preserve: preserve.iter().map(|s| s.to_string()).collect(),
+            },
+        })
+    }
+
+    #[test]
+    fn test_strips_generic_suffixes() {
+        let defaults = SuffixConfig::default();
+        let strip: Vec<&str> = defaults.strip.iter().map(String::as_str).collect();
+        let preserve: Vec<&str> = defaults.preserve.iter().map(String::as_str).collect();
+        let cleaner = suffix_cleaner(&strip, &preserve);
+
+        assert_eq!(cleaner.clean_title("Song (Radio Edit)"), "Song");
+        assert_eq!(cleaner.clean_title("Song [Original Mix]"), "Song");
+        assert_eq!(cleaner.clean_title("Song - Extended Mix"), "Song");
+        assert_eq!(cleaner.clean_title("Song - 2011 Remaster"), "Song");
+        assert_eq!(cleaner.clean_title("Song (Radio Edit) [Explicit]"), "Song");
+        // Remix credits and other suffixes stay
+        assert_eq!(
+            cleaner.clean_title("Song (Artist B Remix) [Radio Edit]"),
+            "Song (Artist B Remix)"
+        );
+        assert_eq!(
+            cleaner.clean_title("Song - Radio Edit (Artist B Remix)"),
+            "Song (Artist B Remix)"
+        );
+        assert_eq!(
+            cleaner.clean_title("Song (Artist B Edit)"),
+            "Song (Artist B Edit)"
+        );
+        assert_eq!(cleaner.clean_title("Song - Part 2"), "Song - Part 2");
+        assert_eq!(cleaner.clean_title("(Radio Edit)"), "(Radio Edit)");
+        // Only titles
+        assert_eq!(cleaner.clean("Album (Radio Edit)"), "Album (Radio Edit)");
+    }
+
+    #[test]
+    fn test_preserve_wins_over_strip() {
+        let cleaner = suffix_cleaner(&[r".*\bmix"], &[r"\bdub\b"]);
+
+        assert_eq!(cleaner.clean_title("Song (Club Mix)"), "Song");
+        assert_eq!(cleaner.clean_title("Song (Dub Mix)"), "Song (Dub Mix)");
+
+        let disabled = TextCleaner::new(&CleanupConfig::default());
+        assert_eq!(
+            disabled.clean_title("Song (Radio Edit)"),
+            "Song (Radio Edit)"
+        );
+    }
+
+    #[test]
+    fn test_pattern_stats() {
+        let cleaner = suffix_cleaner(&["radio edit"], &[]);
+        cleaner.clean_title("Song [Explicit] (Radio Edit)");
+        cleaner.clean_title("Other Song [Explicit]");
+        cleaner.clean_title("Plain");
+        cleaner.clean("[Explicit]");
+
+        let stats = cleaner.stats();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(
+            stats[0],
+            PatternStats {
+                kind: "pattern".to_string(),
+                pattern: r"\s*\[Explicit\]".to_string(),
+                matches: 3,
+                emptied: 1,
+            }
+        );
+        assert_eq!(
+            (stats[1].kind.as_str(), stats[1].pattern.as_str()),
+            ("suffix", "radio edit")
+        );
+        assert_eq!((stats[1].matches, stats[1].emptied), (1, 0));
+    }
 }
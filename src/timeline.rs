@@ -0,0 +1,136 @@
+// Timeline module
+// A day's plays from the scrobble history in order, for `osx-scrobbler timeline`
+
+use crate::history::HistoryEntry;
+use chrono::{NaiveDate, TimeZone};
+
+/// What became of a play
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayStatus {
+    /// Submitted to every service it went to
+    Scrobbled,
+    /// Waiting to be submitted to at least one service
+    Queued,
+    /// Recorded during a private session
+    Private,
+    /// Played by someone else in guest mode
+    Guest,
+    /// Held back for review, as confidence that it's music was low
+    Review,
+    /// Recorded while no service was enabled (or routed to)
+    NotSubmitted,
+}
+
+impl PlayStatus {
+    pub fn of(entry: &HistoryEntry) -> Self {
+        if entry.guest {
+            Self::Guest
+        } else if entry.review {
+            Self::Review
+        } else if entry.private {
+            Self::Private
+        } else if entry.submissions.is_empty() {
+            Self::NotSubmitted
+        } else if entry.submissions.iter().any(|s| s.is_queued()) {
+            Self::Queued
+        } else {
+            Self::Scrobbled
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Scrobbled => "scrobbled",
+            Self::Queued => "queued",
+            Self::Private => "private",
+            Self::Guest => "guest",
+            Self::Review => "review",
+            Self::NotSubmitted => "not submitted",
+        }
+    }
+}
+
+/// Plays started on `day` in the given timezone, oldest first
+///
+/// Deleted scrobbles are left out, including ones replaced by "Edit Last Scrobble".
+pub fn plays_on<'a, Tz: TimeZone>(
+    entries: &'a [HistoryEntry],
+    day: NaiveDate,
+    tz: &Tz,
+) -> Vec<&'a HistoryEntry> {
+    let mut plays: Vec<&HistoryEntry> = entries
+        .iter()
+        .filter(|e| !e.deleted && e.played_at.with_timezone(tz).date_naive() == day)
+        .collect();
+    plays.sort_by_key(|e| e.played_at);
+    plays
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::Submission;
+    use chrono::{DateTime, FixedOffset, Utc};
+
+    fn entry(id: u64, played_at: DateTime<Utc>) -> HistoryEntry {
+        HistoryEntry {
+            id,
+            artist: "Band".to_string(),
+            title: "Song".to_string(),
+            duration: Some(200),
+            played_at,
+            listened_seconds: Some(200),
+            ..Default::default()
+        }
+    }
+
+    fn submission(service: &str, submitted: bool) -> Submission {
+        Submission {
+            service: service.to_string(),
+            submitted,
+            error: (!submitted).then(|| "timeout".to_string()),
+            deleted: false,
+            exported: false,
+        }
+    }
+
+    #[test]
+    fn test_plays_on_uses_local_day() {
+        let mut deleted = entry(4, Utc.with_ymd_and_hms(2026, 1, 1, 12, 0, 0).unwrap());
+        deleted.deleted = true;
+        let entries = vec![
+            entry(1, Utc.with_ymd_and_hms(2026, 1, 1, 15, 0, 0).unwrap()),
+            // 00:30 on Jan 2 at UTC+1
+            entry(2, Utc.with_ymd_and_hms(2026, 1, 1, 23, 30, 0).unwrap()),
+            entry(3, Utc.with_ymd_and_hms(2026, 1, 1, 9, 0, 0).unwrap()),
+            deleted,
+        ];
+        let tz = FixedOffset::east_opt(3600).unwrap();
+        let day = NaiveDate::from_ymd_opt(2026, 1, 1).unwrap();
+
+        let ids: Vec<u64> = plays_on(&entries, day, &tz).iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec![3, 1]);
+
+        let ids: Vec<u64> = plays_on(&entries, day, &Utc).iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec![3, 1, 2]);
+    }
+
+    #[test]
+    fn test_status() {
+        let at = Utc.with_ymd_and_hms(2026, 1, 1, 15, 0, 0).unwrap();
+        let mut play = entry(1, at);
+        assert_eq!(PlayStatus::of(&play), PlayStatus::NotSubmitted);
+
+        play.submissions = vec![submission("lastfm", true)];
+        assert_eq!(PlayStatus::of(&play), PlayStatus::Scrobbled);
+
+        play.submissions
+            .push(submission("listenbrainz:Primary", false));
+        assert_eq!(PlayStatus::of(&play), PlayStatus::Queued);
+
+        play.private = true;
+        assert_eq!(PlayStatus::of(&play), PlayStatus::Private);
+        play.guest = true;
+        assert_eq!(PlayStatus::of(&play), PlayStatus::Guest);
+    }
+}
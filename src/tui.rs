@@ -0,0 +1,262 @@
+// Terminal UI
+// Runs the scrobbler in a terminal instead of the menu bar, e.g. over SSH
+
+use anyhow::Result;
+use osx_scrobbler::energy::{self, Activity};
+use osx_scrobbler::engine::Engine;
+use osx_scrobbler::history::HistoryEntry;
+use osx_scrobbler::media_monitor::SessionProgress;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Gauge, List, ListItem, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::time::{Duration, Instant};
+
+/// Number of scrobbles shown in the recent list
+const RECENT_SCROBBLES: usize = 50;
+
+/// How often the screen is redrawn between polls, to keep the progress bar moving
+const REDRAW_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Run the engine with a terminal UI until the user quits
+pub fn run(engine: Engine) -> Result<()> {
+    let mut terminal = ratatui::init();
+    let result = App {
+        engine,
+        unknown_app: None,
+    }
+    .run(&mut terminal);
+    ratatui::restore();
+    if energy::is_enabled() {
+        println!("{}", energy::report());
+    }
+    result
+}
+
+struct App {
+    engine: Engine,
+    /// App playing right now that isn't allowed or ignored yet
+    unknown_app: Option<String>,
+}
+
+impl App {
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        let mut next_poll_time = Instant::now();
+
+        loop {
+            energy::record(Activity::Wakeup);
+            self.engine.handle_control();
+            if self.engine.quit_requested() {
+                break;
+            }
+            if Instant::now() >= next_poll_time {
+                self.unknown_app = self.engine.poll().unknown_app;
+                next_poll_time = Instant::now() + self.engine.poll_interval();
+                energy::log_hourly();
+            }
+
+            terminal.draw(|frame| self.draw(frame))?;
+
+            let timeout =
+                REDRAW_INTERVAL.min(next_poll_time.saturating_duration_since(Instant::now()));
+            if !event::poll(timeout)? {
+                continue;
+            }
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => break,
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => break,
+                KeyCode::Char('p') => self.engine.set_paused(!self.engine.is_paused()),
+                KeyCode::Char('g') => self.engine.set_guest_mode(!self.engine.is_guest_mode()),
+                KeyCode::Char('x') => {
+                    if let Err(e) = self.engine.clear_now_playing() {
+                        log::warn!("Failed to clear now playing: {:#}", e);
+                    }
+                }
+                KeyCode::Char(c @ ('a' | 'i')) => {
+                    if let Some(bundle_id) = self.unknown_app.take() {
+                        self.engine.set_app_allowed(&bundle_id, c == 'a');
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        log::info!("OSX Scrobbler shutting down");
+        self.engine.shutdown();
+        Ok(())
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [now_playing, progress, lists, footer] = Layout::vertical([
+            Constraint::Length(5),
+            Constraint::Length(3),
+            Constraint::Fill(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [queue, recent] =
+            Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)])
+                .areas(lists);
+
+        let session = self.engine.session();
+        draw_now_playing(frame, now_playing, session.as_ref());
+        draw_progress(frame, progress, session.as_ref());
+
+        let entries = self
+            .engine
+            .history()
+            .map(|h| h.entries())
+            .unwrap_or_default();
+        draw_queue(frame, queue, entries);
+        draw_recent(frame, recent, entries);
+
+        let help = match &self.unknown_app {
+            Some(bundle_id) => Line::from(format!(
+                " New app {}: [a] allow  [i] ignore  [q] quit",
+                bundle_id
+            ))
+            .fg(Color::Yellow),
+            None if self.engine.is_guest_mode() => Line::from(
+                " Guest mode, plays are kept as guest listens: [g] end guest mode  [q] quit",
+            )
+            .fg(Color::Yellow),
+            None if self.engine.is_paused() => {
+                Line::from(" Paused, scrobbles are kept private: [p] resume  [q] quit")
+                    .fg(Color::Yellow)
+            }
+            None => Line::from(" [p] pause  [g] guest mode  [x] clear now playing  [q] quit").dim(),
+        };
+        frame.render_widget(help, footer);
+    }
+}
+
+fn draw_now_playing(frame: &mut Frame, area: Rect, session: Option<&SessionProgress>) {
+    let lines = match session {
+        Some(session) => vec![
+            Line::from(session.track.title.clone()).bold(),
+            Line::from(session.track.artist.clone()),
+            Line::from(session.track.album.clone().unwrap_or_default()).dim(),
+        ],
+        None => vec![Line::from("Nothing playing").dim()],
+    };
+
+    let block = Block::bordered().title(" Now Playing ");
+    let block = match session.and_then(|s| s.bundle_id.as_deref()) {
+        Some(bundle_id) => block.title_bottom(Line::from(format!(" {} ", bundle_id)).dim()),
+        None => block,
+    };
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+fn draw_progress(frame: &mut Frame, area: Rect, session: Option<&SessionProgress>) {
+    let block = Block::bordered().title(" Session ");
+    let Some(session) = session else {
+        frame.render_widget(block, area);
+        return;
+    };
+
+    let ratio = if session.duration > 0 {
+        (session.listened_seconds as f64 / session.duration as f64).min(1.0)
+    } else {
+        0.0
+    };
+
+    let mut label = format_seconds(session.listened_seconds);
+    if session.duration > 0 {
+        label += &format!(" / {}", format_seconds(session.duration));
+    }
+    match (session.scrobbled, session.scrobble_at) {
+        (true, _) => label += " · scrobbled",
+        (false, Some(at)) => label += &format!(" · scrobbles at {}", format_seconds(at)),
+        (false, None) => label += " · not scrobbled",
+    }
+
+    let gauge = Gauge::default()
+        .block(block)
+        .gauge_style(Style::new().fg(if session.scrobbled {
+            Color::Green
+        } else {
+            Color::Blue
+        }))
+        .ratio(ratio)
+        .label(label);
+    frame.render_widget(gauge, area);
+}
+
+/// Scrobbles waiting to be (re-)submitted to some service
+fn draw_queue(frame: &mut Frame, area: Rect, entries: &[HistoryEntry]) {
+    let items: Vec<ListItem> = entries
+        .iter()
+        .rev()
+        .filter(|e| !e.private && !e.deleted)
+        .filter_map(|entry| {
+            let pending: Vec<&str> = entry
+                .submissions
+                .iter()
+                .filter(|s| s.is_queued())
+                .map(|s| s.service.as_str())
+                .collect();
+            (!pending.is_empty()).then(|| {
+                ListItem::new(vec![
+                    Line::from(format!("{} - {}", entry.artist, entry.title)),
+                    Line::from(format!("  → {}", pending.join(", "))).dim(),
+                ])
+            })
+        })
+        .collect();
+
+    let title = format!(" Queue ({}) ", items.len());
+    frame.render_widget(List::new(items).block(Block::bordered().title(title)), area);
+}
+
+fn draw_recent(frame: &mut Frame, area: Rect, entries: &[HistoryEntry]) {
+    let items: Vec<ListItem> = entries
+        .iter()
+        .rev()
+        .filter(|e| !e.deleted)
+        .take(RECENT_SCROBBLES)
+        .map(|entry| {
+            let (status, color) = if entry.guest {
+                ("guest", Color::DarkGray)
+            } else if entry.private {
+                ("private", Color::DarkGray)
+            } else if !entry.submissions.iter().any(|s| s.is_queued()) {
+                ("✓", Color::Green)
+            } else {
+                ("queued", Color::Yellow)
+            };
+
+            ListItem::new(Line::from(vec![
+                format!(
+                    "{} ",
+                    entry
+                        .played_at
+                        .with_timezone(&chrono::Local)
+                        .format("%m-%d %H:%M")
+                )
+                .dim(),
+                format!("{} - {} ", entry.artist, entry.title).into(),
+                status.fg(color),
+            ]))
+        })
+        .collect();
+
+    frame.render_widget(
+        List::new(items).block(Block::bordered().title(" Recent Scrobbles ")),
+        area,
+    );
+}
+
+/// Format seconds as `m:ss`
+fn format_seconds(seconds: u64) -> String {
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
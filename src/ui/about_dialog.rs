@@ -0,0 +1,39 @@
+// About dialog using NSAlert
+
+use objc2_app_kit::{NSAlert, NSAlertSecondButtonReturn, NSAlertStyle};
+use objc2_foundation::{MainThreadMarker, NSString};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Show the version and build details, with a button to copy them for a bug report
+pub fn show_about(details: &str) {
+    // SAFETY: This function must be called from the main thread
+    // The caller (main.rs event loop) ensures this
+    let mtm = unsafe { MainThreadMarker::new_unchecked() };
+
+    let copy = unsafe {
+        let alert = NSAlert::new(mtm);
+        alert.setAlertStyle(NSAlertStyle::Informational);
+        alert.setMessageText(&NSString::from_str("OSX Scrobbler"));
+        alert.setInformativeText(&NSString::from_str(details));
+        alert.addButtonWithTitle(&NSString::from_str("OK"));
+        alert.addButtonWithTitle(&NSString::from_str("Copy"));
+
+        alert.runModal() == NSAlertSecondButtonReturn
+    };
+
+    if copy {
+        if let Err(e) = copy_to_clipboard(details) {
+            log::warn!("Failed to copy build details: {}", e);
+        }
+    }
+}
+
+fn copy_to_clipboard(text: &str) -> std::io::Result<()> {
+    let mut pbcopy = Command::new("pbcopy").stdin(Stdio::piped()).spawn()?;
+    if let Some(mut stdin) = pbcopy.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+    }
+    pbcopy.wait()?;
+    Ok(())
+}
@@ -0,0 +1,130 @@
+// Scrobble editing dialog using NSAlert with text fields
+
+use objc2::rc::Retained;
+use objc2_app_kit::{
+    NSAlert, NSAlertFirstButtonReturn, NSAlertSecondButtonReturn, NSAlertStyle, NSTextField, NSView,
+};
+use objc2_foundation::{MainThreadMarker, NSPoint, NSRect, NSSize, NSString};
+
+const FIELD_WIDTH: f64 = 300.0;
+const FIELD_HEIGHT: f64 = 24.0;
+const FIELD_SPACING: f64 = 8.0;
+
+/// What to do with a play held back for review
+pub enum ReviewChoice {
+    /// Submit it under this artist and title
+    Submit(String, String),
+    Discard,
+}
+
+/// Ask the user for a corrected artist and title
+///
+/// Returns `None` if the dialog was cancelled.
+pub fn show_edit_prompt(artist: &str, title: &str) -> Option<(String, String)> {
+    // SAFETY: This function must be called from the main thread
+    // The caller (main.rs event loop) ensures this
+    let mtm = unsafe { MainThreadMarker::new_unchecked() };
+
+    unsafe {
+        let alert = NSAlert::new(mtm);
+        alert.setAlertStyle(NSAlertStyle::Informational);
+        alert.setMessageText(&NSString::from_str("Edit last scrobble"));
+        alert.setInformativeText(&NSString::from_str(
+            "The scrobble is replaced on ListenBrainz and re-submitted everywhere else. \
+             Future plays of this track are corrected automatically.",
+        ));
+        let (artist_field, title_field) = add_fields(&alert, mtm, artist, title);
+
+        alert.addButtonWithTitle(&NSString::from_str("Save"));
+        alert.addButtonWithTitle(&NSString::from_str("Cancel"));
+
+        if alert.runModal() != NSAlertFirstButtonReturn {
+            return None;
+        }
+
+        field_values(&artist_field, &title_field)
+    }
+}
+
+/// Ask the user whether to submit a play held back for review, and under what artist and title
+///
+/// Returns `None` if the dialog was cancelled or a field was left empty.
+pub fn show_review_prompt(artist: &str, title: &str) -> Option<ReviewChoice> {
+    // SAFETY: This function must be called from the main thread
+    // The caller (main.rs event loop) ensures this
+    let mtm = unsafe { MainThreadMarker::new_unchecked() };
+
+    unsafe {
+        let alert = NSAlert::new(mtm);
+        alert.setAlertStyle(NSAlertStyle::Informational);
+        alert.setMessageText(&NSString::from_str("Review scrobble"));
+        alert.setInformativeText(&NSString::from_str(
+            "This play was held back as it might not be music. Correct it and submit it, \
+             or discard it. Corrections apply to future plays of this track too.",
+        ));
+        let (artist_field, title_field) = add_fields(&alert, mtm, artist, title);
+
+        alert.addButtonWithTitle(&NSString::from_str("Submit"));
+        alert.addButtonWithTitle(&NSString::from_str("Discard"));
+        alert.addButtonWithTitle(&NSString::from_str("Cancel"));
+
+        let response = alert.runModal();
+        if response == NSAlertFirstButtonReturn {
+            let (artist, title) = field_values(&artist_field, &title_field)?;
+            Some(ReviewChoice::Submit(artist, title))
+        } else if response == NSAlertSecondButtonReturn {
+            Some(ReviewChoice::Discard)
+        } else {
+            None
+        }
+    }
+}
+
+/// Add artist and title fields to the alert, artist on top
+unsafe fn add_fields(
+    alert: &NSAlert,
+    mtm: MainThreadMarker,
+    artist: &str,
+    title: &str,
+) -> (Retained<NSTextField>, Retained<NSTextField>) {
+    let container = NSView::initWithFrame(
+        mtm.alloc(),
+        NSRect::new(
+            NSPoint::new(0.0, 0.0),
+            NSSize::new(FIELD_WIDTH, FIELD_HEIGHT * 2.0 + FIELD_SPACING),
+        ),
+    );
+
+    let artist_field = NSTextField::textFieldWithString(&NSString::from_str(artist), mtm);
+    artist_field.setPlaceholderString(Some(&NSString::from_str("Artist")));
+    artist_field.setFrame(NSRect::new(
+        NSPoint::new(0.0, FIELD_HEIGHT + FIELD_SPACING),
+        NSSize::new(FIELD_WIDTH, FIELD_HEIGHT),
+    ));
+    container.addSubview(&artist_field);
+
+    let title_field = NSTextField::textFieldWithString(&NSString::from_str(title), mtm);
+    title_field.setPlaceholderString(Some(&NSString::from_str("Title")));
+    title_field.setFrame(NSRect::new(
+        NSPoint::new(0.0, 0.0),
+        NSSize::new(FIELD_WIDTH, FIELD_HEIGHT),
+    ));
+    container.addSubview(&title_field);
+
+    alert.setAccessoryView(Some(&container));
+    (artist_field, title_field)
+}
+
+/// The trimmed artist and title, or `None` if either is empty
+unsafe fn field_values(
+    artist_field: &NSTextField,
+    title_field: &NSTextField,
+) -> Option<(String, String)> {
+    let artist = artist_field.stringValue().to_string().trim().to_string();
+    let title = title_field.stringValue().to_string().trim().to_string();
+    if artist.is_empty() || title.is_empty() {
+        return None;
+    }
+
+    Some((artist, title))
+}
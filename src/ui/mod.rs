@@ -1,4 +1,17 @@
 // UI module for system tray and dialogs
 
+pub mod about_dialog;
 pub mod app_dialog;
+pub mod edit_dialog;
+pub mod notifications;
+pub mod preferences_dialog;
+pub mod quarantine_dialog;
+pub mod stats_dialog;
+#[cfg(feature = "charts")]
+pub mod stats_window;
 pub mod tray;
+
+/// Format seconds as `m:ss`
+pub fn format_seconds(seconds: u64) -> String {
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
@@ -0,0 +1,368 @@
+// Notifications using UNUserNotificationCenter
+// Now playing shows the album art and when the track will be scrobbled, with Love and Don't
+// Scrobble buttons; scrobbles and expired sign-ins get plain notifications
+
+use super::format_seconds;
+use objc2::rc::Retained;
+use objc2::runtime::{Bool, NSObject, NSObjectProtocol, ProtocolObject};
+use objc2::{declare_class, msg_send_id, mutability, ClassType, DeclaredClass};
+use objc2_app_kit::{NSAlert, NSAlertFirstButtonReturn, NSAlertStyle};
+use objc2_foundation::{MainThreadMarker, NSArray, NSBundle, NSError, NSSet, NSString, NSURL};
+use objc2_user_notifications::{
+    UNAuthorizationOptions, UNMutableNotificationContent, UNNotification, UNNotificationAction,
+    UNNotificationActionOptions, UNNotificationAttachment, UNNotificationCategory,
+    UNNotificationCategoryOptions, UNNotificationContent, UNNotificationInterruptionLevel,
+    UNNotificationPresentationOptions, UNNotificationRequest, UNNotificationResponse,
+    UNUserNotificationCenter, UNUserNotificationCenterDelegate,
+};
+use osx_scrobbler::media_monitor::SessionProgress;
+use osx_scrobbler::scrobbler::Track;
+use std::path::Path;
+use std::sync::Mutex;
+
+const CATEGORY: &str = "now-playing";
+const LOVE_ACTION: &str = "love";
+const DONT_SCROBBLE_ACTION: &str = "dont-scrobble";
+const SCROBBLED_THREAD: &str = "scrobbled";
+const AUTH_THREAD: &str = "auth";
+const FAILED_THREAD: &str = "failed";
+
+/// Button clicked on a notification
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NotificationAction {
+    Love,
+    DontScrobble,
+}
+
+type ActionHandler = Box<dyn Fn(String, NotificationAction) + Send>;
+
+/// Called with the notification's ID when one of its buttons is clicked
+static ACTION_HANDLER: Mutex<Option<ActionHandler>> = Mutex::new(None);
+
+declare_class!(
+    struct NotificationDelegate;
+
+    unsafe impl ClassType for NotificationDelegate {
+        type Super = NSObject;
+        type Mutability = mutability::InteriorMutable;
+        const NAME: &'static str = "OSXScrobblerNotificationDelegate";
+    }
+
+    impl DeclaredClass for NotificationDelegate {
+        type Ivars = ();
+    }
+
+    unsafe impl NSObjectProtocol for NotificationDelegate {}
+
+    unsafe impl UNUserNotificationCenterDelegate for NotificationDelegate {
+        #[method(userNotificationCenter:didReceiveNotificationResponse:withCompletionHandler:)]
+        fn did_receive_response(
+            &self,
+            _center: &UNUserNotificationCenter,
+            response: &UNNotificationResponse,
+            completion_handler: &block2::Block<dyn Fn()>,
+        ) {
+            handle_response(response);
+            completion_handler.call(());
+        }
+
+        // Menu bar apps count as being in the foreground, which hides notifications by default
+        #[method(userNotificationCenter:willPresentNotification:withCompletionHandler:)]
+        fn will_present(
+            &self,
+            _center: &UNUserNotificationCenter,
+            _notification: &UNNotification,
+            completion_handler: &block2::Block<dyn Fn(UNNotificationPresentationOptions)>,
+        ) {
+            completion_handler.call((UNNotificationPresentationOptions::UNNotificationPresentationOptionBanner
+                | UNNotificationPresentationOptions::UNNotificationPresentationOptionList,));
+        }
+    }
+);
+
+impl NotificationDelegate {
+    fn new() -> Retained<Self> {
+        let this = Self::alloc().set_ivars(());
+        unsafe { msg_send_id![super(this), init] }
+    }
+}
+
+fn handle_response(response: &UNNotificationResponse) {
+    let action = unsafe { response.actionIdentifier() }.to_string();
+    let action = match action.as_str() {
+        LOVE_ACTION => NotificationAction::Love,
+        DONT_SCROBBLE_ACTION => NotificationAction::DontScrobble,
+        // Clicked or dismissed
+        _ => return,
+    };
+    let id = unsafe { response.notification().request().identifier() }.to_string();
+
+    if let Some(handler) = ACTION_HANDLER.lock().unwrap().as_ref() {
+        handler(id, action);
+    }
+}
+
+/// Posts notifications and keeps track of the latest now playing one for its buttons
+pub struct Notifier {
+    center: Retained<UNUserNotificationCenter>,
+    // The notification center only keeps a weak reference
+    _delegate: Retained<NotificationDelegate>,
+    /// ID and track of the notification last shown
+    current: Option<(String, Track)>,
+    next_id: u64,
+}
+
+impl Notifier {
+    /// Set up notifications, calling `on_action` when a button is clicked
+    ///
+    /// Returns `None` when not running from the app bundle, which UNUserNotificationCenter
+    /// requires.
+    pub fn new(on_action: impl Fn(String, NotificationAction) + Send + 'static) -> Option<Self> {
+        let bundle_id = unsafe { NSBundle::mainBundle().bundleIdentifier() };
+        if bundle_id.is_none() {
+            log::info!("Not running from the app bundle, notifications are unavailable");
+            return None;
+        }
+
+        *ACTION_HANDLER.lock().unwrap() = Some(Box::new(on_action));
+
+        unsafe {
+            let center = UNUserNotificationCenter::currentNotificationCenter();
+            let delegate = NotificationDelegate::new();
+            center.setDelegate(Some(ProtocolObject::from_ref(&*delegate)));
+
+            let love = UNNotificationAction::actionWithIdentifier_title_options(
+                &NSString::from_str(LOVE_ACTION),
+                &NSString::from_str("Love"),
+                UNNotificationActionOptions::empty(),
+            );
+            let dont_scrobble = UNNotificationAction::actionWithIdentifier_title_options(
+                &NSString::from_str(DONT_SCROBBLE_ACTION),
+                &NSString::from_str("Don't Scrobble"),
+                UNNotificationActionOptions::UNNotificationActionOptionDestructive,
+            );
+            let category =
+                UNNotificationCategory::categoryWithIdentifier_actions_intentIdentifiers_options(
+                    &NSString::from_str(CATEGORY),
+                    &NSArray::from_vec(vec![love, dont_scrobble]),
+                    &NSArray::from_vec(Vec::<Retained<NSString>>::new()),
+                    UNNotificationCategoryOptions::empty(),
+                );
+            center.setNotificationCategories(&NSSet::from_vec(vec![category]));
+
+            Some(Self {
+                center,
+                _delegate: delegate,
+                current: None,
+                next_id: 1,
+            })
+        }
+    }
+
+    /// Ask macOS for permission to show notifications (only prompts the first time)
+    pub fn request_permission(&self) {
+        let handler = block2::RcBlock::new(|granted: Bool, _error: *mut NSError| {
+            if !granted.as_bool() {
+                log::warn!("Notifications are turned off for OSX Scrobbler in System Settings");
+            }
+        });
+        unsafe {
+            self.center
+                .requestAuthorizationWithOptions_completionHandler(
+                    UNAuthorizationOptions::UNAuthorizationOptionAlert,
+                    &handler,
+                );
+        }
+    }
+
+    /// Show a notification for a track that started playing, replacing the previous one
+    pub fn show_now_playing(
+        &mut self,
+        track: &Track,
+        session: Option<&SessionProgress>,
+        artwork: Option<&Path>,
+    ) {
+        let id = self.next_id(CATEGORY);
+
+        unsafe {
+            let content = UNMutableNotificationContent::new();
+            content.setTitle(&NSString::from_str(&track.title));
+            content.setSubtitle(&NSString::from_str(&track.artist));
+            content.setBody(&NSString::from_str(&body(track, session)));
+            content.setCategoryIdentifier(&NSString::from_str(CATEGORY));
+            content.setThreadIdentifier(&NSString::from_str(CATEGORY));
+
+            if let Some(attachment) = artwork.and_then(|path| attachment(&id, path)) {
+                content.setAttachments(&NSArray::from_vec(vec![attachment]));
+            }
+
+            self.post(&id, &content);
+
+            // Only the latest track is kept in Notification Center
+            if let Some((previous, _)) = &self.current {
+                self.center
+                    .removeDeliveredNotificationsWithIdentifiers(&NSArray::from_vec(vec![
+                        NSString::from_str(previous),
+                    ]));
+            }
+        }
+
+        self.current = Some((id, track.clone()));
+    }
+
+    /// Show a notification that a track was scrobbled
+    pub fn show_scrobbled(&mut self, track: &Track) {
+        let id = self.next_id(SCROBBLED_THREAD);
+
+        unsafe {
+            let content = UNMutableNotificationContent::new();
+            content.setTitle(&NSString::from_str("Scrobbled"));
+            content.setBody(&NSString::from_str(&format!(
+                "{} - {}",
+                track.artist, track.title
+            )));
+            content.setThreadIdentifier(&NSString::from_str(SCROBBLED_THREAD));
+            self.post(&id, &content);
+        }
+    }
+
+    /// Show a notification that a scrobble couldn't be submitted to a service
+    pub fn show_failed(&mut self, track: &Track, service: &str, error: &str) {
+        let id = self.next_id(FAILED_THREAD);
+
+        unsafe {
+            let content = UNMutableNotificationContent::new();
+            content.setTitle(&NSString::from_str(&format!(
+                "Couldn't scrobble to {}",
+                service
+            )));
+            content.setSubtitle(&NSString::from_str(&format!(
+                "{} - {}",
+                track.artist, track.title
+            )));
+            content.setBody(&NSString::from_str(&format!(
+                "{}. The scrobble is queued and retried later.",
+                error.trim_end_matches('.')
+            )));
+            content.setThreadIdentifier(&NSString::from_str(FAILED_THREAD));
+            self.post(&id, &content);
+        }
+    }
+
+    /// Warn that a service rejected its credentials
+    ///
+    /// Time sensitive, so it also breaks through Focus modes that allow those.
+    pub fn show_auth_expired(&mut self, service: &str) {
+        let id = self.next_id(AUTH_THREAD);
+
+        unsafe {
+            let content = UNMutableNotificationContent::new();
+            content.setTitle(&NSString::from_str(&format!(
+                "Sign in to {} again",
+                service
+            )));
+            content.setBody(&NSString::from_str(&format!(
+                "{} rejected OSX Scrobbler's credentials. Scrobbles are kept and sent once you \
+                 sign in again.",
+                service
+            )));
+            content.setThreadIdentifier(&NSString::from_str(AUTH_THREAD));
+            content.setInterruptionLevel(UNNotificationInterruptionLevel::TimeSensitive);
+            self.post(&id, &content);
+        }
+    }
+
+    fn next_id(&mut self, prefix: &str) -> String {
+        let id = format!("{}-{}", prefix, self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    fn post(&self, id: &str, content: &UNNotificationContent) {
+        unsafe {
+            let request = UNNotificationRequest::requestWithIdentifier_content_trigger(
+                &NSString::from_str(id),
+                content,
+                None,
+            );
+            self.center
+                .addNotificationRequest_withCompletionHandler(&request, None);
+        }
+    }
+
+    /// Track a notification was shown for, if it's the latest one
+    pub fn track(&self, id: &str) -> Option<&Track> {
+        self.current
+            .as_ref()
+            .filter(|(current, _)| current == id)
+            .map(|(_, track)| track)
+    }
+}
+
+/// Album, length and when the track will be scrobbled
+fn body(track: &Track, session: Option<&SessionProgress>) -> String {
+    let mut parts = Vec::new();
+    if let Some(album) = &track.album {
+        parts.push(album.clone());
+    }
+    if let Some(session) = session {
+        if session.duration > 0 {
+            parts.push(format_seconds(session.duration));
+        }
+        parts.push(match session.scrobble_at {
+            Some(at) => format!("Scrobbles at {}", format_seconds(at)),
+            None => "Won't be scrobbled".to_string(),
+        });
+    }
+    parts.join(" · ")
+}
+
+/// Attach a copy of the album art, since macOS moves attached files into its own store
+fn attachment(id: &str, artwork: &Path) -> Option<Retained<UNNotificationAttachment>> {
+    let extension = artwork.extension()?.to_str()?;
+    let copy = std::env::temp_dir().join(format!("osx-scrobbler-{}.{}", id, extension));
+    if let Err(e) = std::fs::copy(artwork, &copy) {
+        log::warn!("Failed to copy artwork for notification: {}", e);
+        return None;
+    }
+
+    let url = unsafe { NSURL::fileURLWithPath(&NSString::from_str(&copy.to_string_lossy())) };
+    match unsafe {
+        UNNotificationAttachment::attachmentWithIdentifier_URL_options_error(
+            &NSString::from_str("artwork"),
+            &url,
+            None,
+        )
+    } {
+        Ok(attachment) => Some(attachment),
+        Err(e) => {
+            log::warn!("Failed to attach artwork to notification: {}", unsafe {
+                e.localizedDescription()
+            });
+            let _ = std::fs::remove_file(&copy);
+            None
+        }
+    }
+}
+
+/// Ask once whether to show notifications, before macOS asks for the permission
+pub fn show_onboarding_prompt() -> bool {
+    // SAFETY: This function must be called from the main thread
+    // The caller (main.rs event loop) ensures this
+    let mtm = unsafe { MainThreadMarker::new_unchecked() };
+
+    unsafe {
+        let alert = NSAlert::new(mtm);
+        alert.setAlertStyle(NSAlertStyle::Informational);
+        alert.setMessageText(&NSString::from_str("Show now playing notifications?"));
+        alert.setInformativeText(&NSString::from_str(
+            "OSX Scrobbler can show a notification for each new track, with its album art and \
+             buttons to love it or not scrobble it.\n\nmacOS will ask for permission next. You \
+             can change this later with [notifications] enabled in the config file.",
+        ));
+        alert.addButtonWithTitle(&NSString::from_str("Show Notifications"));
+        alert.addButtonWithTitle(&NSString::from_str("Not Now"));
+
+        alert.runModal() == NSAlertFirstButtonReturn
+    }
+}
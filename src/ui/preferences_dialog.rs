@@ -0,0 +1,229 @@
+// Preferences window using NSAlert with a form of labeled fields
+
+use anyhow::{Context, Result};
+use objc2::rc::Retained;
+use objc2_app_kit::{NSAlert, NSAlertFirstButtonReturn, NSAlertStyle, NSTextField, NSView};
+use objc2_foundation::{MainThreadMarker, NSPoint, NSRect, NSSize, NSString};
+use osx_scrobbler::config::Config;
+
+const LABEL_WIDTH: f64 = 160.0;
+const FIELD_WIDTH: f64 = 300.0;
+const ROW_HEIGHT: f64 = 24.0;
+const ROW_SPACING: f64 = 8.0;
+/// Lines of cleanup patterns shown at once
+const PATTERN_LINES: f64 = 4.0;
+
+/// A labeled field of the form
+struct Row {
+    label: String,
+    value: String,
+    placeholder: &'static str,
+    lines: f64,
+}
+
+impl Row {
+    fn text(label: &str, value: String, placeholder: &'static str) -> Self {
+        Self {
+            label: label.to_string(),
+            value,
+            placeholder,
+            lines: 1.0,
+        }
+    }
+
+    /// A field for a secret, which starts empty so it isn't shown on screen
+    fn secret(label: &str, value: &str) -> Self {
+        let placeholder = if value.is_empty() {
+            "Not set"
+        } else {
+            "Set (leave empty to keep)"
+        };
+        Self::text(label, String::new(), placeholder)
+    }
+}
+
+/// Let the user edit the common settings of the config, with `error` from the last attempt
+///
+/// Credentials are those of the active profile; secrets left empty are kept. Returns `None` if the dialog was cancelled, or
+/// the edited config, which still has to be validated and saved.
+pub fn show_preferences(config: &Config, error: Option<&str>) -> Option<Result<Config>> {
+    // SAFETY: This function must be called from the main thread
+    // The caller (main.rs event loop) ensures this
+    let mtm = unsafe { MainThreadMarker::new_unchecked() };
+
+    let mut rows = vec![
+        Row::text(
+            "Refresh interval (s)",
+            config.refresh_interval.to_string(),
+            "",
+        ),
+        Row::text(
+            "Scrobble threshold (%)",
+            config.scrobble_threshold.to_string(),
+            "",
+        ),
+        Row {
+            lines: PATTERN_LINES,
+            ..Row::text(
+                "Cleanup patterns",
+                config.cleanup.patterns.join("\n"),
+                "One regex per line (⌥↩ adds a line)",
+            )
+        },
+        Row::text(
+            "Allowed apps",
+            config.app_filtering.allowed_apps.join(", "),
+            "Bundle IDs, separated by commas",
+        ),
+        Row::text(
+            "Ignored apps",
+            config.app_filtering.ignored_apps.join(", "),
+            "Bundle IDs, separated by commas",
+        ),
+    ];
+    let (lastfm, listenbrainz) = config.credentials();
+    if let Some(lastfm) = lastfm {
+        rows.push(Row::text("Last.fm API key", lastfm.api_key.clone(), ""));
+        rows.push(Row::secret("Last.fm API secret", &lastfm.api_secret));
+        rows.push(Row::secret("Last.fm session key", &lastfm.session_key));
+    }
+    for lb in listenbrainz {
+        rows.push(Row::secret(&format!("{} token", lb.name), &lb.token));
+    }
+
+    let values = unsafe { run_form(mtm, &rows, error) }?;
+    Some(apply(config, values))
+}
+
+/// Show the form and return the value of each row, or `None` if it was cancelled
+unsafe fn run_form(
+    mtm: MainThreadMarker,
+    rows: &[Row],
+    error: Option<&str>,
+) -> Option<Vec<String>> {
+    let alert = NSAlert::new(mtm);
+    alert.setAlertStyle(NSAlertStyle::Informational);
+    alert.setMessageText(&NSString::from_str("Preferences"));
+    let mut text = "Changes are saved to the config file and applied right away. Other settings \
+                    are in the config file."
+        .to_string();
+    if let Some(error) = error {
+        text = format!("Couldn't save: {}\n\n{}", error, text);
+    }
+    alert.setInformativeText(&NSString::from_str(&text));
+
+    let height = rows
+        .iter()
+        .map(|row| ROW_HEIGHT * row.lines + ROW_SPACING)
+        .sum::<f64>()
+        - ROW_SPACING;
+    let container = NSView::initWithFrame(
+        mtm.alloc(),
+        NSRect::new(
+            NSPoint::new(0.0, 0.0),
+            NSSize::new(LABEL_WIDTH + FIELD_WIDTH, height),
+        ),
+    );
+
+    // Laid out from the top, as the view's origin is at the bottom left
+    let mut top = height;
+    let mut fields: Vec<Retained<NSTextField>> = Vec::new();
+    for row in rows {
+        let row_height = ROW_HEIGHT * row.lines;
+        top -= row_height;
+
+        let label = NSTextField::labelWithString(&NSString::from_str(&row.label), mtm);
+        label.setFrame(NSRect::new(
+            NSPoint::new(0.0, top + row_height - ROW_HEIGHT),
+            NSSize::new(LABEL_WIDTH, ROW_HEIGHT),
+        ));
+        container.addSubview(&label);
+
+        let frame = NSRect::new(
+            NSPoint::new(LABEL_WIDTH, top),
+            NSSize::new(FIELD_WIDTH, row_height),
+        );
+        let field = NSTextField::initWithFrame(mtm.alloc(), frame);
+        field.setStringValue(&NSString::from_str(&row.value));
+        if !row.placeholder.is_empty() {
+            field.setPlaceholderString(Some(&NSString::from_str(row.placeholder)));
+        }
+        if row.lines > 1.0 {
+            field.setUsesSingleLineMode(false);
+        }
+        container.addSubview(&field);
+        fields.push(field);
+
+        top -= ROW_SPACING;
+    }
+    alert.setAccessoryView(Some(&container));
+
+    alert.addButtonWithTitle(&NSString::from_str("Save"));
+    alert.addButtonWithTitle(&NSString::from_str("Cancel"));
+
+    if alert.runModal() != NSAlertFirstButtonReturn {
+        return None;
+    }
+
+    Some(
+        fields
+            .iter()
+            .map(|field| field.stringValue().to_string())
+            .collect(),
+    )
+}
+
+/// The config with the form's values, in the order of its rows
+fn apply(config: &Config, values: Vec<String>) -> Result<Config> {
+    let mut values = values.into_iter();
+    let mut next = || values.next().unwrap_or_default().trim().to_string();
+    let mut config = config.clone();
+
+    config.refresh_interval = next()
+        .parse()
+        .context("Refresh interval must be a whole number of seconds")?;
+    config.scrobble_threshold = next()
+        .parse()
+        .context("Scrobble threshold must be a percentage from 1 to 100")?;
+
+    let patterns: Vec<String> = next()
+        .lines()
+        .map(str::trim)
+        .filter(|pattern| !pattern.is_empty())
+        .map(str::to_string)
+        .collect();
+    for pattern in &patterns {
+        regex::Regex::new(pattern)
+            .with_context(|| format!("Invalid cleanup pattern {}", pattern))?;
+    }
+    config.cleanup.patterns = patterns;
+
+    config.app_filtering.allowed_apps = bundle_ids(&next());
+    config.app_filtering.ignored_apps = bundle_ids(&next());
+
+    if let Some(lastfm) = config.lastfm_mut() {
+        lastfm.api_key = next();
+        set_secret(&mut lastfm.api_secret, next());
+        set_secret(&mut lastfm.session_key, next());
+    }
+    for lb in config.listenbrainz_mut() {
+        set_secret(&mut lb.token, next());
+    }
+
+    Ok(config)
+}
+
+/// Replace a secret, unless its field was left empty
+fn set_secret(secret: &mut String, value: String) {
+    if !value.is_empty() {
+        *secret = value;
+    }
+}
+
+fn bundle_ids(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(str::to_string)
+        .collect()
+}
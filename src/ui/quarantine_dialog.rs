@@ -0,0 +1,29 @@
+// Quarantine dialog using NSAlert
+
+use objc2_app_kit::{NSAlert, NSAlertFirstButtonReturn, NSAlertStyle};
+use objc2_foundation::{MainThreadMarker, NSString};
+use std::path::Path;
+
+/// Explain that the app bundle is quarantined and ask whether to clear it
+pub fn show_quarantine_prompt(bundle: &Path) -> bool {
+    // SAFETY: This function must be called from the main thread
+    // The caller (main.rs event loop) ensures this
+    let mtm = unsafe { MainThreadMarker::new_unchecked() };
+
+    unsafe {
+        let alert = NSAlert::new(mtm);
+        alert.setAlertStyle(NSAlertStyle::Warning);
+        alert.setMessageText(&NSString::from_str("OSX Scrobbler may not start at login"));
+        alert.setInformativeText(&NSString::from_str(&format!(
+            "macOS marked {} as downloaded from the internet (quarantined). That's why it \
+             asked whether to open it, and as a login item it can fail to start without \
+             showing that question.\n\nRemoving the mark tells macOS you trust this copy. Only \
+             do this if you got it from the OSX Scrobbler releases or built it yourself.",
+            bundle.display()
+        )));
+        alert.addButtonWithTitle(&NSString::from_str("Remove Quarantine"));
+        alert.addButtonWithTitle(&NSString::from_str("Not Now"));
+
+        alert.runModal() == NSAlertFirstButtonReturn
+    }
+}
@@ -0,0 +1,20 @@
+// Statistics window using NSAlert
+
+use objc2_app_kit::{NSAlert, NSAlertStyle};
+use objc2_foundation::{MainThreadMarker, NSString};
+
+/// Show charts of recent listening, computed from the local history
+pub fn show_stats(report: &str) {
+    // SAFETY: This function must be called from the main thread
+    // The caller (main.rs event loop) ensures this
+    let mtm = unsafe { MainThreadMarker::new_unchecked() };
+
+    unsafe {
+        let alert = NSAlert::new(mtm);
+        alert.setAlertStyle(NSAlertStyle::Informational);
+        alert.setMessageText(&NSString::from_str("Statistics"));
+        alert.setInformativeText(&NSString::from_str(report));
+        alert.addButtonWithTitle(&NSString::from_str("OK"));
+        alert.runModal();
+    }
+}
@@ -0,0 +1,196 @@
+// Statistics window using egui
+// Drawn by a child process, since eframe needs an event loop of its own besides the tray's
+
+use anyhow::{Context, Result};
+use eframe::egui;
+use egui_plot::{Bar, BarChart, Plot};
+use osx_scrobbler::stats::{self, Charts};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Height of each chart, in points
+const CHART_HEIGHT: f32 = 160.0;
+/// Room for artist names beside their bars, in points (longer ones show in full on hover)
+const ARTIST_WIDTH: f32 = 140.0;
+
+/// Open the window in a child process (`osx-scrobbler stats-window`), handing it the charts on stdin
+pub fn open(charts: &Charts) -> Result<()> {
+    let exe = std::env::current_exe().context("Failed to find the running binary")?;
+    let mut child = Command::new(exe)
+        .arg("stats-window")
+        .stdin(Stdio::piped())
+        .spawn()
+        .context("Failed to start the statistics window")?;
+
+    let stdin = child
+        .stdin
+        .take()
+        .context("Statistics window has no stdin")?;
+    serde_json::to_writer(&stdin, charts).context("Failed to send the charts")?;
+    (&stdin).flush()?;
+    drop(stdin);
+
+    // Reap it once the window is closed
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+/// Show the charts until the window is closed (run by the child process)
+pub fn run(charts: Charts) -> Result<()> {
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default()
+            .with_title("Statistics")
+            .with_inner_size([520.0, 640.0]),
+        ..Default::default()
+    };
+    eframe::run_native(
+        "Statistics",
+        options,
+        Box::new(|_| {
+            Ok(Box::new(StatsWindow {
+                charts,
+                focused: false,
+            }))
+        }),
+    )
+    .map_err(|e| anyhow::anyhow!("Failed to show the statistics window: {}", e))
+}
+
+struct StatsWindow {
+    charts: Charts,
+    /// Whether the window was brought to the front (opened from the menu bar, so nothing else does)
+    focused: bool,
+}
+
+impl eframe::App for StatsWindow {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        if !self.focused {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+            self.focused = true;
+        }
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            let days: Vec<String> = self
+                .charts
+                .days
+                .iter()
+                .map(|(day, _)| day.format("%a %e").to_string())
+                .collect();
+            ui.heading(format!(
+                "Scrobbles per day, last {} days",
+                stats::REPORT_DAYS
+            ));
+            chart(
+                ui,
+                "days",
+                self.charts
+                    .days
+                    .iter()
+                    .zip(&days)
+                    .enumerate()
+                    .map(|(i, ((_, count), day))| Bar::new(i as f64, *count as f64).name(day))
+                    .collect(),
+                false,
+                &days,
+            );
+
+            ui.add_space(12.0);
+            let hours: Vec<String> = (0..24).map(|hour| format!("{:02}", hour)).collect();
+            ui.heading(format!(
+                "Scrobbles by hour, last {} days",
+                stats::REPORT_HOUR_DAYS
+            ));
+            chart(
+                ui,
+                "hours",
+                self.charts
+                    .hours
+                    .iter()
+                    .enumerate()
+                    .map(|(hour, count)| {
+                        Bar::new(hour as f64, *count as f64).name(format!("{:02}:00", hour))
+                    })
+                    .collect(),
+                false,
+                &hours,
+            );
+
+            ui.add_space(12.0);
+            ui.heading(format!("Top artists, last {} days", stats::REPORT_DAYS));
+            if self.charts.artists.is_empty() {
+                ui.label("No scrobbles yet");
+                return;
+            }
+            // Listed top down, so the first artist gets the highest position
+            let artists: Vec<String> = self
+                .charts
+                .artists
+                .iter()
+                .rev()
+                .map(|artist| artist.key.clone())
+                .collect();
+            chart(
+                ui,
+                "artists",
+                self.charts
+                    .artists
+                    .iter()
+                    .rev()
+                    .enumerate()
+                    .map(|(i, artist)| {
+                        Bar::new(i as f64, artist.scrobbles as f64).name(format!(
+                            "{} ({})",
+                            artist.key,
+                            stats::format_duration(artist.seconds)
+                        ))
+                    })
+                    .collect(),
+                true,
+                &artists,
+            );
+        });
+    }
+}
+
+/// Bar chart that can't be dragged or zoomed, with `labels` on the axis the bars stand on
+fn chart(ui: &mut egui::Ui, id: &str, bars: Vec<Bar>, horizontal: bool, labels: &[String]) {
+    let label = |value: f64| {
+        // Only whole positions have a bar
+        if value.fract() != 0.0 || value < 0.0 {
+            return String::new();
+        }
+        labels.get(value as usize).cloned().unwrap_or_default()
+    };
+    let count = |value: f64| {
+        if value.fract() == 0.0 && value >= 0.0 {
+            format!("{}", value)
+        } else {
+            String::new()
+        }
+    };
+
+    let mut chart = BarChart::new(bars).width(0.7);
+    let mut plot = Plot::new(id)
+        .height(CHART_HEIGHT)
+        .allow_drag(false)
+        .allow_zoom(false)
+        .allow_scroll(false)
+        .allow_boxed_zoom(false)
+        .allow_double_click_reset(false)
+        .show_x(false)
+        .show_y(false);
+    if horizontal {
+        chart = chart.horizontal();
+        plot = plot
+            .include_x(0.0)
+            .y_axis_min_width(ARTIST_WIDTH)
+            .x_axis_formatter(move |mark, _| count(mark.value))
+            .y_axis_formatter(move |mark, _| label(mark.value));
+    } else {
+        plot = plot
+            .include_y(0.0)
+            .x_axis_formatter(move |mark, _| label(mark.value))
+            .y_axis_formatter(move |mark, _| count(mark.value));
+    }
+    plot.show(ui, |plot_ui| plot_ui.bar_chart(chart));
+}
@@ -1,8 +1,15 @@
 // System tray implementation
 
+use super::format_seconds;
 use anyhow::{Context, Result};
+use chrono::{DateTime, Local, Utc};
+use osx_scrobbler::config::{AppFilteringConfig, MenuBarConfig};
+use osx_scrobbler::energy::{self, Activity};
+use osx_scrobbler::history::HistoryEntry;
+use osx_scrobbler::media_monitor::SessionProgress;
+use osx_scrobbler::scrobbler::Track;
 use tray_icon::{
-    menu::{Menu, MenuItem, PredefinedMenuItem},
+    menu::{CheckMenuItem, Menu, MenuItem, PredefinedMenuItem, Submenu},
     Icon, TrayIcon, TrayIconBuilder,
 };
 
@@ -64,33 +71,157 @@ pub struct TrayState {
 
 /// System tray manager
 pub struct TrayManager {
-    _tray_icon: TrayIcon,
+    tray_icon: TrayIcon,
     state: TrayState,
+    menu_bar: MenuBarConfig,
+    /// Text next to the icon
+    title: Option<String>,
     #[allow(dead_code)]
     menu: Menu,
     now_playing_item: MenuItem,
+    scrobble_countdown_item: MenuItem,
     last_scrobble_item: MenuItem,
+    pub love_item: MenuItem,
+    /// Track the love item shows the result of loving, until something else plays
+    loved: Option<(Track, String)>,
+    pub edit_last_item: MenuItem,
+    review_menu: Submenu,
+    /// Slots for plays held back for review; the first `review_ids.len()` are in the menu
+    pub review_items: Vec<MenuItem>,
+    review_ids: Vec<u64>,
+    pub submit_reviews_item: MenuItem,
+    pub discard_reviews_item: MenuItem,
+    pub scrobble_now_item: MenuItem,
+    pub skip_scrobble_item: MenuItem,
+    pub clear_now_playing_item: MenuItem,
+    pause_menu: Submenu,
+    pub resume_item: MenuItem,
+    /// Pause choices, with how many minutes they pause for (None until resumed)
+    pub pause_items: Vec<(Option<u64>, MenuItem)>,
+    pub guest_mode_item: CheckMenuItem,
+    pub allowed_apps: AppList,
+    pub ignored_apps: AppList,
+    /// Credential profiles, with None for the default credentials (empty without profiles)
+    pub profile_items: Vec<(Option<String>, CheckMenuItem)>,
+    pub stats_item: MenuItem,
+    pub preferences_item: MenuItem,
+    pub about_item: MenuItem,
     pub quit_item: MenuItem,
 }
 
 impl TrayManager {
-    /// Create a new tray manager
-    pub fn new() -> Result<Self> {
+    /// Create a new tray manager, with a Profile menu if any profiles are configured
+    pub fn new(profiles: &[String]) -> Result<Self> {
         let state = TrayState::default();
 
         // Create menu items
         let now_playing_item = MenuItem::new("Now Playing: None", false, None);
+        let scrobble_countdown_item = MenuItem::new(countdown_text(None), false, None);
         let last_scrobble_item = MenuItem::new("Last Scrobbled: None", false, None);
+        let edit_last_item = MenuItem::new("Edit Last Scrobble…", true, None);
+        let love_item = MenuItem::new(LOVE_TEXT, false, None);
+        let review_menu = Submenu::new(REVIEW_TEXT, false);
+        let review_items: Vec<MenuItem> = (0..REVIEW_SLOTS)
+            .map(|_| MenuItem::new("", true, None))
+            .collect();
+        let submit_reviews_item = MenuItem::new("Submit All", true, None);
+        let discard_reviews_item = MenuItem::new("Discard All", true, None);
+        let scrobble_now_item = MenuItem::new("Scrobble Now", false, None);
+        let skip_scrobble_item = MenuItem::new("Don't Scrobble This Play", false, None);
+        let clear_now_playing_item = MenuItem::new("Clear Now Playing", false, None);
+        let pause_menu = Submenu::new(PAUSE_TEXT, true);
+        let resume_item = MenuItem::new("Resume Scrobbling", false, None);
+        let pause_items: Vec<(Option<u64>, MenuItem)> = PAUSE_CHOICES
+            .iter()
+            .map(|&(minutes, text)| (minutes, MenuItem::new(text, true, None)))
+            .collect();
+        let guest_mode_item = CheckMenuItem::new("Guest Mode", true, false, None);
+        let allowed_apps = AppList::new("Allowed", "Move to Ignored")?;
+        let ignored_apps = AppList::new("Ignored", "Move to Allowed")?;
+        let profile_items: Vec<(Option<String>, CheckMenuItem)> = if profiles.is_empty() {
+            Vec::new()
+        } else {
+            std::iter::once((None, CheckMenuItem::new("Default", true, false, None)))
+                .chain(profiles.iter().map(|name| {
+                    let item = CheckMenuItem::new(name, true, false, None);
+                    (Some(name.clone()), item)
+                }))
+                .collect()
+        };
         let separator = PredefinedMenuItem::separator();
+        let stats_item = MenuItem::new("Statistics…", true, None);
+        let preferences_item = MenuItem::new("Preferences…", true, None);
+        let about_item = MenuItem::new("About OSX Scrobbler", true, None);
         let quit_item = MenuItem::new("Quit", true, None);
 
         // Build menu
         let menu = Menu::new();
         menu.append(&now_playing_item)
             .context("Failed to add now playing item")?;
+        menu.append(&scrobble_countdown_item)
+            .context("Failed to add scrobble countdown item")?;
+        menu.append(&love_item).context("Failed to add love item")?;
+        menu.append(&scrobble_now_item)
+            .context("Failed to add scrobble now item")?;
+        menu.append(&skip_scrobble_item)
+            .context("Failed to add skip scrobble item")?;
+        menu.append(&clear_now_playing_item)
+            .context("Failed to add clear now playing item")?;
         menu.append(&last_scrobble_item)
             .context("Failed to add last scrobble item")?;
+        menu.append(&edit_last_item)
+            .context("Failed to add edit last scrobble item")?;
+        review_menu
+            .append(&PredefinedMenuItem::separator())
+            .context("Failed to add separator")?;
+        review_menu
+            .append(&submit_reviews_item)
+            .context("Failed to add submit all item")?;
+        review_menu
+            .append(&discard_reviews_item)
+            .context("Failed to add discard all item")?;
+        menu.append(&review_menu)
+            .context("Failed to add review menu")?;
+        pause_menu
+            .append(&resume_item)
+            .context("Failed to add resume item")?;
+        pause_menu
+            .append(&PredefinedMenuItem::separator())
+            .context("Failed to add separator")?;
+        for (_, item) in &pause_items {
+            pause_menu
+                .append(item)
+                .context("Failed to add pause item")?;
+        }
+        menu.append(&pause_menu)
+            .context("Failed to add pause menu")?;
+        menu.append(&guest_mode_item)
+            .context("Failed to add guest mode item")?;
+        let apps_menu = Submenu::new("Apps", true);
+        apps_menu
+            .append(&allowed_apps.menu)
+            .context("Failed to add allowed apps menu")?;
+        apps_menu
+            .append(&ignored_apps.menu)
+            .context("Failed to add ignored apps menu")?;
+        menu.append(&apps_menu).context("Failed to add apps menu")?;
+        if !profile_items.is_empty() {
+            let profile_menu = Submenu::new("Profile", true);
+            for (_, item) in &profile_items {
+                profile_menu
+                    .append(item)
+                    .context("Failed to add profile item")?;
+            }
+            menu.append(&profile_menu)
+                .context("Failed to add profile menu")?;
+        }
         menu.append(&separator).context("Failed to add separator")?;
+        menu.append(&stats_item)
+            .context("Failed to add statistics item")?;
+        menu.append(&preferences_item)
+            .context("Failed to add preferences item")?;
+        menu.append(&about_item)
+            .context("Failed to add about item")?;
         menu.append(&quit_item).context("Failed to add quit item")?;
 
         // Create tray icon
@@ -106,11 +237,35 @@ impl TrayManager {
         log::info!("Tray icon created successfully");
 
         Ok(Self {
-            _tray_icon: tray_icon,
+            tray_icon,
             state,
+            menu_bar: MenuBarConfig::default(),
+            title: None,
             menu,
             now_playing_item,
+            scrobble_countdown_item,
             last_scrobble_item,
+            love_item,
+            loved: None,
+            edit_last_item,
+            review_menu,
+            review_items,
+            review_ids: Vec::new(),
+            submit_reviews_item,
+            discard_reviews_item,
+            scrobble_now_item,
+            skip_scrobble_item,
+            clear_now_playing_item,
+            pause_menu,
+            resume_item,
+            pause_items,
+            guest_mode_item,
+            allowed_apps,
+            ignored_apps,
+            profile_items,
+            stats_item,
+            preferences_item,
+            about_item,
             quit_item,
         })
     }
@@ -123,12 +278,35 @@ impl TrayManager {
             "Now Playing: None".to_string()
         };
 
-        self.now_playing_item.set_text(text);
+        set_text(&self.now_playing_item, text);
         self.state.now_playing = track;
+        self.update_title();
 
         Ok(())
     }
 
+    /// Show the current track next to the icon, or stop showing it
+    pub fn set_menu_bar(&mut self, config: &MenuBarConfig) {
+        if self.menu_bar != *config {
+            self.menu_bar = config.clone();
+            self.update_title();
+        }
+    }
+
+    fn update_title(&mut self) {
+        let title = self
+            .state
+            .now_playing
+            .as_deref()
+            .filter(|_| self.menu_bar.show_track)
+            .map(|track| truncate(track, self.menu_bar.max_length));
+        if title != self.title {
+            self.tray_icon.set_title(title.as_deref());
+            self.title = title;
+            energy::record(Activity::TrayUpdate);
+        }
+    }
+
     /// Update the last scrobbled display
     pub fn update_last_scrobbled(&mut self, track: Option<String>) -> Result<()> {
         let text = if let Some(ref t) = track {
@@ -137,9 +315,319 @@ impl TrayManager {
             "Last Scrobbled: None".to_string()
         };
 
-        self.last_scrobble_item.set_text(text);
+        set_text(&self.last_scrobble_item, text);
         self.state.last_scrobbled = track;
 
         Ok(())
     }
+
+    /// Update the time left until the current track is scrobbled
+    pub fn update_scrobble_countdown(&mut self, session: Option<&SessionProgress>) -> Result<()> {
+        set_text(&self.scrobble_countdown_item, countdown_text(session));
+        set_enabled(
+            &self.scrobble_now_item,
+            session.is_some_and(|session| !session.scrobbled),
+        );
+        // Also off once skipped, since it then won't be scrobbled anyway
+        set_enabled(
+            &self.skip_scrobble_item,
+            session.is_some_and(|session| !session.scrobbled && session.scrobble_at.is_some()),
+        );
+        set_enabled(&self.clear_now_playing_item, session.is_some());
+
+        // The result of loving stays up while the loved track plays
+        let loved = self
+            .loved
+            .as_ref()
+            .filter(|(track, _)| session.is_some_and(|session| session.track == *track));
+        let text = match loved {
+            Some((_, text)) => text.clone(),
+            None => {
+                self.loved = None;
+                LOVE_TEXT.to_string()
+            }
+        };
+        set_text(&self.love_item, text);
+        set_enabled(&self.love_item, session.is_some());
+
+        Ok(())
+    }
+
+    /// Show which services the track was loved on
+    pub fn show_love_result(&mut self, track: Track, results: &[(String, Result<()>)]) {
+        let names = |ok: bool| -> Vec<&str> {
+            results
+                .iter()
+                .filter(|(_, result)| result.is_ok() == ok)
+                .map(|(name, _)| name.as_str())
+                .collect()
+        };
+        let (loved, failed) = (names(true), names(false));
+        let text = match (loved.is_empty(), failed.is_empty()) {
+            (false, true) => format!("♥ Loved on {}", loved.join(", ")),
+            (false, false) => format!(
+                "♥ Loved on {}, Failed on {}",
+                loved.join(", "),
+                failed.join(", ")
+            ),
+            _ => format!("Couldn't Love on {} (Try Again)", failed.join(", ")),
+        };
+
+        set_text(&self.love_item, text.clone());
+        self.loved = Some((track, text));
+    }
+
+    /// List the plays held back for review, most recent first
+    pub fn update_review(&mut self, queue: &[&HistoryEntry]) -> Result<()> {
+        let ids: Vec<u64> = queue.iter().take(REVIEW_SLOTS).map(|e| e.id).collect();
+        if ids != self.review_ids {
+            for item in &self.review_items[..self.review_ids.len()] {
+                self.review_menu
+                    .remove(item)
+                    .context("Failed to remove review item")?;
+            }
+            for (position, (item, entry)) in self.review_items.iter().zip(queue).enumerate() {
+                set_text(item, format!("{} - {}…", entry.artist, entry.title));
+                self.review_menu
+                    .insert(item, position)
+                    .context("Failed to add review item")?;
+            }
+            self.review_ids = ids;
+            energy::record(Activity::TrayUpdate);
+        }
+
+        let text = match queue.len() {
+            0 => REVIEW_TEXT.to_string(),
+            count => format!("{} ({})", REVIEW_TEXT, count),
+        };
+        if self.review_menu.text() != text {
+            self.review_menu.set_text(text);
+            energy::record(Activity::TrayUpdate);
+        }
+        if self.review_menu.is_enabled() == queue.is_empty() {
+            self.review_menu.set_enabled(!queue.is_empty());
+            energy::record(Activity::TrayUpdate);
+        }
+
+        Ok(())
+    }
+
+    /// History ID of the play in a review slot
+    pub fn review_id(&self, slot: usize) -> Option<u64> {
+        self.review_ids.get(slot).copied()
+    }
+
+    /// Show the allowed and ignored apps in the Apps menu
+    pub fn update_apps(&mut self, filtering: &AppFilteringConfig) -> Result<()> {
+        self.allowed_apps.update(&filtering.allowed_apps)?;
+        self.ignored_apps.update(&filtering.ignored_apps)
+    }
+
+    /// Bundle ID of the app in a slot of the allowed or ignored apps
+    pub fn app(&self, allowed: bool, slot: usize) -> Option<String> {
+        let list = if allowed {
+            &self.allowed_apps
+        } else {
+            &self.ignored_apps
+        };
+        list.apps.get(slot).cloned()
+    }
+
+    /// Show whether scrobbling is paused, and for how much longer
+    pub fn update_paused(&mut self, paused: bool, until: Option<DateTime<Utc>>) -> Result<()> {
+        let text = match (paused, until) {
+            (false, _) => PAUSE_TEXT.to_string(),
+            (true, None) => "Scrobbling Paused".to_string(),
+            (true, Some(until)) => {
+                // Rounded up, so it doesn't say 0 min while still paused
+                let seconds = (until - Utc::now()).num_seconds().max(0);
+                format!("Scrobbling Paused ({} min left)", (seconds + 59) / 60)
+            }
+        };
+
+        if self.pause_menu.text() != text {
+            self.pause_menu.set_text(text);
+            energy::record(Activity::TrayUpdate);
+        }
+        set_enabled(&self.resume_item, paused);
+
+        Ok(())
+    }
+
+    /// Update the guest mode toggle and when it turns itself off
+    pub fn update_guest_mode(&mut self, on: bool, expires: Option<DateTime<Utc>>) -> Result<()> {
+        let text = match expires {
+            Some(expires) => format!(
+                "Guest Mode (until {})",
+                expires.with_timezone(&Local).format("%H:%M")
+            ),
+            None => "Guest Mode".to_string(),
+        };
+
+        if self.guest_mode_item.text() != text {
+            self.guest_mode_item.set_text(text);
+            energy::record(Activity::TrayUpdate);
+        }
+        set_checked(&self.guest_mode_item, on);
+
+        Ok(())
+    }
+
+    /// Check the profile in use
+    pub fn update_profile(&mut self, active: Option<&str>) -> Result<()> {
+        for (name, item) in &self.profile_items {
+            set_checked(item, name.as_deref() == active);
+        }
+
+        Ok(())
+    }
+}
+
+// The menu is updated after every poll, but macOS only has to redraw it when something changed
+
+/// A list of the Apps menu, with a submenu for each app to move it to the other list or remove it
+pub struct AppList {
+    menu: Submenu,
+    title: &'static str,
+    /// Slots for the apps; the first `apps.len()` are in the menu
+    pub slots: Vec<AppSlot>,
+    apps: Vec<String>,
+}
+
+/// An app in an [`AppList`]
+pub struct AppSlot {
+    menu: Submenu,
+    /// Moves the app to the other list
+    pub move_item: MenuItem,
+    /// Takes the app off both lists, so it's asked about again
+    pub remove_item: MenuItem,
+}
+
+impl AppList {
+    fn new(title: &'static str, move_text: &str) -> Result<Self> {
+        let slots = (0..APP_SLOTS)
+            .map(|_| {
+                let slot = AppSlot {
+                    menu: Submenu::new("", true),
+                    move_item: MenuItem::new(move_text, true, None),
+                    remove_item: MenuItem::new("Remove", true, None),
+                };
+                slot.menu
+                    .append(&slot.move_item)
+                    .context("Failed to add move app item")?;
+                slot.menu
+                    .append(&slot.remove_item)
+                    .context("Failed to add remove app item")?;
+                Ok(slot)
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            menu: Submenu::new(title, false),
+            title,
+            slots,
+            apps: Vec::new(),
+        })
+    }
+
+    /// Show the apps, the first `APP_SLOTS` of them with their actions
+    fn update(&mut self, apps: &[String]) -> Result<()> {
+        let shown: Vec<String> = apps.iter().take(APP_SLOTS).cloned().collect();
+        if shown != self.apps {
+            for slot in &self.slots[..self.apps.len()] {
+                self.menu
+                    .remove(&slot.menu)
+                    .context("Failed to remove app menu")?;
+            }
+            for (position, (slot, app)) in self.slots.iter().zip(&shown).enumerate() {
+                if slot.menu.text() != *app {
+                    slot.menu.set_text(app);
+                }
+                self.menu
+                    .insert(&slot.menu, position)
+                    .context("Failed to add app menu")?;
+            }
+            self.apps = shown;
+            energy::record(Activity::TrayUpdate);
+        }
+
+        let text = match apps.len() {
+            0 => self.title.to_string(),
+            count => format!("{} ({})", self.title, count),
+        };
+        if self.menu.text() != text {
+            self.menu.set_text(text);
+            energy::record(Activity::TrayUpdate);
+        }
+        if self.menu.is_enabled() == apps.is_empty() {
+            self.menu.set_enabled(!apps.is_empty());
+            energy::record(Activity::TrayUpdate);
+        }
+
+        Ok(())
+    }
+}
+
+fn set_text(item: &MenuItem, text: String) {
+    if item.text() != text {
+        item.set_text(text);
+        energy::record(Activity::TrayUpdate);
+    }
+}
+
+fn set_enabled(item: &MenuItem, enabled: bool) {
+    if item.is_enabled() != enabled {
+        item.set_enabled(enabled);
+        energy::record(Activity::TrayUpdate);
+    }
+}
+
+fn set_checked(item: &CheckMenuItem, checked: bool) {
+    if item.is_checked() != checked {
+        item.set_checked(checked);
+        energy::record(Activity::TrayUpdate);
+    }
+}
+
+const LOVE_TEXT: &str = "Love Track";
+
+const PAUSE_TEXT: &str = "Pause Scrobbling";
+
+const REVIEW_TEXT: &str = "Needs Review";
+
+/// Plays held back for review listed in the menu; the rest are in `history review`
+const REVIEW_SLOTS: usize = 10;
+
+/// Apps listed in each list of the Apps menu; the rest are only in the config
+const APP_SLOTS: usize = 20;
+
+/// Pause menu choices: minutes to pause for (None until resumed), and their labels
+const PAUSE_CHOICES: &[(Option<u64>, &str)] = &[
+    (Some(15), "For 15 Minutes"),
+    (Some(60), "For 1 Hour"),
+    (None, "Until Resumed"),
+];
+
+/// Text cut off after `max_length` characters with `…` (0 = no limit)
+fn truncate(text: &str, max_length: usize) -> String {
+    if max_length == 0 || text.chars().count() <= max_length {
+        return text.to_string();
+    }
+    let kept: String = text.chars().take(max_length.saturating_sub(1)).collect();
+    format!("{}…", kept.trim_end())
+}
+
+/// Whether the current track will count, and how much longer it has to play to do so
+fn countdown_text(session: Option<&SessionProgress>) -> String {
+    match session {
+        None => "Nothing to Scrobble".to_string(),
+        Some(session) if session.scrobbled => "Scrobbled".to_string(),
+        Some(session) => match session.scrobble_at {
+            Some(at) => format!(
+                "Scrobbling in {}",
+                format_seconds(at.saturating_sub(session.listened_seconds))
+            ),
+            None => "Won't Be Scrobbled".to_string(),
+        },
+    }
 }
@@ -0,0 +1,405 @@
+// Web UI module
+// Serves a small status, history and settings page from the binary, for headless machines, and
+// pushes playback events to WebSocket clients like OBS overlays
+
+use crate::artwork;
+use crate::backup;
+use crate::config::{Config, WebConfig};
+use crate::history::History;
+use crate::media_monitor::SessionProgress;
+use anyhow::{Context, Result};
+use base64::Engine;
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::io::{Cursor, Read, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+const INDEX_HTML: &str = include_str!("../assets/web/index.html");
+const APP_JS: &str = include_str!("../assets/web/app.js");
+const STYLE_CSS: &str = include_str!("../assets/web/style.css");
+
+/// Largest config accepted from the settings page
+const MAX_BODY_BYTES: u64 = 1024 * 1024;
+
+/// Scrobbles returned by the history endpoint when no limit is given
+const DEFAULT_HISTORY_LIMIT: usize = 50;
+
+/// Appended to a client's key to accept a WebSocket handshake (RFC 6455)
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+type BodyResponse = Response<Cursor<Vec<u8>>>;
+
+/// State the engine shares with the server thread
+#[derive(Default)]
+struct Shared {
+    session: Mutex<Option<SessionProgress>>,
+    /// Cached album art of the track being played
+    artwork: Mutex<Option<PathBuf>>,
+    /// The config was saved from the settings page and should be reloaded
+    config_changed: AtomicBool,
+    /// Connected WebSocket clients, each written to by its own thread
+    subscribers: Mutex<Vec<Sender<String>>>,
+}
+
+/// Handle to the web UI running on its own thread
+pub struct WebServer {
+    shared: Arc<Shared>,
+}
+
+impl WebServer {
+    /// Start serving the web UI
+    pub fn start(config: &WebConfig) -> Result<Self> {
+        anyhow::ensure!(!config.token.is_empty(), "web.token is not set");
+
+        let server = Server::http(&config.address)
+            .map_err(|e| anyhow::anyhow!("{}", e))
+            .with_context(|| format!("Failed to listen on {}", config.address))?;
+        let shared = Arc::new(Shared::default());
+
+        let token = config.token.clone();
+        let thread_shared = shared.clone();
+        std::thread::spawn(move || {
+            for request in server.incoming_requests() {
+                handle_request(request, &token, &thread_shared);
+            }
+        });
+
+        log::info!("Web UI listening on http://{}", config.address);
+        Ok(Self { shared })
+    }
+
+    /// Update the now playing info and art shown on the status page
+    pub fn publish(&self, session: Option<SessionProgress>, artwork: Option<PathBuf>) {
+        *self.shared.session.lock().unwrap() = session;
+        *self.shared.artwork.lock().unwrap() = artwork;
+    }
+
+    /// Push an event to every connected WebSocket client, as a JSON-RPC notification like the
+    /// control API's
+    pub fn notify(&self, event: &str, params: Value) {
+        let mut subscribers = self.shared.subscribers.lock().unwrap();
+        if subscribers.is_empty() {
+            return;
+        }
+
+        let message = json!({"jsonrpc": "2.0", "method": event, "params": params}).to_string();
+        subscribers.retain(|subscriber| subscriber.send(message.clone()).is_ok());
+    }
+
+    /// Check whether the config was changed from the settings page since the last call
+    pub fn take_config_change(&self) -> bool {
+        self.shared.config_changed.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// Generate a random token for the web UI
+pub fn generate_token() -> Result<String> {
+    let mut bytes = [0u8; 16];
+    std::fs::File::open("/dev/urandom")
+        .and_then(|mut f| f.read_exact(&mut bytes))
+        .context("Failed to read random bytes")?;
+
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+#[derive(Serialize)]
+struct Status {
+    now_playing: Option<SessionProgress>,
+    /// Name of the current art, changing with the album; fetched from `/api/artwork`
+    artwork: Option<String>,
+    services: Vec<ServiceStatus>,
+    scrobbles: usize,
+}
+
+#[derive(Serialize)]
+struct ServiceStatus {
+    key: String,
+    enabled: bool,
+    queued: usize,
+}
+
+fn handle_request(mut request: Request, token: &str, shared: &Shared) {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+
+    // Browsers can't set headers on a WebSocket, so the feed also takes the token in the URL
+    if path == "/api/events" {
+        let given = query
+            .split('&')
+            .find_map(|param| param.strip_prefix("token="))
+            .unwrap_or_default();
+        if authorized(&request, token) || constant_time_eq(given.as_bytes(), token.as_bytes()) {
+            subscribe(request, shared);
+        } else if let Err(e) = request.respond(text(401, "Missing or wrong token")) {
+            log::debug!("Failed to send web UI response: {}", e);
+        }
+        return;
+    }
+
+    let response = match (request.method(), path) {
+        (Method::Get, "/") => asset(INDEX_HTML, "text/html; charset=utf-8"),
+        (Method::Get, "/app.js") => asset(APP_JS, "text/javascript; charset=utf-8"),
+        (Method::Get, "/style.css") => asset(STYLE_CSS, "text/css; charset=utf-8"),
+        (_, path) if path.starts_with("/api/") => {
+            if !authorized(&request, token) {
+                text(401, "Missing or wrong token")
+            } else {
+                match api(&mut request, path, query, shared) {
+                    Ok(response) => response,
+                    Err(e) => text(400, &format!("{:#}", e)),
+                }
+            }
+        }
+        _ => text(404, "Not found"),
+    };
+
+    if let Err(e) = request.respond(response) {
+        log::debug!("Failed to send web UI response: {}", e);
+    }
+}
+
+fn api(request: &mut Request, path: &str, query: &str, shared: &Shared) -> Result<BodyResponse> {
+    match (request.method(), path) {
+        (Method::Get, "/api/status") => {
+            let config = Config::load()?;
+            // Counted by the database; the page asks every few seconds
+            let totals = History::totals()?;
+            let status = Status {
+                now_playing: shared.session.lock().unwrap().clone(),
+                artwork: shared
+                    .artwork
+                    .lock()
+                    .unwrap()
+                    .as_ref()
+                    .and_then(|path| path.file_name())
+                    .map(|name| name.to_string_lossy().into_owned()),
+                services: service_status(&config, &totals.queued),
+                scrobbles: totals.scrobbles,
+            };
+            json(&status)
+        }
+        (Method::Get, "/api/history") => {
+            let limit = query
+                .split('&')
+                .find_map(|param| param.strip_prefix("limit="))
+                .map(|limit| limit.parse().context("limit must be a number"))
+                .transpose()?
+                .unwrap_or(DEFAULT_HISTORY_LIMIT);
+            json(&History::latest(limit)?)
+        }
+        (Method::Get, "/api/artwork") => {
+            let Some(path) = shared.artwork.lock().unwrap().clone() else {
+                return Ok(text(404, "No artwork"));
+            };
+            let image = std::fs::read(&path).context("Failed to read artwork")?;
+            Ok(Response::from_data(image)
+                .with_header(header("Content-Type", artwork::content_type(&path))))
+        }
+        (Method::Get, "/api/config") => {
+            let mut config = Config::load()?;
+            // Credentials and tokens stay off the page, like in a backup without secrets
+            backup::strip_secrets(&mut config);
+            let content = toml::to_string_pretty(&config).context("Failed to serialize config")?;
+            Ok(text(200, &content))
+        }
+        (Method::Put, "/api/config") => {
+            let mut content = String::new();
+            request
+                .as_reader()
+                .take(MAX_BODY_BYTES)
+                .read_to_string(&mut content)
+                .context("Failed to read request body")?;
+
+            let mut config: Config = toml::from_str(&content).context("Failed to parse config")?;
+            // The page shows them empty, so keep the current ones
+            let disabled = backup::restore_secrets(&mut config, &Config::load()?);
+            config.validate()?;
+            config.save()?;
+            shared.config_changed.store(true, Ordering::Relaxed);
+
+            if disabled.is_empty() {
+                Ok(text(200, "Saved"))
+            } else {
+                Ok(text(
+                    200,
+                    &format!("Saved; turned off {} (no credentials)", disabled.join(", ")),
+                ))
+            }
+        }
+        _ => Ok(text(404, "Not found")),
+    }
+}
+
+/// Take over a WebSocket connection and send it events until it goes away
+///
+/// Messages from the client are never read; a closed connection shows up as a failed write.
+fn subscribe(request: Request, shared: &Shared) {
+    let key = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Sec-WebSocket-Key"))
+        .map(|h| h.value.as_str().trim().to_string());
+    let Some(key) = key else {
+        if let Err(e) = request.respond(text(400, "Expected a WebSocket connection")) {
+            log::debug!("Failed to send web UI response: {}", e);
+        }
+        return;
+    };
+
+    let response =
+        Response::empty(101).with_header(header("Sec-WebSocket-Accept", &accept_key(&key)));
+    let mut stream = request.upgrade("websocket", response);
+
+    // The current track first, so a client doesn't have to wait for the next change
+    let status = json!({
+        "jsonrpc": "2.0",
+        "method": "status",
+        "params": { "now_playing": *shared.session.lock().unwrap() },
+    });
+    let (sender, receiver) = mpsc::channel::<String>();
+    let _ = sender.send(status.to_string());
+    shared.subscribers.lock().unwrap().push(sender);
+
+    std::thread::spawn(move || {
+        for message in receiver {
+            if stream
+                .write_all(&text_frame(&message))
+                .and_then(|_| stream.flush())
+                .is_err()
+            {
+                break;
+            }
+        }
+        log::debug!("WebSocket client disconnected");
+    });
+}
+
+/// `Sec-WebSocket-Accept` answering a client's `Sec-WebSocket-Key`
+fn accept_key(key: &str) -> String {
+    let digest = Sha1::digest(format!("{}{}", key, WEBSOCKET_GUID));
+    base64::engine::general_purpose::STANDARD.encode(digest)
+}
+
+/// An unmasked, unfragmented WebSocket text frame, as sent by servers
+fn text_frame(message: &str) -> Vec<u8> {
+    let payload = message.as_bytes();
+    let mut frame = vec![0x81];
+    match payload.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Configured services with the number of submissions queued for each
+fn service_status(config: &Config, queued: &HashMap<String, usize>) -> Vec<ServiceStatus> {
+    let (lastfm, listenbrainz) = config.credentials();
+    let mut services: Vec<(String, bool)> = Vec::new();
+    if let Some(lastfm) = lastfm {
+        services.push((
+            config.service_key("lastfm"),
+            lastfm.enabled && !lastfm.session_key.is_empty(),
+        ));
+    }
+    for lb in listenbrainz {
+        services.push((
+            config.service_key(&format!("listenbrainz:{}", lb.name)),
+            lb.enabled,
+        ));
+    }
+
+    services
+        .into_iter()
+        .map(|(key, enabled)| ServiceStatus {
+            queued: queued.get(&key).copied().unwrap_or(0),
+            key,
+            enabled,
+        })
+        .collect()
+}
+
+/// Check the request's `Authorization: Bearer <token>` header
+fn authorized(request: &Request, token: &str) -> bool {
+    request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Authorization"))
+        .and_then(|h| h.value.as_str().strip_prefix("Bearer "))
+        .is_some_and(|given| constant_time_eq(given.as_bytes(), token.as_bytes()))
+}
+
+/// Compare without returning early, so the token can't be guessed from response times
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("valid header")
+}
+
+fn asset(content: &str, content_type: &str) -> BodyResponse {
+    Response::from_string(content).with_header(header("Content-Type", content_type))
+}
+
+fn text(status: u16, content: &str) -> BodyResponse {
+    Response::from_string(content)
+        .with_status_code(status)
+        .with_header(header("Content-Type", "text/plain; charset=utf-8"))
+}
+
+fn json<T: Serialize>(value: &T) -> Result<BodyResponse> {
+    let body = serde_json::to_string(value).context("Failed to serialize response")?;
+    Ok(Response::from_string(body).with_header(header("Content-Type", "application/json")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+        assert!(!constant_time_eq(b"", b"secret"));
+    }
+
+    #[test]
+    fn test_websocket_handshake_and_frames() {
+        // Example from RFC 6455
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+
+        assert_eq!(text_frame("Hello"), b"\x81\x05Hello");
+        let frame = text_frame(&"a".repeat(300));
+        assert_eq!(&frame[..4], &[0x81, 126, 0x01, 0x2c]);
+        assert_eq!(frame.len(), 304);
+        let frame = text_frame(&"a".repeat(70_000));
+        assert_eq!(&frame[..2], &[0x81, 127]);
+        assert_eq!(u64::from_be_bytes(frame[2..10].try_into().unwrap()), 70_000);
+    }
+
+    #[test]
+    fn test_generate_token() {
+        let token = generate_token().unwrap();
+        assert_eq!(token.len(), 32);
+        assert!(token.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(token, generate_token().unwrap());
+    }
+}
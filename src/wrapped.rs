@@ -0,0 +1,306 @@
+// Year in review module
+// Summarizes a year of scrobble history as Markdown or HTML, for `osx-scrobbler wrapped`
+
+use crate::history::HistoryEntry;
+use crate::stats::format_duration;
+use chrono::{Datelike, NaiveDate, TimeZone};
+use std::collections::HashMap;
+
+/// Entries in each top list
+const TOP_COUNT: usize = 10;
+
+/// An artist, track or album and how often it was played
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ranked {
+    pub name: String,
+    pub scrobbles: usize,
+}
+
+/// A year of listening
+#[derive(Debug, Clone, PartialEq)]
+pub struct Wrapped {
+    pub year: i32,
+    pub scrobbles: usize,
+    pub seconds: u64,
+    pub top_artists: Vec<Ranked>,
+    pub top_tracks: Vec<Ranked>,
+    pub top_albums: Vec<Ranked>,
+    /// Day with the most scrobbles, and how many
+    pub busiest_day: Option<(NaiveDate, usize)>,
+}
+
+impl Wrapped {
+    /// Summarize the plays of `year` in the given timezone
+    ///
+    /// Deleted scrobbles and guest listens are left out. Plays without a recorded listening time
+    /// (e.g. ones imported from a service) count their track duration instead.
+    pub fn new<Tz: TimeZone>(entries: &[HistoryEntry], year: i32, tz: &Tz) -> Self {
+        let mut artists = HashMap::new();
+        let mut tracks = HashMap::new();
+        let mut albums = HashMap::new();
+        let mut days: HashMap<NaiveDate, usize> = HashMap::new();
+        let mut scrobbles = 0;
+        let mut seconds = 0;
+
+        for entry in entries.iter().filter(|e| !e.deleted && !e.guest) {
+            let day = entry.played_at.with_timezone(tz).date_naive();
+            if day.year() != year {
+                continue;
+            }
+
+            scrobbles += 1;
+            seconds += entry.listened_seconds.or(entry.duration).unwrap_or(0);
+            *days.entry(day).or_default() += 1;
+            *artists.entry(entry.artist.clone()).or_default() += 1;
+            *tracks
+                .entry(format!("{} - {}", entry.artist, entry.title))
+                .or_default() += 1;
+            if let Some(album) = entry.album.as_ref().filter(|a| !a.is_empty()) {
+                *albums
+                    .entry(format!("{} - {}", entry.artist, album))
+                    .or_default() += 1;
+            }
+        }
+
+        // Earliest day wins a tie
+        let busiest_day = days
+            .into_iter()
+            .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)));
+
+        Self {
+            year,
+            scrobbles,
+            seconds,
+            top_artists: top(artists),
+            top_tracks: top(tracks),
+            top_albums: top(albums),
+            busiest_day,
+        }
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut out = format!("# {} in Music\n\n", self.year);
+        if self.scrobbles == 0 {
+            out.push_str("No scrobbles this year.\n");
+            return out;
+        }
+
+        out.push_str(&format!(
+            "- **{}** scrobbles\n- **{}** minutes listened ({})\n",
+            self.scrobbles,
+            self.seconds / 60,
+            format_duration(self.seconds)
+        ));
+        if let Some((day, count)) = self.busiest_day {
+            out.push_str(&format!(
+                "- Busiest day: **{}** with {} scrobbles\n",
+                day.format("%A, %B %-d"),
+                count
+            ));
+        }
+
+        for (heading, list) in self.lists() {
+            if list.is_empty() {
+                continue;
+            }
+            out.push_str(&format!("\n## {}\n\n", heading));
+            for (rank, item) in list.iter().enumerate() {
+                out.push_str(&format!(
+                    "{}. {} ({})\n",
+                    rank + 1,
+                    markdown_escape(&item.name),
+                    item.scrobbles
+                ));
+            }
+        }
+        out
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut body = format!("<h1>{} in Music</h1>\n", self.year);
+        if self.scrobbles == 0 {
+            body.push_str("<p>No scrobbles this year.</p>\n");
+        } else {
+            body.push_str(&format!(
+                "<ul>\n<li><strong>{}</strong> scrobbles</li>\n\
+                 <li><strong>{}</strong> minutes listened ({})</li>\n",
+                self.scrobbles,
+                self.seconds / 60,
+                format_duration(self.seconds)
+            ));
+            if let Some((day, count)) = self.busiest_day {
+                body.push_str(&format!(
+                    "<li>Busiest day: <strong>{}</strong> with {} scrobbles</li>\n",
+                    day.format("%A, %B %-d"),
+                    count
+                ));
+            }
+            body.push_str("</ul>\n");
+
+            for (heading, list) in self.lists() {
+                if list.is_empty() {
+                    continue;
+                }
+                body.push_str(&format!("<h2>{}</h2>\n<ol>\n", heading));
+                for item in list {
+                    body.push_str(&format!(
+                        "<li>{} ({})</li>\n",
+                        html_escape(&item.name),
+                        item.scrobbles
+                    ));
+                }
+                body.push_str("</ol>\n");
+            }
+        }
+
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>{} in Music</title>\n</head>\n<body>\n{}</body>\n</html>\n",
+            self.year, body
+        )
+    }
+
+    fn lists(&self) -> [(&'static str, &[Ranked]); 3] {
+        [
+            ("Top Artists", &self.top_artists),
+            ("Top Tracks", &self.top_tracks),
+            ("Top Albums", &self.top_albums),
+        ]
+    }
+}
+
+/// Most played first, alphabetical on a tie
+fn top(counts: HashMap<String, usize>) -> Vec<Ranked> {
+    let mut ranked: Vec<Ranked> = counts
+        .into_iter()
+        .map(|(name, scrobbles)| Ranked { name, scrobbles })
+        .collect();
+    ranked.sort_by(|a, b| {
+        b.scrobbles
+            .cmp(&a.scrobbles)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    ranked.truncate(TOP_COUNT);
+    ranked
+}
+
+fn markdown_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']' | '<' | '>' | '#') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Utc};
+
+    fn entry(artist: &str, title: &str, played_at: DateTime<Utc>) -> HistoryEntry {
+        HistoryEntry {
+            id: 1,
+            artist: artist.to_string(),
+            title: title.to_string(),
+            album: Some("Album".to_string()),
+            duration: Some(180),
+            played_at,
+            listened_seconds: Some(120),
+            ..Default::default()
+        }
+    }
+
+    fn entries() -> Vec<HistoryEntry> {
+        let mut guest = entry(
+            "Guest",
+            "Song",
+            Utc.with_ymd_and_hms(2026, 5, 1, 0, 0, 0).unwrap(),
+        );
+        guest.guest = true;
+        let mut imported = entry(
+            "B",
+            "Two",
+            Utc.with_ymd_and_hms(2026, 5, 1, 0, 0, 0).unwrap(),
+        );
+        imported.listened_seconds = None;
+        vec![
+            entry(
+                "A",
+                "One",
+                Utc.with_ymd_and_hms(2026, 3, 1, 10, 0, 0).unwrap(),
+            ),
+            entry(
+                "A",
+                "One",
+                Utc.with_ymd_and_hms(2026, 3, 1, 11, 0, 0).unwrap(),
+            ),
+            entry(
+                "A",
+                "Three",
+                Utc.with_ymd_and_hms(2026, 4, 1, 0, 0, 0).unwrap(),
+            ),
+            entry(
+                "Old",
+                "Song",
+                Utc.with_ymd_and_hms(2025, 12, 31, 0, 0, 0).unwrap(),
+            ),
+            guest,
+            imported,
+        ]
+    }
+
+    #[test]
+    fn test_summary() {
+        let wrapped = Wrapped::new(&entries(), 2026, &Utc);
+        assert_eq!(wrapped.scrobbles, 4);
+        assert_eq!(wrapped.seconds, 3 * 120 + 180);
+        assert_eq!(
+            wrapped.top_artists,
+            vec![
+                Ranked {
+                    name: "A".to_string(),
+                    scrobbles: 3
+                },
+                Ranked {
+                    name: "B".to_string(),
+                    scrobbles: 1
+                },
+            ]
+        );
+        assert_eq!(wrapped.top_tracks[0].name, "A - One");
+        assert_eq!(wrapped.top_albums[0].name, "A - Album");
+        assert_eq!(
+            wrapped.busiest_day,
+            Some((NaiveDate::from_ymd_opt(2026, 3, 1).unwrap(), 2))
+        );
+    }
+
+    #[test]
+    fn test_rendering() {
+        let mut entries = entries();
+        entries[0].artist = "<Tag> & *Stars*".to_string();
+        let wrapped = Wrapped::new(&entries, 2026, &Utc);
+
+        let markdown = wrapped.to_markdown();
+        assert!(markdown.starts_with("# 2026 in Music\n"));
+        assert!(markdown.contains("- Busiest day: **Sunday, March 1** with 2 scrobbles\n"));
+        assert!(markdown.contains("\\<Tag\\> & \\*Stars\\*"));
+
+        let html = wrapped.to_html();
+        assert!(html.contains("<h2>Top Artists</h2>\n<ol>\n<li>A (2)</li>\n"));
+        assert!(html.contains("&lt;Tag&gt; &amp; *Stars*"));
+
+        let empty = Wrapped::new(&entries, 2020, &Utc);
+        assert!(empty.to_markdown().contains("No scrobbles this year."));
+    }
+}